@@ -1,20 +1,17 @@
-// Imports and Dependencies
-mod backend;
-mod security;
-mod analytics;
-mod export;
-mod filters;
-mod websocket;
-mod ai;
-mod data_ingestion;
-mod chat_ui;
-
+// Imports and Dependencies. The modules below are the library API (data loading, AI,
+// export, security, state); the GTK window and chat are built on top of them.
 use std::sync::Arc;
-use backend::{SecEdgarApi, AppState, FilingRecord};
-use security::{sanitize_ticker, AuthManager, RBACRole};
-use export::export_filings;
-use filters::FilterPane;
-use websocket::start_realtime_updates;
+use backend::{SecEdgarApi, AppState, DataState, FetchRequest, FilingRecord, until_cancelled};
+use security::{parse_ticker_list, AuthManager, Permission};
+use export::{export_bookmarks, export_filings, FilingField, FILING_EXPORT_FORMATS};
+use config::{AppConfig, Theme};
+use audit::audit_log;
+use filters::{
+    date_sort_key, filing_row_matches, ColumnVisibility, FilterPane, DATE_SORT_COLUMN, FILING_COLUMNS, SEARCH_COLUMNS,
+};
+use shortcuts::ShortcutAction;
+use websocket::{start_realtime_updates, DEFAULT_MAX_RETRY_INTERVAL};
+use atk::prelude::*;
 use gtk::prelude::*;
 use gtk::{
     Application, ApplicationWindow, Button, Entry, ScrolledWindow, Box as GtkBox, Orientation,
@@ -26,112 +23,44 @@ use log::{info, error};
 use pango;
 use polars::prelude::*;
 use crate::ai::{FinfilesAI, OnnxAIModule, RemoteLLMAIModule, FinancialAIModule, CustomModelAIModule};
-use crate::data_ingestion::FinancialDataLoader;
+use crate::data_ingestion::{is_amendment, FinancialDataLoader, LoadStage};
 use crate::chat_ui::FinancialAIChatApp;
 use crate::error::*;
 
+// Start or stop the loading spinner and report the matching busy state to assistive tech
+fn set_busy(spinner: &Spinner, busy: bool) {
+    if busy {
+        spinner.start();
+    } else {
+        spinner.stop();
+    }
+    if let Some(accessible) = spinner.get_accessible() {
+        accessible.notify_state_change(atk::StateType::Busy, busy);
+    }
+}
+
+// Give a label the status bar role, so screen readers announce "Filings loaded.",
+// "Export failed", etc. when its text changes
+fn mark_as_status(label: &Label, name: &str) {
+    if let Some(accessible) = label.get_accessible() {
+        accessible.set_role(atk::Role::Statusbar);
+        accessible.set_name(name);
+    }
+}
+
 // Unified Main Window
-fn build_main_window(app: &Application, state: Arc<AppState>, auth: Arc<AuthManager>, ai_modules: Vec<Arc<dyn FinancialAIModule>>, ai_data: Option<DataFrame>, audit_log_path: std::path::PathBuf, username: String) -> ApplicationWindow {
+fn build_main_window(app: &Application, state: Arc<AppState>, auth: Arc<AuthManager>, ai_modules: Vec<Arc<dyn FinancialAIModule>>, initial_data: DataState, audit_log_path: std::path::PathBuf, username: String) -> ApplicationWindow {
     let window = ApplicationWindow::new(app);
     window.set_title("AA SEC EDGAR + FINFILES AI: Professional Financial Data & AI Platform");
     window.set_default_size(1400, 900);
     window.set_resizable(true);
 
-    // Modern, high-contrast CSS
+    // Modern, high-contrast CSS; the theme comes from config and can be switched at runtime
     let provider = CssProvider::new();
-    provider
-        .load_from_data(
-            br#"
-                window { background: #181c20; }
-                box#main_vbox { background: #23272e; border-radius: 12px; padding: 24px; }
-                box#header_hbox { background: #23272e; border-radius: 8px; margin-bottom: 12px; padding: 12px 8px; }
-                button {
-                    background: linear-gradient(90deg, #1976d2 0%, #1565c0 100%);
-                    color: #fff;
-                    font: bold 16px 'Segoe UI', Arial, sans-serif;
-                    border-radius: 6px;
-                    min-width: 120px;
-                    min-height: 36px;
-                    margin: 0 4px;
-                    box-shadow: 0 2px 8px rgba(25,118,210,0.08);
-                    transition: background 0.2s;
-                }
-                button:hover, button:focus {
-                    background: linear-gradient(90deg, #2196f3 0%, #1976d2 100%);
-                    color: #fff;
-                    outline: 2px solid #90caf9;
-                }
-                entry {
-                    font: 16px 'Segoe UI', Arial, sans-serif;
-                    background: #1a1d22;
-                    color: #e3eaf3;
-                    border-radius: 6px;
-                    border: 1.5px solid #1976d2;
-                    padding: 8px 12px;
-                    min-width: 320px;
-                    margin-right: 8px;
-                }
-                entry:focus {
-                    border: 2px solid #42a5f5;
-                    background: #23272e;
-                }
-                label {
-                    font: 15px 'Segoe UI', Arial, sans-serif;
-                    color: #b0bec5;
-                }
-                treeview {
-                    font: 15px 'Segoe UI', Arial, sans-serif;
-                    background: #23272e;
-                    color: #e3eaf3;
-                    border-radius: 8px;
-                    border: 1.5px solid #1976d2;
-                }
-                treeview row:selected, treeview row:selected:focus {
-                    background: #1976d2;
-                    color: #fff;
-                }
-                treeview header {
-                    background: #1565c0;
-                    color: #fff;
-                    font-weight: bold;
-                    font-size: 15px;
-                }
-                spinner { color: #42a5f5; }
-                #status_label {
-                    color: #90caf9;
-                    font: italic 15px 'Segoe UI', Arial, sans-serif;
-                    margin-top: 8px;
-                }
-                #load_more_button {
-                    background: linear-gradient(90deg, #43a047 0%, #388e3c 100%);
-                    color: #fff;
-                    font-weight: bold;
-                }
-                #load_more_button:hover, #load_more_button:focus {
-                    background: linear-gradient(90deg, #66bb6a 0%, #43a047 100%);
-                    outline: 2px solid #a5d6a7;
-                }
-                #export_button {
-                    background: linear-gradient(90deg, #fbc02d 0%, #f9a825 100%);
-                    color: #23272e;
-                    font-weight: bold;
-                }
-                #export_button:hover, #export_button:focus {
-                    background: linear-gradient(90deg, #ffe082 0%, #fbc02d 100%);
-                    outline: 2px solid #ffe082;
-                }
-                #ai_chat_button {
-                    background: linear-gradient(90deg, #8e24aa 0%, #3949ab 100%);
-                    color: #fff;
-                    font-weight: bold;
-                }
-                #ai_chat_button:hover, #ai_chat_button:focus {
-                    background: linear-gradient(90deg, #ba68c8 0%, #7986cb 100%);
-                    outline: 2px solid #b39ddb;
-                }
-            "#
-        )
-        .expect("Failed to load CSS");
+    let app_config = std::rc::Rc::new(std::cell::RefCell::new(AppConfig::current()));
+    if let Err(e) = theme::apply_theme(&provider, app_config.borrow().theme) {
+        error!("Failed to load theme: {}", e);
+    }
     StyleContext::add_provider_for_screen(
         &gdk::Screen::get_default().unwrap(),
         &provider,
@@ -159,6 +88,15 @@ fn build_main_window(app: &Application, state: Arc<AppState>, auth: Arc<AuthMana
     open_data_label.set_halign(Align::End);
     header_hbox.pack_end(&open_data_label, false, false, 0);
 
+    // Offline mode: say plainly that everything shown is bundled sample data
+    if state.is_offline() {
+        window.set_title("AA SEC EDGAR + FINFILES AI (Offline Demo)");
+        let demo_label = Label::new(Some("DEMO DATA"));
+        demo_label.set_markup("<span background='#e65100' foreground='#fff' weight='bold' size='large' rise='2000'> DEMO DATA (offline) </span>");
+        demo_label.set_tooltip_text(Some("Offline mode: filings and financials are bundled samples, not live SEC data"));
+        header_hbox.pack_end(&demo_label, false, false, 0);
+    }
+
     vbox.pack_start(&header_hbox, false, false, 0);
 
     // Ticker input and filter pane
@@ -168,14 +106,42 @@ fn build_main_window(app: &Application, state: Arc<AppState>, auth: Arc<AuthMana
     ticker_entry.set_tooltip_text(Some("Type a stock ticker, comma-separated, or upload a CSV"));
     ticker_entry.set_width_chars(24);
 
+    // User defaults from config.toml; the last fetched tickers are written back
+    if let Some(ticker) = &app_config.borrow().initial_ticker {
+        ticker_entry.set_text(ticker);
+    }
+
     let fetch_button = Button::new_with_label("Fetch SEC Filings (Ctrl+F)");
     fetch_button.set_widget_name("fetch_button");
     fetch_button.set_tooltip_text(Some("Fetch latest SEC filings for the given ticker(s)"));
 
+    let refresh_button = Button::new_with_label("Refresh (Ctrl+R)");
+    refresh_button.set_widget_name("refresh_button");
+    refresh_button.set_tooltip_text(Some("Re-fetch the current tickers from SEC, bypassing all caches"));
+
+    let upload_csv_button = Button::new_with_label("Upload CSV");
+    upload_csv_button.set_widget_name("upload_csv_button");
+    upload_csv_button.set_tooltip_text(Some("Load tickers from a CSV file (first column, or a 'ticker' column)"));
+
+    let find_company_button = Button::new_with_label("Find by Name");
+    find_company_button.set_widget_name("find_company_button");
+    find_company_button.set_tooltip_text(Some("Search company names for the entered text and pick a ticker to fetch"));
+
+    let bookmarks_button = Button::new_with_label("Bookmarks");
+    bookmarks_button.set_widget_name("bookmarks_button");
+    bookmarks_button.set_tooltip_text(Some("Show bookmarked filings across tickers"));
+
     let export_button = Button::new_with_label("Export (Ctrl+E)");
     export_button.set_widget_name("export_button");
     export_button.set_tooltip_text(Some("Export filings as CSV, PDF, or JSON"));
 
+    let export_format_combo = gtk::ComboBoxText::new();
+    for format in FILING_EXPORT_FORMATS.iter() {
+        export_format_combo.append_text(format.label());
+    }
+    export_format_combo.set_active(Some(0));
+    export_format_combo.set_tooltip_text(Some("Export format"));
+
     let ai_chat_button = Button::new_with_label("Open FINFILES AI Chat");
     ai_chat_button.set_widget_name("ai_chat_button");
     ai_chat_button.set_tooltip_text(Some("Analyze SEC data with FINFILES AI (chat, summary, forecast, anomaly, etc.)"));
@@ -183,16 +149,37 @@ fn build_main_window(app: &Application, state: Arc<AppState>, auth: Arc<AuthMana
     let filter_pane = FilterPane::new();
     hbox.pack_start(&ticker_entry, true, true, 0);
     hbox.pack_start(&fetch_button, false, false, 0);
+    hbox.pack_start(&refresh_button, false, false, 0);
+    hbox.pack_start(&upload_csv_button, false, false, 0);
+    hbox.pack_start(&find_company_button, false, false, 0);
+    hbox.pack_start(&bookmarks_button, false, false, 0);
     hbox.pack_start(&export_button, false, false, 0);
+    hbox.pack_start(&export_format_combo, false, false, 0);
     hbox.pack_start(&ai_chat_button, false, false, 0);
     hbox.pack_start(&filter_pane.widget, false, false, 0);
 
-    // Spinner (loading indicator)
+    // Spinner (loading indicator); its busy state is exposed to assistive tech
     let spinner = Spinner::new();
+    if let Some(accessible) = spinner.get_accessible() {
+        accessible.set_name("Fetch Progress");
+    }
+    set_busy(&spinner, false);
     spinner.set_halign(Align::End);
     spinner.set_valign(Align::Center);
     hbox.pack_start(&spinner, false, false, 0);
 
+    // Cancel button, shown next to the spinner only while a fetch runs
+    let cancel_button = Button::new_with_label("Cancel");
+    cancel_button.set_tooltip_text(Some("Stop the current fetch"));
+    cancel_button.set_no_show_all(true);
+    {
+        let state = state.clone();
+        cancel_button.connect_clicked(move |_| {
+            state.cancel_fetch();
+        });
+    }
+    hbox.pack_start(&cancel_button, false, false, 0);
+
     vbox.pack_start(&hbox, false, false, 0);
 
     // Output area: TreeView for filings
@@ -209,21 +196,42 @@ fn build_main_window(app: &Application, state: Arc<AppState>, auth: Arc<AuthMana
         Type::STRING, // Company Name
         Type::STRING, // Filing Type
         Type::STRING, // Sentiment/AI
+        Type::I64,    // Date sort key
     ]);
-    let filings_view = TreeView::with_model(&filings_store);
+    // Search filters rows client-side; sorting sits on top of the filter so both apply
+    // The search box belongs to the filter pane so presets can save its text
+    let filings_search = filter_pane.search_entry.clone();
+    let filings_filter = gtk::TreeModelFilter::new(&filings_store, None);
+    {
+        let filings_search = filings_search.clone();
+        filings_filter.set_visible_func(move |model, iter| {
+            let query = filings_search.get_text();
+            let fields: Vec<String> = SEARCH_COLUMNS
+                .iter()
+                .map(|&col| model.get_value(iter, col).get().ok().unwrap_or_default())
+                .collect();
+            let fields: Vec<&str> = fields.iter().map(String::as_str).collect();
+            filing_row_matches(&fields, query.as_str())
+        });
+    }
+    filings_search.connect_search_changed(clone!(@weak filings_filter => move |_| {
+        filings_filter.refilter();
+    }));
+    let filings_sorted = gtk::TreeModelSort::new(&filings_filter);
+    let filings_view = TreeView::with_model(&filings_sorted);
     filings_view.set_headers_visible(true);
     filings_view.set_search_column(0);
     filings_view.set_tooltip_column(2);
     filings_view.set_grid_lines(gtk::TreeViewGridLines::Both);
 
-    // Add columns with icons 
+    // Add columns with icons (titles match FILING_COLUMNS)
     let columns = [
-        ("Form", 0, Some("text-x-generic")),
-        ("Date", 1, Some("x-office-calendar")),
-        ("Document", 2, Some("document-open")),
-        ("Company", 4, Some("emblem-people")),
-        ("Filing Type", 5, Some("view-list-details")),
-        ("AI Analysis", 6, Some("system-search")),
+        (FILING_COLUMNS[0], 0, Some("text-x-generic")),
+        (FILING_COLUMNS[1], 1, Some("x-office-calendar")),
+        (FILING_COLUMNS[2], 2, Some("document-open")),
+        (FILING_COLUMNS[3], 4, Some("emblem-people")),
+        (FILING_COLUMNS[4], 5, Some("view-list-details")),
+        (FILING_COLUMNS[5], 6, Some("system-search")),
     ];
     for (title, idx, icon_name) in columns.iter() {
         let renderer = CellRendererText::new();
@@ -235,6 +243,8 @@ fn build_main_window(app: &Application, state: Arc<AppState>, auth: Arc<AuthMana
         column.set_title(title);
         column.pack_start(&renderer, true);
         column.add_attribute(&renderer, "text", *idx);
+        // Clicking a header sorts by it and toggles the order; dates sort by their key
+        column.set_sort_column_id(if *idx == 1 { DATE_SORT_COLUMN } else { *idx });
         filings_view.append_column(&column);
     }
 
@@ -249,12 +259,152 @@ fn build_main_window(app: &Application, state: Arc<AppState>, auth: Arc<AuthMana
         }
     }
 
+    // Column visibility: a gear menu with one check item per column, saved to config
+    let column_visibility = std::rc::Rc::new(std::cell::RefCell::new(ColumnVisibility::load()));
+    let columns_button = gtk::MenuButton::new();
+    columns_button.set_image(Some(&Image::from_icon_name(Some("emblem-system"), gtk::IconSize::Button)));
+    columns_button.set_tooltip_text(Some("Show or hide table columns"));
+    let columns_list = GtkBox::new(Orientation::Vertical, 2);
+    for (i, title) in FILING_COLUMNS.iter().enumerate() {
+        let visible = column_visibility.borrow().is_visible(title);
+        if let Some(column) = filings_view.get_column(i as i32) {
+            column.set_visible(visible);
+        }
+        let check = gtk::CheckButton::new_with_label(title);
+        check.set_active(visible);
+        let filings_view = filings_view.clone();
+        let column_visibility = column_visibility.clone();
+        check.connect_toggled(move |check| {
+            let visible = check.get_active();
+            if let Some(column) = filings_view.get_column(i as i32) {
+                column.set_visible(visible);
+            }
+            let mut state = column_visibility.borrow_mut();
+            state.set_visible(title, visible);
+            if let Err(e) = state.save() {
+                error!("Failed to save column visibility: {}", e);
+            }
+        });
+        columns_list.pack_start(&check, false, false, 0);
+    }
+    columns_list.show_all();
+    let columns_popover = gtk::Popover::new(Some(&columns_button));
+    columns_popover.add(&columns_list);
+    columns_button.set_popover(Some(&columns_popover));
+    hbox.pack_start(&columns_button, false, false, 0);
+
+    // Settings menu: theme choice (system/dark/light), applied immediately and saved to config
+    let settings_button = gtk::MenuButton::new();
+    settings_button.set_image(Some(&Image::from_icon_name(Some("preferences-desktop-theme"), gtk::IconSize::Button)));
+    settings_button.set_tooltip_text(Some("Settings"));
+    let settings_list = GtkBox::new(Orientation::Vertical, 2);
+    settings_list.pack_start(&Label::new(Some("Theme")), false, false, 0);
+    let mut theme_group: Option<gtk::RadioButton> = None;
+    for choice in Theme::ALL.iter().copied() {
+        let radio = gtk::RadioButton::new_with_label_from_widget(theme_group.as_ref(), choice.label());
+        radio.set_active(choice == app_config.borrow().theme);
+        let provider = provider.clone();
+        let app_config = app_config.clone();
+        radio.connect_toggled(move |radio| {
+            if !radio.get_active() {
+                return;
+            }
+            if let Err(e) = theme::apply_theme(&provider, choice) {
+                error!("Failed to load theme: {}", e);
+                return;
+            }
+            if let Err(e) = app_config.borrow_mut().update(|config| config.theme = choice) {
+                error!("Failed to save theme: {}", e);
+            }
+        });
+        settings_list.pack_start(&radio, false, false, 0);
+        theme_group.get_or_insert(radio);
+    }
+    settings_list.show_all();
+    let settings_popover = gtk::Popover::new(Some(&settings_button));
+    settings_popover.add(&settings_list);
+    settings_button.set_popover(Some(&settings_popover));
+    hbox.pack_start(&settings_button, false, false, 0);
+
     // Status label
-    let status_label = Label::new(Some("Ready."));
+    let status_label = Label::new(Some(if state.is_offline() {
+        "Offline demo mode: fetches show bundled sample data."
+    } else {
+        "Ready."
+    }));
     status_label.set_widget_name("status_label");
+    mark_as_status(&status_label, "Status");
     status_label.set_halign(Align::Start);
 
-    vbox.pack_start(&scrolled, true, true, 0);
+    // Fetch error banner: the full error with advice, a Retry button and a close button.
+    // Hidden until a fetch fails.
+    let error_banner = gtk::InfoBar::new();
+    error_banner.set_widget_name("error_banner");
+    error_banner.set_message_type(gtk::MessageType::Error);
+    error_banner.set_show_close_button(true);
+    let retry_response = gtk::ResponseType::Other(1);
+    error_banner.add_button("_Retry", retry_response);
+    let error_details = Label::new(None);
+    error_details.set_halign(Align::Start);
+    error_details.set_line_wrap(true);
+    error_details.set_selectable(true);
+    error_banner.get_content_area().add(&error_details);
+    error_banner.set_no_show_all(true);
+    error_details.show();
+    let show_fetch_error = {
+        let error_banner = error_banner.clone();
+        let error_details = error_details.clone();
+        let status_label = status_label.clone();
+        move |details: &str| {
+            status_label.set_text("Fetch failed.");
+            error_details.set_text(details);
+            error_banner.show();
+        }
+    };
+
+    // Watchlist sidebar: click a ticker to fetch it
+    let watchlist_box = GtkBox::new(Orientation::Vertical, 6);
+    let watchlist_title = Label::new(Some("Watchlist"));
+    watchlist_title.set_halign(Align::Start);
+    let watchlist_list = gtk::ListBox::new();
+    watchlist_list.set_tooltip_text(Some("Click a ticker to fetch its filings"));
+    let watchlist_buttons = GtkBox::new(Orientation::Horizontal, 4);
+    let watchlist_add_button = Button::new_with_label("Add");
+    watchlist_add_button.set_tooltip_text(Some("Add the entered ticker(s) to the watchlist"));
+    let watchlist_remove_button = Button::new_with_label("Remove");
+    watchlist_remove_button.set_tooltip_text(Some("Remove the selected ticker from the watchlist"));
+    watchlist_buttons.pack_start(&watchlist_add_button, false, false, 0);
+    watchlist_buttons.pack_start(&watchlist_remove_button, false, false, 0);
+    watchlist_box.pack_start(&watchlist_title, false, false, 0);
+    watchlist_box.pack_start(&watchlist_list, true, true, 0);
+    watchlist_box.pack_start(&watchlist_buttons, false, false, 0);
+
+    let refresh_watchlist = {
+        let watchlist_list = watchlist_list.clone();
+        let state = state.clone();
+        move || {
+            for child in watchlist_list.get_children() {
+                watchlist_list.remove(&child);
+            }
+            for ticker in state.watchlist() {
+                let row = gtk::ListBoxRow::new();
+                row.add(&Label::new(Some(&ticker)));
+                watchlist_list.add(&row);
+            }
+            watchlist_list.show_all();
+        }
+    };
+    refresh_watchlist();
+
+    let content_hbox = GtkBox::new(Orientation::Horizontal, 12);
+    content_hbox.pack_start(&watchlist_box, false, false, 0);
+    let table_box = GtkBox::new(Orientation::Vertical, 6);
+    table_box.pack_start(&filings_search, false, false, 0);
+    table_box.pack_start(&scrolled, true, true, 0);
+    content_hbox.pack_start(&table_box, true, true, 0);
+
+    vbox.pack_start(&error_banner, false, false, 0);
+    vbox.pack_start(&content_hbox, true, true, 0);
     vbox.pack_start(&status_label, false, false, 0);
 
     scrolled.add(&filings_view);
@@ -271,7 +421,9 @@ fn build_main_window(app: &Application, state: Arc<AppState>, auth: Arc<AuthMana
     vbox.pack_start(&chart_area.widget, false, false, 0);
 
     // Real-time updates via WebSocket
-    start_realtime_updates(state.clone(), filings_store.clone(), status_label.clone());
+    if !state.is_offline() {
+        start_realtime_updates(state.clone(), filings_store.clone(), status_label.clone(), DEFAULT_MAX_RETRY_INTERVAL);
+    }
 
     // Functional Event Handlers
     let display_filings = {
@@ -280,6 +432,7 @@ fn build_main_window(app: &Application, state: Arc<AppState>, auth: Arc<AuthMana
         let load_more_button = load_more_button.clone();
         let chart_area = chart_area.clone();
         let state = state.clone();
+        let filter_pane = filter_pane.clone();
         move |records: &[FilingRecord], append: bool| {
             if !append {
                 filings_store.clear();
@@ -288,7 +441,7 @@ fn build_main_window(app: &Application, state: Arc<AppState>, auth: Arc<AuthMana
             for rec in records {
                 filings_store.insert_with_values(
                     None,
-                    &[0, 1, 2, 3, 4, 5, 6],
+                    &[0, 1, 2, 3, 4, 5, 6, 7],
                     &[
                         &rec.form,
                         &rec.date,
@@ -297,6 +450,7 @@ fn build_main_window(app: &Application, state: Arc<AppState>, auth: Arc<AuthMana
                         &rec.company_name,
                         &rec.filing_type,
                         &rec.ai_summary,
+                        &date_sort_key(&rec.date),
                     ],
                 );
                 shown += 1;
@@ -307,6 +461,7 @@ fn build_main_window(app: &Application, state: Arc<AppState>, auth: Arc<AuthMana
                 status_label.set_text(&format!("Showing {} filings.", shown));
             }
             chart_area.update(records);
+            filter_pane.add_seen_forms(records);
             load_more_button.set_sensitive(state.has_more_filings());
         }
     };
@@ -320,9 +475,13 @@ fn build_main_window(app: &Application, state: Arc<AppState>, auth: Arc<AuthMana
         let load_more_button = load_more_button.clone();
         let display_filings = display_filings.clone();
         let auth = auth.clone();
-        let filter_pane = filter_pane.clone();
+        let audit_log_path = audit_log_path.clone();
+        let cancel_button = cancel_button.clone();
+        let error_banner = error_banner.clone();
+        let show_fetch_error = show_fetch_error.clone();
 
-        move |tickers: Vec<String>, append: bool| {
+        // `request.force` re-fetches past every cache (the Refresh action)
+        move |request: FetchRequest, append: bool| {
             let state = state.clone();
             let filings_store = filings_store.clone();
             let status_label = status_label.clone();
@@ -330,11 +489,30 @@ fn build_main_window(app: &Application, state: Arc<AppState>, auth: Arc<AuthMana
             let load_more_button = load_more_button.clone();
             let display_filings = display_filings.clone();
             let auth = auth.clone();
-            let filter_pane = filter_pane.clone();
+            let audit_log_path = audit_log_path.clone();
+            let cancel_button = cancel_button.clone();
+            let show_fetch_error = show_fetch_error.clone();
 
-            spinner.start();
-            status_label.set_text("Fetching SEC filings...");
+            // Collapse repeated triggers (Enter + button, held Ctrl+F) into one fetch
+            if !state.begin_fetch() {
+                info!("Ignoring fetch request: a fetch is already running.");
+                return;
+            }
+            state.remember_fetch(request.clone());
+            error_banner.hide();
+            let FetchRequest { tickers, filters, force } = request;
+            // Per-user fetch quota; the message says when the user can retry
+            if let Err(e) = auth.check_rate_limit(&auth.current_user()) {
+                status_label.set_text(&format!("{}.", e));
+                state.end_fetch();
+                return;
+            }
+
+            set_busy(&spinner, true);
+            cancel_button.show();
+            status_label.set_text(if force { "Refreshing SEC filings (bypassing caches)..." } else { "Fetching SEC filings..." });
             load_more_button.set_sensitive(false);
+            let cancel = state.fetch_cancel_token();
 
             // RBAC: Only allow access to permitted tickers
             let user = auth.current_user();
@@ -342,46 +520,131 @@ fn build_main_window(app: &Application, state: Arc<AppState>, auth: Arc<AuthMana
 
             // Use tokio for async, scalable fetch
             glib::MainContext::default().spawn_local(async move {
+                // Keep the session's access token valid before doing authenticated work
+                if let Err(e) = auth.refresh_if_needed().await {
+                    match e.hint() {
+                        Some(hint) => show_fetch_error(&format!("{}. {}", e, hint)),
+                        None => show_fetch_error(&e.to_string()),
+                    }
+                    state.end_fetch();
+                    cancel_button.hide();
+                    set_busy(&spinner, false);
+                    return;
+                }
+
                 // Fetch from public SEC EDGAR data
-                audit_log(&user, "fetch_filings", &allowed_tickers);
-                match state.api.fetch_multiple_filings(allowed_tickers, filter_pane.filters()).await {
-                    Ok(records) => {
-                        state.set_filings(records.clone());
-                        display_filings(&records, append);
-                        status_label.set_text("Filings loaded.");
+                audit_log(&audit_log_path, &user, "fetch_filings", &allowed_tickers);
+                state.set_active_tickers(allowed_tickers.clone());
+                let requested = allowed_tickers.len();
+                // A cancelled fetch leaves the table as it was; nothing partial is inserted
+                let fetched = async {
+                    if force {
+                        state.refresh_filings(allowed_tickers.clone(), filters).await
+                    } else {
+                        state.fetch_multiple_filings(allowed_tickers.clone(), filters).await
                     }
-                    Err(e) => {
-                        error!("Error fetching/displaying filings: {}", e);
-                        status_label.set_text(&format!("Error: {}", e));
+                };
+                let batch = match until_cancelled(&cancel, fetched).await {
+                    Some(batch) => batch,
+                    None => {
+                        status_label.set_text("Cancelled");
+                        state.end_fetch();
+                        cancel_button.hide();
+                        set_busy(&spinner, false);
+                        return;
+                    }
+                };
+                for (ticker, e) in &batch.failures {
+                    error!("Error fetching filings for {}: {}", ticker, e);
+                }
+                if batch.failures.len() == requested && requested > 0 {
+                    show_fetch_error(&batch.failure_details());
+                } else {
+                    state.set_filings(batch.records.clone());
+                    display_filings(&state.load_more_filings().unwrap_or_default(), append);
+                    let loaded = if force { "Filings refreshed from SEC (caches bypassed)." } else { "Filings loaded." };
+                    if batch.failures.is_empty() {
+                        status_label.set_text(loaded);
+                    } else {
+                        status_label.set_text(&format!("{} Failed: {}", loaded, batch.failure_summary()));
+                    }
+
+                    // Point the AI at the first successfully fetched ticker's financials
+                    let primary_ticker = allowed_tickers
+                        .iter()
+                        .find(|t| !batch.failures.iter().any(|(failed, _)| failed == *t));
+                    if let Some(ticker) = primary_ticker {
+                        let filings_status = status_label.get_text().to_string();
+                        let previous_data = state.data_state();
+                        state.set_data_state(DataState::Loading);
+                        let progress_label = status_label.clone();
+                        let progress = move |stage: data_ingestion::LoadStage| {
+                            progress_label.set_text(&format!("Loading {} financials: {}", ticker, stage.label()));
+                        };
+                        let loaded = until_cancelled(&cancel, state.load_financials(ticker, Some(progress))).await;
+                        match loaded {
+                            Some(Ok(df)) => {
+                                status_label.set_text(&filings_status);
+                                state.set_data_state(DataState::Loaded(df));
+                            }
+                            Some(Err(e)) => {
+                                status_label.set_text(&filings_status);
+                                error!("Failed to load financial data for {}: {}", ticker, e);
+                                state.set_data_state(DataState::Error(e.to_string()));
+                            }
+                            None => {
+                                status_label.set_text("Filings loaded. Financial data cancelled.");
+                                state.set_data_state(previous_data);
+                            }
+                        }
+
+                        // Keep the other tickers' financials too, for cross-ticker chat questions
+                        let others = allowed_tickers
+                            .iter()
+                            .filter(|t| *t != ticker && !batch.failures.iter().any(|(failed, _)| failed == *t));
+                        for other in others {
+                            let loaded = match until_cancelled(&cancel, state.load_financials(other, None::<fn(data_ingestion::LoadStage)>)).await {
+                                Some(loaded) => loaded,
+                                None => break,
+                            };
+                            if let Err(e) = loaded {
+                                error!("Failed to load financial data for {}: {}", other, e);
+                            }
+                        }
                     }
                 }
-                spinner.stop();
+                state.end_fetch();
+                cancel_button.hide();
+                set_busy(&spinner, false);
             });
         }
     };
 
-    // Keyboard accessibility: Enter triggers fetch, Ctrl+F/Ctrl+E shortcuts
+    // Keyboard accessibility: Enter triggers fetch; Ctrl+F/E/L/R and Alt+Up/Down shortcuts
     let fetch_button_clone = fetch_button.clone();
     ticker_entry.connect_activate(clone!(@strong fetch_button_clone => move |_| {
         fetch_button_clone.clicked();
     }));
 
     // Keyboard shortcuts
-    let fetch_and_display_clone = fetch_and_display.clone();
+    let refresh_button_clone = refresh_button.clone();
     let export_button_clone = export_button.clone();
+    let ticker_entry_clone = ticker_entry.clone();
+    let filings_view_clone = filings_view.clone();
     window.connect_key_press_event(move |_, event| {
-        let ctrl = event.get_state().contains(gdk::ModifierType::CONTROL_MASK);
-        match event.get_keyval() {
-            gdk::keys::constants::F if ctrl => {
-                fetch_button_clone.clicked();
-                Inhibit(true)
-            }
-            gdk::keys::constants::E if ctrl => {
-                export_button_clone.clicked();
-                Inhibit(true)
+        match shortcuts::shortcut_action(&event.get_keyval(), event.get_state()) {
+            Some(ShortcutAction::Fetch) => fetch_button_clone.clicked(),
+            Some(ShortcutAction::Export) => export_button_clone.clicked(),
+            Some(ShortcutAction::Refresh) => refresh_button_clone.clicked(),
+            Some(ShortcutAction::FocusTicker) => {
+                ticker_entry_clone.grab_focus();
+                ticker_entry_clone.select_region(0, -1);
             }
-            _ => Inhibit(false),
+            Some(ShortcutAction::NextFiling) => shortcuts::move_selection(&filings_view_clone, 1),
+            Some(ShortcutAction::PreviousFiling) => shortcuts::move_selection(&filings_view_clone, -1),
+            None => return Inhibit(false),
         }
+        Inhibit(true)
     });
 
     // Fetch button click
@@ -389,22 +652,315 @@ fn build_main_window(app: &Application, state: Arc<AppState>, auth: Arc<AuthMana
         let ticker_entry = ticker_entry.clone();
         let fetch_and_display = fetch_and_display.clone();
         let status_label = status_label.clone();
+        let app_config = app_config.clone();
+        let filter_pane = filter_pane.clone();
         fetch_button.connect_clicked(move |_| {
             let input = ticker_entry.text().to_string();
             if input.trim().is_empty() {
                 status_label.set_text("Please enter a ticker symbol or upload a CSV.");
                 return;
             }
-            let tickers: Vec<String> = input
-                .split(',')
-                .map(|t| sanitize_ticker(t))
-                .filter(|t| !t.is_empty())
-                .collect();
+            let tickers = parse_ticker_list(&input);
             if tickers.is_empty() {
                 status_label.set_text("No valid tickers found.");
                 return;
             }
-            fetch_and_display(tickers, false);
+            if let Err(e) = app_config.borrow_mut().update(|c| c.initial_ticker = Some(tickers.join(","))) {
+                error!("Failed to save config: {}", e);
+            }
+            fetch_and_display(FetchRequest { tickers, filters: filter_pane.filters(), force: false }, false);
+        });
+    }
+
+    // Refresh: re-fetch the tickers on screen (or in the entry) past every cache
+    {
+        let state = state.clone();
+        let ticker_entry = ticker_entry.clone();
+        let fetch_and_display = fetch_and_display.clone();
+        let status_label = status_label.clone();
+        let filter_pane = filter_pane.clone();
+        refresh_button.connect_clicked(move |_| {
+            let mut tickers = state.active_tickers();
+            if tickers.is_empty() {
+                tickers = parse_ticker_list(&ticker_entry.text());
+            }
+            if tickers.is_empty() {
+                status_label.set_text("Nothing to refresh: enter a ticker symbol first.");
+                return;
+            }
+            fetch_and_display(FetchRequest { tickers, filters: filter_pane.filters(), force: true }, false);
+        });
+    }
+
+    // Error banner: Retry reruns the failed fetch with the same tickers and filters
+    {
+        let state = state.clone();
+        let fetch_and_display = fetch_and_display.clone();
+        error_banner.connect_response(move |banner, response| {
+            banner.hide();
+            if response == retry_response {
+                if let Some(request) = state.last_fetch() {
+                    fetch_and_display(request, false);
+                }
+            }
+        });
+    }
+
+    // Upload CSV: fill the ticker entry from the file and fetch those tickers
+    {
+        let window = window.clone();
+        let ticker_entry = ticker_entry.clone();
+        let fetch_button = fetch_button.clone();
+        let status_label = status_label.clone();
+        upload_csv_button.connect_clicked(move |_| {
+            let dialog = gtk::FileChooserDialog::with_buttons(
+                Some("Upload Tickers CSV or List"),
+                Some(&window),
+                gtk::FileChooserAction::Open,
+                &[("_Cancel", gtk::ResponseType::Cancel), ("_Open", gtk::ResponseType::Accept)],
+            );
+            let filter = gtk::FileFilter::new();
+            filter.set_name(Some("CSV files"));
+            filter.add_pattern("*.csv");
+            dialog.add_filter(&filter);
+            // Plain lists: one ticker per line, '#' comments
+            let list_filter = gtk::FileFilter::new();
+            list_filter.set_name(Some("Ticker lists"));
+            list_filter.add_pattern("*.txt");
+            list_filter.add_pattern("*.list");
+            dialog.add_filter(&list_filter);
+            let response = dialog.run();
+            let path = dialog.get_filename();
+            dialog.close();
+            let path = match path {
+                Some(path) if response == gtk::ResponseType::Accept => path,
+                _ => return,
+            };
+            let is_csv = path.extension().map_or(false, |ext| ext.eq_ignore_ascii_case("csv"));
+            let loaded = if is_csv { data_ingestion::load_ticker_csv(&path) } else { data_ingestion::load_ticker_file(&path) };
+            let parsed = match loaded {
+                Ok(parsed) => parsed,
+                Err(e) => {
+                    status_label.set_text(&format!("Error: {}", e));
+                    return;
+                }
+            };
+            if parsed.tickers.is_empty() {
+                status_label.set_text(&format!("No valid tickers found in file ({} rows skipped).", parsed.skipped));
+                return;
+            }
+            ticker_entry.set_text(&parsed.tickers.join(","));
+            fetch_button.clicked();
+            status_label.set_text(&format!(
+                "Fetching {} tickers from {}; skipped {} malformed rows.",
+                parsed.tickers.len(),
+                if is_csv { "CSV" } else { "list" },
+                parsed.skipped
+            ));
+        });
+    }
+
+    // Find by Name: search company titles for the entry text and fetch the picked ticker
+    {
+        let window = window.clone();
+        let state = state.clone();
+        let ticker_entry = ticker_entry.clone();
+        let fetch_button = fetch_button.clone();
+        let status_label = status_label.clone();
+        find_company_button.connect_clicked(move |_| {
+            let name = ticker_entry.text().trim().to_string();
+            if name.is_empty() {
+                status_label.set_text("Type part of a company name to search.");
+                return;
+            }
+            status_label.set_text("Searching company names...");
+            let window = window.clone();
+            let state = state.clone();
+            let ticker_entry = ticker_entry.clone();
+            let fetch_button = fetch_button.clone();
+            let status_label = status_label.clone();
+            glib::MainContext::default().spawn_local(async move {
+                let matches = match state.api.search_companies(&name).await {
+                    Ok(matches) => matches,
+                    Err(e) => {
+                        status_label.set_text(&format!("Error: {}", e));
+                        return;
+                    }
+                };
+                if matches.is_empty() {
+                    status_label.set_text(&format!("No companies match '{}'.", name));
+                    return;
+                }
+                status_label.set_text(&format!("{} companies match '{}'.", matches.len(), name));
+
+                let dialog = gtk::Dialog::with_buttons(
+                    Some("Select Company"),
+                    Some(&window),
+                    gtk::DialogFlags::MODAL,
+                    &[("_Cancel", gtk::ResponseType::Cancel), ("_Fetch", gtk::ResponseType::Accept)],
+                );
+                dialog.set_default_size(480, 360);
+                let list = gtk::ListBox::new();
+                for company in &matches {
+                    let row = Label::new(Some(&format!("{} — {} (CIK {})", company.ticker, company.title, company.cik)));
+                    row.set_halign(Align::Start);
+                    list.add(&row);
+                }
+                if let Some(first) = list.get_row_at_index(0) {
+                    list.select_row(Some(&first));
+                }
+                list.connect_row_activated(clone!(@weak dialog => move |_, _| {
+                    dialog.response(gtk::ResponseType::Accept);
+                }));
+                let scroll = ScrolledWindow::new(None::<&Adjustment>, None::<&Adjustment>);
+                scroll.set_vexpand(true);
+                scroll.add(&list);
+                dialog.get_content_area().pack_start(&scroll, true, true, 0);
+                // Handled on response rather than with a nested run() inside this future
+                dialog.connect_response(move |dialog, response| {
+                    let picked = list.get_selected_row().and_then(|row| matches.get(row.get_index() as usize));
+                    if let Some(company) = picked.filter(|_| response == gtk::ResponseType::Accept) {
+                        ticker_entry.set_text(&company.ticker);
+                        fetch_button.clicked();
+                    }
+                    dialog.close();
+                });
+                dialog.show_all();
+            });
+        });
+    }
+
+    // Bookmarks: list bookmarked filings across tickers to open, remove or export
+    {
+        let window = window.clone();
+        let state = state.clone();
+        let auth = auth.clone();
+        let status_label = status_label.clone();
+        let export_format_combo = export_format_combo.clone();
+        bookmarks_button.connect_clicked(move |_| {
+            let remove_response = gtk::ResponseType::Other(1);
+            let export_response = gtk::ResponseType::Other(2);
+            let dialog = gtk::Dialog::with_buttons(
+                Some("Bookmarks"),
+                Some(&window),
+                gtk::DialogFlags::MODAL,
+                &[
+                    ("_Remove", remove_response),
+                    ("_Export", export_response),
+                    ("_Close", gtk::ResponseType::Close),
+                    ("_Open", gtk::ResponseType::Accept),
+                ],
+            );
+            dialog.set_default_size(560, 360);
+            let list = gtk::ListBox::new();
+            let placeholder = Label::new(Some("No bookmarks yet. Right-click a filing to bookmark it."));
+            placeholder.show();
+            list.set_placeholder(Some(&placeholder));
+            let fill_list = {
+                let list = list.clone();
+                let state = state.clone();
+                move || {
+                    for child in list.get_children() {
+                        list.remove(&child);
+                    }
+                    for bookmark in state.bookmarks() {
+                        let rec = &bookmark.record;
+                        let ticker = if rec.ticker.is_empty() { "—" } else { rec.ticker.as_str() };
+                        let accession = bookmark.accession.as_ref().map(|a| format!(" ({})", a)).unwrap_or_default();
+                        let row = Label::new(Some(&format!(
+                            "{} · {} · {} — {}{}",
+                            ticker, rec.form, rec.date, rec.company_name, accession
+                        )));
+                        row.set_halign(Align::Start);
+                        list.add(&row);
+                    }
+                    list.show_all();
+                }
+            };
+            fill_list();
+            list.connect_row_activated(clone!(@weak dialog => move |_, _| {
+                dialog.response(gtk::ResponseType::Accept);
+            }));
+            let scroll = ScrolledWindow::new(None::<&Adjustment>, None::<&Adjustment>);
+            scroll.set_vexpand(true);
+            scroll.add(&list);
+            dialog.get_content_area().pack_start(&scroll, true, true, 0);
+
+            let state = state.clone();
+            let auth = auth.clone();
+            let status_label = status_label.clone();
+            let export_format_combo = export_format_combo.clone();
+            dialog.connect_response(move |dialog, response| {
+                let bookmarks = state.bookmarks();
+                let selected = list.get_selected_row().and_then(|row| bookmarks.get(row.get_index() as usize).cloned());
+                if response == remove_response {
+                    // Removing keeps the dialog open on the refreshed list
+                    if let Some(bookmark) = selected {
+                        state.remove_bookmark(&bookmark.record.document_url);
+                        fill_list();
+                    }
+                    return;
+                }
+                if response == export_response {
+                    if !auth.has_permission(&auth.current_user(), Permission::Export) {
+                        status_label.set_text("Export is not permitted for your role.");
+                    } else {
+                        let format = export_format_combo
+                            .get_active()
+                            .and_then(|idx| FILING_EXPORT_FORMATS.get(idx as usize).copied())
+                            .unwrap_or(FILING_EXPORT_FORMATS[0]);
+                        match export_bookmarks(&bookmarks, format) {
+                            Ok(path) => status_label.set_text(&format!("Exported bookmarks to {}", path)),
+                            Err(e) => status_label.set_text(&format!("Export failed: {}", e)),
+                        }
+                    }
+                } else if response == gtk::ResponseType::Accept {
+                    if let Some(bookmark) = selected {
+                        if let Err(e) = open::that(&bookmark.record.document_url) {
+                            error!("Failed to open browser: {}", e);
+                        }
+                    }
+                }
+                dialog.close();
+            });
+            dialog.show_all();
+        });
+    }
+
+    // Watchlist: add entered tickers, remove the selected one, click to fetch
+    {
+        let ticker_entry = ticker_entry.clone();
+        let state = state.clone();
+        let refresh_watchlist = refresh_watchlist.clone();
+        watchlist_add_button.connect_clicked(move |_| {
+            let input = ticker_entry.text().to_string();
+            for ticker in parse_ticker_list(&input) {
+                state.add_to_watchlist(&ticker);
+            }
+            refresh_watchlist();
+        });
+    }
+    {
+        let watchlist_list = watchlist_list.clone();
+        let state = state.clone();
+        let refresh_watchlist = refresh_watchlist.clone();
+        watchlist_remove_button.connect_clicked(move |_| {
+            if let Some(row) = watchlist_list.get_selected_row() {
+                if let Some(label) = row.get_child().and_then(|c| c.downcast::<Label>().ok()) {
+                    state.remove_from_watchlist(&label.get_text());
+                    refresh_watchlist();
+                }
+            }
+        });
+    }
+    {
+        let ticker_entry = ticker_entry.clone();
+        let fetch_button = fetch_button.clone();
+        watchlist_list.connect_row_activated(move |_, row| {
+            if let Some(label) = row.get_child().and_then(|c| c.downcast::<Label>().ok()) {
+                ticker_entry.set_text(&label.get_text());
+                fetch_button.clicked();
+            }
         });
     }
 
@@ -412,8 +968,32 @@ fn build_main_window(app: &Application, state: Arc<AppState>, auth: Arc<AuthMana
     {
         let state = state.clone();
         let status_label = status_label.clone();
+        let export_format_combo = export_format_combo.clone();
+        let auth = auth.clone();
         export_button.connect_clicked(move |_| {
-            match export_filings(&state.get_filings()) {
+            if !auth.has_permission(&auth.current_user(), Permission::Export) {
+                status_label.set_text("Export is not permitted for your role.");
+                return;
+            }
+            let data = state.data_state();
+            if !data.allows_export() {
+                status_label.set_text(&data.message().unwrap_or_default());
+                return;
+            }
+            let format = export_format_combo
+                .get_active()
+                .and_then(|idx| FILING_EXPORT_FORMATS.get(idx as usize).copied())
+                .unwrap_or(FILING_EXPORT_FORMATS[0]);
+            // Columns chosen in the config's `export_columns`, else all of them
+            let export_columns = AppConfig::current().export_columns;
+            let columns = match export_columns.as_deref().map(FilingField::parse_list).transpose() {
+                Ok(columns) => columns,
+                Err(e) => {
+                    status_label.set_text(&format!("Export failed: {}", e));
+                    return;
+                }
+            };
+            match export_filings(&state.get_filings(), data.frame(), format, columns.as_deref()) {
                 Ok(path) => status_label.set_text(&format!("Exported to {}", path)),
                 Err(e) => status_label.set_text(&format!("Export failed: {}", e)),
             }
@@ -426,12 +1006,38 @@ fn build_main_window(app: &Application, state: Arc<AppState>, auth: Arc<AuthMana
         let display_filings = display_filings.clone();
         let load_more_button = load_more_button.clone();
         let status_label = status_label.clone();
-        load_more_button.connect_clicked(move |_| {
+        let filter_pane = filter_pane.clone();
+        load_more_button.connect_clicked(move |button| {
             if let Some(records) = state.load_more_filings() {
                 display_filings(&records, true);
-            } else {
+                return;
+            }
+            if !state.has_more_remote_filings() {
                 status_label.set_text("No more filings to load.");
+                return;
             }
+            // Everything held is shown; ask SEC for the next page of older filings
+            button.set_sensitive(false);
+            status_label.set_text("Loading older filings...");
+            let state = state.clone();
+            let display_filings = display_filings.clone();
+            let status_label = status_label.clone();
+            let button = button.clone();
+            let filters = filter_pane.filters();
+            glib::MainContext::default().spawn_local(async move {
+                let batch = state.fetch_next_filings(filters).await;
+                for (ticker, e) in &batch.failures {
+                    error!("Error loading more filings for {}: {}", ticker, e);
+                }
+                if batch.records.is_empty() && !batch.failures.is_empty() {
+                    status_label.set_text(&format!("Error: {}", batch.failure_summary()));
+                } else if batch.records.is_empty() {
+                    status_label.set_text("No more filings to load.");
+                } else {
+                    display_filings(&batch.records, true);
+                }
+                button.set_sensitive(state.has_more_filings());
+            });
         });
     }
 
@@ -449,6 +1055,122 @@ fn build_main_window(app: &Application, state: Arc<AppState>, auth: Arc<AuthMana
         }
     });
 
+    // Right-click a filing row to bookmark it or save its document locally
+    {
+        let window = window.clone();
+        let state = state.clone();
+        let status_label = status_label.clone();
+        filings_view.connect_button_press_event(move |view, event| {
+            if event.get_button() != 3 {
+                return Inhibit(false);
+            }
+            let (x, y) = event.get_position();
+            let record: Option<FilingRecord> = view
+                .get_path_at_pos(x as i32, y as i32)
+                .and_then(|(path, _, _, _)| path)
+                .and_then(|path| {
+                    let model = view.get_model()?;
+                    let iter = model.get_iter(&path)?;
+                    let text = |column: i32| {
+                        let value: Option<String> = model.get_value(&iter, column).get().ok();
+                        value.unwrap_or_default()
+                    };
+                    Some(FilingRecord {
+                        amendment: is_amendment(&text(0)),
+                        form: text(0),
+                        date: text(1),
+                        document: text(2),
+                        document_url: text(3),
+                        company_name: text(4),
+                        filing_type: text(5),
+                        ai_summary: text(6),
+                        ticker: String::new(),
+                    })
+                });
+            let mut record = match record.filter(|r| !r.document_url.is_empty()) {
+                Some(record) => record,
+                None => return Inhibit(false),
+            };
+            // The table has no ticker column; take it from the loaded filings
+            if let Some(loaded) = state.get_filings().into_iter().find(|r| r.document_url == record.document_url) {
+                record.ticker = loaded.ticker;
+            }
+            let url = record.document_url.clone();
+
+            let menu = gtk::Menu::new();
+            let bookmarked = state.is_bookmarked(&url);
+            let bookmark_item = gtk::MenuItem::new_with_label(if bookmarked { "Remove Bookmark" } else { "Bookmark Filing" });
+            {
+                let state = state.clone();
+                let status_label = status_label.clone();
+                bookmark_item.connect_activate(move |_| {
+                    if bookmarked {
+                        state.remove_bookmark(&record.document_url);
+                        status_label.set_text(&format!("Removed bookmark for {} {}", record.form, record.date));
+                    } else if state.add_bookmark(record.clone()) {
+                        status_label.set_text(&format!("Bookmarked {} {}", record.form, record.date));
+                    }
+                });
+            }
+            menu.append(&bookmark_item);
+            let download_item = gtk::MenuItem::new_with_label("Download Document…");
+            let window = window.clone();
+            let status_label = status_label.clone();
+            download_item.connect_activate(move |_| {
+                let dialog = gtk::FileChooserDialog::with_buttons(
+                    Some("Save Filing Document"),
+                    Some(&window),
+                    gtk::FileChooserAction::Save,
+                    &[("_Cancel", gtk::ResponseType::Cancel), ("_Save", gtk::ResponseType::Accept)],
+                );
+                dialog.set_do_overwrite_confirmation(true);
+                let file_name = url.rsplit('/').next().filter(|n| !n.is_empty()).unwrap_or("filing.htm");
+                dialog.set_current_name(file_name);
+                let url = url.clone();
+                let status_label = status_label.clone();
+                dialog.connect_response(move |dialog, response| {
+                    let dest = dialog.get_filename();
+                    dialog.close();
+                    let dest = match dest {
+                        Some(dest) if response == gtk::ResponseType::Accept => dest,
+                        _ => return,
+                    };
+
+                    status_label.set_text("Downloading document...");
+                    let url = url.clone();
+                    let status_label = status_label.clone();
+                    glib::MainContext::default().spawn_local(async move {
+                        let progress_label = status_label.clone();
+                        let downloaded = FinancialDataLoader::download_document(&url, &dest, |received, total| {
+                            let text = match total {
+                                Some(total) if total > 0 => {
+                                    format!("Downloading document... {}%", received * 100 / total)
+                                }
+                                _ => format!("Downloading document... {} KB", received / 1024),
+                            };
+                            progress_label.set_text(&text);
+                        })
+                        .await;
+                        match downloaded {
+                            Ok(bytes) => status_label.set_text(&format!("Saved {} ({} KB)", dest.display(), bytes / 1024)),
+                            Err(e) => {
+                                error!("Failed to download {}: {}", url, e);
+                                status_label.set_text(&format!("Download failed: {}", e));
+                            }
+                        }
+                    });
+                });
+                dialog.show();
+            });
+            menu.append(&download_item);
+            // Attaching keeps the menu alive after this handler returns
+            menu.set_attach_widget(Some(view));
+            menu.show_all();
+            menu.popup_at_pointer(Some(event));
+            Inhibit(true)
+        });
+    }
+
     // Accessibility (focus indicators, tooltips, keyboard navigation)
     fetch_button.set_can_focus(true);
     ticker_entry.set_can_focus(true);
@@ -459,8 +1181,10 @@ fn build_main_window(app: &Application, state: Arc<AppState>, auth: Arc<AuthMana
 
     // Add tooltips for accessibility
     fetch_button.set_tooltip_text(Some("Fetch filings for the entered ticker symbol(s)"));
-    ticker_entry.set_tooltip_text(Some("Enter stock ticker(s) or upload a CSV"));
-    filings_view.set_tooltip_text(Some("List of recent SEC filings. Double-click a row to open the document."));
+    ticker_entry.set_tooltip_text(Some("Enter stock ticker(s) or upload a CSV (Ctrl+L)"));
+    filings_view.set_tooltip_text(Some(
+        "List of recent SEC filings. Double-click a row to open the document. Alt+Up/Down moves between rows.",
+    ));
     load_more_button.set_tooltip_text(Some("Load more filings for this ticker"));
     export_button.set_tooltip_text(Some("Export filings as CSV, PDF, or JSON"));
     ai_chat_button.set_tooltip_text(Some("Open FINFILES AI chat for advanced analysis"));
@@ -471,19 +1195,32 @@ fn build_main_window(app: &Application, state: Arc<AppState>, auth: Arc<AuthMana
     export_button.set_focus_on_click(true);
     ai_chat_button.set_focus_on_click(true);
 
-    // Enable dark mode if available (GTK 3+ uses system theme by default)
-    #[cfg(feature = "v3_16")]
-    {
-        if let Some(settings) = gtk::Settings::get_default() {
-            settings.set_property_gtk_application_prefer_dark_theme(true);
-        }
-    }
-
     // FINFILES AI Chat Integration
     let ai_modules_for_chat = ai_modules.clone();
     let audit_log_path_for_chat = audit_log_path.clone();
     let username_for_chat = username.clone();
-    let ai_data_for_chat = ai_data.clone();
+    state.set_data_state(initial_data);
+
+    // The chat reads the shared frame, so later fetches change what it analyzes
+    {
+        let state = state.clone();
+        let auth = auth.clone();
+        ai_chat_button.connect_clicked(move |_| {
+            let user = auth.current_user();
+            let chat = FinancialAIChatApp::new(
+                ai_modules_for_chat.clone(),
+                DataFrame::default(),
+                audit_log_path_for_chat.clone(),
+                username_for_chat.clone(),
+            )
+            .with_shared_data(state.ai_data())
+            .with_data_state(state.subscribe_data_state())
+            .with_ticker_frames(state.ticker_frames())
+            .with_analysis_cache(state.analysis_cache())
+            .with_permissions(user.permissions());
+            chat.run();
+        });
+    }
 
     // Add FinfilesAI GUI section
     let finfiles_ai_box = GtkBox::new(Orientation::Vertical, 5);
@@ -506,73 +1243,75 @@ fn build_main_window(app: &Application, state: Arc<AppState>, auth: Arc<AuthMana
     analyze_button.set_can_focus(true);
     finfiles_ai_box.append(&analyze_button);
 
-    // Connect analyze button to trigger FinfilesAI analysis
+    // Connect analyze button to trigger FinfilesAI analysis. The backend is created once;
+    // if that fails, each click reports the error instead of panicking.
     let finfiles_ai_output_clone = finfiles_ai_output.clone();
-    let ai_data_for_analyze = ai_data_for_chat.clone();
+    let state_for_analyze = state.clone();
+    let analyzer = ai::init_backend("FINFILES AI", FinfilesAI::new);
     analyze_button.connect_clicked(move |_| {
-        if let Some(df) = &ai_data_for_analyze {
-            let finfiles_ai = FinfilesAI::new().unwrap();
+        let data = state_for_analyze.data_state();
+        if let DataState::Loaded(df) = data {
+            let finfiles_ai = match &analyzer {
+                Ok(ai) => ai.clone(),
+                Err(message) => {
+                    finfiles_ai_output_clone.buffer().unwrap().set_text(&format!("Error: {}", message));
+                    return;
+                }
+            };
             let query = "Analyze the data";
             let output_buffer = finfiles_ai_output_clone.buffer().unwrap();
             output_buffer.set_text("");
+            let timeout = AppConfig::current().analysis_timeout(finfiles_ai.backend_name());
             glib::MainContext::default().spawn_local(async move {
-                match finfiles_ai.analyze(df, query).await {
+                match ai::with_timeout(timeout, finfiles_ai.analyze(&df, query)).await {
                     Ok(result) => {
                         output_buffer.set_text(&result);
                     }
                     Err(e) => {
-                        output_buffer.set_text(&format!("Error: {}", e));
+                        output_buffer.set_text(&ai::error_message(&e));
                     }
                 }
             });
         } else {
-            finfiles_ai_output_clone.buffer().unwrap().set_text("No data available for analysis.");
+            finfiles_ai_output_clone.buffer().unwrap().set_text(&data.message().unwrap_or_default());
         }
     });
 
+    // Render the analyze button, chart and export from the data state, now and on
+    // every transition
+    {
+        let chart_area = chart_area.clone();
+        let export_button = export_button.clone();
+        let analyze_button = analyze_button.clone();
+        let finfiles_ai_output = finfiles_ai_output.clone();
+        let auth = auth.clone();
+        let render = move |data: &DataState| {
+            chart_area.show_data_state(data);
+            let may_export = auth.has_permission(&auth.current_user(), Permission::Export);
+            export_button.set_sensitive(may_export && data.allows_export());
+            analyze_button.set_sensitive(data.frame().is_some());
+            // Once loaded, the output keeps whatever analysis is shown
+            if let Some(message) = data.message() {
+                finfiles_ai_output.buffer().unwrap().set_text(&message);
+            }
+        };
+        let mut data_rx = state.subscribe_data_state();
+        render(&data_rx.borrow_and_update().clone());
+        glib::MainContext::default().spawn_local(async move {
+            while data_rx.changed().await.is_ok() {
+                let data = data_rx.borrow_and_update().clone();
+                render(&data);
+            }
+        });
+    }
+
     // Add FinfilesAI section to the main window
     vbox.append(&finfiles_ai_box);
 
-    // Application Entry Point
-    fn main() {
-        // Initialize logging, monitoring, and security
-        logging::init();
-        security::init_tls();
-        let auth = Arc::new(AuthManager::new());
-
-        // Authenticate user (OAuth2, OIDC, etc.)
-        let user = auth.authenticate_user();
-        if user.is_none() {
-            eprintln!("Authentication failed. Exiting.");
-            return;
-        }
-        let user = user.unwrap();
-
-        // RBAC (Only allow access to permitted tickers/features)
-        if !auth.has_role(&user, RBACRole::User) {
-            eprintln!("Access denied. Contact support.");
-            return;
-        }
-
-        // Start backend microservices (API, DB, cache, websocket, analytics)
-        backend::start_services();
-
-        // GTK Application
-        let app = Application::new(
-            Some("com.aa.sec_edgar_professional"),
-            Default::default(),
-        );
-
-        let state = Arc::new(AppState::new(user.clone()));
-        let auth_arc = auth.clone();
-
-        app.connect_activate(move |app| {
-            let window = build_main_window(app, state.clone(), auth_arc.clone());
-            window.present();
-        });
-
-        app.run();
-    }
+    window.add(&vbox);
+    window.show_all();
+    window
+}
 
     pub mod error {
         use thiserror::Error;
@@ -593,521 +1332,11744 @@ fn build_main_window(app: &Application, state: Arc<AppState>, auth: Arc<AuthMana
             AIModule(String),
             #[error("Authentication error: {0}")]
             Auth(String),
+            #[error("Permission denied: {0}")]
+            PermissionDenied(String),
             #[error("Unknown error: {0}")]
             Unknown(String),
             #[error("Custom model error: {0}")]
             CustomModel(String),
+            #[error("Export error: {0}")]
+            Export(String),
+            #[error("Configuration error: {0}")]
+            Config(String),
+            #[error("HTTP {status}: {body}")]
+            Http { status: u16, body: String },
+            #[error("Ticker not found: {ticker}. Did you mean {}?", .suggestions.join(", "))]
+            TickerNotFoundWithSuggestions { ticker: String, suggestions: Vec<String> },
+            #[error("{}", rate_limit_message(.retry_after))]
+            RateLimited { retry_after: Option<std::time::Duration> },
         }
 
         pub type Result<T> = std::result::Result<T, FinAIError>;
-    }
-
-    pub mod ai {
-        use super::error::*;
-        use polars::prelude::*;
-        use async_trait::async_trait;
-        use std::sync::Arc;
 
-        // Trait for pluggable AI/ML backends.
-        #[async_trait]
-        pub trait FinancialAIModule: Send + Sync {
-            // Analyze a DataFrame with a natural language query.
-            async fn analyze(&self, df: &DataFrame, query: &str) -> Result<String>;
-            fn backend_name(&self) -> &'static str;
+        impl FinAIError {
+            // What the user can do about a failed fetch, for errors where there's advice to give
+            pub fn hint(&self) -> Option<&'static str> {
+                match self {
+                    FinAIError::TickerNotFound(_) | FinAIError::TickerNotFoundWithSuggestions { .. } => {
+                        Some("Check the ticker symbol; retrying won't help until it's corrected.")
+                    }
+                    FinAIError::SecDataNotFound(_) => Some("SEC EDGAR has no filings or financial data for this entity."),
+                    FinAIError::Network(_) => Some("Check your internet connection, then retry."),
+                    FinAIError::Http { status, .. } if *status >= 500 => {
+                        Some("SEC EDGAR is having trouble right now; retry in a moment.")
+                    }
+                    FinAIError::RateLimited { .. } => Some("SEC is limiting requests; wait a little before retrying."),
+                    FinAIError::Auth(_) => Some("Your session may have expired; sign in again."),
+                    FinAIError::PermissionDenied(_) => Some("Ask an administrator for a role with this permission."),
+                    _ => None,
+                }
+            }
         }
 
-        // Independent FINFILES AI model (default, independent, no external dependencies)
-        pub struct FinfilesAI;
+        fn rate_limit_message(retry_after: &Option<std::time::Duration>) -> String {
+            match retry_after {
+                Some(wait) => format!("Rate limited, retry in {}s", wait.as_secs().max(1)),
+                None => "Rate limited, retry later".to_string(),
+            }
+        }
+    }
 
-        pub struct OnnxAIModule {
-            pub model_name: String,
-            session: Arc<Session>, 
+    pub mod config {
+        use super::data_ingestion::SecTimeouts;
+        use super::error::*;
+        use serde::{Deserialize, Serialize};
+        use std::collections::HashMap;
+        use std::fs;
+        use std::path::{Path, PathBuf};
+        use std::sync::{OnceLock, RwLock};
+        use std::time::Duration;
+
+        // Quarters of financial data fetched per ticker when the config doesn't say
+        pub const DEFAULT_PERIODS: usize = 4;
+        // Export directory (relative to the working directory) when the config doesn't say
+        pub const DEFAULT_EXPORT_DIR: &str = "exports";
+        // Seconds an AI backend may spend on one analysis unless the config says otherwise
+        pub const DEFAULT_ANALYSIS_TIMEOUT_SECS: u64 = 30;
+        // Address `--serve` listens on when neither `--bind` nor the config says
+        pub const DEFAULT_SERVE_ADDR: &str = "127.0.0.1:8787";
+        // Most decimal places `number_format.decimals` may ask for
+        pub const MAX_DECIMALS: usize = 8;
+
+        // Color theme for the main window. "System" follows the desktop's dark/light preference.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+        #[serde(rename_all = "snake_case")]
+        pub enum Theme {
+            #[default]
+            System,
+            Dark,
+            Light,
         }
 
-        impl OnnxAIModule {
-            // Initialize the ONNX module with our own independent model.
-            pub fn new() -> Result<Self, crate::error::FinAIError> {
-                log::info!("FINFILES AI: Initializing INDEPENDENT ONNX backend (our own model, no external AI)...");
+        impl Theme {
+            pub const ALL: [Theme; 3] = [Theme::System, Theme::Dark, Theme::Light];
 
-                // Path to our own ONNX model file (must exist and be trained by us)
-                let model_path = Path::new("models/finfiles_independent.onnx");
-                if !model_path.exists() {
-                    return Err(crate::error::FinAIError::AIModule(
-                        "Independent ONNX model file not found.".to_string(),
-                    ));
+            pub fn label(&self) -> &'static str {
+                match self {
+                    Theme::System => "System",
+                    Theme::Dark => "Dark",
+                    Theme::Light => "Light",
                 }
-
-                // Create ONNX Runtime environment
-                let environment = Environment::builder()
-                    .with_name("finfiles_onnx_independent")
-                    .with_log_level(LoggingLevel::Warning)
-                    .build()
-                    .map_err(|e| crate::error::FinAIError::AIModule(format!("ONNX env error: {e}")))?;
-
-                // Load our own ONNX model
-                let session = environment
-                    .new_session_builder()
-                    .map_err(|e| crate::error::FinAIError::AIModule(format!("ONNX session builder error: {e}")))?
-                    .with_model_from_file(model_path)
-                    .map_err(|e| crate::error::FinAIError::AIModule(format!("ONNX model load error: {e}")))?;
-
-                Ok(Self {
-                    model_name: "FinfilesIndependentAI".to_string(),
-                    session: Arc::new(session),
-                })
             }
+        }
 
-            // Run inference using our own independent ONNX model.
-            pub fn run_inference(&self, input: Vec<f32>) -> Result<Vec<f32>, crate::error::FinAIError> {
-                use onnxruntime::ndarray::Array;
-                use onnxruntime::ndarray::IxDyn;
-
-                // Prepare input tensor (example: 1D input)
-                let input_array = Array::from_shape_vec(IxDyn(&[1, input.len()]), input)
-                    .map_err(|e| crate::error::FinAIError::AIModule(format!("Input shape error: {e}")))?;
+        // Scale for monetary amounts in analysis output
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+        #[serde(rename_all = "snake_case")]
+        pub enum UnitScale {
+            // Actual amounts, e.g. "391035000000.00"
+            Raw,
+            Thousands,
+            Millions,
+            #[default]
+            Billions,
+        }
 
-                // Get input/output names
-                let input_name = self.session.inputs[0].name.clone();
-                let output_name = self.session.outputs[0].name.clone();
+        impl UnitScale {
+            pub fn divisor(&self) -> f64 {
+                match self {
+                    UnitScale::Raw => 1.0,
+                    UnitScale::Thousands => 1_000.0,
+                    UnitScale::Millions => 1_000_000.0,
+                    UnitScale::Billions => 1_000_000_000.0,
+                }
+            }
 
-                // Run inference
-                let outputs: Vec<OrtOwnedTensor<f32, _>> = self
-                    .session
-                    .run(vec![(input_name.as_str(), &input_array)])
-                    .map_err(|e| crate::error::FinAIError::AIModule(format!("ONNX inference error: {e}")))?;
+            pub fn suffix(&self) -> &'static str {
+                match self {
+                    UnitScale::Raw => "",
+                    UnitScale::Thousands => "K",
+                    UnitScale::Millions => "M",
+                    UnitScale::Billions => "B",
+                }
+            }
+        }
 
-                // Extract output
-                let output_tensor = outputs
-                    .get(0)
-                    .ok_or_else(|| crate::error::FinAIError::AIModule("No output from ONNX model".to_string()))?;
+        // How numbers are shown in analysis output (`[number_format]` in config.toml).
+        // The defaults give "391.04B" for amounts and "$6.13" per share.
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        #[serde(default)]
+        pub struct NumberFormat {
+            // Digits after the decimal point
+            pub decimals: usize,
+            // Scale for monetary amounts; share counts stay in millions
+            pub scale: UnitScale,
+            // Prefix for monetary and per-share amounts; when unset, amounts have none and
+            // per-share values use "$"
+            pub currency_symbol: Option<String>,
+        }
 
-                Ok(output_tensor.iter().cloned().collect())
+        impl Default for NumberFormat {
+            fn default() -> Self {
+                Self { decimals: 2, scale: UnitScale::default(), currency_symbol: None }
             }
         }
-        pub struct OnnxAIModule;
-        pub struct RemoteLLMAIModule;
-        pub struct CustomModelAIModule {
-            pub name: String,
+
+        // User defaults read at startup from `config.toml` in the user config dir. Every
+        // field is optional in the file; missing fields keep the built-in defaults.
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        #[serde(default)]
+        pub struct AppConfig {
+            // Ticker(s) pre-filled in the main window, e.g. "AAPL" or "AAPL,MSFT"
+            pub initial_ticker: Option<String>,
+            // Backend name as shown in the chat's backend selector (e.g. "FINFILES AI")
+            pub ai_backend: Option<String>,
+            pub export_dir: Option<PathBuf>,
+            pub periods: usize,
+            // SEC EDGAR contact; FINFILES_SEC_CONTACT takes precedence when set
+            pub sec_contact: Option<String>,
+            pub theme: Theme,
+            // Per-backend analysis timeout in seconds, keyed by backend name, e.g.
+            // `[analysis_timeouts]` with `RemoteLLM = 120`
+            pub analysis_timeouts: HashMap<String, u64>,
+            // Use the bundled demo data instead of SEC; `--offline` also turns this on
+            pub offline: bool,
+            // Listen address for `--serve`, e.g. "0.0.0.0:8080"
+            pub serve_addr: Option<String>,
+            // CSV/Excel filing columns in order, e.g. ["date", "form", "url"]; all when unset
+            pub export_columns: Option<Vec<String>>,
+            // Seconds allowed to connect to SEC EDGAR
+            pub connect_timeout_secs: Option<u64>,
+            // Per-request SEC timeout in seconds, keyed by endpoint ("cik_map", "submissions",
+            // "company_facts", "documents"), e.g. `[request_timeouts]` with `company_facts = 120`
+            pub request_timeouts: HashMap<String, u64>,
+            // Decimal places, amount scale and currency symbol of analysis output
+            pub number_format: NumberFormat,
         }
 
-        impl FinfilesAI {
-            pub fn new() -> Result<Self> {
-                log::info!("FINFILES AI: Initializing independent FINFILES AI backend...");
-                Ok(Self {})
+        impl Default for AppConfig {
+            fn default() -> Self {
+                Self {
+                    initial_ticker: None,
+                    ai_backend: None,
+                    export_dir: None,
+                    periods: DEFAULT_PERIODS,
+                    sec_contact: None,
+                    theme: Theme::default(),
+                    analysis_timeouts: HashMap::new(),
+                    offline: false,
+                    serve_addr: None,
+                    export_columns: None,
+                    connect_timeout_secs: None,
+                    request_timeouts: HashMap::new(),
+                    number_format: NumberFormat::default(),
+                }
             }
         }
-        impl OnnxAIModule {
-            pub fn new() -> Result<Self> {
-                log::info!("FINFILES AI: Initializing ONNX backend...");
-                Ok(Self {})
+
+        impl AppConfig {
+            pub fn path() -> Result<PathBuf> {
+                dirs::config_dir()
+                    .map(|dir| dir.join("finfiles").join("config.toml"))
+                    .ok_or_else(|| FinAIError::Config("No user config directory available.".to_string()))
             }
-        }
-        impl RemoteLLMAIModule {
-            pub fn new() -> Result<Self> {
-                log::info!("FINFILES AI: Initializing Remote LLM backend...");
-                Ok(Self {})
+
+            // Parse a (possibly partial) TOML config; absent fields fall back to defaults
+            pub fn from_toml(toml: &str) -> Result<Self> {
+                let config: Self =
+                    toml::from_str(toml).map_err(|e| FinAIError::Config(format!("Invalid config: {e}")))?;
+                Ok(config.sanitized())
             }
-        }
-        impl CustomModelAIModule {
-            pub fn new(name: String) -> Result<Self> {
-                log::info!("FINFILES AI: Initializing custom model backend: {}", name);
-                Ok(Self { name })
+
+            pub fn to_toml(&self) -> Result<String> {
+                toml::to_string_pretty(self).map_err(|e| FinAIError::Config(format!("Failed to serialize config: {e}")))
             }
-        }
 
-        #[async_trait]
-        impl FinancialAIModule for FinfilesAI {
-            async fn analyze(&self, df: &DataFrame, query: &str) -> Result<String> {
-                let normalized_query = query.to_lowercase();
+            // The user config, read from disk on first use and kept in memory after that;
+            // `update` refreshes it
+            pub fn current() -> Self {
+                Self::shared().read().unwrap().clone()
+            }
 
-                // Show table/raw
-                if normalized_query.contains("raw") || normalized_query.contains("table") {
-                    return Ok(format!("SEC Data Table:\n{}", df));
-                }
+            fn shared() -> &'static RwLock<AppConfig> {
+                static CONFIG: OnceLock<RwLock<AppConfig>> = OnceLock::new();
+                CONFIG.get_or_init(|| RwLock::new(Self::load()))
+            }
 
-                // Summary of SEC data
-                if normalized_query.contains("summarize") || normalized_query.contains("summary") {
-                    let quarters = df.column("quarter").ok().and_then(|s| s.utf8().ok()).map(|s| s.len()).unwrap_or(0);
-                    let mut summary_lines = Vec::new();
-                    for col in df.get_columns() {
-                        if let Ok(f64chunked) = col.f64() {
-                            let sum: f64 = f64chunked.into_iter().flatten().sum();
-                            let avg: f64 = if f64chunked.len() > 0 { sum / f64chunked.len() as f64 } else { 0.0 };
-                            let most_recent = f64chunked.into_iter().flatten().last().unwrap_or(0.0);
-                            summary_lines.push(format!(
-                                "  • {}: Total = {:.2}B, Avg = {:.2}B, Most Recent = {:.2}B",
-                                col.name(), sum, avg, most_recent
-                            ));
-                        }
-                    }
-                    return Ok(format!(
-                        "Summary: {} quarters of SEC data loaded.\n{}",
-                        quarters,
-                        summary_lines.join("\n")
-                    ));
+            // Load the user config. A missing file gives the defaults; an unreadable or
+            // invalid one is logged and also gives the defaults.
+            pub fn load() -> Self {
+                match Self::path() {
+                    Ok(path) => Self::load_from(&path),
+                    Err(_) => Self::default(),
                 }
+            }
 
-                // Time-series forecasting
-                if normalized_query.contains("forecast") || normalized_query.contains("predict") {
-                    // Naive forecast: last value as prediction for next period
-                    let mut forecast_lines = Vec::new();
-                    for col in df.get_columns() {
-                        if let Ok(f64chunked) = col.f64() {
-                            let last = f64chunked.into_iter().flatten().last().unwrap_or(0.0);
-                            forecast_lines.push(format!(
-                                "  • {}: Next period forecast (naive) = {:.2}B",
-                                col.name(), last
-                            ));
-                        }
-                    }
-                    return Ok(format!(
-                        "Time-Series Forecast (naive, last value):\n{}",
-                        forecast_lines.join("\n")
-                    ));
-                }
+            pub fn load_from(path: &Path) -> Self {
+                let toml = match fs::read_to_string(path) {
+                    Ok(toml) => toml,
+                    Err(_) => return Self::default(),
+                };
+                Self::from_toml(&toml).unwrap_or_else(|e| {
+                    log::warn!("Ignoring config {}: {}", path.display(), e);
+                    Self::default()
+                })
+            }
 
-                // Anomaly detection
-                if normalized_query.contains("anomaly") || normalized_query.contains("outlier") {
-                    let mut anomaly_lines = Vec::new();
-                    for col in df.get_columns() {
-                        if let Ok(f64chunked) = col.f64() {
-                            let vals: Vec<f64> = f64chunked.into_iter().flatten().collect();
-                            if vals.len() < 2 { continue; }
-                            let mean = vals.iter().sum::<f64>() / vals.len() as f64;
-                            let std = (vals.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / vals.len() as f64).sqrt();
-                            for (i, v) in vals.iter().enumerate() {
-                                if (*v - mean).abs() > 2.0 * std {
-                                    anomaly_lines.push(format!(
-                                        "  • {}: Anomaly detected at period {} (value = {:.2}B, mean = {:.2}B, std = {:.2}B)",
-                                        col.name(), i + 1, v, mean, std
-                                    ));
-                                }
-                            }
-                        }
-                    }
-                    if anomaly_lines.is_empty() {
-                        return Ok("No significant anomalies detected in the available metrics.".to_string());
-                    }
-                    return Ok(format!(
-                        "Anomaly Detection Results:\n{}",
-                        anomaly_lines.join("\n")
-                    ));
-                }
+            pub fn save(&self) -> Result<PathBuf> {
+                let path = Self::path()?;
+                self.save_to(&path)?;
+                Ok(path)
+            }
 
-                // Show quarters/periods
-                if normalized_query.contains("quarter") || normalized_query.contains("period") {
-                    if let Ok(series) = df.column("quarter") {
-                        let quarters: Vec<_> = series.utf8()?.into_iter().flatten().collect();
-                        return Ok(format!(
-                            "Loaded quarters from SEC: {}",
-                            quarters.join(", ")
-                        ));
-                    }
+            pub fn save_to(&self, path: &Path) -> Result<()> {
+                if let Some(dir) = path.parent() {
+                    fs::create_dir_all(dir)
+                        .map_err(|e| FinAIError::Config(format!("Failed to create {}: {e}", dir.display())))?;
                 }
+                fs::write(path, self.to_toml()?)
+                    .map_err(|e| FinAIError::Config(format!("Failed to write {}: {e}", path.display())))
+            }
 
-                // Dynamic metric detection (fuzzy matching, synonyms)
-                let available_metrics: Vec<String> = df
-                    .get_column_names()
-                    .iter()
-                    .filter(|&name| name != &"quarter")
-                    .map(|s| s.to_lowercase())
-                    .collect();
+            // Change settings, write them back to the user config and make them current
+            pub fn update(&mut self, change: impl FnOnce(&mut Self)) -> Result<PathBuf> {
+                change(self);
+                *self = self.clone().sanitized();
+                let path = self.save()?;
+                *Self::shared().write().unwrap() = self.clone();
+                Ok(path)
+            }
 
-                let synonyms = [
-                    ("revenue", "revenues"),
-                    ("net income", "netincomeloss"),
-                    ("eps", "earningspersharediluted"),
-                    ("assets", "assets"),
-                    ("liabilities", "liabilities"),
-                    ("cash", "cashandcashequivalentsatcarryingvalue"),
-                    ("operating cash flow", "operatingcashflow"),
-                ];
+            // How long the named backend may take per analysis
+            pub fn analysis_timeout(&self, backend: &str) -> Duration {
+                let secs = self.analysis_timeouts.get(backend).copied().unwrap_or(DEFAULT_ANALYSIS_TIMEOUT_SECS);
+                Duration::from_secs(secs.max(1))
+            }
 
-                let mut found_metric: Option<String> = None;
-                for metric in &available_metrics {
-                    if normalized_query.contains(metric) {
-                        found_metric = Some(metric.clone());
-                        break;
-                    }
-                }
-                if found_metric.is_none() {
-                    for (syn, canonical) in &synonyms {
-                        if normalized_query.contains(syn) && available_metrics.contains(&canonical.to_string()) {
-                            found_metric = Some(canonical.to_string());
-                            break;
-                        }
-                    }
+            // SEC connect and per-endpoint request timeouts, built-in defaults for any unset
+            pub fn sec_timeouts(&self) -> SecTimeouts {
+                let defaults = SecTimeouts::default();
+                let secs = |value: Option<u64>, default: Duration| value.map_or(default, |s| Duration::from_secs(s.max(1)));
+                let request = |endpoint: &str, default: Duration| secs(self.request_timeouts.get(endpoint).copied(), default);
+                SecTimeouts {
+                    connect: secs(self.connect_timeout_secs, defaults.connect),
+                    cik_map: request("cik_map", defaults.cik_map),
+                    submissions: request("submissions", defaults.submissions),
+                    company_facts: request("company_facts", defaults.company_facts),
+                    documents: request("documents", defaults.documents),
                 }
+            }
 
-                if let Some(metric) = found_metric {
-                    let orig_col = df.get_column_names().iter().find(|name| name.to_lowercase() == metric).unwrap();
-                    if let Ok(series) = df.column(orig_col) {
-                        if let Ok(f64chunked) = series.f64() {
-                            let total: f64 = f64chunked.into_iter().flatten().sum();
-                            let most_recent = f64chunked.into_iter().flatten().last().unwrap_or(0.0);
-                            let avg: f64 = if f64chunked.len() > 0 { total / f64chunked.len() as f64 } else { 0.0 };
-                            return Ok(format!(
-                                "SEC EDGAR {} Analysis:\n  • Total {} (last {} periods): {:.2}B\n  • Average per period: {:.2}B\n  • Most recent period: {:.2}B",
-                                orig_col,
-                                orig_col,
-                                f64chunked.len(),
-                                total,
-                                avg,
-                                most_recent
-                            ));
-                        } else if let Ok(utf8chunked) = series.utf8() {
-                            let values: Vec<_> = utf8chunked.into_iter().flatten().collect();
-                            return Ok(format!(
-                                "SEC EDGAR {} values: {}",
-                                orig_col,
-                                values.join(", ")
-                            ));
-                        } else {
-                            return Ok(format!(
-                                "SEC EDGAR: Column '{}' found, but data type is not supported for analysis.",
-                                orig_col
-                            ));
-                        }
-                    } else {
-                        return Ok(format!(
-                            "SEC EDGAR: Metric '{}' detected but could not retrieve data.",
-                            orig_col
-                        ));
-                    }
-                }
+            pub fn export_dir(&self) -> PathBuf {
+                self.export_dir.clone().unwrap_or_else(|| PathBuf::from(DEFAULT_EXPORT_DIR))
+            }
 
-                let available_list = if available_metrics.is_empty() {
-                    "No financial metrics available.".to_string()
-                } else {
-                    format!("Available metrics: {}", available_metrics.join(", "))
-                };
-                Ok(format!(
-                    "FINFILES AI: Could not detect a specific financial metric in your query.\n{}\nTry asking about one of these metrics, or type 'summarize', 'forecast', 'anomaly', or 'show table'.",
-                    available_list
-                ))
+            pub fn serve_addr(&self) -> String {
+                self.serve_addr.clone().unwrap_or_else(|| DEFAULT_SERVE_ADDR.to_string())
             }
-            fn backend_name(&self) -> &'static str { "FINFILES AI" }
-        }
 
-        #[async_trait]
-        impl FinancialAIModule for OnnxAIModule {
-            async fn analyze(&self, df: &DataFrame, query: &str) -> Result<String> {
-                // For this system, ONNX backend is a stub and delegates to FinfilesAI logic.
-                FinfilesAI.analyze(df, query).await
+            // Blank strings count as unset, at least one period is fetched and decimals are capped
+            fn sanitized(mut self) -> Self {
+                let blank_to_none = |value: Option<String>| value.map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+                self.initial_ticker = blank_to_none(self.initial_ticker);
+                self.ai_backend = blank_to_none(self.ai_backend);
+                self.sec_contact = blank_to_none(self.sec_contact);
+                self.serve_addr = blank_to_none(self.serve_addr);
+                self.periods = self.periods.max(1);
+                self.number_format.decimals = self.number_format.decimals.min(MAX_DECIMALS);
+                self.number_format.currency_symbol = blank_to_none(self.number_format.currency_symbol);
+                self
             }
-            fn backend_name(&self) -> &'static str { "ONNX" }
         }
 
-        #[async_trait]
-        impl FinancialAIModule for RemoteLLMAIModule {
-            async fn analyze(&self, df: &DataFrame, query: &str) -> Result<String> {
-                // For this system, RemoteLLM backend is a stub and delegates to FinfilesAI logic.
-                FinfilesAI.analyze(df, query).await
+        #[cfg(test)]
+        mod tests {
+            use super::*;
+
+            #[test]
+            fn partial_config_merges_over_defaults() {
+                let config = AppConfig::from_toml(
+                    "initial_ticker = \"AAPL,MSFT\"\ntheme = \"dark\"\n\n[analysis_timeouts]\nRemoteLLM = 120\n\n[number_format]\ndecimals = 1\n",
+                )
+                .unwrap();
+                let expected = AppConfig {
+                    initial_ticker: Some("AAPL,MSFT".to_string()),
+                    theme: Theme::Dark,
+                    analysis_timeouts: HashMap::from([("RemoteLLM".to_string(), 120)]),
+                    number_format: NumberFormat { decimals: 1, ..NumberFormat::default() },
+                    ..AppConfig::default()
+                };
+                assert_eq!(config, expected);
+                assert_eq!(config.analysis_timeout("RemoteLLM"), Duration::from_secs(120));
+                assert_eq!(config.analysis_timeout("FINFILES AI"), Duration::from_secs(DEFAULT_ANALYSIS_TIMEOUT_SECS));
+                assert_eq!(config.export_dir(), PathBuf::from(DEFAULT_EXPORT_DIR));
             }
-            fn backend_name(&self) -> &'static str { "RemoteLLM" }
-        }
 
-        #[async_trait]
-        impl FinancialAIModule for CustomModelAIModule {
-            async fn analyze(&self, df: &DataFrame, query: &str) -> Result<String> {
-                // For this system, custom model backend is a stub and delegates to FinfilesAI logic.
-                FinfilesAI.analyze(df, query).await
+            #[test]
+            fn empty_config_is_the_defaults() {
+                assert_eq!(AppConfig::from_toml("").unwrap(), AppConfig::default());
             }
-            fn backend_name(&self) -> &'static str { "CustomModel" }
-        }
-    }
 
-    pub mod data_ingestion {
-        use super::error::*;
-        use polars::prelude::*;
-        use serde::Deserialize;
-        use std::collections::{HashMap, HashSet};
-        use reqwest::Client;
+            #[test]
+            fn out_of_range_values_are_sanitized() {
+                let config = AppConfig::from_toml("periods = 0\nsec_contact = \"  \"\n\n[number_format]\ndecimals = 20\n").unwrap();
+                assert_eq!(config.periods, 1);
+                assert_eq!(config.sec_contact, None);
+                assert_eq!(config.number_format.decimals, MAX_DECIMALS);
+            }
 
-        #[derive(Debug, Deserialize)]
-        pub struct CikEntry {
-            pub cik_str: String,
-            pub ticker: String,
-            pub title: String,
-        }
+            #[test]
+            fn invalid_or_missing_file_gives_defaults() {
+                let path = std::env::temp_dir().join(format!("finfiles-config-{}.toml", std::process::id()));
+                fs::write(&path, "periods = \"many\"").unwrap();
+                assert!(AppConfig::from_toml("periods = \"many\"").is_err());
+                assert_eq!(AppConfig::load_from(&path), AppConfig::default());
+                fs::remove_file(&path).unwrap();
+                assert_eq!(AppConfig::load_from(&path), AppConfig::default());
+            }
 
-        #[derive(Debug, Deserialize)]
-        pub struct CompanySubmissions {
-            pub filings: Filings,
-        }
+            #[test]
+            fn saved_config_loads_back() {
+                let path = std::env::temp_dir().join(format!("finfiles-config-saved-{}", std::process::id())).join("config.toml");
+                let config = AppConfig { periods: 8, offline: true, ..AppConfig::default() };
+                config.save_to(&path).unwrap();
+                assert_eq!(AppConfig::load_from(&path), config);
+                fs::remove_dir_all(path.parent().unwrap()).unwrap();
+            }
 
-        #[derive(Debug, Deserialize)]
-        pub struct Filings {
-            pub recent: RecentFilings,
+            #[test]
+            fn sec_timeouts_override_per_endpoint() {
+                let config = AppConfig::from_toml("connect_timeout_secs = 3\n\n[request_timeouts]\ncompany_facts = 120\ncik_map = 0\n").unwrap();
+                let defaults = SecTimeouts::default();
+                assert_eq!(
+                    config.sec_timeouts(),
+                    SecTimeouts {
+                        connect: Duration::from_secs(3),
+                        company_facts: Duration::from_secs(120),
+                        // Zero would mean every request times out at once
+                        cik_map: Duration::from_secs(1),
+                        ..defaults
+                    }
+                );
+                assert_eq!(AppConfig::default().sec_timeouts(), defaults);
+            }
         }
+    }
 
-        #[derive(Debug, Deserialize)]
-        pub struct RecentFilings {
-            pub accession_number: Vec<String>,
-            pub form: Vec<String>,
+    pub mod theme {
+        use super::config::Theme;
+        use super::error::*;
+        use gtk::prelude::*;
+
+        // Main window stylesheet for dark mode
+        pub const DARK_CSS: &str = r#"
+            window { background: #181c20; }
+            box#main_vbox { background: #23272e; border-radius: 12px; padding: 24px; }
+            box#header_hbox { background: #23272e; border-radius: 8px; margin-bottom: 12px; padding: 12px 8px; }
+            button {
+                background: linear-gradient(90deg, #1976d2 0%, #1565c0 100%);
+                color: #fff;
+                font: bold 16px 'Segoe UI', Arial, sans-serif;
+                border-radius: 6px;
+                min-width: 120px;
+                min-height: 36px;
+                margin: 0 4px;
+                box-shadow: 0 2px 8px rgba(25,118,210,0.08);
+                transition: background 0.2s;
+            }
+            button:hover, button:focus {
+                background: linear-gradient(90deg, #2196f3 0%, #1976d2 100%);
+                color: #fff;
+                outline: 2px solid #90caf9;
+            }
+            entry {
+                font: 16px 'Segoe UI', Arial, sans-serif;
+                background: #1a1d22;
+                color: #e3eaf3;
+                border-radius: 6px;
+                border: 1.5px solid #1976d2;
+                padding: 8px 12px;
+                min-width: 320px;
+                margin-right: 8px;
+            }
+            entry:focus {
+                border: 2px solid #42a5f5;
+                background: #23272e;
+            }
+            label {
+                font: 15px 'Segoe UI', Arial, sans-serif;
+                color: #b0bec5;
+            }
+            treeview {
+                font: 15px 'Segoe UI', Arial, sans-serif;
+                background: #23272e;
+                color: #e3eaf3;
+                border-radius: 8px;
+                border: 1.5px solid #1976d2;
+            }
+            treeview row:selected, treeview row:selected:focus {
+                background: #1976d2;
+                color: #fff;
+            }
+            treeview header {
+                background: #1565c0;
+                color: #fff;
+                font-weight: bold;
+                font-size: 15px;
+            }
+            spinner { color: #42a5f5; }
+            #status_label {
+                color: #90caf9;
+                font: italic 15px 'Segoe UI', Arial, sans-serif;
+                margin-top: 8px;
+            }
+            #load_more_button {
+                background: linear-gradient(90deg, #43a047 0%, #388e3c 100%);
+                color: #fff;
+                font-weight: bold;
+            }
+            #load_more_button:hover, #load_more_button:focus {
+                background: linear-gradient(90deg, #66bb6a 0%, #43a047 100%);
+                outline: 2px solid #a5d6a7;
+            }
+            #export_button {
+                background: linear-gradient(90deg, #fbc02d 0%, #f9a825 100%);
+                color: #23272e;
+                font-weight: bold;
+            }
+            #export_button:hover, #export_button:focus {
+                background: linear-gradient(90deg, #ffe082 0%, #fbc02d 100%);
+                outline: 2px solid #ffe082;
+            }
+            #ai_chat_button {
+                background: linear-gradient(90deg, #8e24aa 0%, #3949ab 100%);
+                color: #fff;
+                font-weight: bold;
+            }
+            #ai_chat_button:hover, #ai_chat_button:focus {
+                background: linear-gradient(90deg, #ba68c8 0%, #7986cb 100%);
+                outline: 2px solid #b39ddb;
+            }
+        "#;
+
+        // Same layout as DARK_CSS with light backgrounds and dark text (WCAG AA contrast)
+        pub const LIGHT_CSS: &str = r#"
+            window { background: #f5f7fa; }
+            box#main_vbox { background: #ffffff; border-radius: 12px; padding: 24px; }
+            box#header_hbox { background: #0d47a1; border-radius: 8px; margin-bottom: 12px; padding: 12px 8px; }
+            button {
+                background: linear-gradient(90deg, #1565c0 0%, #0d47a1 100%);
+                color: #fff;
+                font: bold 16px 'Segoe UI', Arial, sans-serif;
+                border-radius: 6px;
+                min-width: 120px;
+                min-height: 36px;
+                margin: 0 4px;
+                box-shadow: 0 2px 8px rgba(25,118,210,0.08);
+                transition: background 0.2s;
+            }
+            button:hover, button:focus {
+                background: linear-gradient(90deg, #1976d2 0%, #1565c0 100%);
+                color: #fff;
+                outline: 2px solid #0d47a1;
+            }
+            entry {
+                font: 16px 'Segoe UI', Arial, sans-serif;
+                background: #ffffff;
+                color: #1a1d22;
+                border-radius: 6px;
+                border: 1.5px solid #1976d2;
+                padding: 8px 12px;
+                min-width: 320px;
+                margin-right: 8px;
+            }
+            entry:focus {
+                border: 2px solid #0d47a1;
+                background: #ffffff;
+            }
+            label {
+                font: 15px 'Segoe UI', Arial, sans-serif;
+                color: #37474f;
+            }
+            treeview {
+                font: 15px 'Segoe UI', Arial, sans-serif;
+                background: #ffffff;
+                color: #1a1d22;
+                border-radius: 8px;
+                border: 1.5px solid #1976d2;
+            }
+            treeview row:selected, treeview row:selected:focus {
+                background: #1976d2;
+                color: #fff;
+            }
+            treeview header {
+                background: #1565c0;
+                color: #fff;
+                font-weight: bold;
+                font-size: 15px;
+            }
+            spinner { color: #1565c0; }
+            #status_label {
+                color: #0d47a1;
+                font: italic 15px 'Segoe UI', Arial, sans-serif;
+                margin-top: 8px;
+            }
+            #load_more_button {
+                background: linear-gradient(90deg, #2e7d32 0%, #1b5e20 100%);
+                color: #fff;
+                font-weight: bold;
+            }
+            #load_more_button:hover, #load_more_button:focus {
+                background: linear-gradient(90deg, #388e3c 0%, #2e7d32 100%);
+                outline: 2px solid #1b5e20;
+            }
+            #export_button {
+                background: linear-gradient(90deg, #fbc02d 0%, #f9a825 100%);
+                color: #23272e;
+                font-weight: bold;
+            }
+            #export_button:hover, #export_button:focus {
+                background: linear-gradient(90deg, #ffe082 0%, #fbc02d 100%);
+                outline: 2px solid #f57f17;
+            }
+            #ai_chat_button {
+                background: linear-gradient(90deg, #8e24aa 0%, #3949ab 100%);
+                color: #fff;
+                font-weight: bold;
+            }
+            #ai_chat_button:hover, #ai_chat_button:focus {
+                background: linear-gradient(90deg, #ba68c8 0%, #7986cb 100%);
+                outline: 2px solid #4a148c;
+            }
+        "#;
+
+        // The desktop's preference: GTK_THEME or the GTK theme name ending in "dark"
+        pub fn system_prefers_dark() -> bool {
+            let theme_name = std::env::var("GTK_THEME").ok().or_else(|| {
+                gtk::Settings::get_default()
+                    .and_then(|settings| settings.get_property_gtk_theme_name())
+                    .map(|name| name.to_string())
+            });
+            theme_name.map_or(false, |name| name.to_lowercase().contains("dark"))
         }
 
-        #[derive(Debug, Deserialize)]
-        pub struct CompanyFacts {
-            pub facts: HashMap<String, HashMap<String, GaapFact>>,
+        pub fn is_dark(theme: Theme) -> bool {
+            match theme {
+                Theme::System => system_prefers_dark(),
+                Theme::Dark => true,
+                Theme::Light => false,
+            }
         }
 
-        #[derive(Debug, Deserialize)]
-        pub struct GaapFact {
-            pub units: HashMap<String, Vec<FactUnit>>,
+        pub fn css_for(theme: Theme) -> &'static str {
+            if is_dark(theme) {
+                DARK_CSS
+            } else {
+                LIGHT_CSS
+            }
         }
 
-        #[derive(Debug, Deserialize)]
-        pub struct FactUnit {
-            #[serde(rename = "fiscalPeriod")]
-            pub fiscal_period: Option<String>,
-            #[serde(rename = "val")]
-            pub value: Option<f64>,
+        // Swap the provider's stylesheet and tell GTK which variant of the base theme to use
+        pub fn apply_theme(provider: &gtk::CssProvider, theme: Theme) -> Result<()> {
+            provider
+                .load_from_data(css_for(theme).as_bytes())
+                .map_err(|e| FinAIError::Config(format!("Failed to load {} theme: {e}", theme.label())))?;
+            if let Some(settings) = gtk::Settings::get_default() {
+                settings.set_property_gtk_application_prefer_dark_theme(is_dark(theme));
+            }
+            Ok(())
         }
 
-        pub struct FinancialDataLoader;
+        #[cfg(test)]
+        mod tests {
+            use super::*;
 
-        impl FinancialDataLoader {
-            // Loads SEC EDGAR data for a user-specified ticker
-            pub async fn load_sec_data_for_ticker(ticker: &str) -> Result<DataFrame> {
-                log::info!("FINFILES AI: Fetching SEC EDGAR filings for ticker: {}", ticker);
+            #[test]
+            fn explicit_themes_pick_their_stylesheet() {
+                assert_eq!(css_for(Theme::Dark), DARK_CSS);
+                assert_eq!(css_for(Theme::Light), LIGHT_CSS);
+                assert_ne!(DARK_CSS, LIGHT_CSS);
+            }
 
-                let client = Client::builder()
-                    .timeout(std::time::Duration::from_secs(20))
-                    .user_agent("FINFILES AI/1.0 (contact: ai@finfiles.ai)")
-                    .build()
-                    .map_err(|e| FinAIError::Network(format!("Failed to build HTTP client: {e}")))?;
-
-                // Retry logic for transient network errors
-                let mut retries = 0;
-                let cik_map: HashMap<String, CikEntry> = loop {
-                    match client.get("https://www.sec.gov/files/company_tickers.json").send().await {
-                        Ok(resp) => match resp.json().await {
-                            Ok(json) => break json,
-                            Err(e) => return Err(FinAIError::DataParsing(format!("Failed to parse CIK map: {e}"))),
-                        },
-                        Err(_e) if retries < 2 => {
-                            retries += 1;
-                            tokio::time::sleep(std::time::Duration::from_secs(2)).await;
-                            continue;
-                        }
-                        Err(e) => return Err(FinAIError::Network(format!("Failed to fetch CIK map: {e}"))),
+            #[test]
+            fn every_theme_stylesheet_loads() {
+                // Needs a display; skipped where GTK can't start (e.g. headless CI)
+                if gtk::init().is_err() {
+                    return;
+                }
+                let provider = gtk::CssProvider::new();
+                for theme in [Theme::System, Theme::Dark, Theme::Light] {
+                    assert!(apply_theme(&provider, theme).is_ok(), "{}", theme.label());
+                }
+                for css in [DARK_CSS, LIGHT_CSS] {
+                    assert!(gtk::CssProvider::new().load_from_data(css.as_bytes()).is_ok());
+                }
+            }
+        }
+    }
+
+    pub mod shortcuts {
+        use gtk::prelude::*;
+
+        // What a main-window key combination does
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum ShortcutAction {
+            Fetch,
+            Export,
+            Refresh,
+            FocusTicker,
+            NextFiling,
+            PreviousFiling,
+        }
+
+        // Map a key press to a shortcut. Only Ctrl/Alt combinations are claimed, so plain
+        // typing, Shift selection and arrow keys keep working in the ticker entry.
+        pub fn shortcut_action(key: &gdk::keys::Key, state: gdk::ModifierType) -> Option<ShortcutAction> {
+            let ctrl = state.contains(gdk::ModifierType::CONTROL_MASK);
+            let alt = state.contains(gdk::ModifierType::MOD1_MASK);
+            if ctrl == alt {
+                return None;
+            }
+            match key.to_lower() {
+                gdk::keys::constants::f if ctrl => Some(ShortcutAction::Fetch),
+                gdk::keys::constants::e if ctrl => Some(ShortcutAction::Export),
+                gdk::keys::constants::r if ctrl => Some(ShortcutAction::Refresh),
+                gdk::keys::constants::l if ctrl => Some(ShortcutAction::FocusTicker),
+                gdk::keys::constants::Down | gdk::keys::constants::KP_Down if alt => Some(ShortcutAction::NextFiling),
+                gdk::keys::constants::Up | gdk::keys::constants::KP_Up if alt => Some(ShortcutAction::PreviousFiling),
+                _ => None,
+            }
+        }
+
+        // Move the cursor `step` rows (1 or -1) through the view, starting at the first row
+        // when nothing is selected and stopping at either end
+        pub fn move_selection(view: &gtk::TreeView, step: i32) {
+            let model = match view.get_model() {
+                Some(model) => model,
+                None => return,
+            };
+            let iter = match view.get_selection().get_selected() {
+                Some((_, iter)) => {
+                    let moved = if step > 0 { model.iter_next(&iter) } else { model.iter_previous(&iter) };
+                    if !moved {
+                        return;
                     }
+                    iter
+                }
+                None => match model.get_iter_first() {
+                    Some(iter) => iter,
+                    None => return,
+                },
+            };
+            if let Some(path) = model.get_path(&iter) {
+                view.set_cursor(&path, None::<&gtk::TreeViewColumn>, false);
+            }
+        }
+
+        #[cfg(test)]
+        mod tests {
+            use super::*;
+            use gdk::keys::constants as key;
+            use gdk::ModifierType;
+
+            #[test]
+            fn ctrl_and_alt_combinations_map_to_actions() {
+                let ctrl = ModifierType::CONTROL_MASK;
+                let alt = ModifierType::MOD1_MASK;
+                let cases = [
+                    (key::f, ctrl, ShortcutAction::Fetch),
+                    (key::F, ctrl | ModifierType::SHIFT_MASK, ShortcutAction::Fetch),
+                    (key::e, ctrl, ShortcutAction::Export),
+                    (key::r, ctrl, ShortcutAction::Refresh),
+                    (key::l, ctrl, ShortcutAction::FocusTicker),
+                    (key::Down, alt, ShortcutAction::NextFiling),
+                    (key::KP_Down, alt, ShortcutAction::NextFiling),
+                    (key::Up, alt, ShortcutAction::PreviousFiling),
+                    (key::KP_Up, alt, ShortcutAction::PreviousFiling),
+                ];
+                for (keyval, state, action) in cases {
+                    assert_eq!(shortcut_action(&keyval, state), Some(action), "{:?}", keyval);
+                }
+            }
+
+            #[test]
+            fn text_editing_keys_are_left_alone() {
+                let ctrl = ModifierType::CONTROL_MASK;
+                let alt = ModifierType::MOD1_MASK;
+                let cases = [
+                    (key::f, ModifierType::empty()),
+                    (key::l, ModifierType::SHIFT_MASK),
+                    (key::Down, ModifierType::empty()),
+                    (key::Down, ctrl),
+                    (key::f, alt),
+                    (key::f, ctrl | alt),
+                    (key::a, ctrl),
+                ];
+                for (keyval, state) in cases {
+                    assert_eq!(shortcut_action(&keyval, state), None, "{:?} {:?}", keyval, state);
+                }
+            }
+        }
+    }
+
+    pub mod security {
+        use super::data_ingestion::response_error;
+        use super::error::*;
+        use serde::Deserialize;
+        use std::collections::{HashMap, HashSet, VecDeque};
+        use std::fs;
+        use std::path::{Path, PathBuf};
+        use std::sync::Mutex;
+        use std::time::{Duration, Instant, SystemTime};
+
+        // Longest ticker accepted by sanitize_ticker
+        pub const MAX_TICKER_LEN: usize = 10;
+
+        // Normalize user-entered ticker text. Surrounding whitespace is trimmed and the
+        // result is uppercased; it is then accepted only if it matches
+        //
+        //     TICKER := [A-Z0-9] [A-Z0-9.-]{0,9}
+        //
+        // i.e. 1 to 10 characters of ASCII letters, digits, '.' or '-', starting with a
+        // letter or digit (so class shares like "BRK.B" and "BF-B" survive). Anything else,
+        // including embedded spaces, punctuation, or non-ASCII characters, yields "" rather
+        // than a partially stripped ticker.
+        pub fn sanitize_ticker(input: &str) -> String {
+            // ASCII-only case folding, so non-ASCII letters are rejected instead of mapped
+            // to ASCII ("ı" to "I", "ß" to "SS")
+            let ticker = input.trim().to_ascii_uppercase();
+            let valid_len = !ticker.is_empty() && ticker.len() <= MAX_TICKER_LEN;
+            let valid_start = ticker.chars().next().map_or(false, |c| c.is_ascii_alphanumeric());
+            let valid_chars = ticker.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '.' || c == '-');
+            if valid_len && valid_start && valid_chars {
+                ticker
+            } else {
+                String::new()
+            }
+        }
+
+        // Split pasted ticker input on commas, semicolons and any whitespace (including
+        // newlines), sanitize each token and drop invalid or repeated ones, keeping order
+        pub fn parse_ticker_list(input: &str) -> Vec<String> {
+            let mut tickers: Vec<String> = Vec::new();
+            for token in input.split(|c: char| c == ',' || c == ';' || c.is_whitespace()) {
+                let ticker = sanitize_ticker(token);
+                if !ticker.is_empty() && !tickers.contains(&ticker) {
+                    tickers.push(ticker);
+                }
+            }
+            tickers
+        }
+
+        // Roles used for role-based access control
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum RBACRole {
+            Admin,
+            User,
+            Analyst,
+            Viewer,
+        }
+
+        // Individual actions gated by RBAC
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum Permission {
+            FetchFilings,
+            Export,
+            UseRemoteLLM,
+            UploadModel,
+            ViewAuditLog,
+        }
+
+        impl RBACRole {
+            // Default permission set granted by each role
+            pub fn permissions(&self) -> &'static [Permission] {
+                match self {
+                    RBACRole::Admin => &[
+                        Permission::FetchFilings,
+                        Permission::Export,
+                        Permission::UseRemoteLLM,
+                        Permission::UploadModel,
+                        Permission::ViewAuditLog,
+                    ],
+                    RBACRole::User => &[
+                        Permission::FetchFilings,
+                        Permission::Export,
+                        Permission::UseRemoteLLM,
+                        Permission::UploadModel,
+                    ],
+                    RBACRole::Analyst => &[Permission::FetchFilings, Permission::Export],
+                    RBACRole::Viewer => &[Permission::FetchFilings],
+                }
+            }
+
+            // Filing fetches allowed per RATE_LIMIT_WINDOW
+            pub fn fetch_limit(&self) -> usize {
+                match self {
+                    RBACRole::Admin => 60,
+                    RBACRole::User | RBACRole::Analyst => 20,
+                    RBACRole::Viewer => 10,
+                }
+            }
+
+            pub fn from_name(name: &str) -> Option<Self> {
+                match name.trim().to_lowercase().as_str() {
+                    "admin" => Some(RBACRole::Admin),
+                    "user" => Some(RBACRole::User),
+                    "analyst" => Some(RBACRole::Analyst),
+                    "viewer" => Some(RBACRole::Viewer),
+                    _ => None,
+                }
+            }
+        }
+
+        // An authenticated user and their roles
+        #[derive(Debug, Clone)]
+        pub struct User {
+            pub username: String,
+            pub roles: Vec<RBACRole>,
+        }
+
+        impl User {
+            // Union of the permissions granted by all of the user's roles
+            pub fn permissions(&self) -> HashSet<Permission> {
+                self.roles.iter().flat_map(|role| role.permissions().iter().copied()).collect()
+            }
+
+            // Most generous fetch limit of the user's roles; users without roles get the Viewer limit
+            pub fn fetch_limit(&self) -> usize {
+                self.roles.iter().map(|role| role.fetch_limit()).max().unwrap_or(RBACRole::Viewer.fetch_limit())
+            }
+        }
+
+        // Sliding window over which per-user fetch limits apply
+        pub const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
+
+        // OAuth2 token endpoint settings, read from the environment
+        pub const OAUTH_TOKEN_URL_ENV: &str = "FINFILES_OAUTH_TOKEN_URL";
+        pub const OAUTH_CLIENT_ID_ENV: &str = "FINFILES_OAUTH_CLIENT_ID";
+        pub const OAUTH_CLIENT_SECRET_ENV: &str = "FINFILES_OAUTH_CLIENT_SECRET";
+        // Refresh token used to start a session without an interactive login
+        pub const OAUTH_REFRESH_TOKEN_ENV: &str = "FINFILES_REFRESH_TOKEN";
+        // Access tokens are refreshed once they are this close to expiring
+        pub const DEFAULT_REFRESH_MARGIN: Duration = Duration::from_secs(60);
+        // Bearer token clients of `--serve` must present; the server refuses to start without it
+        pub const API_TOKEN_ENV: &str = "FINFILES_API_TOKEN";
+
+        #[derive(Debug, Clone, PartialEq)]
+        pub struct OAuthConfig {
+            pub token_url: String,
+            pub client_id: String,
+            pub client_secret: Option<String>,
+        }
+
+        impl OAuthConfig {
+            // None unless both the token URL and client id are set
+            pub fn from_env() -> Option<Self> {
+                let var = |name: &str| std::env::var(name).ok().map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+                Some(Self {
+                    token_url: var(OAUTH_TOKEN_URL_ENV)?,
+                    client_id: var(OAUTH_CLIENT_ID_ENV)?,
+                    client_secret: var(OAUTH_CLIENT_SECRET_ENV),
+                })
+            }
+        }
+
+        // Access token for the current session and when it stops being valid
+        #[derive(Debug, Clone, PartialEq)]
+        pub struct AuthTokens {
+            pub access_token: String,
+            pub refresh_token: Option<String>,
+            pub expires_at: SystemTime,
+        }
+
+        impl AuthTokens {
+            // Whether the access token has expired or will within `margin` of `now`
+            pub fn expires_within(&self, margin: Duration, now: SystemTime) -> bool {
+                match self.expires_at.duration_since(now) {
+                    Ok(remaining) => remaining <= margin,
+                    Err(_) => true,
+                }
+            }
+        }
+
+        // Token endpoint response (RFC 6749 section 5.1)
+        #[derive(Debug, Deserialize)]
+        struct TokenResponse {
+            access_token: String,
+            #[serde(default)]
+            refresh_token: Option<String>,
+            #[serde(default)]
+            expires_in: Option<u64>,
+        }
+
+        // Lifetime assumed when the token endpoint doesn't report expires_in
+        const DEFAULT_TOKEN_LIFETIME: Duration = Duration::from_secs(3600);
+
+        // Admin-owned role assignments, one `account = "role"` line per OS account, e.g.
+        // `alice = "admin"`. Accounts not listed get Viewer.
+        pub const ROLES_FILE: &str = "/etc/finfiles/roles.toml";
+
+        // Read a roles file. Missing, unparsable, or (on Unix) group- or world-writable
+        // files are ignored, so nobody but the file's owner can grant roles.
+        pub fn load_roles(path: &Path) -> Option<HashMap<String, String>> {
+            let metadata = fs::metadata(path).ok()?;
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                if metadata.permissions().mode() & 0o022 != 0 {
+                    log::warn!("Ignoring roles file {}: writable by group or others", path.display());
+                    return None;
+                }
+            }
+            #[cfg(not(unix))]
+            let _ = metadata;
+            let text = fs::read_to_string(path).ok()?;
+            match toml::from_str(&text) {
+                Ok(roles) => Some(roles),
+                Err(e) => {
+                    log::warn!("Ignoring roles file {}: {}", path.display(), e);
+                    None
+                }
+            }
+        }
+
+        // Authentication and role-based access control
+        pub struct AuthManager {
+            current_user: Mutex<Option<User>>,
+            oauth: Option<OAuthConfig>,
+            tokens: Mutex<Option<AuthTokens>>,
+            refresh_margin: Duration,
+            // Start times of each user's recent fetches, oldest first
+            recent_fetches: Mutex<HashMap<String, VecDeque<Instant>>>,
+            // Token accepted by authorize_bearer
+            api_token: Option<String>,
+            // Where roles are assigned (see ROLES_FILE); None gives everyone Viewer
+            roles_path: Option<PathBuf>,
+        }
+
+        impl AuthManager {
+            pub fn new() -> Self {
+                Self {
+                    current_user: Mutex::new(None),
+                    oauth: OAuthConfig::from_env(),
+                    tokens: Mutex::new(None),
+                    refresh_margin: DEFAULT_REFRESH_MARGIN,
+                    recent_fetches: Mutex::new(HashMap::new()),
+                    api_token: std::env::var(API_TOKEN_ENV).ok().map(|t| t.trim().to_string()).filter(|t| !t.is_empty()),
+                    roles_path: Some(PathBuf::from(ROLES_FILE)),
+                }
+            }
+
+            pub fn with_roles_path(mut self, path: Option<PathBuf>) -> Self {
+                self.roles_path = path;
+                self
+            }
+
+            // Role assigned to an OS account in the roles file; Viewer when unassigned
+            pub fn role_for(&self, account: &str) -> RBACRole {
+                self.roles_path
+                    .as_deref()
+                    .and_then(load_roles)
+                    .and_then(|roles| roles.get(account).and_then(|name| RBACRole::from_name(name)))
+                    .unwrap_or(RBACRole::Viewer)
+            }
+
+            pub fn with_oauth(mut self, oauth: Option<OAuthConfig>) -> Self {
+                self.oauth = oauth;
+                self
+            }
+
+            pub fn with_refresh_margin(mut self, margin: Duration) -> Self {
+                self.refresh_margin = margin;
+                self
+            }
+
+            // Act as this user instead of the one found by authenticate_user
+            pub fn with_current_user(self, user: Option<User>) -> Self {
+                *self.current_user.lock().unwrap() = user;
+                self
+            }
+
+            pub fn with_api_token(mut self, token: Option<String>) -> Self {
+                self.api_token = token;
+                self
+            }
+
+            pub fn has_api_token(&self) -> bool {
+                self.api_token.is_some()
+            }
+
+            // Check an HTTP Authorization header ("Bearer <token>") against the API token and
+            // return the authenticated user it acts as. Fails when no API token is configured.
+            pub fn authorize_bearer(&self, authorization: Option<&str>) -> Result<User> {
+                let expected = self
+                    .api_token
+                    .as_deref()
+                    .ok_or_else(|| FinAIError::Auth(format!("{} is not set.", API_TOKEN_ENV)))?;
+                let presented = authorization
+                    .and_then(|value| value.trim().strip_prefix("Bearer "))
+                    .map(str::trim)
+                    .ok_or_else(|| FinAIError::Auth("Missing bearer token.".to_string()))?;
+                // Compare every byte so the time taken doesn't reveal the matching prefix
+                let matches = presented.len() == expected.len()
+                    && presented.bytes().zip(expected.bytes()).fold(0u8, |diff, (a, b)| diff | (a ^ b)) == 0;
+                if !matches {
+                    return Err(FinAIError::Auth("Invalid bearer token.".to_string()));
+                }
+                Ok(self.current_user())
+            }
+
+            // Store the tokens from a login (or a previous session)
+            pub fn set_tokens(&self, tokens: Option<AuthTokens>) {
+                *self.tokens.lock().unwrap() = tokens;
+            }
+
+            pub fn tokens(&self) -> Option<AuthTokens> {
+                self.tokens.lock().unwrap().clone()
+            }
+
+            // Whether the stored access token should be refreshed before use at `now`
+            pub fn needs_refresh(&self, now: SystemTime) -> bool {
+                self.tokens
+                    .lock()
+                    .unwrap()
+                    .as_ref()
+                    .map_or(false, |tokens| tokens.expires_within(self.refresh_margin, now))
+            }
+
+            // Call before each authenticated operation. Returns the access token to use,
+            // refreshing it first if it is expired or about to expire. Sessions without
+            // OAuth (local env-based login) have no token and return Ok(None).
+            pub async fn refresh_if_needed(&self) -> Result<Option<String>> {
+                let tokens = match self.tokens() {
+                    Some(tokens) => tokens,
+                    None => return Ok(None),
+                };
+                if !self.needs_refresh(SystemTime::now()) {
+                    return Ok(Some(tokens.access_token));
+                }
+                let oauth = self
+                    .oauth
+                    .as_ref()
+                    .ok_or_else(|| FinAIError::Auth("Session expired and no OAuth token endpoint is configured.".to_string()))?;
+                let refresh_token = tokens
+                    .refresh_token
+                    .clone()
+                    .ok_or_else(|| FinAIError::Auth("Session expired; please sign in again.".to_string()))?;
+
+                let refreshed = Self::request_refresh(oauth, &refresh_token).await.map_err(|e| {
+                    log::warn!("Token refresh failed: {}", e);
+                    FinAIError::Auth(format!("Session expired and could not be refreshed ({e}); please sign in again."))
+                })?;
+                let tokens = AuthTokens {
+                    access_token: refreshed.access_token,
+                    // Servers may omit the refresh token when it isn't rotated
+                    refresh_token: refreshed.refresh_token.or(Some(refresh_token)),
+                    expires_at: SystemTime::now()
+                        + refreshed.expires_in.map(Duration::from_secs).unwrap_or(DEFAULT_TOKEN_LIFETIME),
                 };
+                let access_token = tokens.access_token.clone();
+                self.set_tokens(Some(tokens));
+                log::info!("Access token refreshed.");
+                Ok(Some(access_token))
+            }
+
+            async fn request_refresh(oauth: &OAuthConfig, refresh_token: &str) -> Result<TokenResponse> {
+                let mut form = vec![
+                    ("grant_type", "refresh_token"),
+                    ("refresh_token", refresh_token),
+                    ("client_id", oauth.client_id.as_str()),
+                ];
+                if let Some(secret) = &oauth.client_secret {
+                    form.push(("client_secret", secret.as_str()));
+                }
+                let resp = reqwest::Client::new()
+                    .post(&oauth.token_url)
+                    .form(&form)
+                    .send()
+                    .await
+                    .map_err(|e| FinAIError::Network(e.to_string()))?;
+                if !resp.status().is_success() {
+                    return Err(response_error(resp).await);
+                }
+                resp.json()
+                    .await
+                    .map_err(|e| FinAIError::DataParsing(format!("Invalid token response: {e}")))
+            }
 
-                let cik = cik_map.values()
-                    .find(|entry| entry.ticker.eq_ignore_ascii_case(ticker))
-                    .map(|entry| entry.cik_str.clone())
-                    .ok_or_else(|| FinAIError::TickerNotFound(ticker.to_string()))?;
+            // Authenticate the local user. The identity comes from FINFILES_USER (falling back
+            // to the OS user); the role is the OS account's entry in the admin-owned roles
+            // file, Viewer by default.
+            pub fn authenticate_user(&self) -> Option<User> {
+                let account = std::env::var("USER")
+                    .or_else(|_| std::env::var("USERNAME"))
+                    .ok()
+                    .filter(|name| !name.trim().is_empty());
+                let username = std::env::var("FINFILES_USER")
+                    .ok()
+                    .filter(|name| !name.trim().is_empty())
+                    .or_else(|| account.clone())?;
+                let role = account.map_or(RBACRole::Viewer, |account| self.role_for(&account));
+                let user = User { username, roles: vec![role] };
+                *self.current_user.lock().unwrap() = Some(user.clone());
+                // With OAuth configured, a stored refresh token starts the session; the access
+                // token is fetched by the first refresh_if_needed
+                if self.oauth.is_some() {
+                    if let Ok(refresh_token) = std::env::var(OAUTH_REFRESH_TOKEN_ENV) {
+                        self.set_tokens(Some(AuthTokens {
+                            access_token: String::new(),
+                            refresh_token: Some(refresh_token),
+                            expires_at: SystemTime::UNIX_EPOCH,
+                        }));
+                    }
+                }
+                Some(user)
+            }
 
-                // Download the most recent 10-K or 10-Q filings (JSON index)
-                let filings_url = format!(
-                    "https://data.sec.gov/submissions/CIK{:0>10}.json",
-                    cik
+            // The authenticated user, or an anonymous user with no roles
+            pub fn current_user(&self) -> User {
+                self.current_user
+                    .lock()
+                    .unwrap()
+                    .clone()
+                    .unwrap_or_else(|| User { username: "anonymous".to_string(), roles: Vec::new() })
+            }
+
+            pub fn has_role(&self, user: &User, role: RBACRole) -> bool {
+                user.roles.contains(&role)
+            }
+
+            pub fn has_permission(&self, user: &User, permission: Permission) -> bool {
+                user.roles.iter().any(|role| role.permissions().contains(&permission))
+            }
+
+            // Record a fetch for the user, or fail with RateLimited (and record nothing) if
+            // they already ran their role's limit within RATE_LIMIT_WINDOW
+            pub fn check_rate_limit(&self, user: &User) -> Result<()> {
+                self.check_rate_limit_at(user, Instant::now())
+            }
+
+            pub fn check_rate_limit_at(&self, user: &User, now: Instant) -> Result<()> {
+                let mut recent = self.recent_fetches.lock().unwrap();
+                let starts = recent.entry(user.username.clone()).or_default();
+                while starts.front().map_or(false, |t| now.saturating_duration_since(*t) >= RATE_LIMIT_WINDOW) {
+                    starts.pop_front();
+                }
+                if starts.len() >= user.fetch_limit() {
+                    let oldest = starts.front().copied().unwrap_or(now);
+                    let retry_after = RATE_LIMIT_WINDOW.saturating_sub(now.saturating_duration_since(oldest));
+                    return Err(FinAIError::RateLimited { retry_after: Some(retry_after) });
+                }
+                starts.push_back(now);
+                Ok(())
+            }
+
+            // Tickers the user may fetch: all of them with FetchFilings, none otherwise
+            pub fn filter_allowed_tickers(&self, user: &User, tickers: &[String]) -> Vec<String> {
+                if self.has_permission(user, Permission::FetchFilings) {
+                    tickers.to_vec()
+                } else {
+                    Vec::new()
+                }
+            }
+        }
+
+        #[cfg(test)]
+        mod tests {
+            use super::*;
+
+            #[test]
+            fn sanitize_ticker_table() {
+                let cases = [
+                    ("AAPL", "AAPL"),
+                    ("  msft ", "MSFT"),
+                    ("brk.b", "BRK.B"),
+                    ("BF-B", "BF-B"),
+                    ("", ""),
+                    ("   ", ""),
+                    (".AAPL", ""),
+                    ("-AAPL", ""),
+                    ("AAPL;DROP", ""),
+                    ("AA PL", ""),
+                    ("../etc", ""),
+                    ("TOOLONGTICKER", ""),
+                    ("ÄPPL", ""),
+                    ("ıbm", ""),
+                    ("ß", ""),
+                    ("straße", ""),
+                ];
+                for (input, expected) in cases {
+                    assert_eq!(sanitize_ticker(input), expected, "input {:?}", input);
+                }
+            }
+
+            #[test]
+            fn sanitize_ticker_accepts_max_length() {
+                let ticker = "A".repeat(MAX_TICKER_LEN);
+                assert_eq!(sanitize_ticker(&ticker), ticker);
+                assert_eq!(sanitize_ticker(&"A".repeat(MAX_TICKER_LEN + 1)), "");
+            }
+
+            #[test]
+            fn parse_ticker_list_splits_on_separators() {
+                assert_eq!(parse_ticker_list("aapl, msft;goog\nTSLA\tnvda"), vec!["AAPL", "MSFT", "GOOG", "TSLA", "NVDA"]);
+            }
+
+            #[test]
+            fn parse_ticker_list_drops_invalid_and_duplicates() {
+                assert_eq!(parse_ticker_list("AAPL,,aapl; ../x ,MSFT, AAPL"), vec!["AAPL", "MSFT"]);
+                assert!(parse_ticker_list(" ,; \n").is_empty());
+            }
+
+            fn roles_file(name: &str, contents: &str) -> PathBuf {
+                let path = std::env::temp_dir().join(format!("finfiles-roles-{}-{}.toml", name, std::process::id()));
+                fs::write(&path, contents).unwrap();
+                path
+            }
+
+            #[test]
+            fn roles_come_from_the_roles_file() {
+                let path = roles_file("assigned", "alice = \"admin\"\nbob = \"analyst\"\nmallory = \"superuser\"\n");
+                let auth = AuthManager::new().with_roles_path(Some(path.clone()));
+                assert_eq!(auth.role_for("alice"), RBACRole::Admin);
+                assert_eq!(auth.role_for("bob"), RBACRole::Analyst);
+                assert_eq!(auth.role_for("mallory"), RBACRole::Viewer);
+                assert_eq!(auth.role_for("eve"), RBACRole::Viewer);
+                fs::remove_file(path).unwrap();
+            }
+
+            #[test]
+            fn missing_or_invalid_roles_file_gives_viewer() {
+                let auth = AuthManager::new().with_roles_path(None);
+                assert_eq!(auth.role_for("alice"), RBACRole::Viewer);
+                let auth = AuthManager::new().with_roles_path(Some(std::env::temp_dir().join("finfiles-no-such-roles.toml")));
+                assert_eq!(auth.role_for("alice"), RBACRole::Viewer);
+                let path = roles_file("invalid", "alice = [");
+                let auth = AuthManager::new().with_roles_path(Some(path.clone()));
+                assert_eq!(auth.role_for("alice"), RBACRole::Viewer);
+                fs::remove_file(path).unwrap();
+            }
+
+            #[cfg(unix)]
+            #[test]
+            fn world_writable_roles_file_is_ignored() {
+                use std::os::unix::fs::PermissionsExt;
+                let path = roles_file("writable", "alice = \"admin\"\n");
+                fs::set_permissions(&path, fs::Permissions::from_mode(0o666)).unwrap();
+                let auth = AuthManager::new().with_roles_path(Some(path.clone()));
+                assert_eq!(auth.role_for("alice"), RBACRole::Viewer);
+                fs::remove_file(path).unwrap();
+            }
+
+            #[test]
+            fn roles_grant_their_permissions() {
+                let auth = AuthManager::new().with_roles_path(None);
+                let user = |role: RBACRole| User { username: "tester".to_string(), roles: vec![role] };
+                let all = [
+                    Permission::FetchFilings,
+                    Permission::Export,
+                    Permission::UseRemoteLLM,
+                    Permission::UploadModel,
+                    Permission::ViewAuditLog,
+                ];
+                let granted = |role: RBACRole| -> Vec<Permission> {
+                    all.iter().copied().filter(|p| auth.has_permission(&user(role), *p)).collect()
+                };
+                assert_eq!(granted(RBACRole::Admin), all.to_vec());
+                assert_eq!(
+                    granted(RBACRole::User),
+                    vec![Permission::FetchFilings, Permission::Export, Permission::UseRemoteLLM, Permission::UploadModel]
                 );
-                let company_submissions: CompanySubmissions = client.get(&filings_url)
-                    .send().await
-                    .map_err(|e| FinAIError::Network(format!("Failed to fetch company submissions: {e}")))?
-                    .json().await
-                    .map_err(|e| FinAIError::DataParsing(format!("Failed to parse company submissions: {e}")))?;
+                assert_eq!(granted(RBACRole::Analyst), vec![Permission::FetchFilings, Permission::Export]);
+                assert_eq!(granted(RBACRole::Viewer), vec![Permission::FetchFilings]);
+            }
 
-                // Find the latest 10-K or 10-Q
-                let _idx = company_submissions.filings.recent.form.iter().position(|form| form == "10-K" || form == "10-Q")
-                    .ok_or_else(|| FinAIError::SecDataNotFound(ticker.to_string()))?;
+            #[test]
+            fn viewer_is_denied_export() {
+                let auth = AuthManager::new().with_roles_path(None);
+                let viewer = User { username: "tester".to_string(), roles: vec![RBACRole::Viewer] };
+                assert!(!auth.has_permission(&viewer, Permission::Export));
+                let nobody = User { username: "tester".to_string(), roles: Vec::new() };
+                assert!(!auth.has_permission(&nobody, Permission::FetchFilings));
+            }
 
-                let filing_url = format!(
-                    "https://data.sec.gov/api/xbrl/companyfacts/CIK{:0>10}.json",
-                    cik
+            fn tokens_expiring_in(lifetime: Duration) -> AuthTokens {
+                AuthTokens {
+                    access_token: "old".to_string(),
+                    refresh_token: Some("refresh".to_string()),
+                    expires_at: SystemTime::now() + lifetime,
+                }
+            }
+
+            // Token endpoint on a free local port that always issues the access token "new"
+            async fn spawn_token_endpoint() -> OAuthConfig {
+                let app = axum::Router::new().route(
+                    "/token",
+                    axum::routing::post(|| async {
+                        axum::Json(serde_json::json!({ "access_token": "new", "expires_in": 3600 }))
+                    }),
                 );
+                let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+                let addr = listener.local_addr().unwrap();
+                tokio::spawn(async move { axum::serve(listener, app).await });
+                OAuthConfig { token_url: format!("http://{}/token", addr), client_id: "finfiles".to_string(), client_secret: None }
+            }
+
+            #[test]
+            fn only_tokens_near_expiry_need_refresh() {
+                let now = SystemTime::now();
+                let margin = Duration::from_secs(60);
+                assert!(tokens_expiring_in(Duration::from_secs(30)).expires_within(margin, now));
+                assert!(!tokens_expiring_in(Duration::from_secs(3600)).expires_within(margin, now));
+                let expired = AuthTokens { expires_at: now - Duration::from_secs(1), ..tokens_expiring_in(Duration::ZERO) };
+                assert!(expired.expires_within(margin, now));
+            }
+
+            #[tokio::test]
+            async fn token_close_to_expiry_is_refreshed() {
+                let oauth = spawn_token_endpoint().await;
+                let auth = AuthManager::new().with_oauth(Some(oauth)).with_refresh_margin(Duration::from_secs(60));
+                auth.set_tokens(Some(tokens_expiring_in(Duration::from_secs(30))));
+                assert_eq!(auth.refresh_if_needed().await.unwrap().as_deref(), Some("new"));
+                let tokens = auth.tokens().unwrap();
+                assert_eq!(tokens.refresh_token.as_deref(), Some("refresh"));
+                assert!(!auth.needs_refresh(SystemTime::now()));
+            }
+
+            #[tokio::test]
+            async fn fresh_token_is_not_refreshed() {
+                let oauth = spawn_token_endpoint().await;
+                let auth = AuthManager::new().with_oauth(Some(oauth)).with_refresh_margin(Duration::from_secs(60));
+                auth.set_tokens(Some(tokens_expiring_in(Duration::from_secs(3600))));
+                assert_eq!(auth.refresh_if_needed().await.unwrap().as_deref(), Some("old"));
+            }
+
+            #[test]
+            fn requests_over_the_limit_wait_for_the_window() {
+                let auth = AuthManager::new().with_roles_path(None);
+                let viewer = User { username: "tester".to_string(), roles: vec![RBACRole::Viewer] };
+                let start = Instant::now();
+                let limit = viewer.fetch_limit();
+                for i in 0..limit {
+                    auth.check_rate_limit_at(&viewer, start + Duration::from_secs(i as u64)).unwrap();
+                }
+                let rejected = auth.check_rate_limit_at(&viewer, start + Duration::from_secs(limit as u64));
+                match rejected {
+                    Err(FinAIError::RateLimited { retry_after }) => {
+                        assert_eq!(retry_after, Some(RATE_LIMIT_WINDOW - Duration::from_secs(limit as u64)))
+                    }
+                    other => panic!("expected RateLimited, got {:?}", other),
+                }
+                // Once the first request leaves the window there's room for one more
+                assert!(auth.check_rate_limit_at(&viewer, start + RATE_LIMIT_WINDOW).is_ok());
+                assert!(auth.check_rate_limit_at(&viewer, start + RATE_LIMIT_WINDOW).is_err());
+            }
+
+            #[test]
+            fn rate_limits_are_per_user() {
+                let auth = AuthManager::new().with_roles_path(None);
+                let viewer = User { username: "viewer".to_string(), roles: vec![RBACRole::Viewer] };
+                let other = User { username: "other".to_string(), roles: vec![RBACRole::Viewer] };
+                let now = Instant::now();
+                for _ in 0..viewer.fetch_limit() {
+                    auth.check_rate_limit_at(&viewer, now).unwrap();
+                }
+                assert!(auth.check_rate_limit_at(&viewer, now).is_err());
+                assert!(auth.check_rate_limit_at(&other, now).is_ok());
+            }
+        }
+    }
+
+    pub mod audit {
+        use super::error::*;
+        use super::security::User;
+        use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+        use aes_gcm::{Aes256Gcm, Key, Nonce};
+        use chrono::{SecondsFormat, Utc};
+        use serde::{Deserialize, Serialize};
+        use sha2::{Digest, Sha256};
+        use std::fs::{self, File, OpenOptions};
+        use std::io::{Read, Seek, SeekFrom, Write};
+        use std::path::{Path, PathBuf};
+
+        // Secret the audit key is derived from; unset keeps the plaintext log
+        pub const AUDIT_KEY_ENV: &str = "FINFILES_AUDIT_KEY";
+        // When set to "1"/"true", entries use the legacy text layout instead of JSON
+        pub const AUDIT_PLAINTEXT_ENV: &str = "FINFILES_AUDIT_PLAINTEXT";
+        // Hex characters of the response SHA-256 kept in each record
+        const RESPONSE_HASH_LEN: usize = 16;
+
+        // One audit event. Serialized as a single JSON line by default.
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        pub struct AuditRecord {
+            // RFC 3339, UTC
+            pub timestamp: String,
+            pub username: String,
+            // AI backend name, or the app area ("chat", "filings") for non-AI events
+            pub backend: String,
+            pub event: String,
+            pub query: String,
+            // Truncated SHA-256 of the response; the response itself is not logged
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            pub response_hash: Option<String>,
+        }
+
+        impl AuditRecord {
+            pub fn new(username: &str, backend: &str, event: &str, query: &str) -> Self {
+                Self {
+                    timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+                    username: username.to_string(),
+                    backend: backend.to_string(),
+                    event: event.to_string(),
+                    query: query.to_string(),
+                    response_hash: None,
+                }
+            }
+
+            pub fn with_response(mut self, response: &str) -> Self {
+                self.response_hash = Some(response_hash(response));
+                self
+            }
+
+            // The line written to the log: JSON, or the legacy text layout when `plaintext`
+            pub fn to_line(&self, plaintext: bool) -> Result<String> {
+                if plaintext {
+                    let mut line = format!(
+                        "[{}][{}][user:{}] {}: {}",
+                        self.timestamp,
+                        self.backend,
+                        self.username,
+                        self.event,
+                        self.query.replace('\n', " ")
+                    );
+                    if let Some(hash) = &self.response_hash {
+                        line.push_str(&format!(" (response {})", hash));
+                    }
+                    Ok(line)
+                } else {
+                    serde_json::to_string(self)
+                        .map_err(|e| FinAIError::Unknown(format!("Failed to serialize audit record: {e}")))
+                }
+            }
+
+            pub fn from_json_line(line: &str) -> Result<Self> {
+                serde_json::from_str(line.trim())
+                    .map_err(|e| FinAIError::DataParsing(format!("Invalid audit record: {e}")))
+            }
+        }
+
+        pub fn response_hash(response: &str) -> String {
+            let digest = Sha256::digest(response.as_bytes());
+            let hex: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+            hex[..RESPONSE_HASH_LEN].to_string()
+        }
+
+        // Record a main-window event (e.g. a filings fetch) for the given tickers
+        pub fn audit_log(path: &Path, user: &User, event: &str, tickers: &[String]) {
+            let record = AuditRecord::new(&user.username, "filings", event, &tickers.join(","));
+            if let Err(e) = AuditLog::from_env(path.to_path_buf()).record(&record) {
+                log::warn!("Failed to write audit log: {}", e);
+            }
+        }
+        // The log is rotated to "<name>.1" once it grows past this size
+        pub const AUDIT_LOG_MAX_BYTES: u64 = 10 * 1024 * 1024;
+        // Domain separator mixed into the key derivation
+        const KEY_CONTEXT: &[u8] = b"finfiles-audit-log-v1";
+        const NONCE_LEN: usize = 12;
+        const TAG_LEN: usize = 16;
+
+        // Derive the AES-256-GCM key from the configured secret. The secret should be
+        // high-entropy (e.g. 32 random bytes, base64-encoded); it is not stretched.
+        pub fn derive_audit_key(secret: &str) -> [u8; 32] {
+            let mut hasher = Sha256::new();
+            hasher.update(KEY_CONTEXT);
+            hasher.update(secret.as_bytes());
+            hasher.finalize().into()
+        }
+
+        // Associated data chaining a record to the tag of the record before it in the same
+        // file (none for the first), so dropped, reordered or spliced records fail to decrypt
+        fn chain_aad(previous_tag: Option<&[u8]>) -> Vec<u8> {
+            let mut aad = KEY_CONTEXT.to_vec();
+            aad.extend_from_slice(previous_tag.unwrap_or_default());
+            aad
+        }
+
+        // Encrypt one entry as a record: 4-byte big-endian length, then a random nonce
+        // followed by the ciphertext and tag. The length covers nonce and ciphertext.
+        // `previous_tag` is the tag ending the preceding record, None for a file's first record.
+        pub fn encrypt_record(key: &[u8; 32], entry: &str, previous_tag: Option<&[u8]>) -> Result<Vec<u8>> {
+            let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+            let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+            let aad = chain_aad(previous_tag);
+            let ciphertext = cipher
+                .encrypt(&nonce, Payload { msg: entry.as_bytes(), aad: &aad })
+                .map_err(|_| FinAIError::Unknown("Failed to encrypt audit entry".to_string()))?;
+            let len = (NONCE_LEN + ciphertext.len()) as u32;
+            let mut record = Vec::with_capacity(4 + len as usize);
+            record.extend_from_slice(&len.to_be_bytes());
+            record.extend_from_slice(&nonce);
+            record.extend_from_slice(&ciphertext);
+            Ok(record)
+        }
+
+        // Decrypt every record in an encrypted log. A truncated record or one that fails
+        // authentication (wrong key, tampering, or a record missing or moved) is an error
+        // naming the record. Records dropped from the end of the file are not detectable.
+        pub fn decrypt_records(key: &[u8; 32], bytes: &[u8]) -> Result<Vec<String>> {
+            let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+            let mut entries = Vec::new();
+            let mut previous_tag: Option<&[u8]> = None;
+            let mut rest = bytes;
+            while !rest.is_empty() {
+                let index = entries.len() + 1;
+                let truncated = || FinAIError::DataParsing(format!("Audit log record {} is truncated", index));
+                let (len, body) = rest.split_at_checked(4).ok_or_else(truncated)?;
+                let len = u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize;
+                if len < NONCE_LEN + TAG_LEN {
+                    return Err(truncated());
+                }
+                let (record, next) = body.split_at_checked(len).ok_or_else(truncated)?;
+                let (nonce, ciphertext) = record.split_at(NONCE_LEN);
+                let aad = chain_aad(previous_tag);
+                let plaintext = cipher
+                    .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: &aad })
+                    .map_err(|_| {
+                        FinAIError::Auth(format!("Audit log record {} failed authentication (tampered, out of order or wrong key)", index))
+                    })?;
+                entries.push(String::from_utf8_lossy(&plaintext).into_owned());
+                previous_tag = Some(&ciphertext[ciphertext.len() - TAG_LEN..]);
+                rest = next;
+            }
+            Ok(entries)
+        }
+
+        // Append-only audit log, plaintext by default or AES-GCM encrypted per entry
+        #[derive(Clone)]
+        pub struct AuditLog {
+            path: PathBuf,
+            key: Option<[u8; 32]>,
+            max_bytes: u64,
+            plaintext: bool,
+        }
+
+        impl AuditLog {
+            pub fn new(path: PathBuf) -> Self {
+                Self { path, key: None, max_bytes: AUDIT_LOG_MAX_BYTES, plaintext: false }
+            }
+
+            // Encrypt when FINFILES_AUDIT_KEY is set; use text records when FINFILES_AUDIT_PLAINTEXT is
+            pub fn from_env(path: PathBuf) -> Self {
+                let secret = std::env::var(AUDIT_KEY_ENV).ok().filter(|s| !s.trim().is_empty());
+                let plaintext = std::env::var(AUDIT_PLAINTEXT_ENV)
+                    .map(|v| matches!(v.trim().to_lowercase().as_str(), "1" | "true" | "yes"))
+                    .unwrap_or(false);
+                Self::new(path).with_secret(secret.as_deref()).with_plaintext(plaintext)
+            }
+
+            pub fn with_plaintext(mut self, plaintext: bool) -> Self {
+                self.plaintext = plaintext;
+                self
+            }
+
+            // Serialize and append a record in this log's format
+            pub fn record(&self, record: &AuditRecord) -> Result<()> {
+                self.append(&record.to_line(self.plaintext)?)
+            }
+
+            pub fn with_secret(mut self, secret: Option<&str>) -> Self {
+                self.key = secret.map(derive_audit_key);
+                self
+            }
+
+            pub fn with_max_bytes(mut self, max_bytes: u64) -> Self {
+                self.max_bytes = max_bytes;
+                self
+            }
+
+            pub fn path(&self) -> &Path {
+                &self.path
+            }
+
+            pub fn is_encrypted(&self) -> bool {
+                self.key.is_some()
+            }
+
+            // Append one entry, rotating first if the log is over its size limit. Encrypted
+            // records chain to the previous record in the same file; a rotated file starts a new chain.
+            pub fn append(&self, entry: &str) -> Result<()> {
+                self.rotate_if_needed()?;
+                let bytes = match &self.key {
+                    Some(key) => encrypt_record(key, entry, self.last_tag()?.as_deref())?,
+                    None => format!("{}\n", entry.trim_end()).into_bytes(),
+                };
+                let mut file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.path)
+                    .map_err(|e| FinAIError::Unknown(format!("Failed to open {}: {e}", self.path.display())))?;
+                file.write_all(&bytes)
+                    .map_err(|e| FinAIError::Unknown(format!("Failed to write {}: {e}", self.path.display())))
+            }
+
+            // Entries of the current log file, decrypted if needed. A missing file is empty.
+            pub fn read_entries(&self) -> Result<Vec<String>> {
+                let bytes = match fs::read(&self.path) {
+                    Ok(bytes) => bytes,
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+                    Err(e) => return Err(FinAIError::Unknown(format!("Failed to read {}: {e}", self.path.display()))),
+                };
+                match &self.key {
+                    Some(key) => decrypt_records(key, &bytes),
+                    None => Ok(String::from_utf8_lossy(&bytes)
+                        .lines()
+                        .filter(|line| !line.trim().is_empty())
+                        .map(str::to_string)
+                        .collect()),
+                }
+            }
+
+            // Tag ending the last record of the current file, None when the file is empty or missing
+            fn last_tag(&self) -> Result<Option<Vec<u8>>> {
+                let read_error = |e: std::io::Error| FinAIError::Unknown(format!("Failed to read {}: {e}", self.path.display()));
+                let mut file = match File::open(&self.path) {
+                    Ok(file) => file,
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+                    Err(e) => return Err(read_error(e)),
+                };
+                let len = file.metadata().map_err(read_error)?.len();
+                if len == 0 {
+                    return Ok(None);
+                }
+                if len < TAG_LEN as u64 {
+                    return Err(FinAIError::DataParsing(format!("Audit log {} is truncated", self.path.display())));
+                }
+                let mut tag = vec![0u8; TAG_LEN];
+                file.seek(SeekFrom::End(-(TAG_LEN as i64))).map_err(read_error)?;
+                file.read_exact(&mut tag).map_err(read_error)?;
+                Ok(Some(tag))
+            }
+
+            fn rotated_path(&self) -> PathBuf {
+                let mut name = self.path.file_name().map(|n| n.to_os_string()).unwrap_or_default();
+                name.push(".1");
+                self.path.with_file_name(name)
+            }
+
+            fn rotate_if_needed(&self) -> Result<()> {
+                let size = fs::metadata(&self.path).map(|m| m.len()).unwrap_or(0);
+                if size < self.max_bytes {
+                    return Ok(());
+                }
+                let rotated = self.rotated_path();
+                fs::rename(&self.path, &rotated)
+                    .map_err(|e| FinAIError::Unknown(format!("Failed to rotate {}: {e}", self.path.display())))
+            }
+        }
+
+        #[cfg(test)]
+        mod tests {
+            use super::*;
+
+            const KEY: [u8; 32] = [7; 32];
+
+            // Encrypted records for `entries`, each chained to the one before it
+            fn chained_records(entries: &[&str]) -> Vec<Vec<u8>> {
+                let mut records: Vec<Vec<u8>> = Vec::new();
+                for entry in entries {
+                    let previous_tag = records.last().map(|r| r[r.len() - TAG_LEN..].to_vec());
+                    records.push(encrypt_record(&KEY, entry, previous_tag.as_deref()).unwrap());
+                }
+                records
+            }
+
+            #[test]
+            fn encrypted_records_round_trip() {
+                let records = chained_records(&["first", "second", "third"]);
+                assert_eq!(decrypt_records(&KEY, &records.concat()).unwrap(), vec!["first", "second", "third"]);
+                assert!(decrypt_records(&[8; 32], &records.concat()).is_err());
+            }
+
+            #[test]
+            fn flipped_byte_fails_authentication() {
+                let mut bytes = chained_records(&["first", "second"]).concat();
+                let last = bytes.len() - 1;
+                bytes[last] ^= 0x01;
+                assert!(matches!(decrypt_records(&KEY, &bytes), Err(FinAIError::Auth(_))));
+            }
+
+            #[test]
+            fn dropped_record_fails_authentication() {
+                let records = chained_records(&["first", "second", "third"]);
+                let without_middle = [records[0].clone(), records[2].clone()].concat();
+                assert!(matches!(decrypt_records(&KEY, &without_middle), Err(FinAIError::Auth(_))));
+                let without_first = [records[1].clone(), records[2].clone()].concat();
+                assert!(matches!(decrypt_records(&KEY, &without_first), Err(FinAIError::Auth(_))));
+            }
+
+            #[test]
+            fn swapped_records_fail_authentication() {
+                let records = chained_records(&["first", "second", "third"]);
+                let swapped = [records[0].clone(), records[2].clone(), records[1].clone()].concat();
+                assert!(matches!(decrypt_records(&KEY, &swapped), Err(FinAIError::Auth(_))));
+            }
+
+            #[test]
+            fn encrypted_log_chains_appends_in_the_file() {
+                let path = std::env::temp_dir().join(format!("finfiles-audit-chain-{}.log", std::process::id()));
+                let _ = fs::remove_file(&path);
+                let log = AuditLog::new(path.clone()).with_secret(Some("secret"));
+                log.append("first").unwrap();
+                log.append("second").unwrap();
+                assert_eq!(log.read_entries().unwrap(), vec!["first", "second"]);
+                fs::remove_file(path).unwrap();
+            }
+
+            #[test]
+            fn json_line_parses_back_into_the_record() {
+                let record = AuditRecord::new("alice", "finfiles", "analyze", "revenue trend\nfor AAPL").with_response("Revenue grew 4%");
+                let line = record.to_line(false).unwrap();
+                assert!(!line.contains('\n'));
+                let parsed = AuditRecord::from_json_line(&line).unwrap();
+                assert_eq!(parsed, record);
+                assert_eq!(parsed.username, "alice");
+                assert_eq!(parsed.backend, "finfiles");
+                assert_eq!(parsed.event, "analyze");
+                assert_eq!(parsed.query, "revenue trend\nfor AAPL");
+                assert_eq!(parsed.response_hash, Some(response_hash("Revenue grew 4%")));
+                assert_eq!(parsed.response_hash.as_ref().unwrap().len(), RESPONSE_HASH_LEN);
+                assert!(chrono::DateTime::parse_from_rfc3339(&parsed.timestamp).is_ok());
+            }
+
+            #[test]
+            fn records_without_a_response_omit_the_hash() {
+                let line = AuditRecord::new("alice", "filings", "fetch", "AAPL,MSFT").to_line(false).unwrap();
+                assert!(!line.contains("response_hash"));
+                assert_eq!(AuditRecord::from_json_line(&line).unwrap().response_hash, None);
+                assert!(AuditRecord::from_json_line("[2024-01-01][filings] not json").is_err());
+            }
+        }
+    }
+
+    pub mod backend {
+        use super::error::*;
+        use super::ai::{sentiment_summary, AnalysisCache, SharedAnalysisCache};
+        use super::config::AppConfig;
+        use super::data_ingestion::{
+            is_amendment, lookup_cik, search_companies, CikEntry, CompanyMatch, CompanySubmissions, FinancialDataLoader,
+            LoadStage, RecentFilings, RetryPolicy, SecTimeouts, MAX_COMPANY_MATCHES,
+        };
+        use super::filters::FilingFilters;
+        use super::fixtures;
+        use super::security::User;
+        use super::websocket::DEFAULT_WS_URL;
+        use futures::stream::{self, StreamExt};
+        use reqwest::Client;
+        use serde::{Deserialize, Serialize};
+        use std::collections::HashMap;
+        use std::fs;
+        use std::future::Future;
+        use std::path::PathBuf;
+        use polars::prelude::DataFrame;
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::{Arc, Mutex, RwLock};
+        use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+        use tokio::sync::watch;
+        use tokio_util::sync::CancellationToken;
+
+        // A single SEC filing row as shown in the filings table.
+        #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+        pub struct FilingRecord {
+            pub form: String,
+            pub date: String,
+            pub document: String,
+            pub document_url: String,
+            pub company_name: String,
+            pub filing_type: String,
+            pub ai_summary: String,
+            // Ticker the filing was fetched for (or published under, for realtime records)
+            #[serde(default)]
+            pub ticker: String,
+            // Whether this is an amended filing ("10-K/A", "10-Q/A", ...)
+            #[serde(default)]
+            pub amendment: bool,
+        }
+
+        // Builds a FilingRecord with validation: the form is required, and the date and
+        // document URL, when given, must be a YYYY-MM-DD date and an http(s) URL. Other
+        // fields may be left empty.
+        #[derive(Debug, Clone, Default)]
+        pub struct FilingRecordBuilder {
+            record: FilingRecord,
+        }
+
+        impl FilingRecordBuilder {
+            pub fn new(form: impl Into<String>) -> Self {
+                Self { record: FilingRecord { form: form.into(), ..FilingRecord::default() } }
+            }
+
+            pub fn with_date(mut self, date: impl Into<String>) -> Self {
+                self.record.date = date.into();
+                self
+            }
+
+            pub fn with_document(mut self, document: impl Into<String>) -> Self {
+                self.record.document = document.into();
+                self
+            }
+
+            pub fn with_document_url(mut self, document_url: impl Into<String>) -> Self {
+                self.record.document_url = document_url.into();
+                self
+            }
+
+            pub fn with_company_name(mut self, company_name: impl Into<String>) -> Self {
+                self.record.company_name = company_name.into();
+                self
+            }
+
+            pub fn with_filing_type(mut self, filing_type: impl Into<String>) -> Self {
+                self.record.filing_type = filing_type.into();
+                self
+            }
+
+            pub fn with_ai_summary(mut self, ai_summary: impl Into<String>) -> Self {
+                self.record.ai_summary = ai_summary.into();
+                self
+            }
+
+            pub fn with_ticker(mut self, ticker: impl Into<String>) -> Self {
+                self.record.ticker = ticker.into();
+                self
+            }
+
+            // Validate and return the record; the amendment flag follows the form
+            pub fn build(self) -> Result<FilingRecord> {
+                let mut record = self.record;
+                record.form = record.form.trim().to_string();
+                if record.form.is_empty() {
+                    return Err(FinAIError::DataParsing("Filing record has no form type".to_string()));
+                }
+                if !record.date.is_empty() && chrono::NaiveDate::parse_from_str(&record.date, "%Y-%m-%d").is_err() {
+                    return Err(FinAIError::DataParsing(format!(
+                        "Filing {} has an invalid date '{}'",
+                        record.form, record.date
+                    )));
+                }
+                if !record.document_url.is_empty() {
+                    let valid = url::Url::parse(&record.document_url)
+                        .map_or(false, |url| matches!(url.scheme(), "http" | "https") && url.host().is_some());
+                    if !valid {
+                        return Err(FinAIError::DataParsing(format!(
+                            "Filing {} has a malformed document URL '{}'",
+                            record.form, record.document_url
+                        )));
+                    }
+                }
+                record.amendment = is_amendment(&record.form);
+                Ok(record)
+            }
+        }
+
+        impl FilingRecord {
+            pub fn builder(form: impl Into<String>) -> FilingRecordBuilder {
+                FilingRecordBuilder::new(form)
+            }
+
+            // Build filing rows from a company's submissions index
+            pub fn from_submissions(submissions: &CompanySubmissions, cik: &str) -> Vec<FilingRecord> {
+                Self::from_filings(&submissions.filings.recent, &submissions.name, cik)
+            }
+
+            // Build filing rows from one block of filing columns (recent or an older file).
+            // Rows that fail validation are logged and left out.
+            pub fn from_filings(recent: &RecentFilings, company_name: &str, cik: &str) -> Vec<FilingRecord> {
+                let cik_number = cik.trim_start_matches('0');
+                recent
+                    .accession_number
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, accession)| {
+                        let document = recent.primary_document.get(i).cloned().unwrap_or_default();
+                        let built = FilingRecord::builder(recent.form.get(i).cloned().unwrap_or_default())
+                            .with_date(recent.filing_date.get(i).cloned().unwrap_or_default())
+                            .with_document_url(format!(
+                                "https://www.sec.gov/Archives/edgar/data/{}/{}/{}",
+                                cik_number,
+                                accession.replace('-', ""),
+                                document
+                            ))
+                            .with_document(document)
+                            .with_company_name(company_name)
+                            .with_filing_type(recent.primary_doc_description.get(i).cloned().unwrap_or_default())
+                            .build();
+                        match built {
+                            Ok(record) => Some(record.with_sentiment()),
+                            Err(e) => {
+                                log::warn!("Skipping filing {} for CIK {}: {}", accession, cik, e);
+                                None
+                            }
+                        }
+                    })
+                    .collect()
+            }
+
+            // SEC accession number ("0000320193-23-000106") from the document URL's path
+            pub fn accession(&self) -> Option<String> {
+                accession_from_url(&self.document_url)
+            }
+
+            // Fill the AI Analysis column with a sentiment score of the filing's description
+            // (full document text isn't downloaded). Existing summaries are kept.
+            pub fn with_sentiment(mut self) -> Self {
+                if self.ai_summary.is_empty() {
+                    self.ai_summary = sentiment_summary(&self.filing_type);
+                }
+                self
+            }
+        }
+
+        // Result of a multi-ticker fetch: records from the tickers that succeeded, and the
+        // error for each ticker that didn't
+        #[derive(Debug, Default)]
+        pub struct BatchFetch {
+            pub records: Vec<FilingRecord>,
+            pub failures: Vec<(String, FinAIError)>,
+            // Where each successful ticker's next page starts
+            pub cursors: HashMap<String, FilingCursor>,
+        }
+
+        impl BatchFetch {
+            // Combine per-ticker pages; failed tickers are kept apart so the rest still show
+            pub fn from_pages(pages: Vec<(String, Result<FilingPage>)>) -> Self {
+                let mut batch = Self::default();
+                for (ticker, page) in pages {
+                    match page {
+                        Ok(page) => {
+                            batch.records.extend(page.records);
+                            batch.cursors.insert(ticker, page.cursor);
+                        }
+                        Err(e) => batch.failures.push((ticker, e)),
+                    }
+                }
+                batch
+            }
+
+            // One-line summary of the failures for a status label, e.g. "XYZ (Ticker not found: XYZ)"
+            pub fn failure_summary(&self) -> String {
+                self.failures
+                    .iter()
+                    .map(|(ticker, e)| format!("{} ({})", ticker, e))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            }
+
+            // One line per failed ticker with the full error and advice, for the error banner
+            pub fn failure_details(&self) -> String {
+                self.failures
+                    .iter()
+                    .map(|(ticker, e)| match e.hint() {
+                        Some(hint) => format!("{}: {}. {}", ticker, e, hint),
+                        None => format!("{}: {}", ticker, e),
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }
+        }
+
+        // Arguments of a filings fetch, kept so a failed fetch can be retried as it was made
+        #[derive(Debug, Clone, Default)]
+        pub struct FetchRequest {
+            pub tickers: Vec<String>,
+            pub filters: FilingFilters,
+            // Bypass caches (the Refresh action)
+            pub force: bool,
+        }
+
+        // Filings requested per ticker by each fetch and each "Load More" round trip to SEC
+        pub const DEFAULT_FETCH_LIMIT: usize = 100;
+
+        // Position in a ticker's filing history, newest first: how many filings have been
+        // fetched so far and whether SEC has any older ones
+        #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+        pub struct FilingCursor {
+            pub offset: usize,
+            pub exhausted: bool,
+        }
+
+        impl FilingCursor {
+            // The cursor after a page of `fetched` filings taken from this position
+            pub fn advance(self, fetched: usize, has_more: bool) -> Self {
+                Self { offset: self.offset + fetched, exhausted: !has_more }
+            }
+        }
+
+        // One page of a ticker's filings and the cursor for the page after it
+        #[derive(Debug, Clone)]
+        pub struct FilingPage {
+            pub records: Vec<FilingRecord>,
+            pub cursor: FilingCursor,
+        }
+
+        // Filings from `fetched` (in history order) that pass the filters, up to `limit` of
+        // them, and how many of `fetched` were used to find them. A cursor advanced by that
+        // count resumes right after the last match taken, so no match is skipped.
+        pub fn take_matching(fetched: &[FilingRecord], filters: &FilingFilters, limit: usize) -> (Vec<FilingRecord>, usize) {
+            let mut matching = Vec::new();
+            let mut used = 0;
+            for rec in fetched {
+                if matching.len() >= limit {
+                    break;
+                }
+                used += 1;
+                if filters.matches(rec) {
+                    matching.push(rec.clone());
+                }
+            }
+            (matching, used)
+        }
+
+        // Tickers fetched at once by a multi-ticker fetch unless configured otherwise
+        pub const DEFAULT_FETCH_CONCURRENCY: usize = 4;
+        // One ticker's fetch is abandoned after this long so it can't stall the batch
+        pub const TICKER_FETCH_TIMEOUT: Duration = Duration::from_secs(60);
+
+        // Await `work` unless `cancel` fires first; then `work` is dropped unfinished and
+        // None comes back, so nothing it would have produced is applied
+        pub async fn until_cancelled<T>(cancel: &CancellationToken, work: impl Future<Output = T>) -> Option<T> {
+            tokio::select! {
+                result = work => Some(result),
+                _ = cancel.cancelled() => None,
+            }
+        }
+
+        // Run `fetch` for each ticker with at most `concurrency` in flight. Results come
+        // back in input order whatever order they finish in; a ticker that exceeds
+        // TICKER_FETCH_TIMEOUT fails on its own without holding up the others.
+        pub async fn fetch_each<T, F, Fut>(tickers: &[String], concurrency: usize, fetch: F) -> Vec<(String, Result<T>)>
+        where
+            F: Fn(String) -> Fut,
+            Fut: Future<Output = Result<T>>,
+        {
+            let mut results: Vec<(usize, String, Result<T>)> = stream::iter(tickers.iter().cloned().enumerate())
+                .map(|(i, ticker)| {
+                    let fetched = fetch(ticker.clone());
+                    async move {
+                        let result = match tokio::time::timeout(TICKER_FETCH_TIMEOUT, fetched).await {
+                            Ok(result) => result,
+                            Err(_) => Err(FinAIError::Network(format!(
+                                "Timed out fetching {} after {}s",
+                                ticker,
+                                TICKER_FETCH_TIMEOUT.as_secs()
+                            ))),
+                        };
+                        (i, ticker, result)
+                    }
+                })
+                .buffer_unordered(concurrency.max(1))
+                .collect()
+                .await;
+            results.sort_by_key(|(i, _, _)| *i);
+            results.into_iter().map(|(_, ticker, result)| (ticker, result)).collect()
+        }
+
+        // A CIK's filings downloaded so far, plus the older submissions files not yet fetched
+        #[derive(Debug, Clone, Default)]
+        struct CikFilings {
+            company_name: String,
+            records: Vec<FilingRecord>,
+            older_files: Vec<String>,
+        }
+
+        // Client for public SEC EDGAR filing indexes. The ticker map and each CIK's
+        // filings are kept in memory for DEFAULT_CACHE_TTL, so sibling tickers that share
+        // a CIK (share classes) reuse one download.
+        pub struct SecEdgarApi {
+            client: Client,
+            retry: RetryPolicy,
+            timeouts: SecTimeouts,
+            cik_map: Mutex<Option<(Instant, Arc<HashMap<String, CikEntry>>)>>,
+            filings_by_cik: Mutex<HashMap<String, (Instant, CikFilings)>>,
+            concurrency: usize,
+        }
+
+        impl SecEdgarApi {
+            pub fn new() -> Result<Self> {
+                let timeouts = AppConfig::current().sec_timeouts();
+                Ok(Self {
+                    client: FinancialDataLoader::sec_client_with(timeouts)?,
+                    retry: RetryPolicy::default(),
+                    timeouts,
+                    cik_map: Mutex::new(None),
+                    filings_by_cik: Mutex::new(HashMap::new()),
+                    concurrency: DEFAULT_FETCH_CONCURRENCY,
+                })
+            }
+
+            // How many tickers a multi-ticker fetch requests at once. Zero is clamped to 1.
+            pub fn set_concurrency(&mut self, concurrency: usize) {
+                self.concurrency = concurrency.max(1);
+            }
+
+            pub fn concurrency(&self) -> usize {
+                self.concurrency
+            }
+
+            // Forget the in-memory ticker map and filings so the next request goes to SEC
+            pub fn clear_caches(&self) {
+                *self.cik_map.lock().unwrap() = None;
+                self.filings_by_cik.lock().unwrap().clear();
+            }
+
+            pub fn set_retry_policy(&mut self, retry: RetryPolicy) {
+                self.retry = retry;
+            }
+
+            pub fn retry_policy(&self) -> RetryPolicy {
+                self.retry
+            }
+
+            // Rebuilds the client, since the connect timeout is fixed per client
+            pub fn set_timeouts(&mut self, timeouts: SecTimeouts) -> Result<()> {
+                self.client = FinancialDataLoader::sec_client_with(timeouts)?;
+                self.timeouts = timeouts;
+                Ok(())
+            }
+
+            pub fn timeouts(&self) -> SecTimeouts {
+                self.timeouts
+            }
+
+            async fn cik_map(&self) -> Result<Arc<HashMap<String, CikEntry>>> {
+                if let Some((fetched_at, map)) = self.cik_map.lock().unwrap().as_ref() {
+                    if fetched_at.elapsed() < DEFAULT_CACHE_TTL {
+                        return Ok(map.clone());
+                    }
+                }
+                let map = Arc::new(FinancialDataLoader::fetch_cik_map(&self.client, self.retry, self.timeouts.cik_map).await?);
+                *self.cik_map.lock().unwrap() = Some((Instant::now(), map.clone()));
+                Ok(map)
+            }
+
+            // Filings for a CIK, without a ticker set, downloaded at most once per TTL
+            async fn filings_for_cik(&self, cik: &str) -> Result<CikFilings> {
+                if let Some((fetched_at, filings)) = self.filings_by_cik.lock().unwrap().get(cik) {
+                    if fetched_at.elapsed() < DEFAULT_CACHE_TTL {
+                        return Ok(filings.clone());
+                    }
+                }
+                let submissions = FinancialDataLoader::fetch_submissions(&self.client, cik, self.retry, self.timeouts.submissions).await?;
+                let filings = CikFilings {
+                    records: FilingRecord::from_submissions(&submissions, cik),
+                    company_name: submissions.name.clone(),
+                    older_files: submissions.filings.files.iter().map(|file| file.name.clone()).collect(),
+                };
+                self.filings_by_cik
+                    .lock()
+                    .unwrap()
+                    .insert(cik.to_string(), (Instant::now(), filings.clone()));
+                Ok(filings)
+            }
+
+            // Fetch recent filings for a single ticker
+            pub async fn fetch_filings(&self, ticker: &str) -> Result<Vec<FilingRecord>> {
+                let cik = lookup_cik(&*self.cik_map().await?, ticker)?;
+                let mut records = self.filings_for_cik(&cik).await?.records;
+                for rec in &mut records {
+                    rec.ticker = ticker.to_uppercase();
+                }
+                Ok(records)
+            }
+
+            // Companies whose name contains the query, from the cached ticker map
+            pub async fn search_companies(&self, name: &str) -> Result<Vec<CompanyMatch>> {
+                Ok(search_companies(&*self.cik_map().await?, name, MAX_COMPANY_MATCHES))
+            }
+
+            // Up to `limit` filings for a ticker starting at the cursor. Older submissions
+            // files are downloaded only once the cursor reaches past what's already held.
+            pub async fn fetch_filings_page(&self, ticker: &str, cursor: FilingCursor, limit: usize) -> Result<FilingPage> {
+                let cik = lookup_cik(&*self.cik_map().await?, ticker)?;
+                let mut filings = self.filings_for_cik(&cik).await?;
+                let wanted = cursor.offset + limit;
+                while filings.records.len() < wanted && !filings.older_files.is_empty() {
+                    let name = filings.older_files.remove(0);
+                    let older = FinancialDataLoader::fetch_submissions_file(&self.client, &name, self.retry, self.timeouts.submissions).await?;
+                    filings.records.extend(FilingRecord::from_filings(&older, &filings.company_name, &cik));
+                    self.filings_by_cik
+                        .lock()
+                        .unwrap()
+                        .insert(cik.clone(), (Instant::now(), filings.clone()));
+                }
+                let end = wanted.min(filings.records.len());
+                let start = cursor.offset.min(end);
+                let mut records = filings.records[start..end].to_vec();
+                for rec in &mut records {
+                    rec.ticker = ticker.to_uppercase();
+                }
+                let has_more = end < filings.records.len() || !filings.older_files.is_empty();
+                Ok(FilingPage { cursor: cursor.advance(records.len(), has_more), records })
+            }
+
+            // Up to `limit` filings passing the filters, starting at the cursor. Pages are
+            // fetched until enough match or SEC has nothing older, so pages the filters empty
+            // don't end the listing, and the cursor stops after the last filing used.
+            pub async fn fetch_matching_page(
+                &self,
+                ticker: &str,
+                mut cursor: FilingCursor,
+                filters: &FilingFilters,
+                limit: usize,
+            ) -> Result<FilingPage> {
+                let mut records = Vec::new();
+                let page_size = limit.max(DEFAULT_FETCH_LIMIT);
+                while records.len() < limit && !cursor.exhausted {
+                    let page = self.fetch_filings_page(ticker, cursor, page_size).await?;
+                    let (matching, used) = take_matching(&page.records, filters, limit - records.len());
+                    records.extend(matching);
+                    cursor = if used == page.records.len() { page.cursor } else { cursor.advance(used, true) };
+                    if page.records.is_empty() {
+                        break;
+                    }
+                }
+                Ok(FilingPage { records, cursor })
+            }
+
+            // Fetch a page of filings passing the filters at each ticker's cursor (the first
+            // page for tickers without one). Exhausted tickers are skipped. A failing ticker
+            // is recorded and the rest of the batch still runs.
+            pub async fn fetch_multiple_filings(
+                &self,
+                tickers: Vec<String>,
+                filters: FilingFilters,
+                cursors: &HashMap<String, FilingCursor>,
+                limit: usize,
+            ) -> BatchFetch {
+                let cursor = |ticker: &str| cursors.get(ticker).copied().unwrap_or_default();
+                let pending: Vec<String> = tickers.into_iter().filter(|t| !cursor(t).exhausted).collect();
+                let filters = &filters;
+                let pages = fetch_each(&pending, self.concurrency, |ticker| async move {
+                    self.fetch_matching_page(&ticker, cursor(&ticker), filters, limit).await
+                })
+                .await;
+                BatchFetch::from_pages(pages)
+            }
+        }
+
+        // How long cached filings stay fresh by default
+        pub const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+
+        #[derive(Debug, Serialize, Deserialize)]
+        struct CachedFilings {
+            // Seconds since the Unix epoch
+            fetched_at: u64,
+            records: Vec<FilingRecord>,
+        }
+
+        // On-disk cache of fetched filings, one JSON file per ticker.
+        // Callers pass the current time so expiry can be checked against any clock.
+        pub struct FilingsCache {
+            dir: PathBuf,
+            ttl: Duration,
+        }
+
+        impl FilingsCache {
+            pub fn new(dir: PathBuf, ttl: Duration) -> Self {
+                Self { dir, ttl }
+            }
+
+            // Cache under <user cache dir>/finfiles/filings, or disabled if there is none
+            pub fn in_user_cache_dir(ttl: Duration) -> Option<Self> {
+                dirs::cache_dir().map(|dir| Self::new(dir.join("finfiles").join("filings"), ttl))
+            }
+
+            fn path_for(&self, ticker: &str) -> PathBuf {
+                let name: String = ticker
+                    .trim()
+                    .to_uppercase()
+                    .chars()
+                    .filter(|c| c.is_ascii_alphanumeric() || *c == '.' || *c == '-')
+                    .collect();
+                self.dir.join(format!("{}.json", name))
+            }
+
+            fn secs(time: SystemTime) -> u64 {
+                time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+            }
+
+            // Fresh cached filings for a ticker. Missing, expired, or corrupt entries are a miss.
+            pub fn get(&self, ticker: &str, now: SystemTime) -> Option<Vec<FilingRecord>> {
+                let path = self.path_for(ticker);
+                let json = fs::read_to_string(&path).ok()?;
+                let cached: CachedFilings = match serde_json::from_str(&json) {
+                    Ok(cached) => cached,
+                    Err(e) => {
+                        log::warn!("Ignoring corrupt filings cache {}: {}", path.display(), e);
+                        return None;
+                    }
+                };
+                let age = Self::secs(now).saturating_sub(cached.fetched_at);
+                if age >= self.ttl.as_secs() {
+                    return None;
+                }
+                Some(cached.records)
+            }
+
+            pub fn put(&self, ticker: &str, records: &[FilingRecord], now: SystemTime) -> Result<()> {
+                fs::create_dir_all(&self.dir)
+                    .map_err(|e| FinAIError::Unknown(format!("Failed to create {}: {e}", self.dir.display())))?;
+                let cached = CachedFilings { fetched_at: Self::secs(now), records: records.to_vec() };
+                let json = serde_json::to_string(&cached)
+                    .map_err(|e| FinAIError::Unknown(format!("Failed to serialize filings cache: {e}")))?;
+                let path = self.path_for(ticker);
+                fs::write(&path, json)
+                    .map_err(|e| FinAIError::Unknown(format!("Failed to write {}: {e}", path.display())))
+            }
+        }
+
+        // Default number of filings revealed per "Load More" page
+        pub const DEFAULT_PAGE_SIZE: usize = 25;
+
+        // Fetch triggers this soon after an accepted one are ignored
+        pub const FETCH_DEBOUNCE: Duration = Duration::from_millis(500);
+
+        // DataFrame shared between the main window and the AI chat
+        pub type SharedFrame = Arc<RwLock<DataFrame>>;
+
+        // Each loaded ticker's financials in fetch order, for cross-ticker questions
+        pub type SharedTickerFrames = Arc<RwLock<Vec<(String, DataFrame)>>>;
+
+        // The financials the AI works on: none yet, being loaded, loaded, or failed to
+        // load. The chat, analyze button, chart and export all render from this.
+        #[derive(Debug, Clone, Default)]
+        pub enum DataState {
+            #[default]
+            Empty,
+            Loading,
+            Loaded(DataFrame),
+            Error(String),
+        }
+
+        impl DataState {
+            pub fn frame(&self) -> Option<&DataFrame> {
+                match self {
+                    DataState::Loaded(df) => Some(df),
+                    _ => None,
+                }
+            }
+
+            // Export needs data that loaded (or is loading); not in Empty or Error
+            pub fn allows_export(&self) -> bool {
+                matches!(self, DataState::Loading | DataState::Loaded(_))
+            }
+
+            // What to show in place of results; None once data is loaded
+            pub fn message(&self) -> Option<String> {
+                match self {
+                    DataState::Empty => Some("No financial data loaded. Fetch a ticker to load it.".to_string()),
+                    DataState::Loading => Some("Loading financial data...".to_string()),
+                    DataState::Loaded(_) => None,
+                    DataState::Error(e) => Some(format!("Financial data failed to load: {}", e)),
+                }
+            }
+        }
+
+        // Accession number from an EDGAR archive URL (.../edgar/data/<cik>/<18 digits>/<doc>),
+        // formatted with its dashes
+        pub fn accession_from_url(url: &str) -> Option<String> {
+            let digits = url
+                .split('/')
+                .skip_while(|segment| *segment != "data")
+                .nth(2)
+                .filter(|segment| segment.len() == 18 && segment.chars().all(|c| c.is_ascii_digit()))?;
+            Some(format!("{}-{}-{}", &digits[..10], &digits[10..12], &digits[12..]))
+        }
+
+        // A filing flagged for follow-up, persisted with the other bookmarks as JSON
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        pub struct Bookmark {
+            #[serde(flatten)]
+            pub record: FilingRecord,
+            #[serde(default)]
+            pub accession: Option<String>,
+            // When it was bookmarked (RFC 3339, UTC)
+            #[serde(default)]
+            pub added: String,
+        }
+
+        impl Bookmark {
+            pub fn new(record: FilingRecord) -> Self {
+                Self {
+                    accession: record.accession(),
+                    record,
+                    added: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+                }
+            }
+        }
+
+        // Filings held for the table and how many of them have been handed out so far
+        #[derive(Debug, Default)]
+        struct HeldFilings {
+            records: Vec<FilingRecord>,
+            shown: usize,
+        }
+
+        // Shared application state for the main window and background tasks. Called from
+        // async fetch tasks and UI handlers alike; every field behind a lock is locked
+        // briefly and on its own.
+        pub struct AppState {
+            pub user: User,
+            pub api: SecEdgarApi,
+            // Realtime feed endpoint; the built-in default is used when unset
+            pub ws_url: Option<String>,
+            // Tickers of the most recent fetch; realtime updates are limited to these
+            active_tickers: Mutex<Vec<String>>,
+            cache: Option<FilingsCache>,
+            // Saved tickers, persisted as JSON at `watchlist_path`
+            watchlist: Mutex<Vec<String>>,
+            watchlist_path: Option<PathBuf>,
+            // Bookmarked filings, persisted as JSON at `bookmarks_path`
+            bookmarks: Mutex<Vec<Bookmark>>,
+            bookmarks_path: Option<PathBuf>,
+            // Fetched filings and the "Load More" position under one lock, so readers always
+            // see a consistent pair. Never held across an `.await` or while taking another
+            // of AppState's locks.
+            filings: Mutex<HeldFilings>,
+            page_size: Mutex<usize>,
+            // Per-ticker position in SEC's filing history for "Load More"
+            cursors: Mutex<HashMap<String, FilingCursor>>,
+            fetch_limit: usize,
+            // Serve the bundled demo data instead of calling SEC
+            offline: bool,
+            // Financial metrics the AI analyzes; mirrors the frame of `data_state`
+            ai_data: SharedFrame,
+            // Where the AI's financials stand; subscribers see every transition
+            data_state: watch::Sender<DataState>,
+            // Per-ticker financials of the active tickers, kept apart rather than merged
+            ticker_frames: SharedTickerFrames,
+            // Chat responses for unchanged data; cleared by refresh_filings
+            analysis_cache: SharedAnalysisCache,
+            // Set while a filings fetch is running; see begin_fetch/end_fetch
+            fetching: AtomicBool,
+            last_fetch_started: Mutex<Option<Instant>>,
+            // Cancels the in-flight fetch; replaced by each accepted begin_fetch
+            fetch_cancel: Mutex<CancellationToken>,
+            // The most recent fetch started, for Retry
+            last_fetch: Mutex<Option<FetchRequest>>,
+        }
+
+        impl AppState {
+            // Fails when the SEC EDGAR client can't be built
+            pub fn new(user: User) -> Result<Self> {
+                Ok(Self {
+                    user,
+                    api: SecEdgarApi::new()?,
+                    ws_url: None,
+                    active_tickers: Mutex::new(Vec::new()),
+                    cache: FilingsCache::in_user_cache_dir(DEFAULT_CACHE_TTL),
+                    watchlist: Mutex::new(Vec::new()),
+                    watchlist_path: None,
+                    bookmarks: Mutex::new(Vec::new()),
+                    bookmarks_path: None,
+                    filings: Mutex::new(HeldFilings::default()),
+                    page_size: Mutex::new(DEFAULT_PAGE_SIZE),
+                    cursors: Mutex::new(HashMap::new()),
+                    fetch_limit: DEFAULT_FETCH_LIMIT,
+                    offline: false,
+                    ai_data: Arc::new(RwLock::new(DataFrame::default())),
+                    data_state: watch::channel(DataState::Empty).0,
+                    ticker_frames: Arc::new(RwLock::new(Vec::new())),
+                    analysis_cache: Arc::new(Mutex::new(AnalysisCache::default())),
+                    fetching: AtomicBool::new(false),
+                    last_fetch_started: Mutex::new(None),
+                    fetch_cancel: Mutex::new(CancellationToken::new()),
+                    last_fetch: Mutex::new(None),
+                }
+                .with_watchlist_path(dirs::config_dir().map(|dir| dir.join("finfiles").join("watchlist.json")))
+                .with_bookmarks_path(dirs::config_dir().map(|dir| dir.join("finfiles").join("bookmarks.json"))))
+            }
+
+            // Use (and load) the watchlist stored at the given path
+            pub fn with_watchlist_path(mut self, path: Option<PathBuf>) -> Self {
+                let saved: Vec<String> = path
+                    .as_ref()
+                    .and_then(|p| fs::read_to_string(p).ok())
+                    .and_then(|json| serde_json::from_str(&json).ok())
+                    .unwrap_or_default();
+                self.watchlist_path = path;
+                *self.watchlist.lock().unwrap() = Vec::new();
+                for ticker in saved {
+                    self.insert_watchlist_entry(&ticker);
+                }
+                self
+            }
+
+            pub fn watchlist(&self) -> Vec<String> {
+                self.watchlist.lock().unwrap().clone()
+            }
+
+            // Add a ticker (uppercased, case-insensitive de-duplication). Returns false if already present.
+            pub fn add_to_watchlist(&self, ticker: &str) -> bool {
+                let added = self.insert_watchlist_entry(ticker);
+                if added {
+                    self.save_watchlist();
+                }
+                added
+            }
+
+            // Remove a ticker (case-insensitive). Returns false if it wasn't present.
+            pub fn remove_from_watchlist(&self, ticker: &str) -> bool {
+                let removed = {
+                    let mut watchlist = self.watchlist.lock().unwrap();
+                    let before = watchlist.len();
+                    watchlist.retain(|t| !t.eq_ignore_ascii_case(ticker.trim()));
+                    watchlist.len() != before
+                };
+                if removed {
+                    self.save_watchlist();
+                }
+                removed
+            }
+
+            fn insert_watchlist_entry(&self, ticker: &str) -> bool {
+                let ticker = ticker.trim().to_uppercase();
+                let mut watchlist = self.watchlist.lock().unwrap();
+                if ticker.is_empty() || watchlist.contains(&ticker) {
+                    return false;
+                }
+                watchlist.push(ticker);
+                true
+            }
+
+            fn save_watchlist(&self) {
+                let path = match &self.watchlist_path {
+                    Some(path) => path,
+                    None => return,
+                };
+                let json = match serde_json::to_string_pretty(&self.watchlist()) {
+                    Ok(json) => json,
+                    Err(e) => {
+                        log::error!("Failed to serialize watchlist: {}", e);
+                        return;
+                    }
+                };
+                if let Some(dir) = path.parent() {
+                    let _ = fs::create_dir_all(dir);
+                }
+                if let Err(e) = fs::write(path, json) {
+                    log::error!("Failed to save watchlist to {}: {}", path.display(), e);
+                }
+            }
+
+            // Use (and load) the bookmarks stored at the given path
+            pub fn with_bookmarks_path(mut self, path: Option<PathBuf>) -> Self {
+                let saved: Vec<Bookmark> = path
+                    .as_ref()
+                    .and_then(|p| fs::read_to_string(p).ok())
+                    .and_then(|json| serde_json::from_str(&json).ok())
+                    .unwrap_or_default();
+                self.bookmarks_path = path;
+                *self.bookmarks.lock().unwrap() = Vec::new();
+                for bookmark in saved {
+                    self.insert_bookmark(bookmark);
+                }
+                self
+            }
+
+            // Bookmarks across all tickers, oldest first
+            pub fn bookmarks(&self) -> Vec<Bookmark> {
+                self.bookmarks.lock().unwrap().clone()
+            }
+
+            pub fn is_bookmarked(&self, document_url: &str) -> bool {
+                self.bookmarks.lock().unwrap().iter().any(|b| b.record.document_url == document_url)
+            }
+
+            // Bookmark a filing, identified by its document URL. Returns false if it is
+            // already bookmarked or has no URL.
+            pub fn add_bookmark(&self, record: FilingRecord) -> bool {
+                let added = self.insert_bookmark(Bookmark::new(record));
+                if added {
+                    self.save_bookmarks();
+                }
+                added
+            }
+
+            // Returns false if the filing wasn't bookmarked
+            pub fn remove_bookmark(&self, document_url: &str) -> bool {
+                let removed = {
+                    let mut bookmarks = self.bookmarks.lock().unwrap();
+                    let before = bookmarks.len();
+                    bookmarks.retain(|b| b.record.document_url != document_url);
+                    bookmarks.len() != before
+                };
+                if removed {
+                    self.save_bookmarks();
+                }
+                removed
+            }
+
+            fn insert_bookmark(&self, bookmark: Bookmark) -> bool {
+                let mut bookmarks = self.bookmarks.lock().unwrap();
+                let url = bookmark.record.document_url.trim();
+                if url.is_empty() || bookmarks.iter().any(|b| b.record.document_url.trim() == url) {
+                    return false;
+                }
+                bookmarks.push(bookmark);
+                true
+            }
+
+            fn save_bookmarks(&self) {
+                let path = match &self.bookmarks_path {
+                    Some(path) => path,
+                    None => return,
+                };
+                let json = match serde_json::to_string_pretty(&self.bookmarks()) {
+                    Ok(json) => json,
+                    Err(e) => {
+                        log::error!("Failed to serialize bookmarks: {}", e);
+                        return;
+                    }
+                };
+                if let Some(dir) = path.parent() {
+                    let _ = fs::create_dir_all(dir);
+                }
+                if let Err(e) = fs::write(path, json) {
+                    log::error!("Failed to save bookmarks to {}: {}", path.display(), e);
+                }
+            }
+
+            pub fn with_ws_url(mut self, ws_url: Option<String>) -> Self {
+                self.ws_url = ws_url.filter(|url| !url.trim().is_empty());
+                self
+            }
+
+            pub fn with_cache(mut self, cache: Option<FilingsCache>) -> Self {
+                self.cache = cache;
+                self
+            }
+
+            pub fn with_offline(mut self, offline: bool) -> Self {
+                self.offline = offline;
+                self
+            }
+
+            pub fn is_offline(&self) -> bool {
+                self.offline
+            }
+
+            // Financials for the AI and charts: the bundled sample when offline, else SEC's.
+            // `progress` sees each stage of an SEC load. The frame is also kept as the
+            // ticker's entry in `ticker_frames`.
+            pub async fn load_financials<F: FnMut(LoadStage)>(&self, ticker: &str, progress: Option<F>) -> Result<DataFrame> {
+                let df = if self.offline {
+                    fixtures::sample_dataframe()?
+                } else {
+                    FinancialDataLoader::load_sec_data_with_timeouts(ticker, self.api.timeouts(), progress).await?
+                };
+                self.set_ticker_frame(ticker, df.clone());
+                Ok(df)
+            }
+
+            // Shared handle to the per-ticker financials; readers see each update
+            pub fn ticker_frames(&self) -> SharedTickerFrames {
+                self.ticker_frames.clone()
+            }
+
+            // Replace (or add) a ticker's financials
+            pub fn set_ticker_frame(&self, ticker: &str, df: DataFrame) {
+                let ticker = ticker.to_uppercase();
+                let mut frames = self.ticker_frames.write().unwrap();
+                match frames.iter_mut().find(|(t, _)| *t == ticker) {
+                    Some(entry) => entry.1 = df,
+                    None => frames.push((ticker, df)),
+                }
+            }
+
+            // How many tickers are fetched at once. Zero is clamped to 1.
+            pub fn with_fetch_concurrency(mut self, concurrency: usize) -> Self {
+                self.api.set_concurrency(concurrency);
+                self
+            }
+
+            // Filings requested from SEC per ticker on each fetch. Zero is clamped to 1.
+            pub fn with_fetch_limit(mut self, fetch_limit: usize) -> Self {
+                self.fetch_limit = fetch_limit.max(1);
+                self
+            }
+
+            // Shared handle to the AI's DataFrame; readers see each `set_data_state`
+            pub fn ai_data(&self) -> SharedFrame {
+                self.ai_data.clone()
+            }
+
+            pub fn data_state(&self) -> DataState {
+                self.data_state.borrow().clone()
+            }
+
+            // Receiver that wakes on each data state transition
+            pub fn subscribe_data_state(&self) -> watch::Receiver<DataState> {
+                self.data_state.subscribe()
+            }
+
+            // Move to a new data state; the shared frame holds the loaded data, else nothing
+            pub fn set_data_state(&self, state: DataState) {
+                *self.ai_data.write().unwrap() = state.frame().cloned().unwrap_or_default();
+                self.data_state.send_replace(state);
+            }
+
+            pub fn analysis_cache(&self) -> SharedAnalysisCache {
+                self.analysis_cache.clone()
+            }
+
+            // Claim the single fetch slot. Returns false (and the caller should do nothing)
+            // while another fetch is in flight or within FETCH_DEBOUNCE of the last accepted
+            // one. Every accepted call must be paired with end_fetch.
+            pub fn begin_fetch(&self) -> bool {
+                let now = Instant::now();
+                let mut last = self.last_fetch_started.lock().unwrap();
+                if last.map_or(false, |t| now.saturating_duration_since(t) < FETCH_DEBOUNCE) {
+                    return false;
+                }
+                if self.fetching.compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst).is_err() {
+                    return false;
+                }
+                *last = Some(now);
+                *self.fetch_cancel.lock().unwrap() = CancellationToken::new();
+                true
+            }
+
+            pub fn end_fetch(&self) {
+                self.fetching.store(false, Ordering::SeqCst);
+            }
+
+            // Record the arguments of a fetch that's starting, so it can be retried
+            pub fn remember_fetch(&self, request: FetchRequest) {
+                *self.last_fetch.lock().unwrap() = Some(request);
+            }
+
+            pub fn last_fetch(&self) -> Option<FetchRequest> {
+                self.last_fetch.lock().unwrap().clone()
+            }
+
+            // Token for the current fetch; it fires when cancel_fetch is called
+            pub fn fetch_cancel_token(&self) -> CancellationToken {
+                self.fetch_cancel.lock().unwrap().clone()
+            }
+
+            // Cancel the in-flight fetch. Returns false if nothing was running.
+            pub fn cancel_fetch(&self) -> bool {
+                if !self.is_fetching() {
+                    return false;
+                }
+                self.fetch_cancel.lock().unwrap().cancel();
+                true
+            }
+
+            pub fn is_fetching(&self) -> bool {
+                self.fetching.load(Ordering::SeqCst)
+            }
+
+            // Retry behaviour for SEC requests made on this state's behalf
+            pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+                self.api.set_retry_policy(retry);
+                self
+            }
+
+            pub fn retry_policy(&self) -> RetryPolicy {
+                self.api.retry_policy()
+            }
+
+            // Connect and per-endpoint timeouts for SEC requests made on this state's behalf.
+            // If the client can't be rebuilt the previous timeouts stay in effect.
+            pub fn with_sec_timeouts(mut self, timeouts: SecTimeouts) -> Self {
+                if let Err(e) = self.api.set_timeouts(timeouts) {
+                    log::warn!("Keeping previous SEC timeouts: {}", e);
+                }
+                self
+            }
+
+            pub fn sec_timeouts(&self) -> SecTimeouts {
+                self.api.timeouts()
+            }
+
+            // Fetch the first page of filings for several tickers (up to the API's
+            // concurrency at once, results in ticker order), serving fresh cached tickers
+            // without any network access, and restart their "Load More" cursors.
+            // Unfiltered results are cached so changing filters reuses them. Tickers that
+            // fail are reported in the result without stopping the others.
+            pub async fn fetch_multiple_filings(&self, tickers: Vec<String>, filters: FilingFilters) -> BatchFetch {
+                self.fetch_first_pages(tickers, filters, true).await
+            }
+
+            // Like fetch_multiple_filings, but bypassing every cache: the disk cache is not
+            // read (fresh results still replace it), the in-memory ticker map and filings are
+            // dropped, and cached analysis responses are cleared
+            pub async fn refresh_filings(&self, tickers: Vec<String>, filters: FilingFilters) -> BatchFetch {
+                self.api.clear_caches();
+                let cleared = self.analysis_cache.lock().unwrap().clear();
+                log::info!("Forced refresh: cleared {} cached analysis responses", cleared);
+                self.fetch_first_pages(tickers, filters, false).await
+            }
+
+            async fn fetch_first_pages(&self, tickers: Vec<String>, filters: FilingFilters, use_cache: bool) -> BatchFetch {
+                if self.offline {
+                    // Demo data stands in for whatever was requested; there are no more pages
+                    self.cursors.lock().unwrap().clear();
+                    return BatchFetch { records: filters.apply(fixtures::sample_filings()), ..BatchFetch::default() };
+                }
+                let filters = &filters;
+                let pages = fetch_each(&tickers, self.api.concurrency(), |ticker| async move {
+                    // The first page is cached unfiltered; when the filters leave it short,
+                    // older filings are fetched until the page is full
+                    let first = self.fetch_first_page(&ticker, use_cache).await?;
+                    let records = filters.apply(first.records);
+                    if records.len() >= self.fetch_limit || first.cursor.exhausted {
+                        return Ok(FilingPage { records, cursor: first.cursor });
+                    }
+                    let rest = self.api.fetch_matching_page(&ticker, first.cursor, filters, self.fetch_limit - records.len()).await?;
+                    Ok(FilingPage { records: records.into_iter().chain(rest.records).collect(), cursor: rest.cursor })
+                })
+                .await;
+                let batch = BatchFetch::from_pages(pages);
+                *self.cursors.lock().unwrap() = batch.cursors.clone();
+                batch
+            }
+
+            // The disk-cached first page of a ticker's filings, if fresh and `use_cache` allows
+            fn cached_first_page(&self, ticker: &str, use_cache: bool, now: SystemTime) -> Option<FilingPage> {
+                let cached = self.cache.as_ref().filter(|_| use_cache).and_then(|c| c.get(ticker, now))?;
+                Some(FilingPage { cursor: FilingCursor::default().advance(cached.len(), true), records: cached })
+            }
+
+            // First page of a ticker's filings, from the disk cache when fresh and allowed
+            async fn fetch_first_page(&self, ticker: &str, use_cache: bool) -> Result<FilingPage> {
+                let now = SystemTime::now();
+                if let Some(page) = self.cached_first_page(ticker, use_cache, now) {
+                    log::info!("Using cached filings for {}", ticker);
+                    return Ok(page);
+                }
+                let page = self.api.fetch_filings_page(ticker, FilingCursor::default(), self.fetch_limit).await?;
+                if let Some(cache) = &self.cache {
+                    if let Err(e) = cache.put(ticker, &page.records, now) {
+                        log::warn!("Failed to cache filings for {}: {}", ticker, e);
+                    }
+                }
+                Ok(page)
+            }
+
+            // Fetch the next page from SEC for every active ticker that has older filings,
+            // append it to the held filings and advance the cursors
+            pub async fn fetch_next_filings(&self, filters: FilingFilters) -> BatchFetch {
+                let cursors = self.cursors.lock().unwrap().clone();
+                let batch = self.api.fetch_multiple_filings(self.active_tickers(), filters, &cursors, self.fetch_limit).await;
+                self.cursors.lock().unwrap().extend(batch.cursors.clone());
+                let mut held = self.filings.lock().unwrap();
+                held.records.extend(batch.records.iter().cloned());
+                held.shown = held.records.len();
+                drop(held);
+                batch
+            }
+
+            pub fn cursor(&self, ticker: &str) -> FilingCursor {
+                self.cursors.lock().unwrap().get(ticker).copied().unwrap_or_default()
+            }
+
+            // Whether SEC has filings for the active tickers beyond those fetched so far
+            pub fn has_more_remote_filings(&self) -> bool {
+                let tickers = self.active_tickers();
+                let cursors = self.cursors.lock().unwrap();
+                tickers.iter().any(|t| cursors.get(t).map_or(false, |c| !c.exhausted))
+            }
+
+            // Endpoint the realtime feed should connect to
+            pub fn realtime_url(&self) -> &str {
+                self.ws_url.as_deref().unwrap_or(DEFAULT_WS_URL)
+            }
+
+            // Also drops the financials of tickers that are no longer active
+            pub fn set_active_tickers(&self, tickers: Vec<String>) {
+                self.ticker_frames
+                    .write()
+                    .unwrap()
+                    .retain(|(ticker, _)| tickers.iter().any(|t| t.eq_ignore_ascii_case(ticker)));
+                *self.active_tickers.lock().unwrap() = tickers;
+            }
+
+            pub fn active_tickers(&self) -> Vec<String> {
+                self.active_tickers.lock().unwrap().clone()
+            }
+
+            // Company names of the held filings, for realtime records that carry no ticker
+            pub fn active_companies(&self) -> Vec<String> {
+                let mut companies: Vec<String> = Vec::new();
+                for rec in &self.filings.lock().unwrap().records {
+                    let company = rec.company_name.trim();
+                    if !company.is_empty() && !companies.iter().any(|c| c.eq_ignore_ascii_case(company)) {
+                        companies.push(company.to_string());
+                    }
+                }
+                companies
+            }
+
+            pub fn page_size(&self) -> usize {
+                *self.page_size.lock().unwrap()
+            }
+
+            // Set how many filings each "Load More" reveals. Zero is clamped to 1.
+            pub fn set_page_size(&self, page_size: usize) {
+                *self.page_size.lock().unwrap() = page_size.max(1);
+            }
+
+            // Hold a fresh batch with nothing shown yet; load_more_filings yields the first page
+            pub fn set_filings(&self, records: Vec<FilingRecord>) {
+                *self.filings.lock().unwrap() = HeldFilings { records, shown: 0 };
+            }
+
+            pub fn get_filings(&self) -> Vec<FilingRecord> {
+                self.filings.lock().unwrap().records.clone()
+            }
+
+            // More filings to show, either held in memory or still on SEC. The held count is
+            // read from one snapshot; the filings lock is released before checking cursors.
+            pub fn has_more_filings(&self) -> bool {
+                let more_held = {
+                    let held = self.filings.lock().unwrap();
+                    held.shown < held.records.len()
+                };
+                more_held || self.has_more_remote_filings()
+            }
+
+            // Next page of the held filings, or None when everything held has been shown
+            // (see fetch_next_filings for older filings from SEC)
+            pub fn load_more_filings(&self) -> Option<Vec<FilingRecord>> {
+                let page_size = self.page_size();
+                let mut held = self.filings.lock().unwrap();
+                if held.shown >= held.records.len() {
+                    return None;
+                }
+                let end = (held.shown + page_size).min(held.records.len());
+                let page = held.records[held.shown..end].to_vec();
+                held.shown = end;
+                Some(page)
+            }
+        }
+
+        // How long shutdown_services waits for background tasks before aborting them
+        pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+        // Background tasks of the current service session and the signal that stops them
+        struct Services {
+            shutdown: CancellationToken,
+            tasks: Vec<(String, tokio::task::JoinHandle<()>)>,
+        }
+
+        impl Services {
+            fn new() -> Self {
+                Self { shutdown: CancellationToken::new(), tasks: Vec::new() }
+            }
+        }
+
+        static SERVICES: Mutex<Option<Services>> = Mutex::new(None);
+
+        // Begin a service session (a no-op if one is running). Returns its shutdown signal.
+        pub fn start_services() -> CancellationToken {
+            let mut services = SERVICES.lock().unwrap();
+            if services.as_ref().map_or(true, |running| running.shutdown.is_cancelled()) {
+                *services = Some(Services::new());
+            }
+            services.as_ref().unwrap().shutdown.clone()
+        }
+
+        // Signal long-running loops watch to know when to stop; cancelled by shutdown_services.
+        // After a shutdown it stays cancelled until start_services begins a new session.
+        pub fn shutdown_signal() -> CancellationToken {
+            SERVICES.lock().unwrap().get_or_insert_with(Services::new).shutdown.clone()
+        }
+
+        // Run a background task on the tokio runtime until it finishes or shutdown is signalled
+        pub fn spawn_service<F>(name: &str, task: F)
+        where
+            F: Future<Output = ()> + Send + 'static,
+        {
+            let mut services = SERVICES.lock().unwrap();
+            let services = services.get_or_insert_with(Services::new);
+            let shutdown = services.shutdown.clone();
+            let handle = tokio::spawn(async move {
+                tokio::select! {
+                    _ = shutdown.cancelled() => {}
+                    _ = task => {}
+                }
+            });
+            services.tasks.push((name.to_string(), handle));
+        }
+
+        // Signal every service to stop and wait up to `timeout` for their tasks to finish.
+        // Tasks still running at the deadline are aborted; their names are returned.
+        pub async fn shutdown_services(timeout: Duration) -> Vec<String> {
+            // The cancelled session stays in place so later shutdown_signal calls see it
+            let (shutdown, tasks) = match SERVICES.lock().unwrap().as_mut() {
+                Some(services) => (services.shutdown.clone(), std::mem::take(&mut services.tasks)),
+                None => return Vec::new(),
+            };
+            shutdown.cancel();
+            let deadline = tokio::time::Instant::now() + timeout;
+            let mut aborted = Vec::new();
+            for (name, mut handle) in tasks {
+                if tokio::time::timeout_at(deadline, &mut handle).await.is_err() {
+                    handle.abort();
+                    aborted.push(name);
+                }
+            }
+            if aborted.is_empty() {
+                log::info!("Backend services stopped.");
+            } else {
+                log::warn!("Aborted backend services that didn't stop in time: {}", aborted.join(", "));
+            }
+            aborted
+        }
+
+        #[cfg(test)]
+        mod tests {
+            use super::*;
+            use crate::security::RBACRole;
+            use polars::prelude::Series;
+            use std::sync::atomic::AtomicUsize;
+
+            // A fresh directory per call, so tests never touch the user's cache or config
+            fn test_dir(name: &str) -> PathBuf {
+                static NEXT: AtomicUsize = AtomicUsize::new(0);
+                let n = NEXT.fetch_add(1, Ordering::SeqCst);
+                let dir = std::env::temp_dir().join(format!("finfiles-backend-{}-{}-{}", name, std::process::id(), n));
+                let _ = fs::remove_dir_all(&dir);
+                dir
+            }
+
+            fn test_state() -> AppState {
+                AppState::new(User { username: "tester".to_string(), roles: vec![RBACRole::Viewer] })
+                    .unwrap()
+                    .with_cache(Some(FilingsCache::new(test_dir("cache"), DEFAULT_CACHE_TTL)))
+                    .with_watchlist_path(None)
+                    .with_bookmarks_path(None)
+            }
+
+            fn numbered_records(count: usize) -> Vec<FilingRecord> {
+                (0..count)
+                    .map(|i| FilingRecord { document: format!("filing-{}.htm", i), ..FilingRecord::default() })
+                    .collect()
+            }
+
+            fn page_lengths(state: &AppState) -> Vec<usize> {
+                let mut lengths = Vec::new();
+                while let Some(page) = state.load_more_filings() {
+                    lengths.push(page.len());
+                }
+                lengths
+            }
+
+            #[test]
+            fn sixty_records_page_by_ten() {
+                let state = test_state();
+                state.set_page_size(10);
+                state.set_filings(numbered_records(60));
+                assert_eq!(page_lengths(&state), vec![10; 6]);
+                assert!(state.load_more_filings().is_none());
+            }
+
+            #[test]
+            fn sixty_records_page_by_twenty_five() {
+                let state = test_state();
+                state.set_page_size(25);
+                state.set_filings(numbered_records(60));
+                assert_eq!(page_lengths(&state), vec![25, 25, 10]);
+            }
+
+            #[test]
+            fn pages_never_repeat_records() {
+                let state = test_state();
+                state.set_page_size(25);
+                let records = numbered_records(60);
+                state.set_filings(records.clone());
+                let mut shown = Vec::new();
+                while let Some(page) = state.load_more_filings() {
+                    shown.extend(page.into_iter().map(|r| r.document));
+                }
+                let expected: Vec<String> = records.into_iter().map(|r| r.document).collect();
+                assert_eq!(shown, expected);
+            }
+
+            #[test]
+            fn zero_page_size_is_clamped_to_one() {
+                let state = test_state();
+                state.set_page_size(0);
+                assert_eq!(state.page_size(), 1);
+                state.set_filings(numbered_records(3));
+                assert_eq!(page_lengths(&state), vec![1, 1, 1]);
+            }
+
+            fn metrics_frame() -> DataFrame {
+                DataFrame::new(vec![Series::new("quarter", &["2024Q1", "2024Q2"]), Series::new("revenue", &[90.8, 85.8])]).unwrap()
+            }
+
+            #[test]
+            fn data_state_starts_empty() {
+                let state = test_state();
+                assert!(matches!(state.data_state(), DataState::Empty));
+                assert!(!state.data_state().allows_export());
+                assert!(state.data_state().message().is_some());
+            }
+
+            #[test]
+            fn data_state_transitions_reach_subscribers() {
+                let state = test_state();
+                let mut rx = state.subscribe_data_state();
+                state.set_data_state(DataState::Loading);
+                assert!(rx.has_changed().unwrap());
+                assert!(matches!(*rx.borrow_and_update(), DataState::Loading));
+                state.set_data_state(DataState::Loaded(metrics_frame()));
+                assert!(rx.has_changed().unwrap());
+                let loaded = rx.borrow_and_update().clone();
+                assert_eq!(loaded.frame().map(|df| df.height()), Some(2));
+                assert!(loaded.message().is_none());
+                state.set_data_state(DataState::Error("SEC unavailable".to_string()));
+                assert!(matches!(&*rx.borrow_and_update(), DataState::Error(e) if e == "SEC unavailable"));
+            }
+
+            #[test]
+            fn export_is_allowed_only_while_loading_or_loaded() {
+                assert!(!DataState::Empty.allows_export());
+                assert!(!DataState::Error("boom".to_string()).allows_export());
+                assert!(DataState::Loading.allows_export());
+                assert!(DataState::Loaded(metrics_frame()).allows_export());
+            }
+
+            #[test]
+            fn ai_data_mirrors_the_loaded_frame() {
+                let state = test_state();
+                state.set_data_state(DataState::Loaded(metrics_frame()));
+                assert_eq!(state.ai_data.read().unwrap().height(), 2);
+                state.set_data_state(DataState::Error("boom".to_string()));
+                assert_eq!(state.ai_data.read().unwrap().height(), 0);
+            }
+
+            #[tokio::test]
+            async fn shutdown_signal_stays_cancelled_after_shutdown() {
+                let signal = start_services();
+                spawn_service("idle", std::future::pending());
+                assert!(shutdown_services(Duration::from_secs(1)).await.is_empty());
+                assert!(signal.is_cancelled());
+                assert!(shutdown_signal().is_cancelled());
+                assert!(!start_services().is_cancelled());
+            }
+
+            #[test]
+            fn matches_are_taken_before_the_cursor_moves_past_them() {
+                let forms = ["8-K", "10-K", "8-K", "8-K", "10-K", "4"];
+                let fetched: Vec<FilingRecord> =
+                    forms.iter().map(|form| FilingRecord { form: form.to_string(), ..FilingRecord::default() }).collect();
+                let filters = FilingFilters { forms: ["10-K".to_string()].into_iter().collect(), ..FilingFilters::default() };
+
+                let (first, used) = take_matching(&fetched, &filters, 1);
+                assert_eq!((first.len(), used), (1, 2));
+                // Resuming after the used filings finds the next match instead of skipping it
+                let (second, used_after) = take_matching(&fetched[used..], &filters, 1);
+                assert_eq!((second.len(), used + used_after), (1, 5));
+
+                let (all, used) = take_matching(&fetched, &filters, 10);
+                assert_eq!((all.len(), used), (2, fetched.len()));
+                // A page the filters empty is consumed entirely, so fetching continues past it
+                let (none, used) = take_matching(&fetched[..1], &filters, 10);
+                assert_eq!((none.len(), used), (0, 1));
+            }
+
+            #[test]
+            fn cache_hits_until_the_ttl_expires() {
+                let cache = FilingsCache::new(test_dir("ttl"), Duration::from_secs(60));
+                let stored = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+                let records = numbered_records(3);
+                cache.put("aapl", &records, stored).unwrap();
+
+                assert_eq!(cache.get("AAPL", stored), Some(records.clone()));
+                assert_eq!(cache.get("AAPL", stored + Duration::from_secs(59)), Some(records));
+                assert_eq!(cache.get("AAPL", stored + Duration::from_secs(60)), None);
+            }
+
+            #[test]
+            fn cache_misses_unknown_and_corrupt_entries() {
+                let dir = test_dir("miss");
+                let cache = FilingsCache::new(dir.clone(), Duration::from_secs(60));
+                let now = SystemTime::now();
+                assert_eq!(cache.get("MSFT", now), None);
+
+                fs::create_dir_all(&dir).unwrap();
+                fs::write(dir.join("MSFT.json"), "not json").unwrap();
+                assert_eq!(cache.get("MSFT", now), None);
+            }
+
+            #[tokio::test]
+            async fn first_page_is_served_from_a_fresh_cache() {
+                let state = test_state();
+                let records = numbered_records(2);
+                state.cache.as_ref().unwrap().put("AAPL", &records, SystemTime::now()).unwrap();
+
+                let page = state.fetch_first_page("AAPL", true).await.unwrap();
+                assert_eq!(page.records, records);
+                assert_eq!(page.cursor.offset, 2);
+            }
+
+            #[test]
+            fn watchlist_adds_removes_and_dedups() {
+                let state = test_state();
+                assert!(state.add_to_watchlist(" aapl "));
+                assert!(state.add_to_watchlist("MSFT"));
+                assert!(!state.add_to_watchlist("AAPL"));
+                assert!(!state.add_to_watchlist("  "));
+                assert_eq!(state.watchlist(), vec!["AAPL".to_string(), "MSFT".to_string()]);
+
+                assert!(state.remove_from_watchlist("aapl"));
+                assert!(!state.remove_from_watchlist("AAPL"));
+                assert_eq!(state.watchlist(), vec!["MSFT".to_string()]);
+            }
+
+            #[test]
+            fn watchlist_persists_across_states() {
+                let path = test_dir("watchlist").join("watchlist.json");
+                let state = test_state().with_watchlist_path(Some(path.clone()));
+                state.add_to_watchlist("aapl");
+                state.add_to_watchlist("tsla");
+                state.remove_from_watchlist("AAPL");
+
+                let reloaded = test_state().with_watchlist_path(Some(path));
+                assert_eq!(reloaded.watchlist(), vec!["TSLA".to_string()]);
+            }
+
+            #[tokio::test]
+            async fn one_missing_ticker_does_not_fail_the_batch() {
+                let tickers: Vec<String> = ["AAPL", "XYZQ", "MSFT"].iter().map(|t| t.to_string()).collect();
+                let pages = fetch_each(&tickers, 2, |ticker| async move {
+                    if ticker == "XYZQ" {
+                        return Err(FinAIError::TickerNotFound(ticker));
+                    }
+                    let records = vec![FilingRecord { ticker: ticker.clone(), form: "10-K".to_string(), ..FilingRecord::default() }];
+                    Ok(FilingPage { records, cursor: FilingCursor::default().advance(1, false) })
+                })
+                .await;
+
+                let batch = BatchFetch::from_pages(pages);
+                let populated: Vec<&str> = batch.records.iter().map(|r| r.ticker.as_str()).collect();
+                assert_eq!(populated, vec!["AAPL", "MSFT"]);
+                assert_eq!(batch.cursors.len(), 2);
+                assert_eq!(batch.failures.len(), 1);
+                assert!(matches!(&batch.failures[0], (ticker, FinAIError::TickerNotFound(_)) if ticker == "XYZQ"));
+                assert!(batch.failure_summary().starts_with("XYZQ ("));
+            }
+
+            #[test]
+            fn a_second_fetch_is_rejected_while_one_is_in_flight() {
+                let state = test_state();
+                assert!(state.begin_fetch());
+                assert!(state.is_fetching());
+                assert!(!state.begin_fetch());
+
+                state.end_fetch();
+                // Finished, but still within the debounce window of the first fetch
+                assert!(!state.begin_fetch());
+
+                *state.last_fetch_started.lock().unwrap() = Instant::now().checked_sub(FETCH_DEBOUNCE);
+                assert!(state.begin_fetch());
+                assert!(!state.begin_fetch());
+                state.end_fetch();
+            }
+
+            #[tokio::test]
+            async fn a_cancelled_fetch_leaves_the_filings_untouched() {
+                let state = test_state();
+                state.set_page_size(2);
+                state.set_filings(numbered_records(3));
+                state.load_more_filings();
+                let before = state.get_filings();
+
+                assert!(state.begin_fetch());
+                let cancel = state.fetch_cancel_token();
+                let mocked_fetch = async {
+                    tokio::time::sleep(Duration::from_secs(60)).await;
+                    BatchFetch { records: numbered_records(9), ..BatchFetch::default() }
+                };
+                let canceller = cancel.clone();
+                tokio::spawn(async move {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                    canceller.cancel();
+                });
+                if let Some(batch) = until_cancelled(&cancel, mocked_fetch).await {
+                    state.set_filings(batch.records);
+                }
+                state.end_fetch();
+
+                assert_eq!(state.get_filings(), before);
+                // The page already shown stays shown; the next page is the one that was pending
+                assert_eq!(state.load_more_filings(), Some(before[2..].to_vec()));
+                assert!(!state.is_fetching());
+            }
+
+            #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+            async fn concurrent_filings_access_neither_panics_nor_deadlocks() {
+                let state = Arc::new(test_state());
+                let mut tasks = Vec::new();
+                for i in 0..8 {
+                    let state = state.clone();
+                    tasks.push(tokio::spawn(async move {
+                        for round in 0..200 {
+                            if (i + round) % 2 == 0 {
+                                state.set_filings(numbered_records(round % 7));
+                            } else {
+                                // Whatever was set last, a reader sees one whole list
+                                let len = state.get_filings().len();
+                                assert!(len < 7);
+                                state.load_more_filings();
+                                state.has_more_filings();
+                            }
+                            tokio::task::yield_now().await;
+                        }
+                    }));
+                }
+                let all = futures::future::join_all(tasks);
+                let finished = tokio::time::timeout(Duration::from_secs(10), all).await.expect("filings access deadlocked");
+                for task in finished {
+                    task.expect("filings access panicked");
+                }
+            }
+
+            #[test]
+            fn builder_accepts_valid_records() {
+                let record = FilingRecord::builder(" 10-K/A ")
+                    .with_date("2024-01-15")
+                    .with_document_url("https://www.sec.gov/Archives/edgar/data/320193/000032019324000006/aapl-20230930.htm")
+                    .with_document("aapl-20230930.htm")
+                    .with_company_name("Apple Inc.")
+                    .with_ticker("AAPL")
+                    .build()
+                    .unwrap();
+                assert_eq!(record.form, "10-K/A");
+                assert!(record.amendment);
+                assert_eq!(record.ticker, "AAPL");
+
+                // Optional fields may be left empty
+                let bare = FilingRecord::builder("8-K").build().unwrap();
+                assert!(bare.date.is_empty() && bare.document_url.is_empty());
+                assert!(!bare.amendment);
+            }
+
+            #[test]
+            fn builder_rejects_each_invalid_field() {
+                let invalid = [
+                    FilingRecord::builder("  "),
+                    FilingRecord::builder("10-K").with_date("15/01/2024"),
+                    FilingRecord::builder("10-K").with_date("2024-02-30"),
+                    FilingRecord::builder("10-K").with_document_url("not a url"),
+                    FilingRecord::builder("10-K").with_document_url("ftp://www.sec.gov/filing.htm"),
+                    FilingRecord::builder("10-K").with_document_url("https://"),
+                ];
+                for builder in invalid {
+                    assert!(matches!(builder.build(), Err(FinAIError::DataParsing(_))));
+                }
+            }
+
+            #[tokio::test]
+            async fn concurrent_fetches_come_back_in_input_order() {
+                let tickers: Vec<String> = (0..12).map(|i| format!("T{:02}", i)).collect();
+                let in_flight = Arc::new(AtomicUsize::new(0));
+                let peak = Arc::new(AtomicUsize::new(0));
+                let results = fetch_each(&tickers, 4, |ticker| {
+                    let (in_flight, peak) = (in_flight.clone(), peak.clone());
+                    async move {
+                        let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                        peak.fetch_max(now, Ordering::SeqCst);
+                        // Later tickers finish first
+                        let n: u64 = ticker[1..].parse().unwrap();
+                        tokio::time::sleep(Duration::from_millis(5 * (12 - n))).await;
+                        in_flight.fetch_sub(1, Ordering::SeqCst);
+                        Ok(ticker.to_lowercase())
+                    }
+                })
+                .await;
+
+                let order: Vec<&str> = results.iter().map(|(ticker, _)| ticker.as_str()).collect();
+                assert_eq!(order, tickers.iter().map(String::as_str).collect::<Vec<_>>());
+                assert!(results.iter().all(|(ticker, result)| result.as_deref().ok() == Some(ticker.to_lowercase().as_str())));
+                assert_eq!(peak.load(Ordering::SeqCst), 4);
+            }
+
+            #[test]
+            fn fetch_concurrency_is_at_least_one() {
+                assert_eq!(test_state().with_fetch_concurrency(0).api.concurrency(), 1);
+                assert_eq!(test_state().with_fetch_concurrency(6).api.concurrency(), 6);
+            }
+
+            #[test]
+            fn forced_refresh_skips_a_fresh_cache() {
+                let state = test_state();
+                let now = SystemTime::now();
+                state.cache.as_ref().unwrap().put("AAPL", &numbered_records(2), now).unwrap();
+                assert!(state.cached_first_page("AAPL", true, now).is_some());
+                assert!(state.cached_first_page("AAPL", false, now).is_none());
+            }
+
+            #[tokio::test]
+            async fn refresh_drops_in_memory_and_analysis_caches() {
+                let state = test_state();
+                *state.api.cik_map.lock().unwrap() = Some((Instant::now(), Arc::new(HashMap::new())));
+                let key = crate::ai::AnalysisKey::new("FINFILES AI", "Summarize", &DataFrame::default());
+                state.analysis_cache().lock().unwrap().put(key.clone(), "cached".to_string());
+
+                state.refresh_filings(Vec::new(), FilingFilters::default()).await;
+                assert!(state.api.cik_map.lock().unwrap().is_none());
+                assert!(state.analysis_cache().lock().unwrap().get(&key).is_none());
+            }
+
+            #[test]
+            fn retry_reuses_the_last_fetch_request() {
+                let state = test_state();
+                assert!(state.last_fetch().is_none());
+
+                let mut filters = FilingFilters::default();
+                filters.forms.insert("10-K".to_string());
+                state.remember_fetch(FetchRequest { tickers: vec!["AAPL".to_string(), "MSFT".to_string()], filters, force: true });
+                // Reading it for a Retry leaves it in place for the next Retry
+                for _ in 0..2 {
+                    let request = state.last_fetch().unwrap();
+                    assert_eq!(request.tickers, vec!["AAPL", "MSFT"]);
+                    assert!(request.filters.form_selected("10-K/A"));
+                    assert!(!request.filters.form_selected("8-K"));
+                    assert!(request.force);
+                }
+
+                state.remember_fetch(FetchRequest { tickers: vec!["GOOG".to_string()], ..FetchRequest::default() });
+                let request = state.last_fetch().unwrap();
+                assert_eq!(request.tickers, vec!["GOOG"]);
+                assert!(request.filters.forms.is_empty());
+                assert!(!request.force);
+            }
+        }
+    }
+
+    pub mod websocket {
+        use super::backend::FilingRecord;
+        use super::backend::{shutdown_signal, AppState};
+        use super::error::*;
+        use super::filters::date_sort_key;
+        use futures::StreamExt;
+        use gtk::prelude::*;
+        use gtk::{Label, ListStore};
+        use std::sync::{Arc, Weak};
+        use std::time::Duration;
+        use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+        // Realtime filings feed used when no endpoint is configured
+        pub const DEFAULT_WS_URL: &str = "wss://stream.finfiles.ai/v1/filings";
+
+        // Check that a realtime endpoint is a ws:// or wss:// URL
+        pub fn validate_ws_url(url: &str) -> Result<()> {
+            let parsed = url::Url::parse(url.trim())
+                .map_err(|e| FinAIError::Config(format!("Invalid realtime URL '{}': {e}", url)))?;
+            match parsed.scheme() {
+                "ws" | "wss" if parsed.host_str().is_some() => Ok(()),
+                "ws" | "wss" => Err(FinAIError::Config(format!("Realtime URL '{}' has no host", url))),
+                scheme => Err(FinAIError::Config(format!(
+                    "Realtime URL '{}' must use ws:// or wss://, not {}://",
+                    url, scheme
+                ))),
+            }
+        }
+
+        // Default cap for the reconnect backoff
+        pub const DEFAULT_MAX_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+        // Delay before reconnect attempt `attempt` (0-based): 1s, 2s, 4s, ... capped at `max`.
+        pub fn backoff_delay(attempt: u32, max: Duration) -> Duration {
+            let secs = 1u64.checked_shl(attempt.min(63)).unwrap_or(u64::MAX);
+            Duration::from_secs(secs).min(max)
+        }
+
+        // Whether a realtime record belongs to one of the loaded tickers (case-insensitive).
+        // Records without a ticker are matched by company, against the loaded companies or
+        // the tickers themselves. With no tickers loaded nothing matches.
+        pub fn matches_active_tickers(record: &FilingRecord, tickers: &[String], companies: &[String]) -> bool {
+            if tickers.is_empty() {
+                return false;
+            }
+            let ticker = record.ticker.trim();
+            if !ticker.is_empty() {
+                return tickers.iter().any(|t| t.eq_ignore_ascii_case(ticker));
+            }
+            let company = record.company_name.trim();
+            !company.is_empty() && tickers.iter().chain(companies).any(|c| c.trim().eq_ignore_ascii_case(company))
+        }
+
+        // Stream live filings into the table, reconnecting with capped exponential backoff.
+        // The loop ends once the AppState it was started for is dropped or backend services
+        // are shut down.
+        pub fn start_realtime_updates(state: Arc<AppState>, store: ListStore, status_label: Label, max_retry_interval: Duration) {
+            let url = state.realtime_url().to_string();
+            if let Err(e) = validate_ws_url(&url) {
+                log::error!("Realtime updates disabled: {}", e);
+                status_label.set_text(&format!("Realtime updates disabled: {}", e));
+                return;
+            }
+            let state: Weak<AppState> = Arc::downgrade(&state);
+            let shutdown = shutdown_signal();
+            glib::MainContext::default().spawn_local(async move {
+                let mut attempt: u32 = 0;
+                while state.upgrade().is_some() && !shutdown.is_cancelled() {
+                    let connected = tokio::select! {
+                        _ = shutdown.cancelled() => break,
+                        connected = connect_async(url.as_str()) => connected,
+                    };
+                    match connected {
+                        Ok((mut socket, _)) => {
+                            attempt = 0;
+                            status_label.set_text("Live");
+                            loop {
+                                let message = tokio::select! {
+                                    _ = shutdown.cancelled() => {
+                                        let _ = socket.close(None).await;
+                                        break;
+                                    }
+                                    message = socket.next() => match message {
+                                        Some(message) => message,
+                                        None => break,
+                                    },
+                                };
+                                let (active_tickers, active_companies) = match state.upgrade() {
+                                    Some(state) => (state.active_tickers(), state.active_companies()),
+                                    None => return,
+                                };
+                                match message {
+                                    Ok(Message::Text(text)) => match serde_json::from_str::<FilingRecord>(&text) {
+                                        // Records for other tickers are dropped, not queued
+                                        Ok(rec) if matches_active_tickers(&rec, &active_tickers, &active_companies) => {
+                                            insert_record(&store, &rec.with_sentiment())
+                                        }
+                                        Ok(_) => {}
+                                        Err(e) => log::warn!("Ignoring malformed realtime message: {}", e),
+                                    },
+                                    Ok(Message::Close(_)) => break,
+                                    Ok(_) => {}
+                                    Err(e) => {
+                                        log::warn!("Realtime feed error: {}", e);
+                                        break;
+                                    }
+                                }
+                            }
+                        }
+                        Err(e) => log::warn!("Realtime feed connection failed: {}", e),
+                    }
+
+                    if state.upgrade().is_none() || shutdown.is_cancelled() {
+                        break;
+                    }
+                    let delay = backoff_delay(attempt, max_retry_interval);
+                    attempt = attempt.saturating_add(1);
+                    status_label.set_text("Reconnecting…");
+                    tokio::select! {
+                        _ = shutdown.cancelled() => break,
+                        _ = glib::timeout_future(delay) => {}
+                    }
+                }
+                log::info!("Realtime updates stopped.");
+            });
+        }
+
+        fn insert_record(store: &ListStore, rec: &FilingRecord) {
+            store.insert_with_values(
+                None,
+                &[0, 1, 2, 3, 4, 5, 6, 7],
+                &[
+                    &rec.form,
+                    &rec.date,
+                    &rec.document,
+                    &rec.document_url,
+                    &rec.company_name,
+                    &rec.filing_type,
+                    &rec.ai_summary,
+                    &date_sort_key(&rec.date),
+                ],
+            );
+        }
+
+        #[cfg(test)]
+        mod tests {
+            use super::*;
+
+            #[test]
+            fn backoff_doubles_and_caps() {
+                let max = Duration::from_secs(30);
+                let delays: Vec<u64> = (0..8).map(|attempt| backoff_delay(attempt, max).as_secs()).collect();
+                assert_eq!(delays, vec![1, 2, 4, 8, 16, 30, 30, 30]);
+            }
+
+            #[test]
+            fn backoff_survives_huge_attempt_counts() {
+                let max = Duration::from_secs(30);
+                assert_eq!(backoff_delay(64, max), max);
+                assert_eq!(backoff_delay(u32::MAX, max), max);
+                assert_eq!(backoff_delay(3, Duration::from_secs(5)), Duration::from_secs(5));
+            }
+
+            #[test]
+            fn ws_urls_are_accepted() {
+                assert!(validate_ws_url(DEFAULT_WS_URL).is_ok());
+                assert!(validate_ws_url("ws://localhost:9000/feed").is_ok());
+                assert!(validate_ws_url("  wss://staging.example.com  ").is_ok());
+            }
+
+            #[test]
+            fn non_ws_urls_are_rejected() {
+                for url in ["https://stream.finfiles.ai", "ftp://example.com", "stream.finfiles.ai", "", "ws://"] {
+                    assert!(matches!(validate_ws_url(url), Err(FinAIError::Config(_))), "url {:?}", url);
+                }
+            }
+
+            fn feed_record(ticker: &str, company: &str) -> FilingRecord {
+                FilingRecord { ticker: ticker.to_string(), company_name: company.to_string(), ..FilingRecord::default() }
+            }
+
+            #[test]
+            fn realtime_records_match_tickers_case_insensitively() {
+                let tickers = vec!["AAPL".to_string(), "MSFT".to_string()];
+                assert!(matches_active_tickers(&feed_record("aapl", ""), &tickers, &[]));
+                assert!(matches_active_tickers(&feed_record(" MSFT ", "Microsoft Corp"), &tickers, &[]));
+                assert!(!matches_active_tickers(&feed_record("GOOG", "Apple Inc."), &tickers, &["Apple Inc.".to_string()]));
+            }
+
+            #[test]
+            fn realtime_records_without_ticker_match_by_company() {
+                let tickers = vec!["AAPL".to_string()];
+                let companies = vec!["Apple Inc.".to_string()];
+                assert!(matches_active_tickers(&feed_record("", "apple inc."), &tickers, &companies));
+                assert!(matches_active_tickers(&feed_record("", "aapl"), &tickers, &[]));
+                assert!(!matches_active_tickers(&feed_record("", "Microsoft Corp"), &tickers, &companies));
+                assert!(!matches_active_tickers(&feed_record("", ""), &tickers, &companies));
+            }
+
+            #[test]
+            fn nothing_matches_an_empty_watchlist() {
+                let companies = vec!["Apple Inc.".to_string()];
+                assert!(!matches_active_tickers(&feed_record("AAPL", ""), &[], &[]));
+                assert!(!matches_active_tickers(&feed_record("", "Apple Inc."), &[], &companies));
+            }
+        }
+    }
+
+    pub mod filters {
+        use super::backend::FilingRecord;
+        use super::data_ingestion::base_form;
+        use super::error::*;
+        use chrono::NaiveDate;
+        use gtk::prelude::*;
+        use gtk::{Box as GtkBox, Button, CheckButton, ComboBoxText, Entry, Label, MenuButton, Orientation, Popover, SearchEntry};
+        use serde::{Deserialize, Serialize};
+        use std::cell::RefCell;
+        use std::collections::HashSet;
+        use std::fs;
+        use std::path::{Path, PathBuf};
+        use std::rc::Rc;
+
+        // Filing date format used by SEC EDGAR and the date entries
+        const DATE_FORMAT: &str = "%Y-%m-%d";
+
+        // Form types always offered in the form filter; others are added as they appear in data
+        pub const COMMON_FORMS: [&str; 9] = ["10-K", "10-Q", "8-K", "4", "S-1", "DEF 14A", "13F-HR", "20-F", "6-K"];
+
+        // Inclusive date window. A missing bound is open-ended.
+        #[derive(Debug, Clone, Default, PartialEq)]
+        pub struct DateRange {
+            pub from: Option<NaiveDate>,
+            pub to: Option<NaiveDate>,
+        }
+
+        impl DateRange {
+            // Parse "From"/"To" text. Invalid or partial dates are ignored (open-ended).
+            pub fn parse(from: &str, to: &str) -> Self {
+                Self {
+                    from: NaiveDate::parse_from_str(from.trim(), DATE_FORMAT).ok(),
+                    to: NaiveDate::parse_from_str(to.trim(), DATE_FORMAT).ok(),
+                }
+            }
+
+            // Whether a filing date falls inside the range. Dates that can't be parsed are kept.
+            pub fn contains(&self, date: &str) -> bool {
+                let date = match NaiveDate::parse_from_str(date.trim(), DATE_FORMAT) {
+                    Ok(date) => date,
+                    Err(_) => return true,
+                };
+                self.from.map_or(true, |from| date >= from) && self.to.map_or(true, |to| date <= to)
+            }
+        }
+
+        // ListStore column holding date_sort_key for each filing row
+        pub const DATE_SORT_COLUMN: i32 = 7;
+
+        // Other date layouts seen in filing data, tried after DATE_FORMAT
+        const ALT_DATE_FORMATS: [&str; 3] = ["%Y%m%d", "%m/%d/%Y", "%Y/%m/%d"];
+
+        // Chronological sort key for a filing date (days since the Common Era). Accepts
+        // "2024-01-31", "20240131", "01/31/2024", "2024/01/31" and timestamps starting with a
+        // date; anything unparseable sorts before every real date.
+        pub fn date_sort_key(date: &str) -> i64 {
+            let date = date.trim();
+            let prefix = date.get(..10).unwrap_or(date);
+            std::iter::once(DATE_FORMAT)
+                .chain(ALT_DATE_FORMATS)
+                .find_map(|format| {
+                    NaiveDate::parse_from_str(prefix, format)
+                        .or_else(|_| NaiveDate::parse_from_str(date, format))
+                        .ok()
+                })
+                .map_or(i64::MIN, |d| i64::from(chrono::Datelike::num_days_from_ce(&d)))
+        }
+
+        // ListStore columns searched by the filings search box: Form, Document, Company, Filing Type
+        pub const SEARCH_COLUMNS: [i32; 4] = [0, 2, 4, 5];
+
+        // Case-insensitive substring match of a search query against a row's fields.
+        // A blank query matches every row.
+        pub fn filing_row_matches(fields: &[&str], query: &str) -> bool {
+            let query = query.trim().to_lowercase();
+            query.is_empty() || fields.iter().any(|field| field.to_lowercase().contains(&query))
+        }
+
+        // Filter state passed from the FilterPane to the backend fetch
+        #[derive(Debug, Clone, Default)]
+        pub struct FilingFilters {
+            pub date_range: DateRange,
+            // Selected form types (uppercase). Empty means all forms.
+            pub forms: HashSet<String>,
+        }
+
+        impl FilingFilters {
+            // Amendments match their base form too, so "10-K" also shows 10-K/A filings
+            pub fn form_selected(&self, form: &str) -> bool {
+                let form = form.trim().to_uppercase();
+                self.forms.is_empty() || self.forms.contains(&form) || self.forms.contains(base_form(&form))
+            }
+
+            pub fn matches(&self, record: &FilingRecord) -> bool {
+                self.date_range.contains(&record.date) && self.form_selected(&record.form)
+            }
+
+            pub fn apply(&self, records: Vec<FilingRecord>) -> Vec<FilingRecord> {
+                records.into_iter().filter(|rec| self.matches(rec)).collect()
+            }
+        }
+
+        // A named, saved set of filter widget values
+        #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+        pub struct FilterPreset {
+            pub name: String,
+            pub forms: Vec<String>,
+            pub from: String,
+            pub to: String,
+            // Text of the filings search box; presets saved before it was recorded have none
+            #[serde(default)]
+            pub query: String,
+        }
+
+        impl FilterPreset {
+            pub fn to_json(&self) -> Result<String> {
+                serde_json::to_string_pretty(self).map_err(|e| FinAIError::Config(format!("Failed to serialize preset: {e}")))
+            }
+
+            pub fn from_json(json: &str) -> Result<Self> {
+                serde_json::from_str(json).map_err(|e| FinAIError::Config(format!("Invalid preset: {e}")))
+            }
+
+            // Directory holding saved presets: <config dir>/finfiles/filter_presets
+            pub fn presets_dir() -> Result<PathBuf> {
+                dirs::config_dir()
+                    .map(|dir| dir.join("finfiles").join("filter_presets"))
+                    .ok_or_else(|| FinAIError::Config("No user config directory available.".to_string()))
+            }
+
+            fn file_name(name: &str) -> String {
+                let safe: String = name
+                    .trim()
+                    .chars()
+                    .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+                    .collect();
+                format!("{}.json", safe)
+            }
+
+            pub fn save(&self) -> Result<PathBuf> {
+                if self.name.trim().is_empty() {
+                    return Err(FinAIError::Config("Preset name must not be empty.".to_string()));
+                }
+                let dir = Self::presets_dir()?;
+                fs::create_dir_all(&dir)
+                    .map_err(|e| FinAIError::Config(format!("Failed to create {}: {e}", dir.display())))?;
+                let path = dir.join(Self::file_name(&self.name));
+                fs::write(&path, self.to_json()?)
+                    .map_err(|e| FinAIError::Config(format!("Failed to write {}: {e}", path.display())))?;
+                Ok(path)
+            }
+
+            pub fn load(name: &str) -> Result<Self> {
+                let path = Self::presets_dir()?.join(Self::file_name(name));
+                let json = fs::read_to_string(&path)
+                    .map_err(|e| FinAIError::Config(format!("Failed to read {}: {e}", path.display())))?;
+                Self::from_json(&json).map_err(|e| FinAIError::Config(format!("{} ({})", e, path.display())))
+            }
+
+            // Names of all saved presets, sorted
+            pub fn list() -> Vec<String> {
+                let mut names: Vec<String> = Self::presets_dir()
+                    .ok()
+                    .and_then(|dir| fs::read_dir(dir).ok())
+                    .map(|entries| {
+                        entries
+                            .flatten()
+                            .filter_map(|entry| {
+                                let path = entry.path();
+                                if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                                    return None;
+                                }
+                                path.file_stem().and_then(|s| s.to_str()).map(|s| s.to_string())
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                names.sort();
+                names
+            }
+        }
+
+        // Titles of the filings table columns, in display order
+        pub const FILING_COLUMNS: [&str; 6] = ["Form", "Date", "Document", "Company", "Filing Type", "AI Analysis"];
+
+        // Which filings table columns the user has hidden, persisted as JSON in the config
+        // dir. Only the view is affected; exports always include every column.
+        #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+        pub struct ColumnVisibility {
+            #[serde(default)]
+            pub hidden: Vec<String>,
+        }
+
+        impl ColumnVisibility {
+            pub fn path() -> Result<PathBuf> {
+                dirs::config_dir()
+                    .map(|dir| dir.join("finfiles").join("columns.json"))
+                    .ok_or_else(|| FinAIError::Config("No user config directory available.".to_string()))
+            }
+
+            // Load the saved state; a missing or unreadable file shows every column
+            pub fn load() -> Self {
+                Self::path().map(|path| Self::load_from(&path)).unwrap_or_default()
+            }
+
+            pub fn load_from(path: &Path) -> Self {
+                fs::read_to_string(path)
+                    .ok()
+                    .and_then(|json| serde_json::from_str(&json).ok())
+                    .unwrap_or_default()
+            }
+
+            pub fn save(&self) -> Result<PathBuf> {
+                let path = Self::path()?;
+                self.save_to(&path)?;
+                Ok(path)
+            }
+
+            pub fn save_to(&self, path: &Path) -> Result<()> {
+                if let Some(dir) = path.parent() {
+                    fs::create_dir_all(dir)
+                        .map_err(|e| FinAIError::Config(format!("Failed to create {}: {e}", dir.display())))?;
+                }
+                let json = serde_json::to_string_pretty(self)
+                    .map_err(|e| FinAIError::Config(format!("Failed to serialize column visibility: {e}")))?;
+                fs::write(path, json)
+                    .map_err(|e| FinAIError::Config(format!("Failed to write {}: {e}", path.display())))
+            }
+
+            pub fn is_visible(&self, column: &str) -> bool {
+                !self.hidden.iter().any(|c| c == column)
+            }
+
+            pub fn set_visible(&mut self, column: &str, visible: bool) {
+                self.hidden.retain(|c| c != column);
+                if !visible {
+                    self.hidden.push(column.to_string());
+                }
+            }
+        }
+
+        // Filter controls shown next to the ticker entry
+        #[derive(Clone)]
+        pub struct FilterPane {
+            pub widget: GtkBox,
+            // Client-side search over the loaded filings; the window places it above the table
+            pub search_entry: SearchEntry,
+            from_entry: Entry,
+            to_entry: Entry,
+            form_list: GtkBox,
+            form_checks: Rc<RefCell<Vec<CheckButton>>>,
+            preset_combo: ComboBoxText,
+            // Outcome of the last preset save or load
+            preset_status: Label,
+        }
+
+        impl FilterPane {
+            pub fn new() -> Self {
+                let widget = GtkBox::new(Orientation::Horizontal, 6);
+
+                let search_entry = SearchEntry::new();
+                search_entry.set_placeholder_text(Some("Search loaded filings"));
+                search_entry.set_tooltip_text(Some("Filter by form, document, company or filing type"));
+
+                let from_entry = Entry::new();
+                from_entry.set_placeholder_text(Some("YYYY-MM-DD"));
+                from_entry.set_tooltip_text(Some("Only show filings on or after this date"));
+                from_entry.set_width_chars(11);
+
+                let to_entry = Entry::new();
+                to_entry.set_placeholder_text(Some("YYYY-MM-DD"));
+                to_entry.set_tooltip_text(Some("Only show filings on or before this date"));
+                to_entry.set_width_chars(11);
+
+                // Form type multi-select in a popover
+                let form_button = MenuButton::new();
+                form_button.set_label("Forms");
+                form_button.set_tooltip_text(Some("Only show the selected form types (none selected shows all)"));
+                let form_list = GtkBox::new(Orientation::Vertical, 2);
+                let popover = Popover::new(Some(&form_button));
+                popover.add(&form_list);
+                form_button.set_popover(Some(&popover));
+
+                widget.pack_start(&Label::new(Some("From")), false, false, 0);
+                widget.pack_start(&from_entry, false, false, 0);
+                widget.pack_start(&Label::new(Some("To")), false, false, 0);
+                widget.pack_start(&to_entry, false, false, 0);
+                widget.pack_start(&form_button, false, false, 0);
+
+                // Presets: save the current filters under a name, reload them later
+                let preset_name_entry = Entry::new();
+                preset_name_entry.set_placeholder_text(Some("Preset name"));
+                preset_name_entry.set_width_chars(12);
+                let save_preset_button = Button::new_with_label("Save Preset");
+                save_preset_button.set_tooltip_text(Some("Save the current filters as a named preset"));
+                let preset_combo = ComboBoxText::new();
+                preset_combo.set_tooltip_text(Some("Saved filter presets"));
+                let load_preset_button = Button::new_with_label("Load Preset");
+                load_preset_button.set_tooltip_text(Some("Apply the selected filter preset"));
+                widget.pack_start(&preset_name_entry, false, false, 0);
+                widget.pack_start(&save_preset_button, false, false, 0);
+                widget.pack_start(&preset_combo, false, false, 0);
+                widget.pack_start(&load_preset_button, false, false, 0);
+                let preset_status = Label::new(None);
+                widget.pack_start(&preset_status, false, false, 0);
+
+                let pane = Self {
+                    widget,
+                    search_entry,
+                    from_entry,
+                    to_entry,
+                    form_list,
+                    form_checks: Rc::new(RefCell::new(Vec::new())),
+                    preset_combo,
+                    preset_status,
+                };
+                for form in COMMON_FORMS.iter() {
+                    pane.add_form_option(form);
+                }
+                pane.refresh_presets();
+
+                {
+                    let pane = pane.clone();
+                    save_preset_button.connect_clicked(move |_| {
+                        let name = preset_name_entry.get_text().to_string();
+                        match pane.current_preset(&name).save() {
+                            Ok(path) => {
+                                log::info!("Saved filter preset to {}", path.display());
+                                pane.refresh_presets();
+                                pane.preset_status.set_text(&format!("Saved preset \"{}\"", name.trim()));
+                            }
+                            Err(e) => {
+                                log::error!("Failed to save filter preset: {}", e);
+                                pane.preset_status.set_text(&format!("Couldn't save preset: {}", e));
+                            }
+                        }
+                    });
+                }
+                {
+                    let pane = pane.clone();
+                    load_preset_button.connect_clicked(move |_| {
+                        if let Some(name) = pane.preset_combo.get_active_text() {
+                            match FilterPreset::load(&name) {
+                                Ok(preset) => {
+                                    pane.apply_preset(&preset);
+                                    pane.preset_status.set_text(&format!("Loaded preset \"{}\"", name));
+                                }
+                                Err(e) => {
+                                    log::error!("Failed to load filter preset: {}", e);
+                                    pane.preset_status.set_text(&format!("Couldn't load preset: {}", e));
+                                }
+                            }
+                        }
+                    });
+                }
+                pane
+            }
+
+            // Reload the preset dropdown from disk
+            fn refresh_presets(&self) {
+                self.preset_combo.remove_all();
+                for name in FilterPreset::list() {
+                    self.preset_combo.append_text(&name);
+                }
+            }
+
+            // Snapshot the visible widget values as a preset
+            pub fn current_preset(&self, name: &str) -> FilterPreset {
+                let mut forms: Vec<String> = self.filters().forms.into_iter().collect();
+                forms.sort();
+                FilterPreset {
+                    name: name.trim().to_string(),
+                    forms,
+                    from: self.from_entry.get_text().to_string(),
+                    to: self.to_entry.get_text().to_string(),
+                    query: self.search_entry.get_text().to_string(),
+                }
+            }
+
+            // Update the visible widgets (not just the filter state) from a preset
+            pub fn apply_preset(&self, preset: &FilterPreset) {
+                self.from_entry.set_text(&preset.from);
+                self.to_entry.set_text(&preset.to);
+                self.search_entry.set_text(&preset.query);
+                for form in &preset.forms {
+                    self.add_form_option(form);
+                }
+                let selected: HashSet<String> = preset.forms.iter().map(|f| f.trim().to_uppercase()).collect();
+                for check in self.form_checks.borrow().iter() {
+                    let active = check.get_label().map_or(false, |l| selected.contains(l.as_str()));
+                    check.set_active(active);
+                }
+            }
+
+            // Add a check button for a form type unless it is already listed
+            fn add_form_option(&self, form: &str) {
+                let form = form.trim().to_uppercase();
+                if form.is_empty() {
+                    return;
+                }
+                let mut checks = self.form_checks.borrow_mut();
+                if checks.iter().any(|c| c.get_label().map_or(false, |l| l == form.as_str())) {
+                    return;
+                }
+                let check = CheckButton::with_label(&form);
+                self.form_list.pack_start(&check, false, false, 0);
+                check.show();
+                checks.push(check);
+            }
+
+            // Offer any form types seen in fetched data that aren't listed yet
+            pub fn add_seen_forms(&self, records: &[FilingRecord]) {
+                for rec in records {
+                    self.add_form_option(&rec.form);
+                }
+            }
+
+            // Current filter state
+            pub fn filters(&self) -> FilingFilters {
+                let forms = self
+                    .form_checks
+                    .borrow()
+                    .iter()
+                    .filter(|c| c.get_active())
+                    .filter_map(|c| c.get_label().map(|l| l.to_string()))
+                    .collect();
+                FilingFilters {
+                    date_range: DateRange::parse(&self.from_entry.get_text(), &self.to_entry.get_text()),
+                    forms,
+                }
+            }
+        }
+
+        #[cfg(test)]
+        mod tests {
+            use super::*;
+
+            fn record(form: &str, date: &str) -> FilingRecord {
+                FilingRecord { form: form.to_string(), date: date.to_string(), ..FilingRecord::default() }
+            }
+
+            #[test]
+            fn date_range_bounds_are_inclusive() {
+                let range = DateRange::parse("2024-01-01", "2024-03-31");
+                assert!(range.contains("2024-01-01"));
+                assert!(range.contains("2024-03-31"));
+                assert!(range.contains("2024-02-15"));
+                assert!(!range.contains("2023-12-31"));
+                assert!(!range.contains("2024-04-01"));
+            }
+
+            #[test]
+            fn date_range_missing_bounds_are_open_ended() {
+                let from_only = DateRange::parse("2024-01-01", "");
+                assert!(from_only.contains("2099-01-01"));
+                assert!(!from_only.contains("2023-12-31"));
+                let to_only = DateRange::parse("", "2024-01-01");
+                assert!(to_only.contains("1990-01-01"));
+                assert!(!to_only.contains("2024-01-02"));
+                assert!(DateRange::default().contains("2024-06-30"));
+            }
+
+            #[test]
+            fn date_range_ignores_invalid_input() {
+                let partial = DateRange::parse("2024-01", "not a date");
+                assert_eq!(partial, DateRange::default());
+                // Filing dates that can't be parsed are kept rather than dropped
+                assert!(DateRange::parse("2024-01-01", "2024-12-31").contains("unknown"));
+            }
+
+            #[test]
+            fn filters_drop_records_outside_the_range() {
+                let filters = FilingFilters { date_range: DateRange::parse("2024-01-01", "2024-12-31"), ..FilingFilters::default() };
+                let kept = filters.apply(vec![record("10-K", "2023-11-01"), record("10-Q", "2024-05-01"), record("8-K", "2025-01-02")]);
+                assert_eq!(kept, vec![record("10-Q", "2024-05-01")]);
+            }
+
+            fn forms(selected: &[&str]) -> FilingFilters {
+                FilingFilters { forms: selected.iter().map(|f| f.to_string()).collect(), ..FilingFilters::default() }
+            }
+
+            #[test]
+            fn empty_form_set_selects_every_form() {
+                let filters = forms(&[]);
+                assert!(filters.form_selected("10-K"));
+                assert!(filters.form_selected("4"));
+            }
+
+            #[test]
+            fn form_set_membership() {
+                let filters = forms(&["10-K", "8-K"]);
+                assert!(filters.form_selected("10-K"));
+                assert!(filters.form_selected(" 8-k "));
+                assert!(!filters.form_selected("10-Q"));
+                assert!(!filters.form_selected("4"));
+                // Amendments follow their base form
+                assert!(filters.form_selected("10-K/A"));
+                assert!(!filters.form_selected("10-Q/A"));
+            }
+
+            #[test]
+            fn filters_keep_only_selected_forms() {
+                let filters = forms(&["10-K"]);
+                let kept = filters.apply(vec![record("10-K", "2024-02-01"), record("10-Q", "2024-05-01"), record("4", "2024-05-02")]);
+                assert_eq!(kept, vec![record("10-K", "2024-02-01")]);
+            }
+
+            fn preset() -> FilterPreset {
+                FilterPreset {
+                    name: "annual".to_string(),
+                    forms: vec!["10-K".to_string(), "20-F".to_string()],
+                    from: "2020-01-01".to_string(),
+                    to: "2023-12-31".to_string(),
+                    query: "apple".to_string(),
+                }
+            }
+
+            #[test]
+            fn preset_round_trips_through_json() {
+                let preset = preset();
+                assert_eq!(FilterPreset::from_json(&preset.to_json().unwrap()).unwrap(), preset);
+                let saved_before_queries = r#"{"name": "old", "forms": ["8-K"], "from": "", "to": ""}"#;
+                assert_eq!(FilterPreset::from_json(saved_before_queries).unwrap().query, "");
+                assert!(FilterPreset::from_json("{").is_err());
+            }
+
+            #[test]
+            fn applying_a_preset_updates_the_widgets() {
+                // Needs a display; skipped where GTK can't start (e.g. headless CI)
+                if gtk::init().is_err() {
+                    return;
+                }
+                let pane = FilterPane::new();
+                let preset = preset();
+                pane.apply_preset(&preset);
+                assert_eq!(pane.from_entry.get_text().as_str(), "2020-01-01");
+                assert_eq!(pane.to_entry.get_text().as_str(), "2023-12-31");
+                assert_eq!(pane.search_entry.get_text().as_str(), "apple");
+                let forms: HashSet<String> = ["10-K", "20-F"].iter().map(|f| f.to_string()).collect();
+                assert_eq!(pane.filters().forms, forms);
+                assert_eq!(pane.current_preset(" annual "), preset);
+            }
+
+            #[test]
+            fn column_visibility_toggles_and_serializes() {
+                let mut columns = ColumnVisibility::default();
+                assert!(columns.is_visible("Company"));
+                columns.set_visible("Company", false);
+                columns.set_visible("AI Analysis", false);
+                columns.set_visible("Company", false);
+                columns.set_visible("AI Analysis", true);
+                assert_eq!(columns.hidden, vec!["Company".to_string()]);
+                assert!(!columns.is_visible("Company") && columns.is_visible("AI Analysis"));
+
+                let json = serde_json::to_string(&columns).unwrap();
+                assert_eq!(json, r#"{"hidden":["Company"]}"#);
+                assert_eq!(serde_json::from_str::<ColumnVisibility>(&json).unwrap(), columns);
+                // Older files without the field show every column
+                assert_eq!(serde_json::from_str::<ColumnVisibility>("{}").unwrap(), ColumnVisibility::default());
+            }
+
+            #[test]
+            fn column_visibility_persists_to_a_file() {
+                let path = std::env::temp_dir().join(format!("finfiles-columns-{}", std::process::id())).join("columns.json");
+                let mut columns = ColumnVisibility::default();
+                columns.set_visible("Document", false);
+                columns.save_to(&path).unwrap();
+                assert_eq!(ColumnVisibility::load_from(&path), columns);
+
+                fs::write(&path, "not json").unwrap();
+                assert_eq!(ColumnVisibility::load_from(&path), ColumnVisibility::default());
+                fs::remove_file(&path).unwrap();
+                assert_eq!(ColumnVisibility::load_from(&path), ColumnVisibility::default());
+            }
+
+            #[test]
+            fn date_keys_agree_across_formats() {
+                let key = date_sort_key("2024-01-31");
+                for same_day in ["20240131", "01/31/2024", "2024/01/31", " 2024-01-31T16:05:00Z "] {
+                    assert_eq!(date_sort_key(same_day), key, "{}", same_day);
+                }
+            }
+
+            #[test]
+            fn date_keys_sort_chronologically_not_lexically() {
+                let mut dates = vec!["01/15/2024", "2023-12-31", "20230105", "not a date", "2024/02/01", ""];
+                dates.sort_by_key(|date| date_sort_key(date));
+                assert_eq!(dates, vec!["not a date", "", "20230105", "2023-12-31", "01/15/2024", "2024/02/01"]);
+                assert_eq!(date_sort_key("2024-13-01"), i64::MIN);
+            }
+
+            #[test]
+            fn search_matches_any_column_case_insensitively() {
+                let row = ["10-K", "aapl-20230930.htm", "Apple Inc.", "Annual report"];
+                for query in ["10-k", "AAPL-2023", "apple inc", "ANNUAL", "  report  "] {
+                    assert!(filing_row_matches(&row, query), "{}", query);
+                }
+                for query in ["10-Q", "microsoft", "annual report 2023"] {
+                    assert!(!filing_row_matches(&row, query), "{}", query);
+                }
+            }
+
+            #[test]
+            fn blank_search_matches_every_row() {
+                assert!(filing_row_matches(&["8-K", "", "", ""], ""));
+                assert!(filing_row_matches(&[], "   "));
+                assert!(!filing_row_matches(&[], "8-K"));
+            }
+        }
+    }
+
+    pub mod analytics {
+        use super::ai::synonym_column;
+        use super::backend::FilingRecord;
+        use super::backend::DataState;
+        use gtk::prelude::*;
+        use gtk::{Box as GtkBox, ComboBoxText, DrawingArea, Label, Orientation};
+        use polars::prelude::*;
+        use std::cell::{Cell, RefCell};
+        use std::collections::BTreeMap;
+        use std::rc::Rc;
+
+        // Height of the chart drawing area in pixels
+        const CHART_HEIGHT: i32 = 180;
+        // Space reserved around the plot for labels
+        const CHART_MARGIN: f64 = 28.0;
+
+        // Metrics plotted in the financials view after a fetch
+        pub const DEFAULT_CHART_METRICS: [&str; 2] = ["revenue", "net income"];
+        // Series whose peak magnitude is this many times smaller than the largest move to the right axis
+        const DUAL_AXIS_RATIO: f64 = 10.0;
+        // Line colors for overlaid metrics, cycled in order
+        const SERIES_COLORS: [(f64, f64, f64); 5] = [
+            (0.26, 0.65, 0.96),
+            (0.96, 0.62, 0.26),
+            (0.40, 0.80, 0.45),
+            (0.90, 0.35, 0.45),
+            (0.70, 0.55, 0.95),
+        ];
+
+        // Which view the trends chart draws. The line view is the default.
+        #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+        pub enum ChartType {
+            #[default]
+            Line,
+            Bar,
+            // Financial metrics from the loaded DataFrame
+            Metrics,
+        }
+
+        impl ChartType {
+            pub fn label(&self) -> &'static str {
+                match self {
+                    ChartType::Line => "Trend",
+                    ChartType::Bar => "By form",
+                    ChartType::Metrics => "Financials",
+                }
+            }
+
+            fn id(&self) -> &'static str {
+                match self {
+                    ChartType::Line => "line",
+                    ChartType::Bar => "bar",
+                    ChartType::Metrics => "metrics",
+                }
+            }
+
+            fn from_id(id: &str) -> Self {
+                match id {
+                    "bar" => ChartType::Bar,
+                    "metrics" => ChartType::Metrics,
+                    _ => ChartType::Line,
+                }
+            }
+        }
+
+        // One metric's values per period (oldest first) and the same values mapped to 0..1
+        // on its axis. Periods the metric doesn't report are None.
+        #[derive(Debug, Clone, PartialEq)]
+        pub struct MetricSeries {
+            pub name: String,
+            pub values: Vec<Option<f64>>,
+            pub scaled: Vec<Option<f64>>,
+            pub right_axis: bool,
+        }
+
+        // Series prepared for plotting, plus the requested metrics that weren't found
+        #[derive(Debug, Clone, Default, PartialEq)]
+        pub struct MetricChart {
+            pub periods: Vec<String>,
+            pub series: Vec<MetricSeries>,
+            pub missing: Vec<String>,
+            pub left_range: Option<(f64, f64)>,
+            pub right_range: Option<(f64, f64)>,
+            // Shown instead of a chart when there is no data (loading, failed to load)
+            pub notice: Option<String>,
+        }
+
+        // Column for a requested metric: an exact column name, a concept name without the
+        // unit suffix ("Revenues" for "Revenues_USD_B"), or a plain-language synonym
+        fn metric_column(df: &DataFrame, metric: &str) -> Option<String> {
+            let wanted = metric.trim().to_lowercase();
+            df.get_column_names()
+                .iter()
+                .find(|name| {
+                    let lower = name.to_lowercase();
+                    lower == wanted || lower.starts_with(&format!("{}_", wanted))
+                })
+                .map(|name| name.to_string())
+                .or_else(|| synonym_column(df, &wanted))
+        }
+
+        // Value range of the given series, always including zero
+        fn axis_range<'a>(series: impl Iterator<Item = &'a MetricSeries>) -> Option<(f64, f64)> {
+            let values: Vec<f64> = series.flat_map(|s| s.values.iter().flatten().copied()).collect();
+            if values.is_empty() {
+                return None;
+            }
+            let lo = values.iter().copied().fold(0.0, f64::min);
+            let hi = values.iter().copied().fold(0.0, f64::max);
+            Some((lo, hi))
+        }
+
+        fn scale(value: f64, (lo, hi): (f64, f64)) -> f64 {
+            if hi > lo {
+                (value - lo) / (hi - lo)
+            } else {
+                0.0
+            }
+        }
+
+        // Pull the requested metrics out of a loader DataFrame and scale them for plotting.
+        // Periods come from the "quarter" column and are returned oldest first. Metrics that
+        // are missing or have no numeric values are listed in `missing`. Series share one
+        // axis unless their magnitudes differ by more than DUAL_AXIS_RATIO, in which case
+        // the smaller ones are scaled against a right-hand axis.
+        pub fn extract_metric_series(df: &DataFrame, metrics: &[&str]) -> MetricChart {
+            let mut periods: Vec<String> = match df.column("quarter").ok().and_then(|s| s.utf8().ok()) {
+                Some(quarters) => quarters.into_iter().map(|q| q.unwrap_or_default().to_string()).collect(),
+                None => (1..=df.height()).map(|i| i.to_string()).collect(),
+            };
+            periods.reverse();
+
+            let mut chart = MetricChart { periods, ..MetricChart::default() };
+            for metric in metrics {
+                let column = metric_column(df, metric)
+                    .and_then(|name| df.column(&name).ok().and_then(|c| c.cast(&DataType::Float64).ok()).map(|c| (name, c)));
+                let (name, column) = match column {
+                    Some(found) => found,
+                    None => {
+                        chart.missing.push(metric.to_string());
+                        continue;
+                    }
+                };
+                let mut values: Vec<Option<f64>> = match column.f64() {
+                    Ok(ca) => ca.into_iter().collect(),
+                    Err(_) => Vec::new(),
+                };
+                values.reverse();
+                if values.iter().all(Option::is_none) {
+                    chart.missing.push(metric.to_string());
+                    continue;
+                }
+                chart.series.push(MetricSeries { name, values, scaled: Vec::new(), right_axis: false });
+            }
+
+            let peak = |s: &MetricSeries| s.values.iter().flatten().fold(0.0_f64, |m, v| m.max(v.abs()));
+            let largest = chart.series.iter().map(peak).fold(0.0_f64, f64::max);
+            for series in &mut chart.series {
+                series.right_axis = largest > 0.0 && peak(series) * DUAL_AXIS_RATIO < largest;
+            }
+            chart.left_range = axis_range(chart.series.iter().filter(|s| !s.right_axis));
+            chart.right_range = axis_range(chart.series.iter().filter(|s| s.right_axis));
+            for series in &mut chart.series {
+                let range = if series.right_axis { chart.right_range } else { chart.left_range };
+                if let Some(range) = range {
+                    series.scaled = series.values.iter().map(|v| v.map(|v| scale(v, range))).collect();
+                }
+            }
+            chart
+        }
+
+        // Number of filings per month ("YYYY-MM"), oldest first. Records with a date
+        // shorter than a month prefix are skipped.
+        pub fn filings_per_month(records: &[FilingRecord]) -> Vec<(String, usize)> {
+            let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+            for rec in records {
+                if let Some(month) = rec.date.trim().get(..7) {
+                    *counts.entry(month.to_string()).or_insert(0) += 1;
+                }
+            }
+            counts.into_iter().collect()
+        }
+
+        // Number of filings per form type, most frequent first (ties in form order).
+        // Forms are compared case-insensitively and reported uppercase.
+        pub fn filing_counts_by_form(records: &[FilingRecord]) -> Vec<(String, usize)> {
+            let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+            for rec in records {
+                let form = rec.form.trim().to_uppercase();
+                if !form.is_empty() {
+                    *counts.entry(form).or_insert(0) += 1;
+                }
+            }
+            let mut counts: Vec<(String, usize)> = counts.into_iter().collect();
+            counts.sort_by(|a, b| b.1.cmp(&a.1));
+            counts
+        }
+
+        // Filing activity chart shown below the filings table, with a line/bar toggle.
+        // Switching views redraws from the records already loaded.
+        #[derive(Clone)]
+        pub struct FilingTrendsChart {
+            pub widget: GtkBox,
+            area: DrawingArea,
+            records: Rc<RefCell<Vec<FilingRecord>>>,
+            metrics: Rc<RefCell<MetricChart>>,
+            chart_type: Rc<Cell<ChartType>>,
+        }
+
+        impl FilingTrendsChart {
+            pub fn new() -> Self {
+                let widget = GtkBox::new(Orientation::Vertical, 4);
+                let area = DrawingArea::new();
+                area.set_size_request(-1, CHART_HEIGHT);
+                let records: Rc<RefCell<Vec<FilingRecord>>> = Rc::new(RefCell::new(Vec::new()));
+                let metrics = Rc::new(RefCell::new(MetricChart::default()));
+                let chart_type = Rc::new(Cell::new(ChartType::default()));
+
+                let type_combo = ComboBoxText::new();
+                for kind in [ChartType::Line, ChartType::Bar, ChartType::Metrics] {
+                    type_combo.append(Some(kind.id()), kind.label());
+                }
+                type_combo.set_active_id(Some(ChartType::default().id()));
+                type_combo.set_tooltip_text(Some("Filings over time, filing counts by form type, or financial metrics"));
+                {
+                    let area = area.clone();
+                    let chart_type = chart_type.clone();
+                    type_combo.connect_changed(move |combo| {
+                        let id = combo.get_active_id().map(|id| id.to_string()).unwrap_or_default();
+                        chart_type.set(ChartType::from_id(&id));
+                        area.queue_draw();
+                    });
+                }
+
+                {
+                    let records = records.clone();
+                    let metrics = metrics.clone();
+                    let chart_type = chart_type.clone();
+                    area.connect_draw(move |area, cr| {
+                        let width = area.get_allocated_width() as f64;
+                        let height = area.get_allocated_height() as f64;
+                        let records = records.borrow();
+                        match chart_type.get() {
+                            ChartType::Line => draw_counts(cr, width, height, &filings_per_month(&records), false),
+                            ChartType::Bar => draw_counts(cr, width, height, &filing_counts_by_form(&records), true),
+                            ChartType::Metrics => draw_metrics(cr, width, height, &metrics.borrow()),
+                        }
+                        gtk::Inhibit(false)
+                    });
+                }
+
+                let header = GtkBox::new(Orientation::Horizontal, 6);
+                header.pack_start(&Label::new(Some("Filing activity")), false, false, 0);
+                header.pack_end(&type_combo, false, false, 0);
+                widget.pack_start(&header, false, false, 0);
+                widget.pack_start(&area, true, true, 0);
+
+                FilingTrendsChart { widget, area, records, metrics, chart_type }
+            }
+
+            // Replace the charted records and redraw
+            pub fn update(&self, records: &[FilingRecord]) {
+                *self.records.borrow_mut() = records.to_vec();
+                self.area.queue_draw();
+            }
+
+            // Replace the metrics shown in the financials view and redraw
+            pub fn update_metrics(&self, df: &DataFrame, metrics: &[&str]) {
+                *self.metrics.borrow_mut() = extract_metric_series(df, metrics);
+                self.area.queue_draw();
+            }
+
+            // Chart the default metrics of loaded data, else the state's message
+            pub fn show_data_state(&self, data: &DataState) {
+                match data {
+                    DataState::Loaded(df) => self.update_metrics(df, &DEFAULT_CHART_METRICS),
+                    other => {
+                        *self.metrics.borrow_mut() = MetricChart { notice: other.message(), ..MetricChart::default() };
+                        self.area.queue_draw();
+                    }
+                }
+            }
+
+            pub fn chart_type(&self) -> ChartType {
+                self.chart_type.get()
+            }
+        }
+
+        // Draw labelled counts as a polyline (one point per label) or as bars
+        fn draw_counts(cr: &gtk::cairo::Context, width: f64, height: f64, points: &[(String, usize)], bars: bool) {
+            cr.set_source_rgb(0.12, 0.12, 0.14);
+            cr.paint();
+            cr.set_font_size(10.0);
+            if points.is_empty() {
+                cr.set_source_rgb(0.6, 0.6, 0.6);
+                cr.move_to(CHART_MARGIN, height / 2.0);
+                cr.show_text("No filings loaded.");
+                return;
+            }
+
+            let plot_w = (width - 2.0 * CHART_MARGIN).max(1.0);
+            let plot_h = (height - 2.0 * CHART_MARGIN).max(1.0);
+            let max = points.iter().map(|(_, n)| *n).max().unwrap_or(1).max(1) as f64;
+            let step = plot_w / points.len() as f64;
+            let baseline = height - CHART_MARGIN;
+
+            // Axis
+            cr.set_source_rgb(0.4, 0.4, 0.4);
+            cr.set_line_width(1.0);
+            cr.move_to(CHART_MARGIN, CHART_MARGIN);
+            cr.line_to(CHART_MARGIN, baseline);
+            cr.line_to(width - CHART_MARGIN, baseline);
+            cr.stroke();
+            cr.move_to(4.0, CHART_MARGIN + 4.0);
+            cr.show_text(&format!("{}", max as usize));
+
+            cr.set_source_rgb(0.26, 0.65, 0.96);
+            cr.set_line_width(2.0);
+            for (i, (_, count)) in points.iter().enumerate() {
+                let x = CHART_MARGIN + step * (i as f64 + 0.5);
+                let y = baseline - plot_h * (*count as f64 / max);
+                if bars {
+                    cr.rectangle(x - step * 0.35, y, step * 0.7, baseline - y);
+                    cr.fill();
+                } else if i == 0 {
+                    cr.move_to(x, y);
+                } else {
+                    cr.line_to(x, y);
+                }
+            }
+            if !bars {
+                cr.stroke();
+            }
+
+            // Label every point when they fit, otherwise roughly every 60px
+            let every = ((60.0 / step).ceil() as usize).max(1);
+            cr.set_source_rgb(0.8, 0.8, 0.8);
+            for (i, (label, _)) in points.iter().enumerate().step_by(every) {
+                let x = CHART_MARGIN + step * (i as f64 + 0.5);
+                cr.move_to(x - 14.0, height - CHART_MARGIN / 2.0 + 4.0);
+                cr.show_text(label);
+            }
+        }
+
+        // Draw each metric as its own colored line with a legend. Left and right axis
+        // extremes are labelled; missing metrics are noted at the end of the legend.
+        fn draw_metrics(cr: &gtk::cairo::Context, width: f64, height: f64, chart: &MetricChart) {
+            cr.set_source_rgb(0.12, 0.12, 0.14);
+            cr.paint();
+            cr.set_font_size(10.0);
+            if chart.series.is_empty() {
+                cr.set_source_rgb(0.6, 0.6, 0.6);
+                cr.move_to(CHART_MARGIN, height / 2.0);
+                if let Some(notice) = &chart.notice {
+                    cr.show_text(notice);
+                } else if chart.missing.is_empty() {
+                    cr.show_text("No financial data loaded.");
+                } else {
+                    cr.show_text(&format!("Not available: {}", chart.missing.join(", ")));
+                }
+                return;
+            }
+
+            let plot_w = (width - 2.0 * CHART_MARGIN).max(1.0);
+            let plot_h = (height - 2.0 * CHART_MARGIN).max(1.0);
+            let step = plot_w / chart.periods.len().max(1) as f64;
+            let baseline = height - CHART_MARGIN;
+
+            cr.set_source_rgb(0.4, 0.4, 0.4);
+            cr.set_line_width(1.0);
+            cr.move_to(CHART_MARGIN, CHART_MARGIN);
+            cr.line_to(CHART_MARGIN, baseline);
+            cr.line_to(width - CHART_MARGIN, baseline);
+            if chart.right_range.is_some() {
+                cr.line_to(width - CHART_MARGIN, CHART_MARGIN);
+            }
+            cr.stroke();
+            if let Some((lo, hi)) = chart.left_range {
+                cr.move_to(2.0, CHART_MARGIN + 4.0);
+                cr.show_text(&format!("{:.1}", hi));
+                cr.move_to(2.0, baseline);
+                cr.show_text(&format!("{:.1}", lo));
+            }
+            if let Some((lo, hi)) = chart.right_range {
+                cr.move_to(width - CHART_MARGIN + 2.0, CHART_MARGIN + 4.0);
+                cr.show_text(&format!("{:.1}", hi));
+                cr.move_to(width - CHART_MARGIN + 2.0, baseline);
+                cr.show_text(&format!("{:.1}", lo));
+            }
+
+            cr.set_line_width(2.0);
+            for (i, series) in chart.series.iter().enumerate() {
+                let (r, g, b) = SERIES_COLORS[i % SERIES_COLORS.len()];
+                cr.set_source_rgb(r, g, b);
+                // Gaps in a series break the line rather than dropping to zero
+                let mut drawing = false;
+                for (j, value) in series.scaled.iter().enumerate() {
+                    let x = CHART_MARGIN + step * (j as f64 + 0.5);
+                    match value {
+                        Some(v) => {
+                            let y = baseline - plot_h * v;
+                            if drawing {
+                                cr.line_to(x, y);
+                            } else {
+                                cr.move_to(x, y);
+                            }
+                            drawing = true;
+                        }
+                        None => drawing = false,
+                    }
+                }
+                cr.stroke();
+            }
+
+            // Legend along the top edge
+            let mut x = CHART_MARGIN;
+            for (i, series) in chart.series.iter().enumerate() {
+                let (r, g, b) = SERIES_COLORS[i % SERIES_COLORS.len()];
+                cr.set_source_rgb(r, g, b);
+                cr.rectangle(x, 6.0, 10.0, 10.0);
+                cr.fill();
+                let label = if series.right_axis { format!("{} (right)", series.name) } else { series.name.clone() };
+                cr.move_to(x + 14.0, 15.0);
+                cr.show_text(&label);
+                x += 24.0 + 6.0 * label.len() as f64;
+            }
+            if !chart.missing.is_empty() {
+                cr.set_source_rgb(0.6, 0.6, 0.6);
+                cr.move_to(x, 15.0);
+                cr.show_text(&format!("Not available: {}", chart.missing.join(", ")));
+            }
+
+            cr.set_source_rgb(0.8, 0.8, 0.8);
+            for (i, period) in chart.periods.iter().enumerate() {
+                let x = CHART_MARGIN + step * (i as f64 + 0.5);
+                cr.move_to(x - 14.0, height - CHART_MARGIN / 2.0 + 4.0);
+                cr.show_text(period);
+            }
+        }
+
+        #[cfg(test)]
+        mod tests {
+            use super::*;
+
+            fn filing(form: &str, date: &str) -> FilingRecord {
+                FilingRecord { form: form.to_string(), date: date.to_string(), ..FilingRecord::default() }
+            }
+
+            #[test]
+            fn forms_are_counted_most_frequent_first() {
+                let records = vec![
+                    filing("8-K", "2024-03-01"),
+                    filing("10-Q", "2024-05-02"),
+                    filing(" 8-k ", "2024-05-20"),
+                    filing("4", "2024-05-21"),
+                    filing("10-Q", "2024-08-01"),
+                    filing("8-K", "2024-08-02"),
+                    filing("", "2024-08-03"),
+                ];
+                assert_eq!(
+                    filing_counts_by_form(&records),
+                    vec![("8-K".to_string(), 3), ("10-Q".to_string(), 2), ("4".to_string(), 1)]
+                );
+                assert!(filing_counts_by_form(&[]).is_empty());
+            }
+
+            #[test]
+            fn equal_counts_keep_form_order() {
+                let records = vec![filing("S-1", ""), filing("10-K", ""), filing("8-K", ""), filing("10-K", ""), filing("S-1", "")];
+                assert_eq!(
+                    filing_counts_by_form(&records),
+                    vec![("10-K".to_string(), 2), ("S-1".to_string(), 2), ("8-K".to_string(), 1)]
+                );
+            }
+
+            #[test]
+            fn filings_are_counted_per_month_oldest_first() {
+                let records = vec![filing("8-K", "2024-05-20"), filing("4", "2024-03-01"), filing("10-Q", "2024-05-02"), filing("8-K", "2024")];
+                assert_eq!(filings_per_month(&records), vec![("2024-03".to_string(), 1), ("2024-05".to_string(), 2)]);
+            }
+
+            #[test]
+            fn chart_type_ids_round_trip() {
+                for chart_type in [ChartType::Line, ChartType::Bar, ChartType::Metrics] {
+                    assert_eq!(ChartType::from_id(chart_type.id()), chart_type);
+                }
+                assert_eq!(ChartType::from_id("pie"), ChartType::Line);
+            }
+
+            // Loader-shaped frame: newest quarter first, concept columns with unit suffixes
+            fn financials_frame() -> DataFrame {
+                DataFrame::new(vec![
+                    Series::new("quarter", &["2024Q2", "2024Q1", "2023Q4"]),
+                    Series::new("Revenues_USD_B", &[90.8, 119.6, 89.5]),
+                    Series::new("NetIncomeLoss_USD_B", &[23.6, 33.9, 22.9]),
+                    Series::new("EarningsPerShareDiluted_USD/shares", &[1.53, 2.18, 1.46]),
+                    Series::new("Goodwill_USD_B", &[None::<f64>, None, None]),
+                ])
+                .unwrap()
+            }
+
+            fn close(actual: &[Option<f64>], expected: &[f64]) -> bool {
+                actual.len() == expected.len()
+                    && actual.iter().zip(expected).all(|(a, e)| a.map_or(false, |a| (a - e).abs() < 1e-9))
+            }
+
+            #[test]
+            fn metric_series_are_oldest_first_and_scaled_per_axis() {
+                let df = financials_frame();
+                let chart = extract_metric_series(&df, &["revenue", "net income", "eps", "goodwill", "ebitda"]);
+                assert_eq!(chart.periods, vec!["2023Q4", "2024Q1", "2024Q2"]);
+                let names: Vec<&str> = chart.series.iter().map(|s| s.name.as_str()).collect();
+                assert_eq!(names, vec!["Revenues_USD_B", "NetIncomeLoss_USD_B", "EarningsPerShareDiluted_USD/shares"]);
+                // Goodwill has no values and EBITDA no column
+                assert_eq!(chart.missing, vec!["goodwill", "ebitda"]);
+
+                let revenue = &chart.series[0];
+                assert_eq!(revenue.values, vec![Some(89.5), Some(119.6), Some(90.8)]);
+                assert!(!revenue.right_axis);
+                assert!(close(&revenue.scaled, &[89.5 / 119.6, 1.0, 90.8 / 119.6]));
+
+                // EPS is too small to read against revenue, so it gets the right axis
+                let eps = &chart.series[2];
+                assert!(eps.right_axis && !chart.series[1].right_axis);
+                assert_eq!(chart.left_range, Some((0.0, 119.6)));
+                assert_eq!(chart.right_range, Some((0.0, 2.18)));
+                assert!(close(&eps.scaled, &[1.46 / 2.18, 1.0, 1.53 / 2.18]));
+            }
+
+            #[test]
+            fn losses_extend_the_axis_below_zero() {
+                let df = DataFrame::new(vec![Series::new("NetIncomeLoss_USD_B", &[10.0, -5.0])]).unwrap();
+                let chart = extract_metric_series(&df, &DEFAULT_CHART_METRICS);
+                // Without a quarter column periods are numbered
+                assert_eq!(chart.periods, vec!["2", "1"]);
+                assert_eq!(chart.missing, vec!["revenue"]);
+                assert_eq!(chart.left_range, Some((-5.0, 10.0)));
+                assert_eq!(chart.right_range, None);
+                assert!(close(&chart.series[0].scaled, &[0.0, 1.0]));
+            }
+
+            #[test]
+            fn exact_and_unsuffixed_column_names_match() {
+                let df = financials_frame();
+                assert_eq!(metric_column(&df, "Revenues_USD_B").as_deref(), Some("Revenues_USD_B"));
+                assert_eq!(metric_column(&df, " revenues ").as_deref(), Some("Revenues_USD_B"));
+                assert_eq!(metric_column(&df, "ebitda"), None);
+            }
+        }
+    }
+
+    pub mod stats {
+        use polars::prelude::*;
+
+        // Per-column statistics behind the summary, metric, forecast and anomaly answers.
+        // Missing values are skipped; `periods` still counts them.
+        #[derive(Debug, Clone, Copy, Default, PartialEq)]
+        pub struct ColumnStats {
+            // Rows in the column, including missing values
+            pub periods: usize,
+            // Rows with a value
+            pub count: usize,
+            pub sum: f64,
+            pub mean: f64,
+            // Last reported value (0 when the column is empty) and its row
+            pub most_recent: f64,
+            pub most_recent_row: Option<usize>,
+            // Population standard deviation
+            pub std_dev: f64,
+            pub min: f64,
+            pub max: f64,
+        }
+
+        // Summarize a numeric column. Returns None for non-numeric columns.
+        pub fn column_summary(series: &Series) -> Option<ColumnStats> {
+            let chunked = series.f64().ok()?;
+            let values: Vec<f64> = chunked.into_iter().flatten().collect();
+            let most_recent_row = chunked.into_iter().enumerate().filter(|(_, v)| v.is_some()).map(|(i, _)| i).last();
+            Some(ColumnStats { periods: chunked.len(), most_recent_row, ..summarize(&values) })
+        }
+
+        // Summarize a slice of reported values
+        pub fn summarize(values: &[f64]) -> ColumnStats {
+            if values.is_empty() {
+                return ColumnStats::default();
+            }
+            let sum: f64 = values.iter().sum();
+            let mean = sum / values.len() as f64;
+            ColumnStats {
+                periods: values.len(),
+                count: values.len(),
+                sum,
+                mean,
+                most_recent: values[values.len() - 1],
+                most_recent_row: Some(values.len() - 1),
+                std_dev: std_dev(values, mean),
+                min: values.iter().copied().fold(f64::INFINITY, f64::min),
+                max: values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            }
+        }
+
+        // Population standard deviation around a precomputed mean
+        pub fn std_dev(values: &[f64], mean: f64) -> f64 {
+            if values.is_empty() {
+                return 0.0;
+            }
+            (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64).sqrt()
+        }
+
+        // Values from periods where both series were reported, in order
+        pub fn complete_pairs(x: &[Option<f64>], y: &[Option<f64>]) -> (Vec<f64>, Vec<f64>) {
+            x.iter()
+                .zip(y)
+                .filter_map(|(a, b)| Some(((*a)?, (*b)?)))
+                .unzip()
+        }
+
+        // Least-squares slope of values against their index (0 when fewer than two values)
+        pub fn trend_slope(values: &[f64]) -> f64 {
+            let n = values.len() as f64;
+            if values.len() < 2 {
+                return 0.0;
+            }
+            let mean_x = (n - 1.0) / 2.0;
+            let mean_y = values.iter().sum::<f64>() / n;
+            let mut num = 0.0;
+            let mut den = 0.0;
+            for (i, y) in values.iter().enumerate() {
+                let dx = i as f64 - mean_x;
+                num += dx * (y - mean_y);
+                den += dx * dx;
+            }
+            if den == 0.0 { 0.0 } else { num / den }
+        }
+
+        // Continue the least-squares line `horizon` periods past the last value
+        pub fn linear_projection(values: &[f64], horizon: usize) -> Vec<f64> {
+            if values.is_empty() {
+                return Vec::new();
+            }
+            let n = values.len() as f64;
+            let slope = trend_slope(values);
+            let intercept = values.iter().sum::<f64>() / n - slope * (n - 1.0) / 2.0;
+            (0..horizon).map(|h| intercept + slope * (n + h as f64)).collect()
+        }
+
+        // Repeat the value from one season earlier (the last `season` values, cycled)
+        pub fn seasonal_naive(values: &[f64], season: usize, horizon: usize) -> Vec<f64> {
+            if season == 0 || values.len() < season {
+                return Vec::new();
+            }
+            let last_season = &values[values.len() - season..];
+            (0..horizon).map(|h| last_season[h % season]).collect()
+        }
+
+        // Linear trend plus an additive seasonal offset per position in the season. Each
+        // offset is the mean residual from the trend at that position, centered to sum to 0.
+        pub fn seasonal_additive(values: &[f64], season: usize, horizon: usize) -> Vec<f64> {
+            if season == 0 || values.len() < season {
+                return Vec::new();
+            }
+            let n = values.len();
+            let slope = trend_slope(values);
+            let intercept = values.iter().sum::<f64>() / n as f64 - slope * (n as f64 - 1.0) / 2.0;
+            let mut totals = vec![0.0; season];
+            let mut counts = vec![0usize; season];
+            for (t, v) in values.iter().enumerate() {
+                totals[t % season] += v - (intercept + slope * t as f64);
+                counts[t % season] += 1;
+            }
+            let mut offsets: Vec<f64> = totals.iter().zip(&counts).map(|(total, count)| total / *count as f64).collect();
+            let mean_offset = offsets.iter().sum::<f64>() / season as f64;
+            for offset in &mut offsets {
+                *offset -= mean_offset;
+            }
+            (n..n + horizon).map(|t| intercept + slope * t as f64 + offsets[t % season]).collect()
+        }
+
+        // Linearly interpolated quantile of already-sorted values
+        pub fn quantile(sorted: &[f64], q: f64) -> f64 {
+            if sorted.is_empty() {
+                return 0.0;
+            }
+            let pos = q * (sorted.len() - 1) as f64;
+            let lo = pos.floor() as usize;
+            let hi = pos.ceil() as usize;
+            sorted[lo] + (sorted[hi] - sorted[lo]) * (pos - lo as f64)
+        }
+
+        // Bounds `sensitivity` standard deviations either side of the mean
+        pub fn zscore_bounds(stats: &ColumnStats, sensitivity: f64) -> (f64, f64) {
+            (stats.mean - sensitivity * stats.std_dev, stats.mean + sensitivity * stats.std_dev)
+        }
+
+        // Tukey fences `sensitivity` interquartile ranges outside the first and third quartiles
+        pub fn iqr_bounds(values: &[f64], sensitivity: f64) -> (f64, f64) {
+            let mut sorted = values.to_vec();
+            sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+            let q1 = quantile(&sorted, 0.25);
+            let q3 = quantile(&sorted, 0.75);
+            let iqr = q3 - q1;
+            (q1 - sensitivity * iqr, q3 + sensitivity * iqr)
+        }
+
+        // Distance a value lies outside [lower, upper] (0 when inside)
+        pub fn excess(value: f64, lower: f64, upper: f64) -> f64 {
+            if value < lower { lower - value } else if value > upper { value - upper } else { 0.0 }
+        }
+
+        #[cfg(test)]
+        mod tests {
+            use super::*;
+
+            #[test]
+            fn column_summary_of_empty_column() {
+                let series = Series::new("revenue", Vec::<f64>::new());
+                let stats = column_summary(&series).unwrap();
+                assert_eq!(stats.periods, 0);
+                assert_eq!(stats.count, 0);
+                assert_eq!(stats.most_recent_row, None);
+                assert_eq!(stats.sum, 0.0);
+            }
+
+            #[test]
+            fn column_summary_of_single_value() {
+                let series = Series::new("revenue", &[12.5]);
+                let stats = column_summary(&series).unwrap();
+                assert_eq!(stats.periods, 1);
+                assert_eq!(stats.count, 1);
+                assert_eq!(stats.sum, 12.5);
+                assert_eq!(stats.mean, 12.5);
+                assert_eq!(stats.most_recent, 12.5);
+                assert_eq!(stats.most_recent_row, Some(0));
+                assert_eq!(stats.std_dev, 0.0);
+                assert_eq!((stats.min, stats.max), (12.5, 12.5));
+            }
+
+            #[test]
+            fn column_summary_of_all_null_column() {
+                let series = Series::new("revenue", &[None::<f64>, None, None]);
+                let stats = column_summary(&series).unwrap();
+                assert_eq!(stats.periods, 3);
+                assert_eq!(stats.count, 0);
+                assert_eq!(stats.most_recent_row, None);
+                assert_eq!(stats.mean, 0.0);
+            }
+
+            #[test]
+            fn column_summary_skips_missing_values() {
+                let series = Series::new("revenue", &[Some(1.0), None, Some(3.0), None]);
+                let stats = column_summary(&series).unwrap();
+                assert_eq!(stats.periods, 4);
+                assert_eq!(stats.count, 2);
+                assert_eq!(stats.mean, 2.0);
+                assert_eq!(stats.most_recent, 3.0);
+                assert_eq!(stats.most_recent_row, Some(2));
+            }
+
+            #[test]
+            fn column_summary_rejects_non_numeric_columns() {
+                let series = Series::new("quarter", &["Q1", "Q2"]);
+                assert!(column_summary(&series).is_none());
+            }
+        }
+    }
+
+    pub mod ai {
+        use super::error::*;
+        use polars::prelude::*;
+        use async_trait::async_trait;
+        use super::security::sanitize_ticker;
+        use super::data_ingestion::{compare_periods, period_sort_key, period_source, response_error, FactSource, MetricUnit};
+        use super::stats::{
+            column_summary, complete_pairs, excess, iqr_bounds, linear_projection, seasonal_additive, seasonal_naive, summarize,
+            trend_slope, zscore_bounds,
+        };
+        use futures::stream::{self, BoxStream, StreamExt};
+        use onnxruntime::{environment::Environment, session::Session, tensor::OrtOwnedTensor, LoggingLevel};
+        use serde::{Deserialize, Serialize};
+        use std::collections::HashMap;
+        use std::fmt;
+        use std::fs::{self, File};
+        use std::io::Read;
+        use std::future::Future;
+        use std::path::{Path, PathBuf};
+        use std::sync::{Arc, Mutex};
+        use std::time::Duration;
+
+        // Run an analysis, giving up with an AIModule error once `limit` has passed
+        pub async fn with_timeout<T>(limit: Duration, analysis: impl Future<Output = Result<T>>) -> Result<T> {
+            tokio::time::timeout(limit, analysis)
+                .await
+                .map_err(|_| FinAIError::AIModule(format!("timed out after {}s", limit.as_secs())))?
+        }
+
+        // Shown instead of an analysis when the loaded frame has nothing to analyze
+        pub const NO_METRICS_MESSAGE: &str = "No financial metrics were found for this ticker.";
+
+        // Check that a frame can be analyzed: at least one row and at least one numeric
+        // metric column besides "quarter". Otherwise fails with DataParsing(NO_METRICS_MESSAGE).
+        pub fn validate_frame(df: &DataFrame) -> Result<()> {
+            let has_metric = df.get_columns().iter().any(|col| col.name() != "quarter" && col.dtype().is_numeric());
+            if df.height() == 0 || !has_metric {
+                return Err(FinAIError::DataParsing(NO_METRICS_MESSAGE.to_string()));
+            }
+            Ok(())
+        }
+
+        // Whether an analysis failed because the frame had no metrics (see validate_frame)
+        pub fn is_missing_metrics(e: &FinAIError) -> bool {
+            matches!(e, FinAIError::DataParsing(message) if message == NO_METRICS_MESSAGE)
+        }
+
+        // Analysis error as shown to the user; a frame without metrics gets the plain message
+        pub fn error_message(e: &FinAIError) -> String {
+            if is_missing_metrics(e) {
+                NO_METRICS_MESSAGE.to_string()
+            } else {
+                format!("Error: {}", e)
+            }
+        }
+
+        // Create a backend for a long-lived control. A failure is logged and becomes the
+        // message the control shows each time it's used, instead of a panic.
+        pub fn init_backend<T>(name: &str, init: impl FnOnce() -> Result<T>) -> std::result::Result<Arc<T>, String> {
+            init().map(Arc::new).map_err(|e| {
+                log::error!("Failed to initialize {}: {}", name, e);
+                format!("{} is unavailable: {}", name, e)
+            })
+        }
+
+        // Trait for pluggable AI/ML backends.
+        #[async_trait]
+        pub trait FinancialAIModule: Send + Sync {
+            // Analyze a DataFrame with a natural language query.
+            async fn analyze(&self, df: &DataFrame, query: &str) -> Result<String>;
+            // Machine-readable variant of `analyze`. Backends without structured output
+            // return their text as `AnalysisResult::Text`.
+            async fn analyze_structured(&self, df: &DataFrame, query: &str) -> Result<AnalysisResult> {
+                Ok(AnalysisResult::Text { text: self.analyze(df, query).await? })
+            }
+            // Streaming variant of `analyze` yielding the response chunk by chunk.
+            // Backends that can't stream return the whole response as a single chunk.
+            async fn analyze_stream(&self, df: &DataFrame, query: &str) -> Result<BoxStream<'static, Result<String>>> {
+                let text = self.analyze(df, query).await?;
+                Ok(stream::once(async move { Ok(text) }).boxed())
+            }
+            fn backend_name(&self) -> &'static str;
+        }
+
+        // Concatenate a response stream, stopping at the first error
+        pub async fn collect_stream(mut chunks: BoxStream<'static, Result<String>>) -> Result<String> {
+            let mut text = String::new();
+            while let Some(chunk) = chunks.next().await {
+                text.push_str(&chunk?);
+            }
+            Ok(text)
+        }
+
+        // Responses kept by the chat's analysis cache before the least recently used is dropped
+        pub const DEFAULT_ANALYSIS_CACHE_SIZE: usize = 64;
+
+        // Hash of a DataFrame's column names, types and values, so any change to the data
+        // gives a different cache key
+        pub fn dataframe_hash(df: &DataFrame) -> u64 {
+            use std::hash::{Hash, Hasher};
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            df.shape().hash(&mut hasher);
+            for series in df.get_columns() {
+                series.name().hash(&mut hasher);
+                series.dtype().to_string().hash(&mut hasher);
+                for value in series.iter() {
+                    value.to_string().hash(&mut hasher);
+                }
+            }
+            hasher.finish()
+        }
+
+        // What an analysis depends on: backend, query (case and spacing ignored) and data
+        #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+        pub struct AnalysisKey {
+            pub backend: String,
+            pub query: String,
+            pub data_hash: u64,
+        }
+
+        impl AnalysisKey {
+            pub fn new(backend: &str, query: &str, df: &DataFrame) -> Self {
+                Self {
+                    backend: backend.to_string(),
+                    query: query.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase(),
+                    data_hash: dataframe_hash(df),
+                }
+            }
+
+            // Key for a multi-ticker comparison; the hash covers every ticker and its frame
+            pub fn for_frames(backend: &str, query: &str, frames: &[(String, DataFrame)]) -> Self {
+                use std::hash::{Hash, Hasher};
+                let mut hasher = std::collections::hash_map::DefaultHasher::new();
+                for (ticker, df) in frames {
+                    ticker.hash(&mut hasher);
+                    dataframe_hash(df).hash(&mut hasher);
+                }
+                Self { data_hash: hasher.finish(), ..Self::new(backend, query, &DataFrame::default()) }
+            }
+        }
+
+        // Analysis cache shared between the chat windows and the main window's refresh
+        pub type SharedAnalysisCache = Arc<Mutex<AnalysisCache>>;
+
+        // Bounded least-recently-used cache of analysis responses
+        #[derive(Debug, Clone)]
+        pub struct AnalysisCache {
+            capacity: usize,
+            // Most recently used last
+            entries: Vec<(AnalysisKey, String)>,
+        }
+
+        impl Default for AnalysisCache {
+            fn default() -> Self {
+                Self::new(DEFAULT_ANALYSIS_CACHE_SIZE)
+            }
+        }
+
+        impl AnalysisCache {
+            pub fn new(capacity: usize) -> Self {
+                Self { capacity: capacity.max(1), entries: Vec::new() }
+            }
+
+            // Cached response for the key, marking it most recently used
+            pub fn get(&mut self, key: &AnalysisKey) -> Option<String> {
+                let pos = self.entries.iter().position(|(k, _)| k == key)?;
+                let entry = self.entries.remove(pos);
+                let response = entry.1.clone();
+                self.entries.push(entry);
+                Some(response)
+            }
+
+            // Store a response, evicting the least recently used entry when full
+            pub fn put(&mut self, key: AnalysisKey, response: String) {
+                self.entries.retain(|(k, _)| *k != key);
+                if self.entries.len() >= self.capacity {
+                    self.entries.remove(0);
+                }
+                self.entries.push((key, response));
+            }
+
+            pub fn len(&self) -> usize {
+                self.entries.len()
+            }
+
+            pub fn is_empty(&self) -> bool {
+                self.entries.is_empty()
+            }
+
+            // Drop every entry; returns how many were removed
+            pub fn clear(&mut self) -> usize {
+                let removed = self.entries.len();
+                self.entries.clear();
+                removed
+            }
+        }
+
+        // Drain complete server-sent events from `buffer`, returning their `data:` payloads.
+        // A trailing partial event stays in the buffer until more bytes arrive.
+        pub fn drain_sse_events(buffer: &mut String) -> Vec<String> {
+            let mut events = Vec::new();
+            while let Some(end) = buffer.find("\n\n") {
+                let event: String = buffer.drain(..end + 2).collect();
+                let data: Vec<&str> = event
+                    .lines()
+                    .filter_map(|line| line.strip_prefix("data:"))
+                    .map(|d| d.strip_prefix(' ').unwrap_or(d))
+                    .collect();
+                if !data.is_empty() {
+                    events.push(data.join("\n"));
+                }
+            }
+            events
+        }
+
+        // Built-in aliases for lowercase us-gaap concept names. Several aliases may map to
+        // one concept; user entries from synonyms.json are added on top.
+        const DEFAULT_SYNONYMS: &[(&str, &[&str])] = &[
+            ("revenues", &["revenue", "revenues", "sales", "net sales", "turnover", "top line"]),
+            ("netincomeloss", &["net income", "net loss", "profit", "net profit", "earnings", "bottom line"]),
+            ("earningspersharediluted", &["eps", "diluted eps", "earnings per share", "diluted earnings per share"]),
+            ("earningspersharebasic", &["basic eps", "basic earnings per share"]),
+            ("commonstocksharesoutstanding", &["shares outstanding", "share count", "outstanding shares"]),
+            ("weightedaveragenumberofdilutedsharesoutstanding", &["diluted shares", "diluted share count"]),
+            ("assets", &["assets", "total assets"]),
+            ("liabilities", &["liabilities", "total liabilities"]),
+            ("cashandcashequivalentsatcarryingvalue", &["cash", "cash and equivalents", "cash on hand"]),
+            ("operatingcashflow", &["operating cash flow", "cash from operations"]),
+        ];
+
+        // Alias -> canonical metric table, searched longest alias first so "earnings per
+        // share" wins over "earnings" and "operating cash flow" over "cash"
+        #[derive(Debug, Clone, Default, PartialEq)]
+        pub struct SynonymTable {
+            // (alias, canonical), both lowercase, longest alias first
+            entries: Vec<(String, String)>,
+        }
+
+        impl SynonymTable {
+            pub fn defaults() -> Self {
+                let mut table = Self::default();
+                for (canonical, aliases) in DEFAULT_SYNONYMS {
+                    for alias in *aliases {
+                        table.insert(alias, canonical);
+                    }
+                }
+                table
+            }
+
+            // User file: a JSON object of canonical metric -> list of aliases
+            pub fn user_path() -> Option<PathBuf> {
+                dirs::config_dir().map(|dir| dir.join("finfiles").join("synonyms.json"))
+            }
+
+            // Defaults plus the user's synonyms.json, if present and valid
+            pub fn load() -> Self {
+                let mut table = Self::defaults();
+                if let Some(path) = Self::user_path() {
+                    if let Ok(json) = fs::read_to_string(&path) {
+                        match table.with_overrides_json(&json) {
+                            Ok(merged) => table = merged,
+                            Err(e) => log::warn!("Ignoring {}: {}", path.display(), e),
+                        }
+                    }
+                }
+                table
+            }
+
+            // Add aliases from JSON; an alias already in the table is re-pointed
+            pub fn with_overrides_json(mut self, json: &str) -> Result<Self> {
+                let overrides: HashMap<String, Vec<String>> = serde_json::from_str(json)
+                    .map_err(|e| FinAIError::Config(format!("Invalid synonyms file: {e}")))?;
+                for (canonical, aliases) in &overrides {
+                    for alias in aliases {
+                        self.insert(alias, canonical);
+                    }
+                }
+                Ok(self)
+            }
+
+            pub fn insert(&mut self, alias: &str, canonical: &str) {
+                let alias = alias.trim().to_lowercase();
+                let canonical = canonical.trim().to_lowercase();
+                if alias.is_empty() || canonical.is_empty() {
+                    return;
+                }
+                self.entries.retain(|(a, _)| *a != alias);
+                self.entries.push((alias, canonical));
+                self.entries.sort_by(|a, b| b.0.len().cmp(&a.0.len()).then_with(|| a.0.cmp(&b.0)));
+            }
+
+            pub fn canonical(&self, alias: &str) -> Option<&str> {
+                let alias = alias.trim().to_lowercase();
+                self.entries.iter().find(|(a, _)| *a == alias).map(|(_, c)| c.as_str())
+            }
+
+            // (alias, canonical) pairs, longest alias first
+            pub fn entries(&self) -> impl Iterator<Item = (&str, &str)> {
+                self.entries.iter().map(|(a, c)| (a.as_str(), c.as_str()))
+            }
+        }
+
+        // Synonym table used by FinfilesAI, loaded on first use
+        pub fn synonyms() -> &'static SynonymTable {
+            static TABLE: std::sync::OnceLock<SynonymTable> = std::sync::OnceLock::new();
+            TABLE.get_or_init(SynonymTable::load)
+        }
+
+        // Lowercase words of a query or name; '_' stays inside words so column names
+        // like "revenues_usd_b" are single tokens
+        fn tokenize(text: &str) -> Vec<String> {
+            text.to_lowercase()
+                .split(|c: char| !c.is_alphanumeric() && c != '_')
+                .filter(|t| !t.is_empty())
+                .map(str::to_string)
+                .collect()
+        }
+
+        // Word weights for filing sentiment, in the style of finance-specific lexicons
+        // (Loughran-McDonald): positive words score above zero, negative below
+        const SENTIMENT_LEXICON: &[(&str, i32)] = &[
+            ("growth", 2), ("increase", 1), ("increased", 1), ("record", 2), ("strong", 2), ("improved", 2),
+            ("improvement", 2), ("gain", 2), ("gains", 2), ("profit", 2), ("profitable", 2), ("exceeded", 2),
+            ("success", 2), ("successful", 2), ("achieve", 1), ("achieved", 1), ("favorable", 2), ("expansion", 1),
+            ("acquisition", 1), ("dividend", 1), ("approval", 1), ("approved", 1), ("completion", 1), ("upgrade", 1),
+            ("loss", -2), ("losses", -2), ("decline", -2), ("declined", -2), ("decrease", -1), ("decreased", -1),
+            ("impairment", -2), ("restatement", -3), ("restated", -3), ("default", -3), ("bankruptcy", -3),
+            ("litigation", -2), ("lawsuit", -2), ("investigation", -2), ("weakness", -2), ("adverse", -2),
+            ("termination", -2), ("terminated", -2), ("delisting", -3), ("delinquent", -2), ("concern", -1), ("resignation", -1), ("departure", -1), ("layoffs", -2), ("restructuring", -1),
+            ("downgrade", -2), ("penalty", -2), ("fraud", -3), ("late", -1),
+        ];
+        // Words that flip the sign of the next lexicon word
+        const SENTIMENT_NEGATIONS: &[&str] = &["no", "not", "never", "without"];
+        // Scores at or beyond this magnitude are labelled positive/negative
+        const SENTIMENT_THRESHOLD: f64 = 0.05;
+
+        // Lexicon sentiment of a piece of text: a score in [-1, 1] and its label
+        #[derive(Debug, Clone, Copy, PartialEq)]
+        pub struct Sentiment {
+            pub score: f64,
+            pub label: &'static str,
+        }
+
+        // Deterministic lexicon scoring. The summed word weights are normalized to
+        // [-1, 1]; text with no words at all gives None (shown as "n/a").
+        pub fn score_sentiment(text: &str) -> Option<Sentiment> {
+            let tokens = tokenize(text);
+            if !tokens.iter().any(|t| t.chars().any(|c| c.is_alphabetic())) {
+                return None;
+            }
+            let mut total = 0;
+            for (i, token) in tokens.iter().enumerate() {
+                let weight = SENTIMENT_LEXICON.iter().find(|(word, _)| word == token).map_or(0, |(_, w)| *w);
+                let negated = i > 0 && SENTIMENT_NEGATIONS.contains(&tokens[i - 1].as_str());
+                total += if negated { -weight } else { weight };
+            }
+            let total = total as f64;
+            let score = total / (total * total + 15.0).sqrt();
+            let label = if score >= SENTIMENT_THRESHOLD {
+                "Positive"
+            } else if score <= -SENTIMENT_THRESHOLD {
+                "Negative"
+            } else {
+                "Neutral"
+            };
+            Some(Sentiment { score, label })
+        }
+
+        // Text for the filings table's AI Analysis column, e.g. "Negative (-0.46)" or "n/a"
+        pub fn sentiment_summary(text: &str) -> String {
+            match score_sentiment(text) {
+                Some(sentiment) => format!("{} ({:+.2})", sentiment.label, sentiment.score),
+                None => "n/a".to_string(),
+            }
+        }
+
+        // Whether the phrase's words appear consecutively in the tokens
+        fn contains_phrase(tokens: &[String], phrase: &str) -> bool {
+            let phrase = tokenize(phrase);
+            !phrase.is_empty() && tokens.windows(phrase.len()).any(|window| window == phrase.as_slice())
+        }
+
+        // Whether a lowercase column name is the canonical concept, with or without the
+        // loader's unit suffix ("revenues" or "revenues_usd_b")
+        fn is_concept_column(lower_name: &str, canonical: &str) -> bool {
+            lower_name == canonical || lower_name.starts_with(&format!("{}_", canonical))
+        }
+
+        // Find the DataFrame column for a synonym, matching the concept name with or
+        // without the loader's unit suffix ("Revenues" or "Revenues_USD_B")
+        pub fn synonym_column(df: &DataFrame, synonym: &str) -> Option<String> {
+            let canonical = synonyms().canonical(synonym)?;
+            df.get_column_names()
+                .iter()
+                .find(|name| is_concept_column(&name.to_lowercase(), canonical))
+                .map(|name| name.to_string())
+        }
+
+        // Which analysis a FinfilesAI query selects, in the order the branches are tried
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum QueryIntent {
+            Table,
+            Summary,
+            Forecast,
+            Anomaly,
+            Ratios,
+            Correlation,
+            Periods,
+            MetricList,
+            Metric,
+            Unknown,
+        }
+
+        impl QueryIntent {
+            pub fn label(&self) -> &'static str {
+                match self {
+                    QueryIntent::Table => "table",
+                    QueryIntent::Summary => "summary",
+                    QueryIntent::Forecast => "forecast",
+                    QueryIntent::Anomaly => "anomaly detection",
+                    QueryIntent::Ratios => "ratios",
+                    QueryIntent::Correlation => "correlation",
+                    QueryIntent::Periods => "periods",
+                    QueryIntent::MetricList => "metrics listing",
+                    QueryIntent::Metric => "metric",
+                    QueryIntent::Unknown => "unrecognized query",
+                }
+            }
+        }
+
+        // How FinfilesAI will interpret a query, without running the analysis. `metric` is
+        // the DataFrame column a metric query resolved to.
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        pub struct QueryPlan {
+            pub intent: QueryIntent,
+            pub metric: Option<String>,
+        }
+
+        impl fmt::Display for QueryPlan {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match (&self.intent, &self.metric) {
+                    (QueryIntent::Metric, Some(metric)) => write!(f, "values of {}", metric),
+                    (QueryIntent::Summary | QueryIntent::Forecast | QueryIntent::Anomaly | QueryIntent::Correlation, _) => {
+                        write!(f, "{} of all metrics", self.intent.label())
+                    }
+                    _ => write!(f, "{}", self.intent.label()),
+                }
+            }
+        }
+
+        // Independent FINFILES AI model (default, independent, no external dependencies)
+        pub struct FinfilesAI;
+
+        pub struct OnnxAIModule {
+            pub model_name: String,
+            session: Arc<Session>, 
+        }
+
+        // Largest model file accepted from an upload
+        pub const MAX_MODEL_BYTES: u64 = 1024 * 1024 * 1024;
+
+        // Check that bytes look like the start of a serialized ONNX ModelProto. There is no
+        // magic number; a model begins with field 1 (ir_version, varint), i.e. 0x08 followed
+        // by a small version number.
+        pub fn validate_onnx_header(header: &[u8]) -> Result<()> {
+            match header {
+                [0x08, version, ..] if (1..=20).contains(version) => Ok(()),
+                [] | [_] => Err(FinAIError::CustomModel("The file is too small to be an ONNX model.".to_string())),
+                _ => Err(FinAIError::CustomModel(
+                    "The file does not start with an ONNX model header.".to_string(),
+                )),
+            }
+        }
+
+        // Validate an uploaded model file: ".onnx" extension, sane size, and ONNX header
+        pub fn validate_model_file(path: &Path) -> Result<()> {
+            let is_onnx = path
+                .extension()
+                .and_then(|e| e.to_str())
+                .map_or(false, |e| e.eq_ignore_ascii_case("onnx"));
+            if !is_onnx {
+                return Err(FinAIError::CustomModel("Only .onnx model files are supported.".to_string()));
+            }
+            let size = fs::metadata(path)
+                .map_err(|e| FinAIError::CustomModel(format!("Cannot read {}: {e}", path.display())))?
+                .len();
+            if size > MAX_MODEL_BYTES {
+                return Err(FinAIError::CustomModel(format!(
+                    "The model is too large ({} MB; limit {} MB).",
+                    size / (1024 * 1024),
+                    MAX_MODEL_BYTES / (1024 * 1024)
+                )));
+            }
+            let mut header = Vec::with_capacity(2);
+            File::open(path)
+                .and_then(|f| f.take(2).read_to_end(&mut header))
+                .map_err(|e| FinAIError::CustomModel(format!("Cannot read {}: {e}", path.display())))?;
+            validate_onnx_header(&header)
+        }
+
+        // Directory uploaded models are copied into
+        pub fn models_dir() -> Result<PathBuf> {
+            dirs::data_dir()
+                .map(|dir| dir.join("finfiles").join("models"))
+                .ok_or_else(|| FinAIError::Config("No user data directory available.".to_string()))
+        }
+
+        // Validate an uploaded model, copy it into the models directory (under a sanitized
+        // file name) and load it. The copy is removed again if loading fails.
+        pub fn install_uploaded_model(path: &Path) -> Result<OnnxAIModule> {
+            validate_model_file(path)?;
+            let stem: String = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .unwrap_or("model")
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+                .collect();
+            let dir = models_dir()?;
+            fs::create_dir_all(&dir)
+                .map_err(|e| FinAIError::CustomModel(format!("Failed to create {}: {e}", dir.display())))?;
+            let dest = dir.join(format!("{}.onnx", stem));
+            fs::copy(path, &dest)
+                .map_err(|e| FinAIError::CustomModel(format!("Failed to copy model to {}: {e}", dest.display())))?;
+            match OnnxAIModule::from_path(&dest) {
+                Ok(mut module) => {
+                    module.model_name = stem;
+                    Ok(module)
+                }
+                Err(e) => {
+                    let _ = fs::remove_file(&dest);
+                    Err(e)
+                }
+            }
+        }
+
+        impl OnnxAIModule {
+            // Initialize the ONNX module with our own independent model.
+            pub fn new() -> Result<Self> {
+                Self::from_path(Path::new("models/finfiles_independent.onnx"))
+            }
+
+            // Initialize the ONNX module from a model file at the given path.
+            pub fn from_path(model_path: &Path) -> Result<Self> {
+                log::info!("FINFILES AI: Initializing INDEPENDENT ONNX backend (our own model, no external AI)...");
+
+                // Our own ONNX model file (must exist and be trained by us)
+                if !model_path.exists() {
+                    return Err(crate::error::FinAIError::AIModule(
+                        "Independent ONNX model file not found.".to_string(),
+                    ));
+                }
+
+                // Create ONNX Runtime environment
+                let environment = Environment::builder()
+                    .with_name("finfiles_onnx_independent")
+                    .with_log_level(LoggingLevel::Warning)
+                    .build()
+                    .map_err(|e| crate::error::FinAIError::AIModule(format!("ONNX env error: {e}")))?;
+
+                // Load our own ONNX model
+                let session = environment
+                    .new_session_builder()
+                    .map_err(|e| crate::error::FinAIError::AIModule(format!("ONNX session builder error: {e}")))?
+                    .with_model_from_file(model_path)
+                    .map_err(|e| crate::error::FinAIError::AIModule(format!("ONNX model load error: {e}")))?;
+
+                Ok(Self {
+                    model_name: "FinfilesIndependentAI".to_string(),
+                    session: Arc::new(session),
+                })
+            }
+
+            // Run inference using our own independent ONNX model.
+            pub fn run_inference(&self, input: Vec<f32>) -> Result<Vec<f32>> {
+                use onnxruntime::ndarray::Array;
+                use onnxruntime::ndarray::IxDyn;
+
+                // Prepare input tensor (example: 1D input)
+                let input_array = Array::from_shape_vec(IxDyn(&[1, input.len()]), input)
+                    .map_err(|e| crate::error::FinAIError::AIModule(format!("Input shape error: {e}")))?;
+
+                // Get input/output names
+                let input_name = self.session.inputs[0].name.clone();
+                let output_name = self.session.outputs[0].name.clone();
+
+                // Run inference
+                let outputs: Vec<OrtOwnedTensor<f32, _>> = self
+                    .session
+                    .run(vec![(input_name.as_str(), &input_array)])
+                    .map_err(|e| crate::error::FinAIError::AIModule(format!("ONNX inference error: {e}")))?;
+
+                // Extract output
+                let output_tensor = outputs
+                    .get(0)
+                    .ok_or_else(|| crate::error::FinAIError::AIModule("No output from ONNX model".to_string()))?;
+
+                Ok(output_tensor.iter().cloned().collect())
+            }
+
+            // Extract the most recent period's numeric metrics as the model's input features.
+            // The non-numeric `quarter` column is skipped.
+            fn latest_features(df: &DataFrame) -> Result<(Vec<String>, Vec<f32>)> {
+                let mut names = Vec::new();
+                let mut features = Vec::new();
+                for col in df.get_columns() {
+                    if col.name() == "quarter" { continue; }
+                    if let Ok(f64chunked) = col.f64() {
+                        let latest = f64chunked.into_iter().flatten().last().unwrap_or(0.0);
+                        names.push(col.name().to_string());
+                        features.push(latest as f32);
+                    }
+                }
+                if features.is_empty() {
+                    return Err(FinAIError::AIModule("No numeric metrics available for ONNX inference.".to_string()));
+                }
+                Ok((names, features))
+            }
+        }
+        // Environment variable naming the remote LLM's SSE endpoint
+        pub const REMOTE_LLM_URL_ENV: &str = "FINFILES_REMOTE_LLM_URL";
+
+        pub struct RemoteLLMAIModule {
+            // When unset the backend falls back to FinfilesAI
+            endpoint: Option<String>,
+            client: reqwest::Client,
+        }
+        pub struct CustomModelAIModule {
+            pub name: String,
+        }
+
+        impl FinfilesAI {
+            pub fn new() -> Result<Self> {
+                log::info!("FINFILES AI: Initializing independent FINFILES AI backend...");
+                Ok(Self {})
+            }
+
+            // Interpret a query the way analyze_structured will, without running it
+            pub fn explain(&self, query: &str, df: &DataFrame) -> QueryPlan {
+                let q = query.to_lowercase();
+                let plan = |intent| QueryPlan { intent, metric: None };
+                if q.contains("raw") || q.contains("table") {
+                    return plan(QueryIntent::Table);
+                }
+                if q.contains("summarize") || q.contains("summary") {
+                    return plan(QueryIntent::Summary);
+                }
+                if q.contains("forecast") || q.contains("predict") {
+                    return plan(QueryIntent::Forecast);
+                }
+                if q.contains("anomaly") || q.contains("outlier") {
+                    return plan(QueryIntent::Anomaly);
+                }
+                if q.contains("ratio") {
+                    return plan(QueryIntent::Ratios);
+                }
+                if q.contains("correlat") {
+                    return plan(QueryIntent::Correlation);
+                }
+                let words = tokenize(&q);
+                if words.iter().any(|w| w == "metrics" || w == "columns") {
+                    return plan(QueryIntent::MetricList);
+                }
+                if (q.contains("quarter") || q.contains("period")) && df.column("quarter").is_ok() {
+                    return plan(QueryIntent::Periods);
+                }
+
+                let available_metrics: Vec<String> = df
+                    .get_column_names()
+                    .iter()
+                    .filter(|&name| name != &"quarter")
+                    .map(|s| s.to_lowercase())
+                    .collect();
+                let metric = Self::resolve_metric(&available_metrics, &q).and_then(|metric| {
+                    df.get_column_names().iter().find(|name| name.to_lowercase() == metric).map(|name| name.to_string())
+                });
+                match metric {
+                    Some(metric) => QueryPlan { intent: QueryIntent::Metric, metric: Some(metric) },
+                    None => plan(QueryIntent::Unknown),
+                }
+            }
+
+            // Resolve the metric a query refers to, first by column name, then via synonyms.
+            // Names match whole words only ("cash" does not match "cashier"); a column matches
+            // by its full name or its concept name without the unit suffix, and the longest
+            // matching name wins.
+            fn resolve_metric(available_metrics: &[String], normalized_query: &str) -> Option<String> {
+                let query_tokens = tokenize(normalized_query);
+                let mut best: Option<(usize, &String)> = None;
+                for metric in available_metrics {
+                    let concept = metric.split('_').next().unwrap_or(metric);
+                    let matched = [metric.as_str(), concept]
+                        .iter()
+                        .filter(|name| contains_phrase(&query_tokens, name))
+                        .map(|name| name.len())
+                        .max();
+                    if let Some(len) = matched {
+                        if best.map_or(true, |(best_len, _)| len > best_len) {
+                            best = Some((len, metric));
+                        }
+                    }
+                }
+                if let Some((_, metric)) = best {
+                    return Some(metric.clone());
+                }
+                for (alias, canonical) in synonyms().entries() {
+                    if contains_phrase(&query_tokens, alias) {
+                        if let Some(metric) = available_metrics.iter().find(|m| is_concept_column(m, canonical)) {
+                            return Some(metric.clone());
+                        }
+                    }
+                }
+                None
+            }
+
+            // Rank tickers by the trend of the queried metric, most declining first. The trend
+            // is the least-squares slope over each ticker's periods in chronological order,
+            // relative to its average so large and small companies compare fairly.
+            pub fn rank_trends(&self, frames: &[(String, DataFrame)], query: &str) -> Result<String> {
+                if frames.is_empty() {
+                    return Ok("FINFILES AI: No tickers loaded; fetch some tickers first.".to_string());
+                }
+                let mut available_metrics: Vec<String> = Vec::new();
+                for (_, df) in frames {
+                    for name in df.get_column_names() {
+                        let lower = name.to_lowercase();
+                        if lower != "quarter" && !available_metrics.contains(&lower) {
+                            available_metrics.push(lower);
+                        }
+                    }
+                }
+                let metric = match FinfilesAI::resolve_metric(&available_metrics, &query.to_lowercase()) {
+                    Some(metric) => metric,
+                    None => {
+                        return Ok(format!(
+                            "FINFILES AI: Could not detect a metric to check across tickers.\nAvailable metrics: {}",
+                            available_metrics.join(", ")
+                        ));
+                    }
+                };
+
+                // (ticker, relative slope per period, first value, last value, periods)
+                let mut trends: Vec<(String, Option<(f64, f64, f64, usize)>)> = Vec::new();
+                for (ticker, df) in frames {
+                    let values: Option<Vec<Option<f64>>> = df
+                        .get_column_names()
+                        .iter()
+                        .find(|name| name.to_lowercase() == metric)
+                        .and_then(|name| df.column(name).ok())
+                        .and_then(|series| series.f64().ok())
+                        .map(|values| values.into_iter().collect());
+                    let trend = values.and_then(|values| {
+                        let series: Vec<f64> = forecast_rows(df, false).iter().filter_map(|&row| values.get(row).copied().flatten()).collect();
+                        if series.len() < 2 {
+                            return None;
+                        }
+                        let mean = series.iter().sum::<f64>() / series.len() as f64;
+                        let slope = trend_slope(&series);
+                        let relative = if mean != 0.0 { slope / mean.abs() } else { slope };
+                        Some((relative, series[0], series[series.len() - 1], series.len()))
+                    });
+                    trends.push((ticker.clone(), trend));
+                }
+                trends.sort_by(|a, b| {
+                    let key = |t: &Option<(f64, f64, f64, usize)>| t.map_or(f64::INFINITY, |t| t.0);
+                    key(&a.1).partial_cmp(&key(&b.1)).unwrap_or(std::cmp::Ordering::Equal)
+                });
+
+                let unit = MetricUnit::from_column_name(&metric);
+                let lines: Vec<String> = trends
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (ticker, trend))| match trend {
+                        Some((relative, first, last, periods)) => format!(
+                            "  {}. {}: {} ({:+.1}% per period; {} -> {} over {} periods)",
+                            i + 1,
+                            ticker,
+                            if *relative < 0.0 { "declining" } else if *relative > 0.0 { "rising" } else { "flat" },
+                            relative * 100.0,
+                            unit.format(*first),
+                            unit.format(*last),
+                            periods
+                        ),
+                        None => format!("  {}. {}: not enough data", i + 1, ticker),
+                    })
+                    .collect();
+                let declining = trends.iter().filter(|(_, t)| t.map_or(false, |t| t.0 < 0.0)).count();
+                Ok(format!(
+                    "SEC EDGAR {} Trend Across {} Tickers ({} declining), most declining first:\n{}",
+                    metric,
+                    trends.len(),
+                    declining,
+                    lines.join("\n")
+                ))
+            }
+
+            // Compare the same metric across several tickers, aligned by period and ranked
+            // by each ticker's most recent value. Tickers missing the metric are kept and
+            // marked as unavailable.
+            pub fn compare(&self, frames: &[(String, DataFrame)], query: &str) -> Result<String> {
+                if frames.is_empty() {
+                    return Ok("FINFILES AI: No tickers loaded for comparison.".to_string());
+                }
+                let normalized_query = query.to_lowercase();
+
+                let mut available_metrics: Vec<String> = Vec::new();
+                for (_, df) in frames {
+                    for name in df.get_column_names() {
+                        let lower = name.to_lowercase();
+                        if lower != "quarter" && !available_metrics.contains(&lower) {
+                            available_metrics.push(lower);
+                        }
+                    }
+                }
+                let metric = match FinfilesAI::resolve_metric(&available_metrics, &normalized_query) {
+                    Some(metric) => metric,
+                    None => {
+                        return Ok(format!(
+                            "FINFILES AI: Could not detect a metric to compare.\nAvailable metrics: {}",
+                            available_metrics.join(", ")
+                        ));
+                    }
+                };
+
+                // ticker -> (period -> value), None when the ticker lacks the metric
+                let mut per_ticker: Vec<(String, Option<HashMap<String, f64>>)> = Vec::new();
+                let mut periods: Vec<String> = Vec::new();
+                for (ticker, df) in frames {
+                    let quarters: Vec<String> = df
+                        .column("quarter")
+                        .ok()
+                        .and_then(|s| s.utf8().ok())
+                        .map(|s| s.into_iter().map(|q| q.unwrap_or("").to_string()).collect())
+                        .unwrap_or_default();
+                    let values = df
+                        .get_column_names()
+                        .iter()
+                        .find(|name| name.to_lowercase() == metric)
+                        .and_then(|name| df.column(name).ok())
+                        .and_then(|series| series.f64().ok())
+                        .map(|f64chunked| {
+                            let mut by_period = HashMap::new();
+                            for (q, v) in quarters.iter().zip(f64chunked.into_iter()) {
+                                if let Some(v) = v {
+                                    by_period.insert(q.clone(), v);
+                                }
+                            }
+                            by_period
+                        });
+                    for q in &quarters {
+                        if !periods.contains(q) {
+                            periods.push(q.clone());
+                        }
+                    }
+                    per_ticker.push((ticker.to_uppercase(), values));
+                }
+                periods.sort_by(|a, b| compare_periods(b, a)); // Descending (most recent first)
+
+                // Rank by the latest reported value; unavailable tickers sink to the bottom
+                let latest = |values: &Option<HashMap<String, f64>>| -> Option<f64> {
+                    values.as_ref().and_then(|m| periods.iter().find_map(|p| m.get(p).copied()))
+                };
+                per_ticker.sort_by(|a, b| {
+                    latest(&b.1)
+                        .unwrap_or(f64::NEG_INFINITY)
+                        .partial_cmp(&latest(&a.1).unwrap_or(f64::NEG_INFINITY))
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                });
+
+                let unit = MetricUnit::from_column_name(&metric);
+                let mut lines = Vec::new();
+                let header: Vec<String> = per_ticker.iter().map(|(t, _)| format!("{:>12}", t)).collect();
+                lines.push(format!("{:<10}{}", "Period", header.join("")));
+                for period in &periods {
+                    let cells: Vec<String> = per_ticker
+                        .iter()
+                        .map(|(_, values)| match values.as_ref().and_then(|m| m.get(period)) {
+                            Some(v) => format!("{:>12}", unit.format(*v)),
+                            None => format!("{:>12}", "n/a"),
+                        })
+                        .collect();
+                    lines.push(format!("{:<10}{}", period, cells.join("")));
+                }
+
+                let ranking: Vec<String> = per_ticker
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (ticker, values))| match latest(values) {
+                        Some(v) => format!("  {}. {}: {}", i + 1, ticker, unit.format(v)),
+                        None => format!("  {}. {}: unavailable", i + 1, ticker),
+                    })
+                    .collect();
+
+                Ok(format!(
+                    "SEC EDGAR {} Comparison:\n{}\n\nRanking (most recent period):\n{}",
+                    metric,
+                    lines.join("\n"),
+                    ranking.join("\n")
+                ))
+            }
+        }
+
+        // Whether a query asks about every loaded ticker, e.g. "which of my tickers has
+        // declining revenue?"
+        pub fn is_cross_ticker_query(query: &str) -> bool {
+            let query = query.to_lowercase();
+            ["my tickers", "which ticker", "which of my", "across tickers", "all tickers", "each ticker"]
+                .iter()
+                .any(|phrase| query.contains(phrase))
+        }
+
+        // Whether a query explicitly asks for a comparison ("compare", "comparison", ...)
+        pub fn is_comparison_query(query: &str) -> bool {
+            query
+                .split(|c: char| !c.is_ascii_alphabetic())
+                .any(|word| word.to_lowercase().starts_with("compar"))
+        }
+
+        // Extract comma-separated tickers from a chat query such as
+        // "Compare revenue for AAPL, MSFT, GOOG". Returns an empty list unless the query
+        // asks for a comparison and at least two valid tickers are present; callers still
+        // check the tickers against SEC's CIK map.
+        pub fn comparison_tickers(query: &str) -> Vec<String> {
+            let segments: Vec<&str> = query.split(',').collect();
+            if segments.len() < 2 || !is_comparison_query(query) {
+                return Vec::new();
+            }
+            let mut tickers = Vec::new();
+            for (i, segment) in segments.iter().enumerate() {
+                let mut words = segment.split_whitespace();
+                // The ticker sits right before the first comma and right after every other one
+                let candidate = if i == 0 { words.last() } else { words.next() };
+                if let Some(word) = candidate {
+                    let ticker = sanitize_ticker(word.trim_matches(|c: char| !c.is_ascii_alphanumeric()));
+                    if !ticker.is_empty() && !tickers.contains(&ticker) {
+                        tickers.push(ticker);
+                    }
+                }
+            }
+            if tickers.len() < 2 { Vec::new() } else { tickers }
+        }
+        impl RemoteLLMAIModule {
+            pub fn new() -> Result<Self> {
+                log::info!("FINFILES AI: Initializing Remote LLM backend...");
+                let endpoint = std::env::var(REMOTE_LLM_URL_ENV).ok().filter(|url| !url.trim().is_empty());
+                Ok(Self { endpoint, client: reqwest::Client::new() })
+            }
+
+            // POST the query and data to the endpoint and stream back its SSE `data:` events.
+            // A `[DONE]` event ends the stream.
+            async fn stream_remote(&self, endpoint: &str, df: &DataFrame, query: &str) -> Result<BoxStream<'static, Result<String>>> {
+                validate_frame(df)?;
+                let body = serde_json::json!({ "query": query, "data": df.to_string() });
+                let response = self
+                    .client
+                    .post(endpoint)
+                    .header(reqwest::header::ACCEPT, "text/event-stream")
+                    .json(&body)
+                    .send()
+                    .await
+                    .map_err(|e| FinAIError::Network(format!("Remote LLM request failed: {e}")))?;
+                if !response.status().is_success() {
+                    return Err(response_error(response).await);
+                }
+
+                let events = response
+                    .bytes_stream()
+                    .scan(String::new(), |buffer, bytes| {
+                        let events = match bytes {
+                            Ok(bytes) => {
+                                buffer.push_str(&String::from_utf8_lossy(&bytes));
+                                drain_sse_events(buffer).into_iter().map(Ok).collect()
+                            }
+                            Err(e) => vec![Err(FinAIError::Network(format!("Remote LLM stream error: {e}")))],
+                        };
+                        futures::future::ready(Some(stream::iter(events)))
+                    })
+                    .flatten()
+                    .take_while(|event| futures::future::ready(!matches!(event, Ok(data) if data.trim() == "[DONE]")));
+                Ok(events.boxed())
+            }
+        }
+        impl CustomModelAIModule {
+            pub fn new(name: String) -> Result<Self> {
+                log::info!("FINFILES AI: Initializing custom model backend: {}", name);
+                Ok(Self { name })
+            }
+        }
+
+        // Per-metric totals for the summary mode
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        pub struct MetricSummary {
+            pub metric: String,
+            pub total: f64,
+            pub average: f64,
+            pub most_recent: f64,
+            // Filing the most recent value came from, when the data records it
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            pub source: Option<FactSource>,
+        }
+
+        // Quarters in a season for the seasonal forecasts
+        pub const SEASON_LENGTH: usize = 4;
+        // Periods (two full years) needed before a seasonal forecast is attempted
+        pub const MIN_SEASONAL_PERIODS: usize = 2 * SEASON_LENGTH;
+
+        // Projection used by the forecast mode
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+        #[serde(rename_all = "snake_case")]
+        pub enum ForecastMethod {
+            // Last value carried forward
+            #[default]
+            Naive,
+            // Least-squares line continued
+            Linear,
+            // Same quarter one year earlier
+            SeasonalNaive,
+            // Linear trend plus the average offset of each quarter from it
+            SeasonalAdditive,
+        }
+
+        impl ForecastMethod {
+            pub fn label(&self) -> &'static str {
+                match self {
+                    ForecastMethod::Naive => "naive, last value",
+                    ForecastMethod::Linear => "linear trend",
+                    ForecastMethod::SeasonalNaive => "seasonal naive",
+                    ForecastMethod::SeasonalAdditive => "seasonal, additive",
+                }
+            }
+
+            pub fn is_seasonal(&self) -> bool {
+                matches!(self, ForecastMethod::SeasonalNaive | ForecastMethod::SeasonalAdditive)
+            }
+
+            // Read hints from a query such as "forecast seasonal", "forecast seasonal naive"
+            // or "forecast trend"
+            pub fn from_query(normalized_query: &str) -> Self {
+                let words: Vec<&str> = normalized_query.split_whitespace().collect();
+                let has = |word: &str| words.contains(&word);
+                if has("seasonal") || has("seasonality") {
+                    if has("naive") { ForecastMethod::SeasonalNaive } else { ForecastMethod::SeasonalAdditive }
+                } else if has("linear") || has("trend") {
+                    ForecastMethod::Linear
+                } else {
+                    ForecastMethod::Naive
+                }
+            }
+
+            // Projections for the next periods (one, or a full season for seasonal methods)
+            // from chronologically ordered values. Seasonal methods with fewer than
+            // MIN_SEASONAL_PERIODS values fall back to Linear; returns the method used.
+            pub fn project(self, values: &[f64]) -> (ForecastMethod, Vec<f64>) {
+                let method = if self.is_seasonal() && values.len() < MIN_SEASONAL_PERIODS { ForecastMethod::Linear } else { self };
+                let projections = match method {
+                    ForecastMethod::Naive => values.last().copied().into_iter().collect(),
+                    ForecastMethod::Linear => linear_projection(values, 1),
+                    ForecastMethod::SeasonalNaive => seasonal_naive(values, SEASON_LENGTH, SEASON_LENGTH),
+                    ForecastMethod::SeasonalAdditive => seasonal_additive(values, SEASON_LENGTH, SEASON_LENGTH),
+                };
+                (method, projections)
+            }
+        }
+
+        // Per-metric next-period projection for the forecast mode
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        pub struct MetricForecast {
+            pub metric: String,
+            pub last: f64,
+            // Least-squares trend per period over the reported values
+            pub slope: f64,
+            pub forecast: f64,
+            // Method actually used (seasonal requests fall back to linear on short series)
+            #[serde(default)]
+            pub method: ForecastMethod,
+            // Projection for each upcoming period, starting with `forecast`
+            #[serde(default)]
+            pub projections: Vec<f64>,
+        }
+
+        // Outlier test used by the anomaly mode
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+        #[serde(rename_all = "snake_case")]
+        pub enum AnomalyMethod {
+            // Flag values more than `sensitivity` population standard deviations from the mean
+            ZScore,
+            // Flag values more than `sensitivity` interquartile ranges outside Q1..Q3
+            Iqr,
+        }
+
+        impl AnomalyMethod {
+            pub fn default_sensitivity(&self) -> f64 {
+                match self {
+                    AnomalyMethod::ZScore => 2.0,
+                    AnomalyMethod::Iqr => 1.5,
+                }
+            }
+
+            pub fn label(&self) -> &'static str {
+                match self {
+                    AnomalyMethod::ZScore => "z-score",
+                    AnomalyMethod::Iqr => "IQR",
+                }
+            }
+        }
+
+        #[derive(Debug, Clone, Copy, PartialEq)]
+        pub struct AnomalyOptions {
+            pub method: AnomalyMethod,
+            pub sensitivity: f64,
+        }
+
+        impl Default for AnomalyOptions {
+            fn default() -> Self {
+                Self::new(AnomalyMethod::ZScore)
+            }
+        }
+
+        impl AnomalyOptions {
+            pub fn new(method: AnomalyMethod) -> Self {
+                Self { method, sensitivity: method.default_sensitivity() }
+            }
+
+            pub fn with_sensitivity(mut self, sensitivity: f64) -> Self {
+                if sensitivity.is_finite() && sensitivity > 0.0 {
+                    self.sensitivity = sensitivity;
+                }
+                self
+            }
+
+            // Read hints from a query such as "anomaly iqr" or "outliers sensitivity 3"
+            pub fn from_query(normalized_query: &str) -> Self {
+                let method = if normalized_query.split_whitespace().any(|w| w == "iqr") {
+                    AnomalyMethod::Iqr
+                } else {
+                    AnomalyMethod::ZScore
+                };
+                let words: Vec<&str> = normalized_query.split_whitespace().collect();
+                let sensitivity = words
+                    .windows(2)
+                    .find(|w| w[0] == "sensitivity")
+                    .and_then(|w| w[1].trim_matches(|c: char| !c.is_ascii_digit() && c != '.').parse::<f64>().ok());
+                let options = Self::new(method);
+                match sensitivity {
+                    Some(s) => options.with_sensitivity(s),
+                    None => options,
+                }
+            }
+        }
+
+        // A value flagged by the anomaly mode
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        pub struct Anomaly {
+            pub metric: String,
+            // 1-based period index
+            pub period: usize,
+            pub value: f64,
+            pub mean: f64,
+            pub std: f64,
+            pub method: AnomalyMethod,
+            // Bounds a value must stay within to not be flagged
+            pub lower: f64,
+            pub upper: f64,
+            // How far past the nearest bound the value lies
+            pub excess: f64,
+        }
+
+        // Pearson correlation between two metrics for the correlation mode
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        pub struct MetricCorrelation {
+            pub metric_a: String,
+            pub metric_b: String,
+            pub r: f64,
+        }
+
+        // Ratios for one period; None means division by zero
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        pub struct PeriodRatios {
+            pub period: String,
+            pub current_ratio: Option<f64>,
+            pub net_margin: Option<f64>,
+            // Net income divided by diluted EPS, in millions of shares
+            pub implied_diluted_shares: Option<f64>,
+        }
+
+        // Correlation needs at least this many periods to mean anything
+        pub const MIN_CORRELATION_PERIODS: usize = 3;
+
+        // One numeric column in the metrics listing
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        pub struct MetricInfo {
+            pub metric: String,
+            // Periods with a value
+            pub count: usize,
+            // Value for the most recent period that has one
+            pub latest: Option<f64>,
+            pub unit: MetricUnit,
+        }
+
+        // Every numeric column except "quarter", sorted by name (case-insensitive). The
+        // latest value follows the "quarter" labels when present, else row order.
+        pub fn list_metrics(df: &DataFrame) -> Vec<MetricInfo> {
+            let quarters: Option<Vec<Option<String>>> = df
+                .column("quarter")
+                .ok()
+                .and_then(|s| s.utf8().ok())
+                .map(|s| s.into_iter().map(|q| q.map(str::to_string)).collect());
+            let mut metrics: Vec<MetricInfo> = df
+                .get_columns()
+                .iter()
+                .filter(|col| col.name() != "quarter" && col.dtype().is_numeric())
+                .filter_map(|col| {
+                    let values: Vec<Option<f64>> = col.cast(&DataType::Float64).ok()?.f64().ok()?.into_iter().collect();
+                    let present = values.iter().enumerate().filter_map(|(i, v)| v.map(|v| (i, v)));
+                    let latest = match &quarters {
+                        Some(quarters) => {
+                            let label = |i: usize| quarters.get(i).cloned().flatten().unwrap_or_default();
+                            present.max_by(|a, b| compare_periods(&label(a.0), &label(b.0))).map(|(_, v)| v)
+                        }
+                        None => present.last().map(|(_, v)| v),
+                    };
+                    Some(MetricInfo {
+                        metric: col.name().to_string(),
+                        count: values.iter().flatten().count(),
+                        latest,
+                        unit: MetricUnit::from_column_name(col.name()),
+                    })
+                })
+                .collect();
+            metrics.sort_by_key(|m| m.metric.to_lowercase());
+            metrics
+        }
+
+        // Machine-readable result of an analysis, one variant per query mode
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        #[serde(tag = "mode", rename_all = "snake_case")]
+        pub enum AnalysisResult {
+            Table { table: String },
+            Summary { periods: usize, metrics: Vec<MetricSummary> },
+            Forecast {
+                // Method the query asked for
+                #[serde(default)]
+                method: ForecastMethod,
+                forecasts: Vec<MetricForecast>,
+            },
+            Anomaly { anomalies: Vec<Anomaly> },
+            Ratios {
+                periods: Vec<PeriodRatios>,
+                // Ratios that could not be computed, with the inputs they were missing
+                missing: Vec<String>,
+            },
+            Correlation {
+                periods: usize,
+                strongest_positive: Option<MetricCorrelation>,
+                strongest_negative: Option<MetricCorrelation>,
+            },
+            Periods { periods: Vec<String> },
+            MetricList { metrics: Vec<MetricInfo> },
+            Metric {
+                metric: String,
+                periods: usize,
+                values: Vec<f64>,
+                total: f64,
+                average: f64,
+                most_recent: f64,
+                // Most recent value minus the one before it
+                #[serde(default, skip_serializing_if = "Option::is_none")]
+                change: Option<f64>,
+                #[serde(default, skip_serializing_if = "Option::is_none")]
+                source: Option<FactSource>,
+            },
+            TextMetric { metric: String, values: Vec<String> },
+            Unknown { message: String, available_metrics: Vec<String> },
+            // Free-form output from backends without structured support
+            Text { text: String },
+        }
+
+        // " (from 10-K filed 2023-10-27)" when the value's filing is known, else nothing
+        fn citation(source: &Option<FactSource>) -> String {
+            source.as_ref().map(|s| format!(" ({})", s.citation())).unwrap_or_default()
+        }
+
+        impl fmt::Display for AnalysisResult {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match self {
+                    AnalysisResult::Table { table } => write!(f, "SEC Data Table:\n{}", table),
+                    AnalysisResult::Summary { periods, metrics } => {
+                        let lines: Vec<String> = metrics
+                            .iter()
+                            .map(|m| {
+                                let unit = MetricUnit::from_column_name(&m.metric);
+                                let total = if unit.is_summable() { format!("Total = {}, ", unit.format(m.total)) } else { String::new() };
+                                format!(
+                                    "  • {}: {}Avg = {}, Most Recent = {}{}",
+                                    m.metric,
+                                    total,
+                                    unit.format(m.average),
+                                    unit.format(m.most_recent),
+                                    citation(&m.source)
+                                )
+                            })
+                            .collect();
+                        write!(f, "Summary: {} quarters of SEC data loaded.\n{}", periods, lines.join("\n"))
+                    }
+                    AnalysisResult::Forecast { method, forecasts } => {
+                        let lines: Vec<String> = forecasts
+                            .iter()
+                            .map(|m| {
+                                let unit = MetricUnit::from_column_name(&m.metric);
+                                let note = if m.method != *method {
+                                    format!(
+                                        " ({}; seasonal needs {} periods)",
+                                        m.method.label(),
+                                        MIN_SEASONAL_PERIODS
+                                    )
+                                } else {
+                                    String::new()
+                                };
+                                if m.projections.len() > 1 {
+                                    let projections: Vec<String> = m.projections.iter().map(|v| unit.format(*v)).collect();
+                                    format!("  • {}: Next {} periods = {}{}", m.metric, projections.len(), projections.join(", "), note)
+                                } else {
+                                    format!("  • {}: Next period forecast = {}{}", m.metric, unit.format(m.forecast), note)
+                                }
+                            })
+                            .collect();
+                        write!(f, "Time-Series Forecast ({}):\n{}", method.label(), lines.join("\n"))
+                    }
+                    AnalysisResult::Anomaly { anomalies } => {
+                        if anomalies.is_empty() {
+                            return write!(f, "No significant anomalies detected in the available metrics.");
+                        }
+                        let lines: Vec<String> = anomalies
+                            .iter()
+                            .map(|a| {
+                                let unit = MetricUnit::from_column_name(&a.metric);
+                                format!(
+                                    "  • {}: Anomaly detected at period {} by {} (value = {}, bounds = {} to {}, outside by {}, mean = {}, std = {})",
+                                    a.metric,
+                                    a.period,
+                                    a.method.label(),
+                                    unit.format(a.value),
+                                    unit.format(a.lower),
+                                    unit.format(a.upper),
+                                    unit.format(a.excess),
+                                    unit.format(a.mean),
+                                    unit.format(a.std)
+                                )
+                            })
+                            .collect();
+                        write!(f, "Anomaly Detection Results:\n{}", lines.join("\n"))
+                    }
+                    AnalysisResult::Ratios { periods, missing } => {
+                        let ratio = |v: Option<f64>| v.map(|v| format!("{:.2}", v)).unwrap_or_else(|| "n/a".to_string());
+                        let percent = |v: Option<f64>| v.map(|v| format!("{:.1}%", v * 100.0)).unwrap_or_else(|| "n/a".to_string());
+                        let shares = |v: Option<f64>| v.map(|v| MetricUnit::MillionsOfShares.format(v)).unwrap_or_else(|| "n/a".to_string());
+                        let lines: Vec<String> = periods
+                            .iter()
+                            .map(|p| format!(
+                                "  • {}: Current ratio = {}, Net margin = {}, Implied diluted shares = {}",
+                                p.period,
+                                ratio(p.current_ratio),
+                                percent(p.net_margin),
+                                shares(p.implied_diluted_shares)
+                            ))
+                            .collect();
+                        write!(f, "Financial Ratios:\n{}", lines.join("\n"))?;
+                        if !missing.is_empty() {
+                            write!(f, "\nNot computed (missing inputs):\n  • {}", missing.join("\n  • "))?;
+                        }
+                        Ok(())
+                    }
+                    AnalysisResult::Correlation { periods, strongest_positive, strongest_negative } => {
+                        if *periods < MIN_CORRELATION_PERIODS {
+                            return write!(
+                                f,
+                                "Correlation analysis needs at least {} periods of data; only {} loaded.",
+                                MIN_CORRELATION_PERIODS, periods
+                            );
+                        }
+                        let describe = |c: &Option<MetricCorrelation>| match c {
+                            Some(c) => format!("{} vs {} (r = {:.3})", c.metric_a, c.metric_b, c.r),
+                            None => "none found".to_string(),
+                        };
+                        write!(
+                            f,
+                            "Correlation Analysis ({} periods):\n  • Strongest positive: {}\n  • Strongest negative: {}",
+                            periods,
+                            describe(strongest_positive),
+                            describe(strongest_negative)
+                        )
+                    }
+                    AnalysisResult::Periods { periods } => write!(f, "Loaded quarters from SEC: {}", periods.join(", ")),
+                    AnalysisResult::MetricList { metrics } => {
+                        if metrics.is_empty() {
+                            return write!(f, "No financial metrics available.");
+                        }
+                        let lines: Vec<String> = metrics
+                            .iter()
+                            .map(|m| format!(
+                                "  • {}: {} periods, latest = {} ({})",
+                                m.metric,
+                                m.count,
+                                m.latest.map(|v| m.unit.format(v)).unwrap_or_else(|| "n/a".to_string()),
+                                m.unit.label()
+                            ))
+                            .collect();
+                        write!(f, "Available metrics ({}):\n{}", metrics.len(), lines.join("\n"))
+                    }
+                    AnalysisResult::Metric { metric, periods, total, average, most_recent, change, source, .. } => {
+                        let unit = MetricUnit::from_column_name(metric);
+                        write!(f, "SEC EDGAR {} Analysis:", metric)?;
+                        if unit.is_summable() {
+                            write!(f, "\n  • Total {} (last {} periods): {}", metric, periods, unit.format(*total))?;
+                        }
+                        write!(
+                            f,
+                            "\n  • Average per period: {}\n  • Most recent period: {}{}",
+                            unit.format(*average),
+                            unit.format(*most_recent),
+                            citation(source)
+                        )?;
+                        if let Some(change) = change {
+                            let prior = most_recent - change;
+                            let percent = if prior != 0.0 { format!(" ({:+.1}%)", change / prior.abs() * 100.0) } else { String::new() };
+                            write!(f, "\n  • Change from prior period: {}{}", unit.format_change(*change), percent)?;
+                        }
+                        Ok(())
+                    }
+                    AnalysisResult::TextMetric { metric, values } => write!(f, "SEC EDGAR {} values: {}", metric, values.join(", ")),
+                    AnalysisResult::Unknown { message, .. } => write!(f, "{}", message),
+                    AnalysisResult::Text { text } => write!(f, "{}", text),
+                }
+            }
+        }
+
+        // Row indices oldest first, following the "quarter" labels when present (the loader
+        // stores the most recent period first). Seasonal forecasts only use quarterly rows,
+        // leaving out full fiscal years.
+        fn forecast_rows(df: &DataFrame, quarterly_only: bool) -> Vec<usize> {
+            let labels: Vec<String> = match df.column("quarter").ok().and_then(|s| s.utf8().ok()) {
+                Some(quarters) => quarters.into_iter().map(|q| q.unwrap_or("").to_string()).collect(),
+                None => return (0..df.height()).collect(),
+            };
+            let mut rows: Vec<usize> = (0..labels.len())
+                .filter(|&row| !quarterly_only || !matches!(period_sort_key(&labels[row]).1, 0 | 5))
+                .collect();
+            rows.sort_by(|&a, &b| compare_periods(&labels[a], &labels[b]));
+            rows
+        }
+
+        // Flag outliers in every numeric column using the given method and sensitivity
+        pub fn detect_anomalies(df: &DataFrame, options: AnomalyOptions) -> Vec<Anomaly> {
+            let mut anomalies = Vec::new();
+            for col in df.get_columns() {
+                if let Ok(f64chunked) = col.f64() {
+                    let vals: Vec<f64> = f64chunked.into_iter().flatten().collect();
+                    if vals.len() < 2 { continue; }
+                    let stats = summarize(&vals);
+                    let (lower, upper) = match options.method {
+                        AnomalyMethod::ZScore => zscore_bounds(&stats, options.sensitivity),
+                        AnomalyMethod::Iqr => iqr_bounds(&vals, options.sensitivity),
+                    };
+                    // Unreported periods are skipped but keep their place in the numbering
+                    for (i, v) in f64chunked.into_iter().enumerate().filter_map(|(i, v)| Some((i, v?))) {
+                        let excess = excess(v, lower, upper);
+                        if excess > 0.0 {
+                            anomalies.push(Anomaly {
+                                metric: col.name().to_string(),
+                                period: i + 1,
+                                value: v,
+                                mean: stats.mean,
+                                std: stats.std_dev,
+                                method: options.method,
+                                lower,
+                                upper,
+                                excess,
+                            });
+                        }
+                    }
+                }
+            }
+            anomalies
+        }
+
+        // Divide, treating a zero denominator as not computable
+        fn safe_ratio(numerator: f64, denominator: f64) -> Option<f64> {
+            if denominator == 0.0 { None } else { Some(numerator / denominator) }
+        }
+
+        // Pearson correlation coefficient (None when either series has no variance)
+        pub fn pearson(x: &[f64], y: &[f64]) -> Option<f64> {
+            let n = x.len().min(y.len());
+            if n < 2 {
+                return None;
+            }
+            let mean_x = x[..n].iter().sum::<f64>() / n as f64;
+            let mean_y = y[..n].iter().sum::<f64>() / n as f64;
+            let mut cov = 0.0;
+            let mut var_x = 0.0;
+            let mut var_y = 0.0;
+            for i in 0..n {
+                let dx = x[i] - mean_x;
+                let dy = y[i] - mean_y;
+                cov += dx * dy;
+                var_x += dx * dx;
+                var_y += dy * dy;
+            }
+            if var_x == 0.0 || var_y == 0.0 {
+                return None;
+            }
+            Some(cov / (var_x.sqrt() * var_y.sqrt()))
+        }
+
+        #[async_trait]
+        impl FinancialAIModule for FinfilesAI {
+            async fn analyze(&self, df: &DataFrame, query: &str) -> Result<String> {
+                Ok(self.analyze_structured(df, query).await?.to_string())
+            }
+
+            async fn analyze_structured(&self, df: &DataFrame, query: &str) -> Result<AnalysisResult> {
+                validate_frame(df)?;
+                let normalized_query = query.to_lowercase();
+                let plan = self.explain(query, df);
+
+                // Show table/raw
+                if plan.intent == QueryIntent::Table {
+                    return Ok(AnalysisResult::Table { table: df.to_string() });
+                }
+
+                // Summary of SEC data
+                if plan.intent == QueryIntent::Summary {
+                    let quarters = df.column("quarter").ok().and_then(|s| s.utf8().ok()).map(|s| s.len()).unwrap_or(0);
+                    let mut metrics = Vec::new();
+                    for col in df.get_columns() {
+                        if let Some(stats) = column_summary(col) {
+                            metrics.push(MetricSummary {
+                                metric: col.name().to_string(),
+                                total: stats.sum,
+                                average: stats.mean,
+                                most_recent: stats.most_recent,
+                                source: stats.most_recent_row.and_then(|row| period_source(df, row)),
+                            });
+                        }
+                    }
+                    return Ok(AnalysisResult::Summary { periods: quarters, metrics });
+                }
+
+                // Time-series forecasting
+                if plan.intent == QueryIntent::Forecast {
+                    let method = ForecastMethod::from_query(&normalized_query);
+                    let rows = forecast_rows(df, method.is_seasonal());
+                    let mut forecasts = Vec::new();
+                    for col in df.get_columns() {
+                        if let Ok(f64chunked) = col.f64() {
+                            let vals: Vec<f64> = rows.iter().filter_map(|&row| f64chunked.get(row)).collect();
+                            let last = vals.last().copied().unwrap_or(0.0);
+                            let (used, projections) = method.project(&vals);
+                            forecasts.push(MetricForecast {
+                                metric: col.name().to_string(),
+                                last,
+                                slope: trend_slope(&vals),
+                                forecast: projections.first().copied().unwrap_or(last),
+                                method: used,
+                                projections,
+                            });
+                        }
+                    }
+                    return Ok(AnalysisResult::Forecast { method, forecasts });
+                }
+
+                // Anomaly detection
+                if plan.intent == QueryIntent::Anomaly {
+                    let anomalies = detect_anomalies(df, AnomalyOptions::from_query(&normalized_query));
+                    return Ok(AnalysisResult::Anomaly { anomalies });
+                }
+
+                // Common financial ratios per period
+                if plan.intent == QueryIntent::Ratios {
+                    let column_values = |synonym: &str| -> Option<(MetricUnit, Vec<Option<f64>>)> {
+                        let name = synonym_column(df, synonym)?;
+                        let values = df.column(&name).ok()?.f64().ok()?.into_iter().collect();
+                        Some((MetricUnit::from_column_name(&name), values))
+                    };
+                    let assets = column_values("assets");
+                    let liabilities = column_values("liabilities");
+                    let revenue = column_values("revenue");
+                    let net_income = column_values("net income");
+                    let eps = column_values("eps");
+
+                    let mut missing = Vec::new();
+                    let mut require = |label: &str, inputs: &[(&str, bool)]| {
+                        let absent: Vec<&str> = inputs.iter().filter(|(_, present)| !present).map(|(name, _)| *name).collect();
+                        if !absent.is_empty() {
+                            missing.push(format!("{} (needs {})", label, absent.join(", ")));
+                        }
+                    };
+                    require("Current ratio", &[("assets", assets.is_some()), ("liabilities", liabilities.is_some())]);
+                    require("Net margin", &[("net income", net_income.is_some()), ("revenue", revenue.is_some())]);
+                    require("Implied diluted shares", &[("net income", net_income.is_some()), ("eps", eps.is_some())]);
+
+                    let quarters: Vec<String> = df
+                        .column("quarter")
+                        .ok()
+                        .and_then(|s| s.utf8().ok())
+                        .map(|s| s.into_iter().map(|q| q.unwrap_or("").to_string()).collect())
+                        .unwrap_or_else(|| (1..=df.height()).map(|i| format!("Period {}", i)).collect());
+
+                    // Both inputs must be reported for the period
+                    let pair = |a: &Option<(MetricUnit, Vec<Option<f64>>)>, b: &Option<(MetricUnit, Vec<Option<f64>>)>, i: usize| {
+                        match (a, b) {
+                            (Some((ua, va)), Some((ub, vb))) => Some((ua.unscale(va[i]?), ub.unscale(vb[i]?))),
+                            _ => None,
+                        }
+                    };
+                    let periods = quarters
+                        .iter()
+                        .enumerate()
+                        .map(|(i, period)| PeriodRatios {
+                            period: period.clone(),
+                            current_ratio: pair(&assets, &liabilities, i).and_then(|(a, l)| safe_ratio(a, l)),
+                            net_margin: pair(&net_income, &revenue, i).and_then(|(n, r)| safe_ratio(n, r)),
+                            implied_diluted_shares: pair(&net_income, &eps, i)
+                                .and_then(|(n, e)| safe_ratio(n, e))
+                                .map(|shares| MetricUnit::MillionsOfShares.scale(shares)),
+                        })
+                        .collect();
+                    return Ok(AnalysisResult::Ratios { periods, missing });
+                }
+
+                // Correlation between every pair of numeric metrics
+                if plan.intent == QueryIntent::Correlation {
+                    let periods = df.height();
+                    let mut series: Vec<(String, Vec<Option<f64>>)> = Vec::new();
+                    for col in df.get_columns() {
+                        if col.name() == "quarter" { continue; }
+                        if let Ok(f64chunked) = col.f64() {
+                            let vals: Vec<Option<f64>> = f64chunked.into_iter().collect();
+                            series.push((col.name().to_string(), vals));
+                        }
+                    }
+                    let mut strongest_positive: Option<MetricCorrelation> = None;
+                    let mut strongest_negative: Option<MetricCorrelation> = None;
+                    if periods >= MIN_CORRELATION_PERIODS {
+                        for i in 0..series.len() {
+                            for j in (i + 1)..series.len() {
+                                let (x, y) = complete_pairs(&series[i].1, &series[j].1);
+                                if x.len() < MIN_CORRELATION_PERIODS { continue; }
+                                if let Some(r) = pearson(&x, &y) {
+                                    let pair = MetricCorrelation { metric_a: series[i].0.clone(), metric_b: series[j].0.clone(), r };
+                                    if r > 0.0 && strongest_positive.as_ref().map_or(true, |c| r > c.r) {
+                                        strongest_positive = Some(pair);
+                                    } else if r < 0.0 && strongest_negative.as_ref().map_or(true, |c| r < c.r) {
+                                        strongest_negative = Some(pair);
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    return Ok(AnalysisResult::Correlation { periods, strongest_positive, strongest_negative });
+                }
+
+                // List numeric metrics with counts, latest values and units
+                if plan.intent == QueryIntent::MetricList {
+                    return Ok(AnalysisResult::MetricList { metrics: list_metrics(df) });
+                }
+
+                // Show quarters/periods
+                if plan.intent == QueryIntent::Periods {
+                    if let Ok(series) = df.column("quarter") {
+                        let periods: Vec<String> = series.utf8()?.into_iter().flatten().map(|q| q.to_string()).collect();
+                        return Ok(AnalysisResult::Periods { periods });
+                    }
+                }
+
+                // Dynamic metric detection (fuzzy matching, synonyms)
+                let available_metrics: Vec<String> = df
+                    .get_column_names()
+                    .iter()
+                    .filter(|&name| name != &"quarter")
+                    .map(|s| s.to_lowercase())
+                    .collect();
+
+                if let Some(orig_col) = plan.metric {
+                    if let Ok(series) = df.column(&orig_col) {
+                        if let (Ok(f64chunked), Some(stats)) = (series.f64(), column_summary(series)) {
+                            let values: Vec<f64> = f64chunked.into_iter().flatten().collect();
+                            let change = values.len().checked_sub(2).map(|i| values[i + 1] - values[i]);
+                            return Ok(AnalysisResult::Metric {
+                                metric: orig_col,
+                                periods: stats.periods,
+                                values,
+                                total: stats.sum,
+                                average: stats.mean,
+                                most_recent: stats.most_recent,
+                                change,
+                                source: stats.most_recent_row.and_then(|row| period_source(df, row)),
+                            });
+                        } else if let Ok(utf8chunked) = series.utf8() {
+                            let values: Vec<String> = utf8chunked.into_iter().flatten().map(|v| v.to_string()).collect();
+                            return Ok(AnalysisResult::TextMetric { metric: orig_col, values });
+                        } else {
+                            return Ok(AnalysisResult::Unknown {
+                                message: format!(
+                                    "SEC EDGAR: Column '{}' found, but data type is not supported for analysis.",
+                                    orig_col
+                                ),
+                                available_metrics,
+                            });
+                        }
+                    } else {
+                        return Ok(AnalysisResult::Unknown {
+                            message: format!("SEC EDGAR: Metric '{}' detected but could not retrieve data.", orig_col),
+                            available_metrics,
+                        });
+                    }
+                }
+
+                let available_list = if available_metrics.is_empty() {
+                    "No financial metrics available.".to_string()
+                } else {
+                    format!("Available metrics: {}", available_metrics.join(", "))
+                };
+                Ok(AnalysisResult::Unknown {
+                    message: format!(
+                        "FINFILES AI: Could not detect a specific financial metric in your query.\n{}\nTry asking about one of these metrics, or type 'summarize', 'forecast' (or 'forecast seasonal'), 'anomaly', 'correlation', 'ratios', 'metrics', or 'show table'.",
+                        available_list
+                    ),
+                    available_metrics,
+                })
+            }
+            fn backend_name(&self) -> &'static str { "FINFILES AI" }
+        }
+
+        #[async_trait]
+        impl FinancialAIModule for OnnxAIModule {
+            async fn analyze(&self, df: &DataFrame, _query: &str) -> Result<String> {
+                validate_frame(df)?;
+                let (names, features) = Self::latest_features(df)?;
+                let outputs = self.run_inference(features)?;
+
+                let output_lines: Vec<String> = outputs
+                    .iter()
+                    .enumerate()
+                    .map(|(i, v)| match names.get(i) {
+                        Some(name) if outputs.len() == names.len() => format!("  • {}: {:.4}", name, v),
+                        _ => format!("  • output[{}]: {:.4}", i, v),
+                    })
+                    .collect();
+                Ok(format!(
+                    "ONNX Model ({}) Output ({} input features from the most recent period):\n{}",
+                    self.model_name,
+                    names.len(),
+                    output_lines.join("\n")
+                ))
+            }
+            fn backend_name(&self) -> &'static str { "ONNX" }
+        }
+
+        #[async_trait]
+        impl FinancialAIModule for RemoteLLMAIModule {
+            async fn analyze(&self, df: &DataFrame, query: &str) -> Result<String> {
+                match &self.endpoint {
+                    Some(endpoint) => collect_stream(self.stream_remote(endpoint, df, query).await?).await,
+                    // Without an endpoint, RemoteLLM delegates to FinfilesAI logic.
+                    None => FinfilesAI.analyze(df, query).await,
+                }
+            }
+            async fn analyze_structured(&self, df: &DataFrame, query: &str) -> Result<AnalysisResult> {
+                match &self.endpoint {
+                    Some(_) => Ok(AnalysisResult::Text { text: self.analyze(df, query).await? }),
+                    None => FinfilesAI.analyze_structured(df, query).await,
+                }
+            }
+            async fn analyze_stream(&self, df: &DataFrame, query: &str) -> Result<BoxStream<'static, Result<String>>> {
+                match &self.endpoint {
+                    Some(endpoint) => self.stream_remote(endpoint, df, query).await,
+                    None => {
+                        let text = FinfilesAI.analyze(df, query).await?;
+                        Ok(stream::once(async move { Ok(text) }).boxed())
+                    }
+                }
+            }
+            fn backend_name(&self) -> &'static str { "RemoteLLM" }
+        }
+
+        #[async_trait]
+        impl FinancialAIModule for CustomModelAIModule {
+            async fn analyze(&self, df: &DataFrame, query: &str) -> Result<String> {
+                // For this system, custom model backend is a stub and delegates to FinfilesAI logic.
+                FinfilesAI.analyze(df, query).await
+            }
+            async fn analyze_structured(&self, df: &DataFrame, query: &str) -> Result<AnalysisResult> {
+                FinfilesAI.analyze_structured(df, query).await
+            }
+            fn backend_name(&self) -> &'static str { "CustomModel" }
+        }
+
+        #[cfg(test)]
+        mod tests {
+            use super::*;
+
+            #[test]
+            fn perfectly_correlated_series() {
+                let x = [1.0, 2.0, 3.0, 4.0, 5.0];
+                let y = [10.0, 20.0, 30.0, 40.0, 50.0];
+                assert!((pearson(&x, &y).unwrap() - 1.0).abs() < 1e-12);
+                let inverse: Vec<f64> = y.iter().map(|v| -v).collect();
+                assert!((pearson(&x, &inverse).unwrap() + 1.0).abs() < 1e-12);
+            }
+
+            #[test]
+            fn pearson_needs_variance_and_two_points() {
+                assert_eq!(pearson(&[1.0, 2.0, 3.0], &[4.0, 4.0, 4.0]), None);
+                assert_eq!(pearson(&[1.0], &[2.0]), None);
+                assert_eq!(pearson(&[], &[]), None);
+            }
+
+            #[test]
+            fn pearson_uses_the_shorter_series() {
+                let r = pearson(&[1.0, 2.0, 3.0], &[2.0, 4.0, 6.0, -100.0]).unwrap();
+                assert!((r - 1.0).abs() < 1e-12);
+            }
+
+            fn revenue_frame(values: &[f64]) -> DataFrame {
+                let quarters: Vec<String> = (1..=values.len()).map(|q| format!("2024Q{}", q)).collect();
+                DataFrame::new(vec![Series::new("quarter", quarters), Series::new("revenue", values)]).unwrap()
+            }
+
+            #[test]
+            fn cache_hits_on_the_same_key() {
+                let df = revenue_frame(&[1.0, 2.0]);
+                let mut cache = AnalysisCache::new(4);
+                cache.put(AnalysisKey::new("local", "Revenue trend?", &df), "up".to_string());
+                assert_eq!(cache.get(&AnalysisKey::new("local", "  revenue   TREND? ", &df)), Some("up".to_string()));
+                assert_eq!(cache.get(&AnalysisKey::new("remote", "Revenue trend?", &df)), None);
+            }
+
+            #[test]
+            fn cache_misses_after_the_data_changes() {
+                let before = revenue_frame(&[1.0, 2.0]);
+                let after = revenue_frame(&[1.0, 3.0]);
+                assert_eq!(dataframe_hash(&before), dataframe_hash(&revenue_frame(&[1.0, 2.0])));
+                assert_ne!(dataframe_hash(&before), dataframe_hash(&after));
+                let mut cache = AnalysisCache::new(4);
+                cache.put(AnalysisKey::new("local", "trend", &before), "up".to_string());
+                assert_eq!(cache.get(&AnalysisKey::new("local", "trend", &after)), None);
+            }
+
+            #[test]
+            fn cache_evicts_the_least_recently_used() {
+                let df = revenue_frame(&[1.0]);
+                let key = |q: &str| AnalysisKey::new("local", q, &df);
+                let mut cache = AnalysisCache::new(2);
+                cache.put(key("a"), "A".to_string());
+                cache.put(key("b"), "B".to_string());
+                // Touch "a" so "b" becomes the eviction candidate
+                assert!(cache.get(&key("a")).is_some());
+                cache.put(key("c"), "C".to_string());
+                assert_eq!(cache.len(), 2);
+                assert_eq!(cache.get(&key("b")), None);
+                assert_eq!(cache.get(&key("a")), Some("A".to_string()));
+                assert_eq!(cache.get(&key("c")), Some("C".to_string()));
+                assert_eq!(cache.clear(), 2);
+                assert!(cache.is_empty());
+            }
+
+            #[test]
+            fn comparison_needs_an_explicit_request() {
+                assert!(comparison_tickers("Revenue, margins and cash flow for AAPL, MSFT").is_empty());
+                assert!(comparison_tickers("How did AAPL do this year, and why?").is_empty());
+                assert_eq!(comparison_tickers("Compare revenue for AAPL, msft, GOOG"), vec!["AAPL", "MSFT", "GOOG"]);
+                assert_eq!(comparison_tickers("Revenue comparison: BRK.B, AAPL."), vec!["BRK.B", "AAPL"]);
+            }
+
+            #[test]
+            fn comparison_drops_invalid_tokens() {
+                assert_eq!(comparison_tickers("Compare revenue for AAPL, $$$, MSFT"), vec!["AAPL", "MSFT"]);
+                assert!(comparison_tickers("Compare revenue for AAPL, ABCDEFGHIJKLMNOP").is_empty());
+                assert!(comparison_tickers("Compare revenue for AAPL, aapl").is_empty());
+            }
+
+            // A serialized ONNX model (IR 7, opset 13) with one Identity node mapping a float
+            // input "x" of shape [1, n] to output "y"
+            const IDENTITY_MODEL: &[u8] = &[
+                0x08, 0x07, 0x12, 0x0d, 0x66, 0x69, 0x6e, 0x66, 0x69, 0x6c, 0x65, 0x73, 0x2d, 0x74, 0x65, 0x73,
+                0x74, 0x3a, 0x52, 0x0a, 0x1a, 0x0a, 0x01, 0x78, 0x12, 0x01, 0x79, 0x1a, 0x08, 0x69, 0x64, 0x65,
+                0x6e, 0x74, 0x69, 0x74, 0x79, 0x22, 0x08, 0x49, 0x64, 0x65, 0x6e, 0x74, 0x69, 0x74, 0x79, 0x12,
+                0x08, 0x69, 0x64, 0x65, 0x6e, 0x74, 0x69, 0x74, 0x79, 0x5a, 0x14, 0x0a, 0x01, 0x78, 0x12, 0x0f,
+                0x0a, 0x0d, 0x08, 0x01, 0x12, 0x09, 0x0a, 0x02, 0x08, 0x01, 0x0a, 0x03, 0x12, 0x01, 0x6e, 0x62,
+                0x14, 0x0a, 0x01, 0x79, 0x12, 0x0f, 0x0a, 0x0d, 0x08, 0x01, 0x12, 0x09, 0x0a, 0x02, 0x08, 0x01,
+                0x0a, 0x03, 0x12, 0x01, 0x6e, 0x42, 0x04, 0x0a, 0x00, 0x10, 0x0d,
+            ];
+
+            fn model_file(name: &str, bytes: &[u8]) -> PathBuf {
+                let path = std::env::temp_dir().join(format!("finfiles-{}-{}.onnx", name, std::process::id()));
+                fs::write(&path, bytes).unwrap();
+                path
+            }
+
+            fn quarterly_frame() -> DataFrame {
+                df!(
+                    "quarter" => &["FY2023-Q1", "FY2023-Q2"],
+                    "revenue" => &[100.0, 120.0],
+                    "net_income" => &[10.0, 15.0]
+                )
+                .unwrap()
+            }
+
+            #[test]
+            fn identity_model_round_trips_its_input() {
+                let module = OnnxAIModule::from_path(&model_file("identity", IDENTITY_MODEL)).unwrap();
+                assert_eq!(module.run_inference(vec![1.5, -2.0, 3.0]).unwrap(), vec![1.5, -2.0, 3.0]);
+            }
+
+            #[tokio::test]
+            async fn quarter_column_is_not_a_model_feature() {
+                let (names, features) = OnnxAIModule::latest_features(&quarterly_frame()).unwrap();
+                assert_eq!(names, vec!["revenue".to_string(), "net_income".to_string()]);
+                assert_eq!(features, vec![120.0, 15.0]);
+
+                let module = OnnxAIModule::from_path(&model_file("identity-analyze", IDENTITY_MODEL)).unwrap();
+                let text = module.analyze(&quarterly_frame(), "Summarize").await.unwrap();
+                assert!(text.contains("2 input features"));
+                assert!(text.contains("revenue: 120.0000"));
+                assert!(text.contains("net_income: 15.0000"));
+            }
+
+            #[test]
+            fn missing_model_file_is_an_ai_module_error() {
+                let missing = std::env::temp_dir().join("finfiles-no-such-model.onnx");
+                assert!(matches!(OnnxAIModule::from_path(&missing), Err(FinAIError::AIModule(_))));
+            }
+
+            fn balance_frame(with_eps: bool) -> DataFrame {
+                let mut df = df!(
+                    "quarter" => &["FY2023-Q1", "FY2023-Q2"],
+                    "Assets_USD_B" => &[300.0, 350.0],
+                    "Liabilities_USD_B" => &[200.0, 0.0],
+                    "Revenues_USD_B" => &[100.0, 120.0],
+                    "NetIncomeLoss_USD_B" => &[20.0, 30.0]
+                )
+                .unwrap();
+                if with_eps {
+                    df.with_column(Series::new("EarningsPerShareDiluted_USD/shares", &[2.0, 3.0])).unwrap();
+                }
+                df
+            }
+
+            #[tokio::test]
+            async fn ratios_per_period() {
+                let result = FinfilesAI.analyze_structured(&balance_frame(true), "Show ratios").await.unwrap();
+                let (periods, missing) = match result {
+                    AnalysisResult::Ratios { periods, missing } => (periods, missing),
+                    other => panic!("expected ratios, got {:?}", other),
+                };
+                assert!(missing.is_empty());
+                assert_eq!(
+                    periods,
+                    vec![
+                        PeriodRatios {
+                            period: "FY2023-Q1".to_string(),
+                            current_ratio: Some(1.5),
+                            net_margin: Some(0.2),
+                            implied_diluted_shares: Some(10_000.0),
+                        },
+                        // No liabilities reported as zero: the current ratio can't be computed
+                        PeriodRatios {
+                            period: "FY2023-Q2".to_string(),
+                            current_ratio: None,
+                            net_margin: Some(0.25),
+                            implied_diluted_shares: Some(10_000.0),
+                        },
+                    ]
+                );
+            }
+
+            #[tokio::test]
+            async fn ratios_name_their_missing_inputs() {
+                let result = FinfilesAI.analyze_structured(&balance_frame(false), "ratios").await.unwrap();
+                match result {
+                    AnalysisResult::Ratios { periods, missing } => {
+                        assert_eq!(missing, vec!["Implied diluted shares (needs eps)".to_string()]);
+                        assert!(periods.iter().all(|p| p.implied_diluted_shares.is_none()));
+                    }
+                    other => panic!("expected ratios, got {:?}", other),
+                }
+            }
+
+            fn single_outlier_frame() -> DataFrame {
+                df!("revenue" => &[10.0, 11.0, 9.0, 10.0, 12.0, 10.0, 11.0, 9.0, 50.0]).unwrap()
+            }
+
+            #[test]
+            fn zscore_flags_the_single_outlier() {
+                let anomalies = detect_anomalies(&single_outlier_frame(), AnomalyOptions::new(AnomalyMethod::ZScore));
+                assert_eq!(anomalies.len(), 1);
+                let outlier = &anomalies[0];
+                assert_eq!((outlier.metric.as_str(), outlier.period, outlier.value), ("revenue", 9, 50.0));
+                assert!(outlier.upper < 50.0 && outlier.excess > 0.0);
+            }
+
+            #[test]
+            fn iqr_flags_the_single_outlier() {
+                let anomalies = detect_anomalies(&single_outlier_frame(), AnomalyOptions::new(AnomalyMethod::Iqr));
+                assert_eq!(anomalies.len(), 1);
+                assert_eq!((anomalies[0].period, anomalies[0].value), (9, 50.0));
+                // Quartiles 10 and 11 give fences 1.5 IQRs outside them
+                assert_eq!((anomalies[0].lower, anomalies[0].upper), (8.5, 12.5));
+                assert_eq!(anomalies[0].excess, 37.5);
+            }
+
+            #[test]
+            fn anomaly_options_follow_the_query() {
+                let options = AnomalyOptions::from_query("outliers iqr sensitivity 3");
+                assert_eq!((options.method, options.sensitivity), (AnomalyMethod::Iqr, 3.0));
+                assert_eq!(AnomalyOptions::from_query("anomaly"), AnomalyOptions::new(AnomalyMethod::ZScore));
+            }
+
+            // Three years of quarters: trend +2 per quarter plus offsets 10, -5, -10, 5
+            fn seasonal_series(quarters: usize) -> Vec<f64> {
+                let offsets = [10.0, -5.0, -10.0, 5.0];
+                (0..quarters).map(|t| 100.0 + 2.0 * t as f64 + offsets[t % 4]).collect()
+            }
+
+            #[test]
+            fn seasonal_naive_repeats_the_last_season() {
+                let values = seasonal_series(12);
+                assert_eq!(ForecastMethod::SeasonalNaive.project(&values), (ForecastMethod::SeasonalNaive, values[8..].to_vec()));
+            }
+
+            #[test]
+            fn seasonal_additive_tracks_the_pattern() {
+                let (method, projections) = ForecastMethod::SeasonalAdditive.project(&seasonal_series(12));
+                assert_eq!(method, ForecastMethod::SeasonalAdditive);
+                // The series continues as 134, 121, 118, 135
+                let expected = &seasonal_series(16)[12..];
+                for (projected, actual) in projections.iter().zip(expected) {
+                    assert!((projected - actual).abs() < 2.0, "{} vs {}", projected, actual);
+                }
+                // Quarter-to-quarter moves follow the seasonal offsets exactly
+                let steps: Vec<f64> = projections.windows(2).map(|w| w[1] - w[0]).collect();
+                for (step, expected) in steps.iter().zip([-13.0, -3.0, 17.0]) {
+                    assert!((step - expected).abs() < 1e-9);
+                }
+                // A straight line misses the seasonal swing
+                let linear = linear_projection(&seasonal_series(12), 1)[0];
+                assert!((linear - expected[0]).abs() > (projections[0] - expected[0]).abs());
+            }
+
+            #[test]
+            fn short_series_fall_back_to_linear() {
+                let (method, projections) = ForecastMethod::SeasonalAdditive.project(&seasonal_series(MIN_SEASONAL_PERIODS - 1));
+                assert_eq!(method, ForecastMethod::Linear);
+                assert_eq!(projections.len(), 1);
+            }
+
+            #[tokio::test]
+            async fn seasonal_forecast_uses_quarterly_rows_only() {
+                // Newest first as the loader stores them, with a full fiscal year mixed in
+                let mut quarters = Vec::new();
+                let mut revenue = Vec::new();
+                for (t, value) in seasonal_series(12).into_iter().enumerate().rev() {
+                    quarters.push(format!("FY{}-Q{}", 2021 + t / 4, t % 4 + 1));
+                    revenue.push(value);
+                }
+                quarters.push("FY2022".to_string());
+                revenue.push(1000.0);
+                let df = df!("quarter" => quarters, "revenue" => revenue).unwrap();
+
+                let result = FinfilesAI.analyze_structured(&df, "forecast seasonal").await.unwrap();
+                match result {
+                    AnalysisResult::Forecast { method, forecasts } => {
+                        assert_eq!(method, ForecastMethod::SeasonalAdditive);
+                        assert_eq!(forecasts[0].method, ForecastMethod::SeasonalAdditive);
+                        assert_eq!(forecasts[0].projections, ForecastMethod::SeasonalAdditive.project(&seasonal_series(12)).1);
+                    }
+                    other => panic!("expected a forecast, got {:?}", other),
+                }
+            }
+
+            #[test]
+            fn frames_without_metrics_are_rejected() {
+                let rejected = [
+                    DataFrame::default(),
+                    df!("quarter" => &["FY2023-Q1", "FY2023-Q2"]).unwrap(),
+                    df!("quarter" => &["FY2023-Q1"], "ticker" => &["AAPL"]).unwrap(),
+                    df!("quarter" => &["FY2023-Q1"], "revenue" => &["94.8"]).unwrap(),
+                    df!("revenue" => Vec::<f64>::new()).unwrap(),
+                ];
+                for df in &rejected {
+                    let err = validate_frame(df).unwrap_err();
+                    assert!(is_missing_metrics(&err), "{:?}", df);
+                    assert_eq!(error_message(&err), NO_METRICS_MESSAGE);
+                }
+            }
+
+            #[test]
+            fn frames_with_a_numeric_metric_are_accepted() {
+                let df = df!("quarter" => &["FY2023-Q1"], "revenue" => &[94.8]).unwrap();
+                assert!(validate_frame(&df).is_ok());
+                let counts = df!("filings" => &[3i64, 4]).unwrap();
+                assert!(validate_frame(&counts).is_ok());
+            }
+
+            // Newest period first, as the loader stores them
+            fn revenue_history(newest_first: &[f64]) -> DataFrame {
+                let quarters: Vec<String> = (0..newest_first.len()).map(|i| format!("FY2023-Q{}", newest_first.len() - i)).collect();
+                df!("quarter" => quarters, "revenue" => newest_first).unwrap()
+            }
+
+            #[test]
+            fn declining_tickers_rank_first() {
+                let frames = vec![
+                    ("AAPL".to_string(), revenue_history(&[120.0, 110.0, 100.0])),
+                    ("INTC".to_string(), revenue_history(&[60.0, 70.0, 80.0])),
+                    ("NEWCO".to_string(), revenue_history(&[5.0])),
+                ];
+                let text = FinfilesAI.rank_trends(&frames, "which ticker has declining revenue?").unwrap();
+                let lines: Vec<&str> = text.lines().collect();
+                assert!(lines[0].contains("revenue Trend Across 3 Tickers (1 declining)"));
+                assert!(lines[1].starts_with("  1. INTC: declining (-14.3% per period;"));
+                assert!(lines[2].starts_with("  2. AAPL: rising (+9.1% per period;"));
+                assert_eq!(lines[3], "  3. NEWCO: not enough data");
+            }
+
+            #[test]
+            fn ranking_needs_loaded_tickers_and_a_metric() {
+                assert!(FinfilesAI.rank_trends(&[], "declining revenue").unwrap().contains("No tickers loaded"));
+                let frames = vec![("AAPL".to_string(), revenue_history(&[1.0, 2.0]))];
+                assert!(FinfilesAI.rank_trends(&frames, "which is declining?").unwrap().contains("Could not detect a metric"));
+            }
+
+            fn mixed_frame() -> DataFrame {
+                df!(
+                    "quarter" => &["FY2023-Q1", "FY2023-Q2"],
+                    "Revenues_USD_B" => &[90.0, 95.0],
+                    "NetIncomeLoss_USD_B" => &[20.0, 25.0],
+                    "segment" => &["Products", "Services"]
+                )
+                .unwrap()
+            }
+
+            #[tokio::test]
+            async fn each_query_type_has_its_own_variant() {
+                let df = mixed_frame();
+                let cases = [
+                    ("show raw table", "table"),
+                    ("summarize", "summary"),
+                    ("forecast", "forecast"),
+                    ("any anomaly?", "anomaly"),
+                    ("ratios", "ratios"),
+                    ("correlation", "correlation"),
+                    ("which quarters are loaded", "periods"),
+                    ("metrics", "metric_list"),
+                    ("what was revenue", "metric"),
+                    ("segment", "text_metric"),
+                    ("hello there", "unknown"),
+                ];
+                for (query, mode) in cases {
+                    let result = FinfilesAI.analyze_structured(&df, query).await.unwrap();
+                    let json = serde_json::to_value(&result).unwrap();
+                    assert_eq!(json["mode"], mode, "{}", query);
+                    // The text response is the structured result rendered
+                    assert_eq!(FinfilesAI.analyze(&df, query).await.unwrap(), result.to_string());
+                }
+            }
+
+            #[tokio::test]
+            async fn metric_results_carry_typed_fields() {
+                let result = FinfilesAI.analyze_structured(&mixed_frame(), "what was revenue").await.unwrap();
+                match &result {
+                    AnalysisResult::Metric { metric, periods, values, total, change, .. } => {
+                        assert_eq!(metric, "Revenues_USD_B");
+                        assert_eq!((*periods, values.clone(), *total, *change), (2, vec![90.0, 95.0], 185.0, Some(5.0)));
+                    }
+                    other => panic!("expected a metric result, got {:?}", other),
+                }
+                let json = serde_json::to_string(&result).unwrap();
+                assert_eq!(serde_json::from_str::<AnalysisResult>(&json).unwrap(), result);
+
+                match FinfilesAI.analyze_structured(&mixed_frame(), "hello there").await.unwrap() {
+                    AnalysisResult::Unknown { available_metrics, .. } => {
+                        assert_eq!(available_metrics, vec!["revenues_usd_b", "netincomeloss_usd_b", "segment"]);
+                    }
+                    other => panic!("expected an unknown result, got {:?}", other),
+                }
+            }
+
+            #[tokio::test]
+            async fn eps_is_shown_in_dollars_without_a_total() {
+                let df = df!(
+                    "quarter" => &["FY2024-Q3", "FY2024-Q4"],
+                    "EarningsPerShareDiluted_USD/shares" => &[5.94, 6.13]
+                )
+                .unwrap();
+                let result = FinfilesAI.analyze_structured(&df, "diluted earnings per share").await.unwrap();
+                assert!(matches!(&result, AnalysisResult::Metric { metric, .. } if metric == "EarningsPerShareDiluted_USD/shares"));
+                let text = result.to_string();
+                assert!(text.contains(&format!("Most recent period: {}", MetricUnit::PerShare.format(6.13))), "{}", text);
+                assert!(text.contains("Change from prior period: +19¢ (+3.2%)"), "{}", text);
+                // Summing EPS across periods is meaningless
+                assert!(!text.contains("Total"), "{}", text);
+            }
+
+            fn explain_frame() -> DataFrame {
+                df!(
+                    "quarter" => &["FY2023-Q1"],
+                    "Revenues_USD_B" => &[90.0],
+                    "NetIncomeLoss_USD_B" => &[20.0],
+                    "EarningsPerShareDiluted_USD/shares" => &[1.5]
+                )
+                .unwrap()
+            }
+
+            #[test]
+            fn explain_reports_intent_and_metric() {
+                let df = explain_frame();
+                let plan = |query: &str| FinfilesAI.explain(query, &df);
+                assert_eq!(plan("Forecast revenue"), QueryPlan { intent: QueryIntent::Forecast, metric: None });
+                assert_eq!(plan("summarize").to_string(), "summary of all metrics");
+                assert_eq!(plan("list the quarters").intent, QueryIntent::Periods);
+                assert_eq!(plan("netincomeloss_usd_b").metric.as_deref(), Some("NetIncomeLoss_USD_B"));
+                assert_eq!(plan("what's the weather").to_string(), "unrecognized query");
+            }
+
+            #[test]
+            fn explain_resolves_synonyms_to_columns() {
+                let df = explain_frame();
+                let cases = [
+                    ("net sales in 2023?", "Revenues_USD_B"),
+                    ("how much profit", "NetIncomeLoss_USD_B"),
+                    ("diluted earnings per share", "EarningsPerShareDiluted_USD/shares"),
+                ];
+                for (query, column) in cases {
+                    let plan = FinfilesAI.explain(query, &df);
+                    assert_eq!(plan, QueryPlan { intent: QueryIntent::Metric, metric: Some(column.to_string()) }, "{}", query);
+                    assert_eq!(plan.to_string(), format!("values of {}", column));
+                }
+            }
+
+            #[test]
+            fn longer_aliases_take_precedence() {
+                let table = SynonymTable::defaults();
+                let lengths: Vec<usize> = table.entries().map(|(alias, _)| alias.len()).collect();
+                assert!(lengths.windows(2).all(|w| w[0] >= w[1]));
+                assert_eq!(table.canonical("Earnings per Share"), Some("earningspersharediluted"));
+                assert_eq!(table.canonical("earnings"), Some("netincomeloss"));
+
+                let metrics = vec!["cashandcashequivalentsatcarryingvalue_usd_b".to_string(), "operatingcashflow_usd_b".to_string()];
+                let resolved = FinfilesAI::resolve_metric(&metrics, "operating cash flow this year");
+                assert_eq!(resolved.as_deref(), Some("operatingcashflow_usd_b"));
+                assert_eq!(FinfilesAI::resolve_metric(&metrics, "cash on hand").as_deref(), Some(metrics[0].as_str()));
+            }
+
+            #[test]
+            fn user_overrides_add_and_repoint_aliases() {
+                let json = r#"{"revenues": ["bookings", " Top Line "], "grossprofit": ["profit"]}"#;
+                let table = SynonymTable::defaults().with_overrides_json(json).unwrap();
+                assert_eq!(table.canonical("bookings"), Some("revenues"));
+                assert_eq!(table.canonical("top line"), Some("revenues"));
+                assert_eq!(table.canonical("profit"), Some("grossprofit"));
+                // Aliases the file doesn't mention keep their defaults
+                assert_eq!(table.canonical("net profit"), Some("netincomeloss"));
+
+                let mut table = table;
+                table.insert("  ", "revenues");
+                assert_eq!(table.canonical(""), None);
+
+                assert!(matches!(SynonymTable::defaults().with_overrides_json("[1, 2]"), Err(FinAIError::Config(_))));
+            }
+
+            #[test]
+            fn short_metric_names_do_not_match_inside_words() {
+                let df = df!(
+                    "quarter" => &["FY2023-Q1"],
+                    "Cash" => &[1.0],
+                    "EPS" => &[2.0],
+                    "NI" => &[3.0],
+                    "Revenues_USD_B" => &[4.0]
+                )
+                .unwrap();
+                for query in ["cashier hiring", "steps to take", "unique products"] {
+                    assert_eq!(FinfilesAI.explain(query, &df).intent, QueryIntent::Unknown, "{}", query);
+                }
+                let cases = [("what is ni?", "NI"), ("cash position", "Cash"), ("EPS, please", "EPS"), ("revenues", "Revenues_USD_B")];
+                for (query, column) in cases {
+                    assert_eq!(FinfilesAI.explain(query, &df).metric.as_deref(), Some(column), "{}", query);
+                }
+            }
+
+            #[test]
+            fn the_longest_whole_word_match_wins() {
+                let metrics = vec!["income".to_string(), "operating income".to_string()];
+                let resolved = FinfilesAI::resolve_metric(&metrics, "operating income last year");
+                assert_eq!(resolved.as_deref(), Some("operating income"));
+                assert_eq!(FinfilesAI::resolve_metric(&metrics, "incomes"), None);
+            }
+
+            #[test]
+            fn metrics_listing_content_and_order() {
+                // Newest period first, as the loader stores them
+                let df = df!(
+                    "quarter" => &["FY2023-Q2", "FY2023-Q1", "FY2022-Q4"],
+                    "revenues_USD_B" => &[Some(95.0), Some(90.0), None],
+                    "Assets" => &[None, Some(2.0), Some(3.0)],
+                    "eps_USD/shares" => &[1.5, 1.4, 1.3],
+                    "Employees" => &[160i64, 150, 140],
+                    "note" => &["a", "b", "c"]
+                )
+                .unwrap();
+                let info = |metric: &str, count, latest, unit| MetricInfo { metric: metric.to_string(), count, latest: Some(latest), unit };
+                assert_eq!(
+                    list_metrics(&df),
+                    vec![
+                        info("Assets", 2, 2.0, MetricUnit::Raw),
+                        info("Employees", 3, 160.0, MetricUnit::Raw),
+                        info("eps_USD/shares", 3, 1.5, MetricUnit::PerShare),
+                        info("revenues_USD_B", 2, 95.0, MetricUnit::Billions),
+                    ]
+                );
+            }
+
+            #[tokio::test]
+            async fn metrics_command_returns_the_listing() {
+                let df = df!("quarter" => &["FY2023-Q1"], "revenue" => &[1.0]).unwrap();
+                match FinfilesAI.analyze_structured(&df, "which columns are there?").await.unwrap() {
+                    AnalysisResult::MetricList { metrics } => assert_eq!(metrics, list_metrics(&df)),
+                    other => panic!("expected the metrics listing, got {:?}", other),
+                }
+            }
+
+            #[test]
+            fn sentiment_of_positive_negative_and_neutral_text() {
+                assert_eq!(sentiment_summary("Record revenue growth and strong profit"), "Positive (+0.90)");
+                assert_eq!(sentiment_summary("Impairment and litigation losses"), "Negative (-0.84)");
+                assert_eq!(sentiment_summary("Annual report"), "Neutral (+0.00)");
+                // A negation flips the word after it
+                assert_eq!(score_sentiment("No losses this year").unwrap().label, "Positive");
+            }
+
+            #[test]
+            fn sentiment_is_deterministic_and_needs_words() {
+                let text = "Restated results after an SEC investigation";
+                assert_eq!(score_sentiment(text), score_sentiment(text));
+                assert!(score_sentiment(text).unwrap().score <= -SENTIMENT_THRESHOLD);
+                assert_eq!(sentiment_summary(""), "n/a");
+                assert_eq!(sentiment_summary("2023-12-31"), "n/a");
+            }
+
+            // Streams fixed chunks, the earlier ones arriving more slowly
+            struct ChunkedModule(Vec<&'static str>);
+
+            #[async_trait]
+            impl FinancialAIModule for ChunkedModule {
+                async fn analyze(&self, df: &DataFrame, query: &str) -> Result<String> {
+                    collect_stream(self.analyze_stream(df, query).await?).await
+                }
+                async fn analyze_stream(&self, _df: &DataFrame, _query: &str) -> Result<BoxStream<'static, Result<String>>> {
+                    let count = self.0.len() as u64;
+                    let chunks = self.0.iter().enumerate().map(|(i, chunk)| (count - i as u64, chunk.to_string())).collect::<Vec<_>>();
+                    Ok(stream::iter(chunks)
+                        .then(|(delay, chunk)| async move {
+                            tokio::time::sleep(Duration::from_millis(delay * 5)).await;
+                            Ok(chunk)
+                        })
+                        .boxed())
+                }
+                fn backend_name(&self) -> &'static str { "Chunked" }
+            }
+
+            #[tokio::test]
+            async fn streamed_chunks_arrive_in_order() {
+                let module = ChunkedModule(vec!["Revenue ", "rose ", "12% ", "year over year."]);
+                let df = DataFrame::default();
+                let chunks: Vec<String> =
+                    module.analyze_stream(&df, "revenue").await.unwrap().map(|chunk| chunk.unwrap()).collect().await;
+                assert_eq!(chunks, vec!["Revenue ", "rose ", "12% ", "year over year."]);
+                assert_eq!(module.analyze(&df, "revenue").await.unwrap(), "Revenue rose 12% year over year.");
+            }
+
+            #[tokio::test]
+            async fn collecting_stops_at_the_first_error() {
+                let chunks = vec![Ok("partial ".to_string()), Err(FinAIError::Network("dropped".to_string())), Ok("never".to_string())];
+                let result = collect_stream(stream::iter(chunks).boxed()).await;
+                assert!(matches!(result, Err(FinAIError::Network(_))));
+            }
+
+            #[test]
+            fn sse_events_are_drained_as_they_complete() {
+                let mut buffer = "data: Revenue \n\ndata: rose\ndata: sharply\n\ndata: par".to_string();
+                assert_eq!(drain_sse_events(&mut buffer), vec!["Revenue ".to_string(), "rose\nsharply".to_string()]);
+                assert_eq!(buffer, "data: par");
+            }
+
+            #[test]
+            fn onnx_headers_are_checked() {
+                assert!(validate_onnx_header(&IDENTITY_MODEL[..2]).is_ok());
+                assert!(validate_onnx_header(&[0x08, 0x09, 0x12]).is_ok());
+                let bad_headers: [&[u8]; 4] = [b"PK\x03\x04", b"{\"model\"", &[0x08, 0x00], &[0x08, 0x40]];
+                for bad in bad_headers {
+                    assert!(matches!(validate_onnx_header(bad), Err(FinAIError::CustomModel(m)) if m.contains("ONNX model header")));
+                }
+                let short_headers: [&[u8]; 2] = [b"", &[0x08]];
+                for short in short_headers {
+                    assert!(matches!(validate_onnx_header(short), Err(FinAIError::CustomModel(m)) if m.contains("too small")));
+                }
+            }
+
+            #[test]
+            fn uploaded_model_files_are_validated() {
+                assert!(validate_model_file(&model_file("valid-upload", IDENTITY_MODEL)).is_ok());
+                assert!(validate_model_file(&model_file("zip-upload", b"PK\x03\x04rest")).is_err());
+                assert!(validate_model_file(&model_file("empty-upload", b"")).is_err());
+                assert!(validate_model_file(&std::env::temp_dir().join("finfiles-no-such-upload.onnx")).is_err());
+
+                // The extension is checked before the contents
+                let renamed = std::env::temp_dir().join(format!("finfiles-model-{}.bin", std::process::id()));
+                fs::write(&renamed, IDENTITY_MODEL).unwrap();
+                assert!(matches!(validate_model_file(&renamed), Err(FinAIError::CustomModel(m)) if m.contains(".onnx")));
+            }
+
+            #[test]
+            fn failed_backend_init_becomes_a_user_message() {
+                let failed = init_backend("FINFILES AI", || -> Result<FinfilesAI> {
+                    Err(FinAIError::AIModule("model file missing".to_string()))
+                });
+                assert_eq!(failed.err().as_deref(), Some("FINFILES AI is unavailable: AI module error: model file missing"));
+
+                let created = init_backend("FINFILES AI", FinfilesAI::new).unwrap();
+                assert_eq!(created.backend_name(), "FINFILES AI");
+            }
+
+            // Answers after the given delay
+            struct SlowModule(Duration);
+
+            #[async_trait]
+            impl FinancialAIModule for SlowModule {
+                async fn analyze(&self, _df: &DataFrame, _query: &str) -> Result<String> {
+                    tokio::time::sleep(self.0).await;
+                    Ok("done".to_string())
+                }
+                fn backend_name(&self) -> &'static str { "Slow" }
+            }
+
+            #[tokio::test]
+            async fn analysis_past_the_timeout_is_an_error() {
+                let module = SlowModule(Duration::from_secs(30));
+                let df = DataFrame::default();
+                let started = std::time::Instant::now();
+                let err = with_timeout(Duration::from_secs(1), module.analyze(&df, "Summarize")).await.unwrap_err();
+                assert!(started.elapsed() < Duration::from_secs(10));
+                assert!(matches!(&err, FinAIError::AIModule(m) if m == "timed out after 1s"));
+                assert_eq!(error_message(&err), "Error: AI module error: timed out after 1s");
+            }
+
+            #[tokio::test]
+            async fn analysis_within_the_timeout_is_returned() {
+                let module = SlowModule(Duration::from_millis(10));
+                let text = with_timeout(Duration::from_secs(5), module.analyze(&DataFrame::default(), "Summarize")).await;
+                assert_eq!(text.unwrap(), "done");
+            }
+
+            #[tokio::test]
+            async fn missing_periods_are_left_out_of_averages() {
+                let df = df!(
+                    "quarter" => &["FY2021", "FY2022", "FY2023"],
+                    "NetIncomeLoss_USD_B" => &[Some(10.0), None, Some(20.0)]
+                )
+                .unwrap();
+                match FinfilesAI::new().unwrap().analyze_structured(&df, "Summarize").await.unwrap() {
+                    AnalysisResult::Summary { periods, metrics } => {
+                        assert_eq!(periods, 3);
+                        assert_eq!(metrics.len(), 1);
+                        assert_eq!(metrics[0].average, 15.0);
+                        assert_eq!(metrics[0].total, 30.0);
+                        assert_eq!(metrics[0].most_recent, 20.0);
+                    }
+                    other => panic!("expected a summary, got {:?}", other),
+                }
+            }
+
+            #[test]
+            fn correlations_use_only_periods_with_both_values() {
+                let (x, y) = complete_pairs(&[Some(1.0), None, Some(3.0), Some(4.0)], &[Some(2.0), Some(5.0), None, Some(8.0)]);
+                assert_eq!((x, y), (vec![1.0, 4.0], vec![2.0, 8.0]));
+            }
+        }
+    }
+
+    pub mod data_ingestion {
+        use super::config::{AppConfig, NumberFormat, DEFAULT_PERIODS};
+        use super::error::*;
+        use super::security::sanitize_ticker;
+        use chrono::NaiveDate;
+        use polars::prelude::*;
+        use quick_xml::events::Event;
+        use quick_xml::Reader;
+        use futures::StreamExt;
+        use serde::{Deserialize, Serialize};
+        use std::collections::{HashMap, HashSet};
+        use std::fs::{self, File};
+        use std::io::{BufRead, BufReader, Read, Write};
+        use std::path::Path;
+        use std::time::Duration;
+        use reqwest::Client;
+
+        #[derive(Debug, Deserialize)]
+        pub struct CikEntry {
+            pub cik_str: String,
+            pub ticker: String,
+            pub title: String,
+        }
+
+        #[derive(Debug, Deserialize)]
+        pub struct CompanySubmissions {
+            #[serde(default)]
+            pub name: String,
+            // Standard Industrial Classification code, e.g. "3571"; empty when unclassified
+            #[serde(default)]
+            pub sic: String,
+            #[serde(default, rename = "sicDescription")]
+            pub sic_description: String,
+            pub filings: Filings,
+        }
+
+        #[derive(Debug, Deserialize)]
+        pub struct Filings {
+            pub recent: RecentFilings,
+            // Older filings, split into further JSON files in the same column layout as `recent`
+            #[serde(default)]
+            pub files: Vec<FilingsFile>,
+        }
+
+        #[derive(Debug, Clone, Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        pub struct FilingsFile {
+            pub name: String,
+            #[serde(default)]
+            pub filing_count: usize,
+        }
+
+        #[derive(Debug, Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        pub struct RecentFilings {
+            pub accession_number: Vec<String>,
+            pub form: Vec<String>,
+            #[serde(default)]
+            pub filing_date: Vec<String>,
+            // Period end each filing reports on (empty for forms without one)
+            #[serde(default)]
+            pub report_date: Vec<String>,
+            #[serde(default)]
+            pub primary_document: Vec<String>,
+            #[serde(default)]
+            pub primary_doc_description: Vec<String>,
+        }
+
+        impl RecentFilings {
+            // Index of the latest 10-K or 10-Q (amendments included). For the latest reported
+            // period, the most recently filed amendment supersedes the original.
+            pub fn latest_periodic(&self) -> Option<usize> {
+                let field = |column: &Vec<String>, i: usize| column.get(i).cloned().unwrap_or_default();
+                self.form
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, form)| matches!(base_form(form), "10-K" | "10-Q"))
+                    .max_by(|(a, form_a), (b, form_b)| {
+                        (field(&self.report_date, *a), field(&self.filing_date, *a), is_amendment(form_a))
+                            .cmp(&(field(&self.report_date, *b), field(&self.filing_date, *b), is_amendment(form_b)))
+                            // Same dates: the earlier entry in SEC's newest-first listing wins
+                            .then(b.cmp(a))
+                    })
+                    .map(|(i, _)| i)
+            }
+        }
+
+        // Form type without its amendment suffix, e.g. "10-K/A" -> "10-K"
+        pub fn base_form(form: &str) -> &str {
+            let form = form.trim();
+            form.strip_suffix("/A").or_else(|| form.strip_suffix("/a")).unwrap_or(form)
+        }
+
+        // Whether a form type is an amendment ("10-K/A", "10-Q/A", ...)
+        pub fn is_amendment(form: &str) -> bool {
+            base_form(form).len() < form.trim().len()
+        }
+
+        #[derive(Debug, Deserialize)]
+        pub struct CompanyFacts {
+            pub facts: HashMap<String, HashMap<String, GaapFact>>,
+        }
+
+        #[derive(Debug, Deserialize)]
+        pub struct GaapFact {
+            pub units: HashMap<String, Vec<FactUnit>>,
+        }
+
+        #[derive(Debug, Deserialize)]
+        pub struct FactUnit {
+            // SEC companyfacts reports these as "fp" and "fy"
+            #[serde(rename = "fp", alias = "fiscalPeriod")]
+            pub fiscal_period: Option<String>,
+            #[serde(rename = "fy", alias = "fiscalYear")]
+            pub fiscal_year: Option<i32>,
+            pub frame: Option<String>,
+            // Period end and filing dates (ISO YYYY-MM-DD, so they compare as strings)
+            pub end: Option<String>,
+            pub filed: Option<String>,
+            #[serde(rename = "val")]
+            pub value: Option<f64>,
+            // Accession number and form type of the filing that reported the value
+            #[serde(rename = "accn")]
+            pub accession: Option<String>,
+            pub form: Option<String>,
+        }
+
+        impl FactUnit {
+            // Period label used as the DataFrame's "quarter" value, e.g. "FY2023-Q2", or
+            // "FY2023" for a full fiscal year. Without a fiscal year it's the bare period.
+            pub fn period_label(&self) -> Option<String> {
+                let fp = self.fiscal_period.as_ref()?;
+                Some(match self.fiscal_year {
+                    Some(fy) if fp.eq_ignore_ascii_case("FY") => format!("FY{}", fy),
+                    Some(fy) => format!("FY{}-{}", fy, fp),
+                    None => fp.clone(),
+                })
+            }
+
+            // Ordering used to pick between facts for the same period: latest filing first,
+            // then an amendment over the original it restates, then latest period end, then
+            // frame name so the choice is deterministic
+            fn recency(&self) -> (Option<&str>, bool, Option<&str>, Option<&str>) {
+                (
+                    self.filed.as_deref(),
+                    self.form.as_deref().map_or(false, is_amendment),
+                    self.end.as_deref(),
+                    self.frame.as_deref(),
+                )
+            }
+
+            // The filing this fact came from, when SEC reported its accession number
+            pub fn source(&self) -> Option<FactSource> {
+                Some(FactSource {
+                    accession: self.accession.clone()?,
+                    form: self.form.clone().unwrap_or_default(),
+                    filed: self.filed.clone().unwrap_or_default(),
+                })
+            }
+        }
+
+        // Provenance columns added to the metrics DataFrame, one value per period
+        pub const SOURCE_ACCESSION_COLUMN: &str = "source_accession";
+        pub const SOURCE_FORM_COLUMN: &str = "source_form";
+        pub const FILED_DATE_COLUMN: &str = "filed_date";
+
+        // Filing a period's values were taken from
+        #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+        pub struct FactSource {
+            pub accession: String,
+            pub form: String,
+            pub filed: String,
+        }
+
+        impl FactSource {
+            // Short citation, e.g. "from 10-K filed 2023-10-27"
+            pub fn citation(&self) -> String {
+                match (self.form.is_empty(), self.filed.is_empty()) {
+                    (false, false) => format!("from {} filed {}", self.form, self.filed),
+                    (false, true) => format!("from {} {}", self.form, self.accession),
+                    (true, false) => format!("from filing {} filed {}", self.accession, self.filed),
+                    (true, true) => format!("from filing {}", self.accession),
+                }
+            }
+        }
+
+        // The filing that supplied most of a period's values (the latest filed on a tie)
+        fn dominant_source(sources: &[FactSource]) -> Option<FactSource> {
+            let mut counts: HashMap<&str, usize> = HashMap::new();
+            for source in sources {
+                *counts.entry(source.accession.as_str()).or_insert(0) += 1;
+            }
+            sources
+                .iter()
+                .max_by(|a, b| (counts[a.accession.as_str()], &a.filed).cmp(&(counts[b.accession.as_str()], &b.filed)))
+                .cloned()
+        }
+
+        // Source of the given row of a metrics DataFrame, read from its provenance columns.
+        // None for frames without them (such as the bundled demo data).
+        pub fn period_source(df: &DataFrame, row: usize) -> Option<FactSource> {
+            let text = |name: &str| -> Option<String> {
+                df.column(name).ok()?.utf8().ok()?.get(row).map(|v| v.to_string())
+            };
+            Some(FactSource {
+                accession: text(SOURCE_ACCESSION_COLUMN)?,
+                form: text(SOURCE_FORM_COLUMN).unwrap_or_default(),
+                filed: text(FILED_DATE_COLUMN).unwrap_or_default(),
+            })
+        }
+
+        // Chronological key for a period label: (fiscal year, quarter), with a full fiscal
+        // year after its Q4. Labels that don't parse sort before all others.
+        pub fn period_sort_key(label: &str) -> (i32, u8) {
+            let label = label.trim().to_uppercase();
+            let (year, period) = match label.strip_prefix("FY") {
+                Some(rest) => rest.split_once('-').unwrap_or((rest, "FY")),
+                None => return (i32::MIN, 0),
+            };
+            let year = match year.parse::<i32>() {
+                Ok(year) => year,
+                Err(_) => return (i32::MIN, 0),
+            };
+            let quarter = match period {
+                "Q1" => 1,
+                "Q2" => 2,
+                "Q3" => 3,
+                "Q4" => 4,
+                "FY" => 5,
+                _ => 0,
+            };
+            (year, quarter)
+        }
+
+        // Order period labels oldest first; equal keys fall back to the label text
+        pub fn compare_periods(a: &str, b: &str) -> std::cmp::Ordering {
+            (period_sort_key(a), a).cmp(&(period_sort_key(b), b))
+        }
+
+        // Collapse facts reported in several filings. Facts are keyed on
+        // (fp, fy, frame) and the most recently filed one is kept; where
+        // different frames share a period label, the most recent of those wins too.
+        // Returns period label -> value.
+        pub fn dedup_facts(units: &[FactUnit]) -> HashMap<String, f64> {
+            dedup_fact_units(units)
+                .into_iter()
+                .filter_map(|(label, item)| item.value.map(|v| (label, v)))
+                .collect()
+        }
+
+        // As dedup_facts, returning the chosen fact for each period label
+        pub fn dedup_fact_units(units: &[FactUnit]) -> HashMap<String, &FactUnit> {
+            let mut by_key: HashMap<(&str, Option<i32>, Option<&str>), &FactUnit> = HashMap::new();
+            for item in units.iter().filter(|u| u.value.is_some()) {
+                if let Some(fp) = item.fiscal_period.as_deref() {
+                    let key = (fp, item.fiscal_year, item.frame.as_deref());
+                    match by_key.get(&key) {
+                        Some(existing) if existing.recency() >= item.recency() => {}
+                        _ => {
+                            by_key.insert(key, item);
+                        }
+                    }
+                }
+            }
+
+            let mut by_period: HashMap<String, &FactUnit> = HashMap::new();
+            for item in by_key.into_values() {
+                if let Some(label) = item.period_label() {
+                    match by_period.get(&label) {
+                        Some(existing) if existing.recency() >= item.recency() => {}
+                        _ => {
+                            by_period.insert(label, item);
+                        }
+                    }
+                }
+            }
+
+            by_period
+        }
+
+        // How a metric's XBRL unit is scaled for display
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+        #[serde(rename_all = "snake_case")]
+        pub enum MetricUnit {
+            // Monetary amounts (USD, EUR, ...), shown in billions
+            Billions,
+            // Per-share amounts (USD/shares), shown in dollars
+            PerShare,
+            // Share counts, shown in millions
+            MillionsOfShares,
+            // Anything else (ratios, pure numbers), shown as-is
+            Raw,
+        }
+
+        impl MetricUnit {
+            // Classify an XBRL unit key such as "USD", "USD/shares", or "shares"
+            pub fn from_unit_key(unit: &str) -> Self {
+                let unit = unit.trim();
+                if unit.to_lowercase().ends_with("/shares") {
+                    MetricUnit::PerShare
+                } else if unit.eq_ignore_ascii_case("shares") {
+                    MetricUnit::MillionsOfShares
+                } else if unit.len() == 3 && unit.chars().all(|c| c.is_ascii_uppercase()) {
+                    MetricUnit::Billions
+                } else {
+                    MetricUnit::Raw
+                }
+            }
+
+            // Recover the unit from a column name built by the loader ("Revenues_USD_B",
+            // "EarningsPerShareDiluted_USD/shares", "CommonStockSharesOutstanding_shares_M")
+            pub fn from_column_name(name: &str) -> Self {
+                let name = name.to_lowercase();
+                if name.ends_with("_b") {
+                    MetricUnit::Billions
+                } else if name.ends_with("_m") {
+                    MetricUnit::MillionsOfShares
+                } else if name.ends_with("/shares") {
+                    MetricUnit::PerShare
+                } else {
+                    MetricUnit::Raw
+                }
+            }
+
+            pub fn scale(&self, value: f64) -> f64 {
+                match self {
+                    MetricUnit::Billions => value / 1_000_000_000.0,
+                    MetricUnit::MillionsOfShares => value / 1_000_000.0,
+                    MetricUnit::PerShare | MetricUnit::Raw => value,
+                }
+            }
+
+            // Undo `scale`, recovering the value as reported
+            pub fn unscale(&self, value: f64) -> f64 {
+                match self {
+                    MetricUnit::Billions => value * 1_000_000_000.0,
+                    MetricUnit::MillionsOfShares => value * 1_000_000.0,
+                    MetricUnit::PerShare | MetricUnit::Raw => value,
+                }
+            }
+
+            // Suffix appended to the column name to record the scaling
+            pub fn column_suffix(&self) -> &'static str {
+                match self {
+                    MetricUnit::Billions => "_B",
+                    MetricUnit::MillionsOfShares => "_M",
+                    MetricUnit::PerShare | MetricUnit::Raw => "",
+                }
+            }
+
+            pub fn label(&self) -> &'static str {
+                match self {
+                    MetricUnit::Billions => "billions",
+                    MetricUnit::PerShare => "per share",
+                    MetricUnit::MillionsOfShares => "millions of shares",
+                    MetricUnit::Raw => "raw",
+                }
+            }
+
+            // Format an already-scaled value with its unit label, per the configured
+            // number format
+            pub fn format(&self, value: f64) -> String {
+                format_value(value, *self, number_format())
+            }
+
+            // Signed change between two scaled values, e.g. "+1.20B" or "-3.00M shares".
+            // Per-share changes under a dollar are shown in cents ("+19¢").
+            pub fn format_change(&self, delta: f64) -> String {
+                let sign = if delta < 0.0 { "-" } else { "+" };
+                match self {
+                    MetricUnit::PerShare if delta.abs() < 1.0 => format!("{}{:.0}¢", sign, delta.abs() * 100.0),
+                    _ => format!("{}{}", sign, self.format(delta.abs())),
+                }
+            }
+
+            // Whether adding values across periods means anything (not for EPS)
+            pub fn is_summable(&self) -> bool {
+                *self != MetricUnit::PerShare
+            }
+        }
+
+        // Number format from the user config, loaded on first use
+        pub fn number_format() -> &'static NumberFormat {
+            static FORMAT: std::sync::OnceLock<NumberFormat> = std::sync::OnceLock::new();
+            FORMAT.get_or_init(|| AppConfig::current().number_format)
+        }
+
+        // Format a value already scaled for `unit` (as stored in the loader's columns).
+        // Monetary amounts are rescaled to `format.scale` ("391.04B", "$391035.00M");
+        // share counts stay in millions and per-share values in currency units.
+        pub fn format_value(value: f64, unit: MetricUnit, format: &NumberFormat) -> String {
+            let decimals = format.decimals;
+            let sign = if value < 0.0 { "-" } else { "" };
+            let symbol = format.currency_symbol.as_deref();
+            match unit {
+                MetricUnit::Billions => {
+                    let amount = unit.unscale(value.abs()) / format.scale.divisor();
+                    format!("{}{}{:.*}{}", sign, symbol.unwrap_or(""), decimals, amount, format.scale.suffix())
+                }
+                MetricUnit::MillionsOfShares => format!("{:.*}M shares", decimals, value),
+                MetricUnit::PerShare => format!("{}{}{:.*}", sign, symbol.unwrap_or("$"), decimals, value.abs()),
+                MetricUnit::Raw => format!("{:.*}", decimals, value),
+            }
+        }
+
+        // SEC asks automated clients to identify themselves with a real contact address
+        pub const SEC_CONTACT_ENV: &str = "FINFILES_SEC_CONTACT";
+        // When set to "1"/"true", the loader refuses to run without a configured contact
+        pub const SEC_STRICT_ENV: &str = "FINFILES_SEC_STRICT";
+        // Development fallback only; production deployments should set FINFILES_SEC_CONTACT
+        const DEFAULT_SEC_CONTACT: &str = "dev@localhost";
+
+        // Build the User-Agent header value from a contact (e.g. "Jane Doe jane@example.com")
+        pub fn sec_user_agent(contact: &str) -> String {
+            format!("FINFILES AI/1.0 ({})", contact.trim())
+        }
+
+        // Resolve the SEC contact from the environment, then the user config, honoring strict mode
+        pub fn sec_contact() -> Result<String> {
+            let contact = std::env::var(SEC_CONTACT_ENV)
+                .ok()
+                .map(|c| c.trim().to_string())
+                .filter(|c| !c.is_empty())
+                .or_else(|| AppConfig::current().sec_contact);
+            let strict = std::env::var(SEC_STRICT_ENV)
+                .map(|v| matches!(v.trim().to_lowercase().as_str(), "1" | "true" | "yes"))
+                .unwrap_or(false);
+
+            match contact {
+                Some(contact) => Ok(contact),
+                None if strict => Err(FinAIError::Config(format!(
+                    "{} (or sec_contact in config.toml) must be set to a real contact (name and email) for SEC EDGAR requests",
+                    SEC_CONTACT_ENV
+                ))),
+                None => {
+                    log::warn!(
+                        "FINFILES AI: {} is not set; using development contact '{}'. SEC may block unidentified clients.",
+                        SEC_CONTACT_ENV,
+                        DEFAULT_SEC_CONTACT
+                    );
+                    Ok(DEFAULT_SEC_CONTACT.to_string())
+                }
+            }
+        }
+
+        // Most Form 4 filings fetched per ticker; each is a separate request
+        pub const MAX_FORM4_FILINGS: usize = 20;
+
+        // One transaction row from a Form 4 ownership document
+        #[derive(Debug, Clone, Default, PartialEq)]
+        pub struct InsiderTransaction {
+            pub insider: String,
+            // ISO date, None when the filing's date could not be parsed
+            pub transaction_date: Option<String>,
+            pub transaction_code: String,
+            pub shares: Option<f64>,
+            pub price: Option<f64>,
+        }
+
+        // Accept "2024-01-05" as well as suffixed forms like "2024-01-05-05:00"
+        fn parse_filing_date(raw: &str) -> Option<String> {
+            let raw = raw.trim();
+            let date = raw.get(..10).unwrap_or(raw);
+            NaiveDate::parse_from_str(date, "%Y-%m-%d").ok().map(|d| d.format("%Y-%m-%d").to_string())
+        }
+
+        // Extract transactions from a Form 4 ownership XML document. Both non-derivative
+        // and derivative transactions are returned; owner names are joined with "; ".
+        pub fn parse_form4_xml(xml: &str) -> Result<Vec<InsiderTransaction>> {
+            let mut reader = Reader::from_str(xml);
+            reader.trim_text(true);
+
+            let mut path: Vec<String> = Vec::new();
+            let mut owners: Vec<String> = Vec::new();
+            let mut transactions: Vec<InsiderTransaction> = Vec::new();
+            let mut current: Option<InsiderTransaction> = None;
+
+            loop {
+                match reader.read_event() {
+                    Ok(Event::Start(e)) => {
+                        let name = String::from_utf8_lossy(e.local_name().as_ref()).into_owned();
+                        if name == "nonDerivativeTransaction" || name == "derivativeTransaction" {
+                            current = Some(InsiderTransaction::default());
+                        }
+                        path.push(name);
+                    }
+                    Ok(Event::End(_)) => {
+                        if let Some(name) = path.pop() {
+                            if name == "nonDerivativeTransaction" || name == "derivativeTransaction" {
+                                if let Some(tx) = current.take() {
+                                    transactions.push(tx);
+                                }
+                            }
+                        }
+                    }
+                    Ok(Event::Text(t)) => {
+                        let text = t
+                            .unescape()
+                            .map_err(|e| FinAIError::DataParsing(format!("Invalid Form 4 text: {e}")))?
+                            .trim()
+                            .to_string();
+                        let in_field = |field: &str| path.iter().any(|p| p == field);
+                        if path.last().map(|p| p == "rptOwnerName").unwrap_or(false) {
+                            owners.push(text);
+                        } else if let Some(tx) = current.as_mut() {
+                            if in_field("transactionDate") {
+                                tx.transaction_date = parse_filing_date(&text);
+                            } else if path.last().map(|p| p == "transactionCode").unwrap_or(false) {
+                                tx.transaction_code = text;
+                            } else if in_field("transactionShares") {
+                                tx.shares = text.parse().ok();
+                            } else if in_field("transactionPricePerShare") {
+                                tx.price = text.parse().ok();
+                            }
+                        }
+                    }
+                    Ok(Event::Eof) => break,
+                    Err(e) => return Err(FinAIError::DataParsing(format!("Invalid Form 4 XML: {e}"))),
+                    _ => {}
+                }
+            }
+
+            let insider = owners.join("; ");
+            for tx in &mut transactions {
+                tx.insider = insider.clone();
+            }
+            Ok(transactions)
+        }
+
+        // Build the insider-transactions DataFrame (an empty frame keeps the same columns)
+        pub fn insider_transactions_to_dataframe(transactions: &[InsiderTransaction]) -> Result<DataFrame> {
+            let insiders: Vec<&str> = transactions.iter().map(|t| t.insider.as_str()).collect();
+            let dates: Vec<Option<&str>> = transactions.iter().map(|t| t.transaction_date.as_deref()).collect();
+            let codes: Vec<&str> = transactions.iter().map(|t| t.transaction_code.as_str()).collect();
+            let shares: Vec<Option<f64>> = transactions.iter().map(|t| t.shares).collect();
+            let prices: Vec<Option<f64>> = transactions.iter().map(|t| t.price).collect();
+            DataFrame::new(vec![
+                Series::new("insider", insiders),
+                Series::new("transaction_date", dates),
+                Series::new("transaction_code", codes),
+                Series::new("shares", shares),
+                Series::new("price", prices),
+            ])
+            .map_err(|e| FinAIError::DataParsing(format!("Failed to build DataFrame: {e}")))
+        }
+
+        // One position from a 13F-HR information table
+        #[derive(Debug, Clone, Default, PartialEq)]
+        pub struct Holding {
+            pub holder: String,
+            pub issuer: String,
+            pub cusip: String,
+            pub shares: Option<f64>,
+            // Reported market value (as filed; thousands of USD before 2023, USD after)
+            pub value: Option<f64>,
+        }
+
+        // Parse a 13F information table event by event, so only the current row is held
+        // while reading. Namespace prefixes (ns1:infoTable) are ignored.
+        pub fn parse_13f_info_table<R: BufRead>(reader: R, holder: &str) -> Result<Vec<Holding>> {
+            let mut reader = Reader::from_reader(reader);
+            reader.trim_text(true);
+
+            let mut buf = Vec::new();
+            let mut element = String::new();
+            let mut holdings = Vec::new();
+            let mut current: Option<Holding> = None;
+
+            loop {
+                match reader.read_event_into(&mut buf) {
+                    Ok(Event::Start(e)) => {
+                        element = String::from_utf8_lossy(e.local_name().as_ref()).into_owned();
+                        if element == "infoTable" {
+                            current = Some(Holding { holder: holder.to_string(), ..Holding::default() });
+                        }
+                    }
+                    Ok(Event::End(e)) => {
+                        if e.local_name().as_ref() == b"infoTable" {
+                            if let Some(holding) = current.take() {
+                                holdings.push(holding);
+                            }
+                        }
+                        element.clear();
+                    }
+                    Ok(Event::Text(t)) => {
+                        if let Some(holding) = current.as_mut() {
+                            let text = t
+                                .unescape()
+                                .map_err(|e| FinAIError::DataParsing(format!("Invalid 13F text: {e}")))?
+                                .trim()
+                                .to_string();
+                            match element.as_str() {
+                                "nameOfIssuer" => holding.issuer = text,
+                                "cusip" => holding.cusip = text,
+                                "value" => holding.value = text.replace(',', "").parse().ok(),
+                                "sshPrnamt" => holding.shares = text.replace(',', "").parse().ok(),
+                                _ => {}
+                            }
+                        }
+                    }
+                    Ok(Event::Eof) => break,
+                    Err(e) => return Err(FinAIError::DataParsing(format!("Invalid 13F XML: {e}"))),
+                    _ => {}
+                }
+                buf.clear();
+            }
+            Ok(holdings)
+        }
+
+        pub fn holdings_to_dataframe(holdings: &[Holding]) -> Result<DataFrame> {
+            let holders: Vec<&str> = holdings.iter().map(|h| h.holder.as_str()).collect();
+            let issuers: Vec<&str> = holdings.iter().map(|h| h.issuer.as_str()).collect();
+            let cusips: Vec<&str> = holdings.iter().map(|h| h.cusip.as_str()).collect();
+            let shares: Vec<Option<f64>> = holdings.iter().map(|h| h.shares).collect();
+            let values: Vec<Option<f64>> = holdings.iter().map(|h| h.value).collect();
+            DataFrame::new(vec![
+                Series::new("holder", holders),
+                Series::new("issuer", issuers),
+                Series::new("cusip", cusips),
+                Series::new("shares", shares),
+                Series::new("value", values),
+            ])
+            .map_err(|e| FinAIError::DataParsing(format!("Failed to build DataFrame: {e}")))
+        }
+
+        #[derive(Debug, Deserialize)]
+        struct FilingIndex {
+            directory: FilingDirectory,
+        }
+
+        #[derive(Debug, Deserialize)]
+        struct FilingDirectory {
+            item: Vec<FilingIndexItem>,
+        }
+
+        #[derive(Debug, Deserialize)]
+        struct FilingIndexItem {
+            name: String,
+        }
+
+        // Canonical form of a ticker for matching share classes and secondary listings:
+        // uppercase, with "." or "/" class separators written as "-" (BRK.B -> BRK-B)
+        pub fn normalize_ticker_symbol(ticker: &str) -> String {
+            ticker.trim().to_uppercase().replace(['.', '/'], "-")
+        }
+
+        // Tickers read from an uploaded CSV or ticker list file, in file order without
+        // repeats, and how many rows were skipped for being unreadable, empty, or not a
+        // valid ticker
+        #[derive(Debug, Clone, Default, PartialEq)]
+        pub struct TickerCsv {
+            pub tickers: Vec<String>,
+            pub skipped: usize,
+        }
+
+        // Read tickers from CSV text. A first row with a "ticker" column (any case) is a
+        // header and selects that column; otherwise every row's first column is used.
+        pub fn parse_ticker_csv<R: Read>(input: R) -> TickerCsv {
+            let mut reader = csv::ReaderBuilder::new().has_headers(false).flexible(true).trim(csv::Trim::All).from_reader(input);
+            let mut parsed = TickerCsv::default();
+            let mut column = 0;
+            for (row, record) in reader.records().enumerate() {
+                let record = match record {
+                    Ok(record) => record,
+                    Err(_) => {
+                        parsed.skipped += 1;
+                        continue;
+                    }
+                };
+                if row == 0 {
+                    if let Some(header) = record.iter().position(|field| field.eq_ignore_ascii_case("ticker")) {
+                        column = header;
+                        continue;
+                    }
+                }
+                let ticker = record.get(column).map(sanitize_ticker).unwrap_or_default();
+                if ticker.is_empty() {
+                    parsed.skipped += 1;
+                } else if !parsed.tickers.contains(&ticker) {
+                    parsed.tickers.push(ticker);
+                }
+            }
+            parsed
+        }
+
+        pub fn load_ticker_csv(path: &Path) -> Result<TickerCsv> {
+            let file = File::open(path)
+                .map_err(|e| FinAIError::DataParsing(format!("Failed to open {}: {e}", path.display())))?;
+            Ok(parse_ticker_csv(BufReader::new(file)))
+        }
+
+        // Read a ticker list: one ticker per line. Blank lines and comments (from '#' to the
+        // end of the line) are ignored; lines that aren't a valid ticker count as skipped.
+        pub fn parse_ticker_file(text: &str) -> TickerCsv {
+            let mut parsed = TickerCsv::default();
+            for line in text.lines() {
+                let entry = line.split('#').next().unwrap_or_default().trim();
+                if entry.is_empty() {
+                    continue;
+                }
+                let ticker = sanitize_ticker(entry);
+                if ticker.is_empty() {
+                    parsed.skipped += 1;
+                } else if !parsed.tickers.contains(&ticker) {
+                    parsed.tickers.push(ticker);
+                }
+            }
+            parsed
+        }
+
+        pub fn load_ticker_file(path: &Path) -> Result<TickerCsv> {
+            let text = fs::read_to_string(path)
+                .map_err(|e| FinAIError::DataParsing(format!("Failed to read {}: {e}", path.display())))?;
+            Ok(parse_ticker_file(&text))
+        }
+
+        // Find the CIK for a ticker. An exact (case-insensitive) match wins; otherwise the
+        // ticker is compared in canonical form, so class shares listed as "BRK-B" resolve
+        // from "BRK.B". Several tickers may share one CIK, and each resolves to it.
+        pub fn lookup_cik(cik_map: &HashMap<String, CikEntry>, ticker: &str) -> Result<String> {
+            if let Some(entry) = cik_map.values().find(|entry| entry.ticker.eq_ignore_ascii_case(ticker)) {
+                return Ok(entry.cik_str.clone());
+            }
+            let wanted = normalize_ticker_symbol(ticker);
+            if let Some(entry) = cik_map.values().find(|entry| normalize_ticker_symbol(&entry.ticker) == wanted) {
+                return Ok(entry.cik_str.clone());
+            }
+
+            let suggestions = suggest_tickers(cik_map, ticker, 3);
+            if suggestions.is_empty() {
+                Err(FinAIError::TickerNotFound(ticker.to_string()))
+            } else {
+                Err(FinAIError::TickerNotFoundWithSuggestions { ticker: ticker.to_string(), suggestions })
+            }
+        }
+
+        // Edit distance between two strings (case-sensitive; callers normalize)
+        fn levenshtein(a: &str, b: &str) -> usize {
+            let b: Vec<char> = b.chars().collect();
+            let mut prev: Vec<usize> = (0..=b.len()).collect();
+            for (i, ca) in a.chars().enumerate() {
+                let mut curr = vec![i + 1; b.len() + 1];
+                for (j, cb) in b.iter().enumerate() {
+                    let cost = if ca == *cb { 0 } else { 1 };
+                    curr[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(curr[j] + 1);
+                }
+                prev = curr;
+            }
+            prev[b.len()]
+        }
+
+        // Largest edit distance still offered as a suggestion
+        const MAX_SUGGESTION_DISTANCE: usize = 2;
+
+        // Closest tickers to a mistyped one, compared against each ticker and the first
+        // word of its company title. Ties go to the entry listed first in SEC's map
+        // (which is ordered by market value), so "APPL" suggests AAPL before APPS.
+        pub fn suggest_tickers(cik_map: &HashMap<String, CikEntry>, query: &str, limit: usize) -> Vec<String> {
+            let query = query.trim().to_uppercase();
+            if query.is_empty() {
+                return Vec::new();
+            }
+            let mut scored: Vec<(usize, u64, &str)> = cik_map
+                .iter()
+                .map(|(key, entry)| {
+                    let ticker_distance = levenshtein(&query, &entry.ticker.to_uppercase());
+                    let title_word = entry.title.split_whitespace().next().unwrap_or("").to_uppercase();
+                    let title_distance = levenshtein(&query, &title_word);
+                    let rank = key.parse::<u64>().unwrap_or(u64::MAX);
+                    (ticker_distance.min(title_distance), rank, entry.ticker.as_str())
+                })
+                .filter(|(distance, _, _)| *distance <= MAX_SUGGESTION_DISTANCE)
+                .collect();
+            scored.sort();
+
+            let mut suggestions: Vec<String> = Vec::new();
+            for (_, _, ticker) in scored {
+                let ticker = ticker.to_uppercase();
+                if !suggestions.contains(&ticker) {
+                    suggestions.push(ticker);
+                }
+                if suggestions.len() == limit {
+                    break;
+                }
+            }
+            suggestions
+        }
+
+        // Most company-name matches returned by search_companies
+        pub const MAX_COMPANY_MATCHES: usize = 20;
+
+        // Most peer tickers returned by load_peers
+        pub const MAX_PEERS: usize = 5;
+
+        // The submissions' SIC code, or None when it is missing or not numeric
+        pub fn sic_code(sic: &str) -> Option<&str> {
+            let sic = sic.trim();
+            (!sic.is_empty() && sic.chars().all(|c| c.is_ascii_digit())).then_some(sic)
+        }
+
+        // CIKs listed in an EDGAR company browse feed (atom output), in feed order
+        pub fn parse_company_ciks(atom: &str) -> Result<Vec<String>> {
+            let mut reader = Reader::from_str(atom);
+            reader.trim_text(true);
+            let mut in_cik = false;
+            let mut ciks = Vec::new();
+            loop {
+                match reader.read_event() {
+                    Ok(Event::Start(e)) => in_cik = e.local_name().as_ref() == b"cik",
+                    Ok(Event::End(_)) => in_cik = false,
+                    Ok(Event::Text(t)) if in_cik => {
+                        let cik = t
+                            .unescape()
+                            .map_err(|e| FinAIError::DataParsing(format!("Invalid company feed text: {e}")))?
+                            .trim()
+                            .to_string();
+                        if !cik.is_empty() && !ciks.contains(&cik) {
+                            ciks.push(cik);
+                        }
+                    }
+                    Ok(Event::Eof) => break,
+                    Err(e) => return Err(FinAIError::DataParsing(format!("Invalid company feed: {e}"))),
+                    _ => {}
+                }
+            }
+            Ok(ciks)
+        }
+
+        // Tickers for the given CIKs (one per company, in order), leaving out `own_cik` and
+        // companies without a listed ticker
+        pub fn peer_tickers(cik_map: &HashMap<String, CikEntry>, own_cik: &str, ciks: &[String], limit: usize) -> Vec<String> {
+            let bare = |cik: &str| cik.trim().trim_start_matches('0').to_string();
+            let own = bare(own_cik);
+            // The map is keyed by row number; keep file order so a company's primary ticker wins
+            let mut rows: Vec<(&String, &CikEntry)> = cik_map.iter().collect();
+            rows.sort_by_key(|(row, _)| row.parse::<usize>().unwrap_or(usize::MAX));
+            let entries: Vec<&CikEntry> = rows.into_iter().map(|(_, entry)| entry).collect();
+            ciks.iter()
+                .map(|cik| bare(cik))
+                .filter(|cik| *cik != own)
+                .filter_map(|cik| entries.iter().find(|entry| bare(&entry.cik_str) == cik).map(|entry| entry.ticker.clone()))
+                .take(limit)
+                .collect()
+        }
+
+        // A company whose title matched a name search
+        #[derive(Debug, Clone, PartialEq)]
+        pub struct CompanyMatch {
+            pub ticker: String,
+            pub title: String,
+            pub cik: String,
+        }
+
+        // Companies whose title contains `query` (case-insensitive), best first: an exact
+        // title, then titles starting with the query, then a word starting with it, then
+        // any other substring. Ties go to SEC's map order (market value), then the ticker.
+        pub fn search_companies(cik_map: &HashMap<String, CikEntry>, query: &str, limit: usize) -> Vec<CompanyMatch> {
+            let query = query.trim().to_lowercase();
+            if query.is_empty() {
+                return Vec::new();
+            }
+            let mut scored: Vec<(u8, u64, &CikEntry)> = cik_map
+                .iter()
+                .filter_map(|(key, entry)| {
+                    let title = entry.title.to_lowercase();
+                    let position = title.find(&query)?;
+                    let relevance = if title == query {
+                        0
+                    } else if position == 0 {
+                        1
+                    } else if title.split_whitespace().any(|word| word.starts_with(&query)) {
+                        2
+                    } else {
+                        3
+                    };
+                    Some((relevance, key.parse::<u64>().unwrap_or(u64::MAX), entry))
+                })
+                .collect();
+            scored.sort_by(|a, b| (a.0, a.1, &a.2.ticker).cmp(&(b.0, b.1, &b.2.ticker)));
+            scored
+                .into_iter()
+                .take(limit)
+                .map(|(_, _, entry)| CompanyMatch {
+                    ticker: entry.ticker.to_uppercase(),
+                    title: entry.title.clone(),
+                    cik: entry.cik_str.clone(),
+                })
+                .collect()
+        }
+
+        // Longest response body kept in an `FinAIError::Http`
+        const MAX_ERROR_BODY_LEN: usize = 200;
+        // Server-requested waits longer than this fail the request instead of sleeping
+        pub const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+        // Parse a Retry-After header: delay-seconds, or an HTTP date measured from `now`.
+        // Dates in the past mean no wait.
+        pub fn parse_retry_after(value: &str, now: chrono::DateTime<chrono::Utc>) -> Option<Duration> {
+            let value = value.trim();
+            if let Ok(secs) = value.parse::<u64>() {
+                return Some(Duration::from_secs(secs));
+            }
+            let at = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+            Some((at.with_timezone(&chrono::Utc) - now).to_std().unwrap_or(Duration::ZERO))
+        }
+
+        // Error for a failed response: RateLimited (with any Retry-After) for 429,
+        // otherwise an Http error with the status and trimmed body
+        pub async fn response_error(resp: reqwest::Response) -> FinAIError {
+            let status = resp.status();
+            if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+                let retry_after = resp
+                    .headers()
+                    .get(reqwest::header::RETRY_AFTER)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| parse_retry_after(v, chrono::Utc::now()));
+                return FinAIError::RateLimited { retry_after };
+            }
+            http_error(status, resp.text().await.unwrap_or_default())
+        }
+
+        // Error for a non-success HTTP response, keeping the status and a trimmed body
+        pub fn http_error(status: reqwest::StatusCode, body: String) -> FinAIError {
+            let body = body.trim();
+            let body = match body.char_indices().nth(MAX_ERROR_BODY_LEN) {
+                Some((end, _)) => format!("{}…", &body[..end]),
+                None => body.to_string(),
+            };
+            FinAIError::Http { status: status.as_u16(), body }
+        }
+
+        // How SEC requests retry transient failures
+        #[derive(Debug, Clone, Copy, PartialEq)]
+        pub struct RetryPolicy {
+            pub max_retries: u32,
+            // Delay before the first retry
+            pub base_delay: Duration,
+            // Multiplier applied to the delay after each retry
+            pub backoff: f64,
+        }
+
+        impl Default for RetryPolicy {
+            fn default() -> Self {
+                Self { max_retries: 2, base_delay: Duration::from_secs(2), backoff: 2.0 }
+            }
+        }
+
+        impl RetryPolicy {
+            // Never retry
+            pub fn none() -> Self {
+                Self { max_retries: 0, ..Self::default() }
+            }
+
+            // Exponential delay before retry `attempt` (0-based), with up to ±25% jitter so
+            // concurrent clients don't retry in lockstep
+            pub fn delay(&self, attempt: u32) -> Duration {
+                let exp = self.base_delay.as_secs_f64() * self.backoff.max(1.0).powi(attempt as i32);
+                let nanos = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map(|d| d.subsec_nanos())
+                    .unwrap_or(0);
+                let jitter = 0.75 + (nanos % 1000) as f64 / 2000.0;
+                Duration::from_secs_f64(exp * jitter)
+            }
+        }
+
+        // Connect and per-request timeouts for SEC requests. The multi-megabyte company
+        // facts download gets the longest; resolving a CIK should fail fast.
+        #[derive(Debug, Clone, Copy, PartialEq)]
+        pub struct SecTimeouts {
+            pub connect: Duration,
+            pub cik_map: Duration,
+            pub submissions: Duration,
+            pub company_facts: Duration,
+            // Form 4 and 13F documents and filing downloads
+            pub documents: Duration,
+        }
+
+        impl Default for SecTimeouts {
+            fn default() -> Self {
+                Self {
+                    connect: Duration::from_secs(5),
+                    cik_map: Duration::from_secs(10),
+                    submissions: Duration::from_secs(20),
+                    company_facts: Duration::from_secs(60),
+                    documents: Duration::from_secs(20),
+                }
+            }
+        }
+
+        // XBRL taxonomies in order of preference
+        const TAXONOMY_PREFERENCE: [&str; 2] = ["us-gaap", "ifrs-full"];
+
+        // Steps of a financials load, reported to the optional progress callback in order
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum LoadStage {
+            ResolvingCik,
+            FetchingSubmissions,
+            FetchingFacts,
+            BuildingFrame,
+        }
+
+        impl LoadStage {
+            pub fn label(&self) -> &'static str {
+                match self {
+                    LoadStage::ResolvingCik => "Resolving CIK…",
+                    LoadStage::FetchingSubmissions => "Fetching submissions…",
+                    LoadStage::FetchingFacts => "Fetching company facts…",
+                    LoadStage::BuildingFrame => "Building data table…",
+                }
+            }
+        }
+
+        pub struct FinancialDataLoader;
+
+        impl FinancialDataLoader {
+            // Build the HTTP client used for all SEC EDGAR requests, with the configured timeouts
+            pub fn sec_client() -> Result<Client> {
+                Self::sec_client_with(AppConfig::current().sec_timeouts())
+            }
+
+            // SEC client with the given connect timeout. Requests default to the documents
+            // timeout; the JSON endpoints set their own.
+            pub fn sec_client_with(timeouts: SecTimeouts) -> Result<Client> {
+                let contact = sec_contact()?;
+                Client::builder()
+                    .connect_timeout(timeouts.connect)
+                    .timeout(timeouts.documents)
+                    .user_agent(sec_user_agent(&contact))
+                    .build()
+                    .map_err(|e| FinAIError::Network(format!("Failed to build HTTP client: {e}")))
+            }
+
+            // Resolve a ticker to its CIK using SEC's company_tickers.json
+            pub async fn resolve_cik(client: &Client, ticker: &str, retry: RetryPolicy, timeout: Duration) -> Result<String> {
+                let cik_map = Self::fetch_cik_map(client, retry, timeout).await?;
+                lookup_cik(&cik_map, ticker)
+            }
+
+            // Split tickers into those SEC's CIK map knows and those it doesn't, fetching
+            // the map once
+            pub async fn partition_known_tickers(tickers: &[String]) -> Result<(Vec<String>, Vec<String>)> {
+                let timeouts = AppConfig::current().sec_timeouts();
+                let client = Self::sec_client_with(timeouts)?;
+                let cik_map = Self::fetch_cik_map(&client, RetryPolicy::default(), timeouts.cik_map).await?;
+                Ok(tickers.iter().cloned().partition(|ticker| lookup_cik(&cik_map, ticker).is_ok()))
+            }
+
+            // GET a URL, retrying transient failures (timeouts, connection errors, 429, 5xx)
+            // per `retry`. A 404 fails immediately with `not_found`. `timeout` limits each
+            // attempt; without one the client's default applies.
+            async fn get_with_retry(
+                client: &Client,
+                url: &str,
+                retry: RetryPolicy,
+                timeout: Option<Duration>,
+                what: &str,
+                not_found: impl Fn() -> FinAIError,
+            ) -> Result<reqwest::Response> {
+                let mut attempt = 0;
+                loop {
+                    let mut request = client.get(url);
+                    if let Some(timeout) = timeout {
+                        request = request.timeout(timeout);
+                    }
+                    let transient = match request.send().await {
+                        Ok(resp) if resp.status().is_success() => return Ok(resp),
+                        Ok(resp) if resp.status() == reqwest::StatusCode::NOT_FOUND => return Err(not_found()),
+                        Ok(resp) => {
+                            let status = resp.status();
+                            let error = response_error(resp).await;
+                            if status == reqwest::StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+                                error
+                            } else {
+                                return Err(error);
+                            }
+                        }
+                        Err(e) if e.is_timeout() => FinAIError::Network(format!("Timed out fetching {what}: {e}")),
+                        Err(e) if e.is_connect() || e.is_request() => {
+                            FinAIError::Network(format!("Failed to fetch {what}: {e}"))
+                        }
+                        Err(e) => return Err(FinAIError::Network(format!("Failed to fetch {what}: {e}"))),
+                    };
+                    if attempt >= retry.max_retries {
+                        return Err(transient);
+                    }
+                    // A server-specified wait replaces the backoff, unless it's too long to sit out
+                    let delay = match &transient {
+                        FinAIError::RateLimited { retry_after: Some(wait) } if *wait > MAX_RETRY_AFTER => {
+                            return Err(transient);
+                        }
+                        FinAIError::RateLimited { retry_after: Some(wait) } => *wait,
+                        _ => retry.delay(attempt),
+                    };
+                    log::warn!("{} (retry {} of {} in {:?})", transient, attempt + 1, retry.max_retries, delay);
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+            }
+
+            // Download SEC's ticker -> CIK map (company_tickers.json)
+            pub async fn fetch_cik_map(client: &Client, retry: RetryPolicy, timeout: Duration) -> Result<HashMap<String, CikEntry>> {
+                let url = "https://www.sec.gov/files/company_tickers.json";
+                Self::get_with_retry(client, url, retry, Some(timeout), "CIK map", || {
+                    FinAIError::Network("CIK map not found at SEC".to_string())
+                })
+                .await?
+                .json()
+                .await
+                .map_err(|e| FinAIError::DataParsing(format!("Failed to parse CIK map: {e}")))
+            }
+
+            // Download the company's submissions index (recent filings) for a CIK
+            pub async fn fetch_submissions(
+                client: &Client,
+                cik: &str,
+                retry: RetryPolicy,
+                timeout: Duration,
+            ) -> Result<CompanySubmissions> {
+                let filings_url = format!(
+                    "https://data.sec.gov/submissions/CIK{:0>10}.json",
+                    cik
+                );
+                Self::get_with_retry(client, &filings_url, retry, Some(timeout), "company submissions", || {
+                    FinAIError::SecDataNotFound(format!("CIK {}", cik))
+                })
+                .await?
+                .json()
+                .await
+                .map_err(|e| FinAIError::DataParsing(format!("Failed to parse company submissions: {e}")))
+            }
+
+            // Download one of the older filings files listed in a submissions index,
+            // e.g. "CIK0000320193-submissions-001.json"
+            pub async fn fetch_submissions_file(
+                client: &Client,
+                name: &str,
+                retry: RetryPolicy,
+                timeout: Duration,
+            ) -> Result<RecentFilings> {
+                if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.') {
+                    return Err(FinAIError::DataParsing(format!("Invalid submissions file name '{}'", name)));
+                }
+                let file_url = format!("https://data.sec.gov/submissions/{}", name);
+                Self::get_with_retry(client, &file_url, retry, Some(timeout), "older filings", || {
+                    FinAIError::SecDataNotFound(format!("submissions file {}", name))
+                })
+                .await?
+                .json()
+                .await
+                .map_err(|e| FinAIError::DataParsing(format!("Failed to parse older filings: {e}")))
+            }
+
+            // Download XBRL company facts for a CIK
+            pub async fn fetch_company_facts(
+                client: &Client,
+                cik: &str,
+                retry: RetryPolicy,
+                timeout: Duration,
+            ) -> Result<CompanyFacts> {
+                let facts_url = format!(
+                    "https://data.sec.gov/api/xbrl/companyfacts/CIK{:0>10}.json",
+                    cik
+                );
+                Self::get_with_retry(client, &facts_url, retry, Some(timeout), "company facts", || {
+                    FinAIError::SecDataNotFound(format!("CIK {}", cik))
+                })
+                .await?
+                .json()
+                .await
+                .map_err(|e| FinAIError::DataParsing(format!("Failed to parse company facts: {e}")))
+            }
+
+            // Check a CIK is 1-10 digits and return it zero-padded to 10
+            pub fn normalize_cik(cik: &str) -> Result<String> {
+                let cik = cik.trim();
+                if cik.is_empty() || cik.len() > 10 || !cik.chars().all(|c| c.is_ascii_digit()) {
+                    return Err(FinAIError::TickerNotFound(format!("Invalid CIK: {}", cik)));
+                }
+                Ok(format!("{:0>10}", cik))
+            }
+
+            // Loads SEC EDGAR data for a user-specified ticker. `progress`, when given, is
+            // called as each stage starts.
+            pub async fn load_sec_data_for_ticker<F: FnMut(LoadStage)>(ticker: &str, progress: Option<F>) -> Result<DataFrame> {
+                Self::load_sec_data_with_timeouts(ticker, AppConfig::current().sec_timeouts(), progress).await
+            }
+
+            // As `load_sec_data_for_ticker`, with explicit SEC timeouts
+            pub async fn load_sec_data_with_timeouts<F: FnMut(LoadStage)>(
+                ticker: &str,
+                timeouts: SecTimeouts,
+                progress: Option<F>,
+            ) -> Result<DataFrame> {
+                log::info!("FINFILES AI: Fetching SEC EDGAR filings for ticker: {}", ticker);
+
+                let mut progress = progress;
+                let mut report = |stage: LoadStage| {
+                    if let Some(progress) = progress.as_mut() {
+                        progress(stage);
+                    }
+                };
+                let client = Self::sec_client_with(timeouts)?;
+                let retry = RetryPolicy::default();
+                report(LoadStage::ResolvingCik);
+                let cik = Self::resolve_cik(&client, ticker, retry, timeouts.cik_map).await?;
+                Self::load_sec_data_with_client(&client, &cik, ticker, retry, timeouts, &mut report).await
+            }
+
+            // Loads SEC EDGAR data directly by CIK, for entities without a usable ticker
+            pub async fn load_sec_data_for_cik<F: FnMut(LoadStage)>(cik: &str, progress: Option<F>) -> Result<DataFrame> {
+                let cik = Self::normalize_cik(cik)?;
+                log::info!("FINFILES AI: Fetching SEC EDGAR filings for CIK: {}", cik);
+
+                let mut progress = progress;
+                let mut report = |stage: LoadStage| {
+                    if let Some(progress) = progress.as_mut() {
+                        progress(stage);
+                    }
+                };
+                let timeouts = AppConfig::current().sec_timeouts();
+                let client = Self::sec_client_with(timeouts)?;
+                Self::load_sec_data_with_client(&client, &cik, &cik, RetryPolicy::default(), timeouts, &mut report).await
+            }
+
+            // Shared submissions + companyfacts path; `label` names the entity in errors
+            async fn load_sec_data_with_client(
+                client: &Client,
+                cik: &str,
+                label: &str,
+                retry: RetryPolicy,
+                timeouts: SecTimeouts,
+                report: &mut impl FnMut(LoadStage),
+            ) -> Result<DataFrame> {
+                let cik = Self::normalize_cik(cik)?;
+
+                // Download the most recent 10-K or 10-Q filings (JSON index)
+                report(LoadStage::FetchingSubmissions);
+                let company_submissions = Self::fetch_submissions(client, &cik, retry, timeouts.submissions).await?;
+
+                // Find the latest 10-K or 10-Q (or an amendment of one)
+                let _idx = company_submissions.filings.recent.latest_periodic()
+                    .ok_or_else(|| FinAIError::SecDataNotFound(label.to_string()))?;
+
+                // Download XBRL company financials
+                report(LoadStage::FetchingFacts);
+                let facts = Self::fetch_company_facts(client, &cik, retry, timeouts.company_facts).await?;
+
+                report(LoadStage::BuildingFrame);
+                let periods = AppConfig::current().periods;
+                let (df, taxonomy) = Self::facts_to_dataframe_with_periods(&facts, label, periods)?;
+                log::info!("FINFILES AI: Using {} taxonomy for {}", taxonomy, label);
+                Ok(df)
+            }
+
+            // Up to MAX_PEERS tickers of other filers in the ticker's SIC industry, from SEC's
+            // company browse by SIC code. Companies without an SIC code have no peers.
+            pub async fn load_peers(ticker: &str) -> Result<Vec<String>> {
+                let timeouts = AppConfig::current().sec_timeouts();
+                let client = Self::sec_client_with(timeouts)?;
+                let retry = RetryPolicy::default();
+                let cik_map = Self::fetch_cik_map(&client, retry, timeouts.cik_map).await?;
+                let cik = lookup_cik(&cik_map, ticker)?;
+                let submissions = Self::fetch_submissions(&client, &cik, retry, timeouts.submissions).await?;
+                let sic = match sic_code(&submissions.sic) {
+                    Some(sic) => sic,
+                    None => {
+                        log::info!("FINFILES AI: No SIC classification for {}; no peers", ticker);
+                        return Ok(Vec::new());
+                    }
+                };
+
+                let url = format!(
+                    "https://www.sec.gov/cgi-bin/browse-edgar?action=getcompany&SIC={}&owner=include&count=40&output=atom",
+                    sic
+                );
+                let feed = Self::get_with_retry(&client, &url, retry, Some(timeouts.submissions), "SIC company list", || {
+                    FinAIError::SecDataNotFound(format!("SIC {}", sic))
+                })
+                .await?
+                .text()
+                .await
+                .map_err(|e| FinAIError::Network(format!("Failed to read SIC company list: {e}")))?;
+                let peers = peer_tickers(&cik_map, &cik, &parse_company_ciks(&feed)?, MAX_PEERS);
+                log::info!(
+                    "FINFILES AI: {} peers of {} in SIC {} ({}): {}",
+                    peers.len(),
+                    ticker,
+                    sic,
+                    submissions.sic_description,
+                    peers.join(", ")
+                );
+                Ok(peers)
+            }
+
+            // Load recent insider transactions from the ticker's Form 4 filings. Filers with
+            // no Form 4s yield an empty frame; individual documents that fail to download
+            // or parse are skipped with a warning.
+            pub async fn load_insider_transactions(ticker: &str) -> Result<DataFrame> {
+                log::info!("FINFILES AI: Fetching Form 4 filings for ticker: {}", ticker);
+
+                let timeouts = AppConfig::current().sec_timeouts();
+                let client = Self::sec_client_with(timeouts)?;
+                let retry = RetryPolicy::default();
+                let cik = Self::resolve_cik(&client, ticker, retry, timeouts.cik_map).await?;
+                let submissions = Self::fetch_submissions(&client, &cik, retry, timeouts.submissions).await?;
+                let recent = &submissions.filings.recent;
+                let cik_number = cik.trim_start_matches('0');
+
+                let mut transactions = Vec::new();
+                let form4s = recent
+                    .form
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, form)| form.as_str() == "4")
+                    .take(MAX_FORM4_FILINGS);
+                for (i, _) in form4s {
+                    let (accession, document) = match (recent.accession_number.get(i), recent.primary_document.get(i)) {
+                        (Some(a), Some(d)) => (a, d),
+                        _ => continue,
+                    };
+                    // primaryDocument points at the XSLT-rendered view; the raw XML sits beside it
+                    let raw_document = document.rsplit('/').next().unwrap_or(document);
+                    let url = format!(
+                        "https://www.sec.gov/Archives/edgar/data/{}/{}/{}",
+                        cik_number,
+                        accession.replace('-', ""),
+                        raw_document
+                    );
+                    let fetched = Self::get_with_retry(&client, &url, retry, None, "Form 4 document", || {
+                        FinAIError::SecDataNotFound(format!("Form 4 document {}", accession))
+                    })
+                    .await;
+                    let xml = match fetched {
+                        Ok(resp) => match resp.text().await {
+                            Ok(xml) => xml,
+                            Err(e) => {
+                                log::warn!("Form 4 {}: failed to read document: {}", accession, e);
+                                continue;
+                            }
+                        },
+                        Err(e) => {
+                            log::warn!("Form 4 {}: failed to fetch document: {}", accession, e);
+                            continue;
+                        }
+                    };
+                    match parse_form4_xml(&xml) {
+                        Ok(rows) => transactions.extend(rows),
+                        Err(e) => log::warn!("Form 4 {}: {}", accession, e),
+                    }
+                }
+
+                insider_transactions_to_dataframe(&transactions)
+            }
+
+            // Load the holdings from an institutional manager's most recent 13F-HR. The
+            // information table is streamed to a temporary file and parsed incrementally,
+            // so large managers don't need the whole document in memory.
+            pub async fn load_13f_for_manager_cik(cik: &str) -> Result<DataFrame> {
+                let cik = Self::normalize_cik(cik)?;
+                log::info!("FINFILES AI: Fetching 13F-HR holdings for manager CIK: {}", cik);
+
+                let timeouts = AppConfig::current().sec_timeouts();
+                let client = Self::sec_client_with(timeouts)?;
+                let submissions = Self::fetch_submissions(&client, &cik, RetryPolicy::default(), timeouts.submissions).await?;
+                let recent = &submissions.filings.recent;
+                let accession = recent
+                    .form
+                    .iter()
+                    .position(|form| base_form(form) == "13F-HR")
+                    .and_then(|i| recent.accession_number.get(i))
+                    .ok_or_else(|| FinAIError::SecDataNotFound(format!("No 13F-HR filings for CIK {}", cik)))?;
+
+                let folder = format!(
+                    "https://www.sec.gov/Archives/edgar/data/{}/{}",
+                    cik.trim_start_matches('0'),
+                    accession.replace('-', "")
+                );
+                let retry = RetryPolicy::default();
+                let index: FilingIndex = Self::get_with_retry(&client, &format!("{}/index.json", folder), retry, None, "13F filing index", || {
+                    FinAIError::SecDataNotFound(format!("13F filing index for {}", accession))
+                })
+                    .await?
+                    .json().await
+                    .map_err(|e| FinAIError::DataParsing(format!("Failed to parse 13F filing index: {e}")))?;
+
+                // The information table is the XML document that isn't the cover page
+                let table_name = index
+                    .directory
+                    .item
+                    .iter()
+                    .map(|item| item.name.as_str())
+                    .find(|name| name.to_lowercase().ends_with(".xml") && *name != "primary_doc.xml")
+                    .ok_or_else(|| FinAIError::SecDataNotFound(format!("No 13F information table in {}", accession)))?;
+
+                let response = Self::get_with_retry(&client, &format!("{}/{}", folder, table_name), retry, None, "13F information table", || {
+                    FinAIError::SecDataNotFound(format!("13F information table {}", table_name))
+                })
+                .await?;
+
+                let tmp_path = std::env::temp_dir().join(format!("finfiles_13f_{}.xml", accession));
+                let result = async {
+                    let mut file = File::create(&tmp_path)
+                        .map_err(|e| FinAIError::Unknown(format!("Failed to create {}: {e}", tmp_path.display())))?;
+                    let mut chunks = response.bytes_stream();
+                    while let Some(chunk) = chunks.next().await {
+                        let chunk = chunk.map_err(|e| FinAIError::Network(format!("13F download interrupted: {e}")))?;
+                        file.write_all(&chunk)
+                            .map_err(|e| FinAIError::Unknown(format!("Failed to write {}: {e}", tmp_path.display())))?;
+                    }
+                    let file = File::open(&tmp_path)
+                        .map_err(|e| FinAIError::Unknown(format!("Failed to open {}: {e}", tmp_path.display())))?;
+                    parse_13f_info_table(BufReader::new(file), &submissions.name)
+                }
+                .await;
+                let _ = fs::remove_file(&tmp_path);
+
+                holdings_to_dataframe(&result?)
+            }
+
+            // Download a filing document to `dest` with the SEC client, reporting bytes
+            // received (and the total, when known) as chunks arrive. Data is written to a
+            // ".part" file that replaces `dest` only once the download completes.
+            pub async fn download_document(
+                url: &str,
+                dest: &Path,
+                mut on_progress: impl FnMut(u64, Option<u64>),
+            ) -> Result<u64> {
+                let client = Self::sec_client()?;
+                Self::download_with_client(&client, url, dest, RetryPolicy::default(), &mut on_progress).await
+            }
+
+            async fn download_with_client(
+                client: &Client,
+                url: &str,
+                dest: &Path,
+                retry: RetryPolicy,
+                on_progress: &mut impl FnMut(u64, Option<u64>),
+            ) -> Result<u64> {
+                let response = Self::get_with_retry(client, url, retry, None, "filing document", || {
+                    FinAIError::SecDataNotFound(format!("Filing document {}", url))
+                })
+                .await?;
+                let total = response.content_length();
+
+                let mut part_name = dest.file_name().map(|n| n.to_os_string()).unwrap_or_default();
+                part_name.push(".part");
+                let part_path = dest.with_file_name(part_name);
+                let result = async {
+                    let mut file = File::create(&part_path)
+                        .map_err(|e| FinAIError::Unknown(format!("Failed to create {}: {e}", part_path.display())))?;
+                    let mut received = 0u64;
+                    let mut chunks = response.bytes_stream();
+                    while let Some(chunk) = chunks.next().await {
+                        let chunk = chunk.map_err(|e| FinAIError::Network(format!("Download interrupted: {e}")))?;
+                        file.write_all(&chunk)
+                            .map_err(|e| FinAIError::Unknown(format!("Failed to write {}: {e}", part_path.display())))?;
+                        received += chunk.len() as u64;
+                        on_progress(received, total);
+                    }
+                    fs::rename(&part_path, dest)
+                        .map_err(|e| FinAIError::Unknown(format!("Failed to save {}: {e}", dest.display())))?;
+                    Ok(received)
+                }
+                .await;
+                if result.is_err() {
+                    let _ = fs::remove_file(&part_path);
+                }
+                result
+            }
+
+            // Build the metrics DataFrame from XBRL company facts. Taxonomies are tried in
+            // order (us-gaap, ifrs-full, then any other except dei) and the first with data
+            // is used on its own, so us-gaap and IFRS metrics are never mixed. Returns the
+            // frame and the taxonomy it came from.
+            pub fn facts_to_dataframe(facts: &CompanyFacts, ticker: &str) -> Result<(DataFrame, String)> {
+                Self::facts_to_dataframe_with_periods(facts, ticker, DEFAULT_PERIODS)
+            }
+
+            // As facts_to_dataframe, keeping the most recent `periods` quarters (at least 1)
+            pub fn facts_to_dataframe_with_periods(facts: &CompanyFacts, ticker: &str, periods: usize) -> Result<(DataFrame, String)> {
+                let mut taxonomies: Vec<&str> = TAXONOMY_PREFERENCE
+                    .iter()
+                    .copied()
+                    .filter(|t| facts.facts.contains_key(*t))
+                    .collect();
+                let mut others: Vec<&str> = facts.facts
+                    .keys()
+                    .map(|k| k.as_str())
+                    .filter(|k| !TAXONOMY_PREFERENCE.contains(k) && *k != "dei")
+                    .collect();
+                others.sort();
+                taxonomies.extend(others);
+
+                for taxonomy in taxonomies {
+                    let metrics = &facts.facts[taxonomy];
+
+                    // Extract all available metrics for the last 4 quarters
+                    let mut quarter_set: HashSet<String> = HashSet::new();
+                    // metric (with currency) -> quarter -> value
+                    let mut metric_map: HashMap<String, HashMap<String, f64>> = HashMap::new();
+                    // quarter -> filings its values were taken from
+                    let mut sources: HashMap<String, Vec<FactSource>> = HashMap::new();
+
+                    for (metric, fact) in metrics {
+                        for (currency, units) in &fact.units {
+                            let unit = MetricUnit::from_unit_key(currency);
+                            let metric_key = format!("{}_{}{}", metric, currency, unit.column_suffix());
+                            for (q, item) in dedup_fact_units(units) {
+                                let val = match item.value {
+                                    Some(val) => val,
+                                    None => continue,
+                                };
+                                quarter_set.insert(q.clone());
+                                if let Some(source) = item.source() {
+                                    sources.entry(q.clone()).or_default().push(source);
+                                }
+                                metric_map.entry(metric_key.clone())
+                                    .or_default()
+                                    .insert(q, unit.scale(val));
+                            }
+                        }
+                    }
+
+                    let mut quarters: Vec<String> = quarter_set.into_iter().collect();
+                    quarters.sort_by(|a, b| compare_periods(b, a)); // Descending (most recent first)
+                    let quarters = quarters.into_iter().take(periods.max(1)).collect::<Vec<_>>();
+
+                    if quarters.is_empty() {
+                        continue;
+                    }
+
+                    // Build DataFrame columns dynamically
+                    let mut columns: Vec<Series> = Vec::new();
+                    columns.push(Series::new("quarter", &quarters));
+
+                    // Include all available financial metrics. Periods a metric wasn't
+                    // reported for stay null so they don't read as zero.
+                    for (metric, qmap) in &metric_map {
+                        let vals: Vec<Option<f64>> = quarters.iter().map(|q| qmap.get(q).copied()).collect();
+                        columns.push(Series::new(metric, vals));
+                    }
+
+                    // Provenance so analyses can cite the filing behind each period
+                    let period_sources: Vec<Option<FactSource>> =
+                        quarters.iter().map(|q| sources.get(q).and_then(|s| dominant_source(s))).collect();
+                    let source_text = |field: fn(&FactSource) -> &String| -> Vec<Option<String>> {
+                        period_sources.iter().map(|s| s.as_ref().map(|s| field(s).clone())).collect()
+                    };
+                    columns.push(Series::new(SOURCE_ACCESSION_COLUMN, source_text(|s| &s.accession)));
+                    columns.push(Series::new(SOURCE_FORM_COLUMN, source_text(|s| &s.form)));
+                    columns.push(Series::new(FILED_DATE_COLUMN, source_text(|s| &s.filed)));
+
+                    let df = DataFrame::new(columns)
+                        .map_err(|e| FinAIError::DataParsing(format!("Failed to build DataFrame: {e}")))?;
+                    return Ok((df, taxonomy.to_string()));
+                }
+
+                Err(FinAIError::SecDataNotFound(ticker.to_string()))
+            }
+        }
+
+        #[cfg(test)]
+        mod tests {
+            use super::*;
+            use crate::config::UnitScale;
+
+            fn number_format(decimals: usize, scale: UnitScale, currency_symbol: Option<&str>) -> NumberFormat {
+                NumberFormat { decimals, scale, currency_symbol: currency_symbol.map(str::to_string) }
+            }
+
+            #[test]
+            fn default_format_matches_the_documented_output() {
+                let format = NumberFormat::default();
+                assert_eq!(format_value(391.0353, MetricUnit::Billions, &format), "391.04B");
+                assert_eq!(format_value(6.13, MetricUnit::PerShare, &format), "$6.13");
+            }
+
+            #[test]
+            fn amounts_follow_the_configured_scale() {
+                let value = 391.0353;
+                assert_eq!(format_value(value, MetricUnit::Billions, &number_format(2, UnitScale::Millions, None)), "391035.30M");
+                assert_eq!(format_value(value, MetricUnit::Billions, &number_format(0, UnitScale::Thousands, None)), "391035300K");
+                assert_eq!(format_value(value, MetricUnit::Billions, &number_format(0, UnitScale::Raw, None)), "391035300000");
+                assert_eq!(format_value(-12.5, MetricUnit::Billions, &number_format(1, UnitScale::Billions, Some("$"))), "-$12.5B");
+            }
+
+            #[test]
+            fn precision_applies_to_every_unit() {
+                let format = number_format(1, UnitScale::Billions, Some("€"));
+                assert_eq!(format_value(391.0353, MetricUnit::Billions, &format), "€391.0B");
+                assert_eq!(format_value(15204.1373, MetricUnit::MillionsOfShares, &format), "15204.1M shares");
+                assert_eq!(format_value(-1.5, MetricUnit::PerShare, &format), "-€1.5");
+                assert_eq!(format_value(0.256, MetricUnit::Raw, &number_format(3, UnitScale::Billions, None)), "0.256");
+            }
+
+            // Revenue facts in the shape of SEC's companyfacts JSON: the FY2023 Q2 value from its
+            // 10-Q, and the same period restated as a comparative in the following year's 10-Q
+            const REVENUE_FACTS: &str = r#"{
+                "cik": 320193,
+                "entityName": "Apple Inc.",
+                "facts": {
+                    "us-gaap": {
+                        "Revenues": {
+                            "label": "Revenues",
+                            "units": {
+                                "USD": [
+                                    {"start": "2023-01-01", "end": "2023-04-01", "val": 94836000000, "accn": "0000320193-23-000064",
+                                     "fy": 2023, "fp": "Q2", "form": "10-Q", "filed": "2023-05-05", "frame": "CY2023Q1"},
+                                    {"start": "2023-01-01", "end": "2023-04-01", "val": 94840000000, "accn": "0000320193-24-000069",
+                                     "fy": 2023, "fp": "Q2", "form": "10-Q", "filed": "2024-05-03"}
+                                ]
+                            }
+                        }
+                    }
+                }
+            }"#;
+
+            fn revenue_units() -> Vec<FactUnit> {
+                let mut facts: CompanyFacts = serde_json::from_str(REVENUE_FACTS).unwrap();
+                let mut revenues = facts.facts.remove("us-gaap").unwrap().remove("Revenues").unwrap();
+                revenues.units.remove("USD").unwrap()
+            }
+
+            #[test]
+            fn companyfacts_fy_and_fp_give_the_period_label() {
+                let units = revenue_units();
+                assert_eq!(units[0].fiscal_year, Some(2023));
+                assert_eq!(units[0].fiscal_period.as_deref(), Some("Q2"));
+                assert_eq!(units[0].period_label().as_deref(), Some("FY2023-Q2"));
+            }
+
+            #[test]
+            fn latest_filed_fact_wins_across_frames() {
+                let values = dedup_facts(&revenue_units());
+                assert_eq!(values.len(), 1);
+                assert_eq!(values["FY2023-Q2"], 94840000000.0);
+            }
+
+            #[test]
+            fn two_frames_for_one_period_give_one_label() {
+                let chosen = dedup_fact_units(&revenue_units());
+                assert_eq!(chosen.keys().collect::<Vec<_>>(), vec!["FY2023-Q2"]);
+                assert_eq!(chosen["FY2023-Q2"].accession.as_deref(), Some("0000320193-24-000069"));
+            }
+
+            #[test]
+            fn periods_sort_chronologically_across_years() {
+                assert_eq!(compare_periods("FY2022-Q4", "FY2023-Q1"), std::cmp::Ordering::Less);
+                let mut labels = vec!["FY2023-Q1", "FY2023", "FY2022-Q4", "FY2023-Q4", "Q2", "FY2022"];
+                labels.sort_by(|a, b| compare_periods(a, b));
+                assert_eq!(labels, vec!["Q2", "FY2022-Q4", "FY2022", "FY2023-Q1", "FY2023-Q4", "FY2023"]);
+            }
+
+            fn listing(rows: &[(&str, &str, &str)]) -> RecentFilings {
+                let column = |pick: fn(&(&str, &str, &str)) -> &str| rows.iter().map(|row| pick(row).to_string()).collect();
+                RecentFilings {
+                    accession_number: (0..rows.len()).map(|i| format!("0000320193-24-{:06}", i)).collect(),
+                    form: column(|row| row.0),
+                    report_date: column(|row| row.1),
+                    filing_date: column(|row| row.2),
+                    primary_document: vec![String::new(); rows.len()],
+                    primary_doc_description: vec![String::new(); rows.len()],
+                }
+            }
+
+            #[test]
+            fn amendment_supersedes_the_original_for_the_same_period() {
+                // Newest first, as SEC lists them
+                let recent = listing(&[
+                    ("8-K", "", "2024-02-01"),
+                    ("10-K/A", "2023-09-30", "2024-01-15"),
+                    ("10-K", "2023-09-30", "2023-11-03"),
+                    ("10-Q", "2023-07-01", "2023-08-04"),
+                ]);
+                assert_eq!(recent.latest_periodic(), Some(1));
+
+                // Filed the same day, the amendment still wins whichever order they're listed in
+                let same_day = listing(&[("10-K", "2023-09-30", "2023-11-03"), ("10-K/A", "2023-09-30", "2023-11-03")]);
+                assert_eq!(same_day.latest_periodic(), Some(1));
+            }
+
+            #[test]
+            fn amendments_belong_to_their_base_form() {
+                assert!(is_amendment("10-K/A"));
+                assert!(is_amendment(" 10-q/a "));
+                assert!(!is_amendment("10-K"));
+                assert_eq!(base_form("10-K/A"), "10-K");
+                assert_eq!(base_form("10-Q"), "10-Q");
+            }
+
+            #[test]
+            fn ticker_file_skips_comments_blanks_and_invalid_lines() {
+                let text = "# watchlist\n\naapl\n  msft   # Microsoft\n\t\nBRK.B\nnot a ticker\n$$$\nAAPL\n#TSLA\n";
+                assert_eq!(
+                    parse_ticker_file(text),
+                    TickerCsv { tickers: vec!["AAPL".to_string(), "MSFT".to_string(), "BRK.B".to_string()], skipped: 2 }
+                );
+                assert_eq!(parse_ticker_file(""), TickerCsv::default());
+            }
+
+            #[test]
+            fn ticker_file_loads_from_disk() {
+                let path = std::env::temp_dir().join(format!("finfiles-tickers-{}.txt", std::process::id()));
+                fs::write(&path, "nvda\r\n# comment\r\namd\r\n").unwrap();
+                assert_eq!(load_ticker_file(&path).unwrap().tickers, vec!["NVDA".to_string(), "AMD".to_string()]);
+                fs::remove_file(&path).unwrap();
+                assert!(matches!(load_ticker_file(&path), Err(FinAIError::DataParsing(_))));
+            }
+
+            #[test]
+            fn csv_header_selects_the_ticker_column() {
+                let csv = "Company,Ticker,Sector\n\"Apple, Inc.\",aapl,Tech\nMicrosoft,\" msft \",Tech\nNobody,,None\nBerkshire,\"BRK.B\",Finance\n";
+                assert_eq!(
+                    parse_ticker_csv(csv.as_bytes()),
+                    TickerCsv { tickers: vec!["AAPL".to_string(), "MSFT".to_string(), "BRK.B".to_string()], skipped: 1 }
+                );
+            }
+
+            #[test]
+            fn csv_without_header_uses_the_first_column() {
+                let csv = "tsla,Tesla\n\"nvda\",\"NVIDIA, Corp\"\nnot valid!,x\nTSLA\n";
+                assert_eq!(
+                    parse_ticker_csv(csv.as_bytes()),
+                    TickerCsv { tickers: vec!["TSLA".to_string(), "NVDA".to_string()], skipped: 1 }
+                );
+            }
+
+            #[test]
+            fn csv_unterminated_quote_is_skipped() {
+                // The open quote swallows the rest of the input into one field, which isn't a ticker
+                let csv = "ticker\nAAPL\n\"MSFT\nGOOG\n";
+                let parsed = parse_ticker_csv(csv.as_bytes());
+                assert_eq!(parsed.tickers, vec!["AAPL".to_string()]);
+                assert_eq!(parsed.skipped, 1);
+            }
+
+            // A foreign filer reporting under IFRS only, plus the dei cover-page facts every filer has
+            const IFRS_FACTS: &str = r#"{
+                "facts": {
+                    "dei": {
+                        "EntityCommonStockSharesOutstanding": {"units": {"shares": [
+                            {"val": 1000000, "fy": 2023, "fp": "FY", "end": "2023-12-31", "filed": "2024-03-01"}
+                        ]}}
+                    },
+                    "ifrs-full": {
+                        "Revenue": {"units": {"EUR": [
+                            {"val": 5000000000, "fy": 2023, "fp": "FY", "end": "2023-12-31", "filed": "2024-03-01",
+                             "accn": "0001234567-24-000010", "form": "20-F"},
+                            {"val": 4000000000, "fy": 2022, "fp": "FY", "end": "2022-12-31", "filed": "2023-03-01",
+                             "accn": "0001234567-23-000010", "form": "20-F"}
+                        ]}}
+                    }
+                }
+            }"#;
+
+            #[test]
+            fn ifrs_only_filers_get_a_frame() {
+                let facts: CompanyFacts = serde_json::from_str(IFRS_FACTS).unwrap();
+                let (df, taxonomy) = FinancialDataLoader::facts_to_dataframe(&facts, "ASML").unwrap();
+                assert_eq!(taxonomy, "ifrs-full");
+                assert_eq!(df.height(), 2);
+                let quarters: Vec<Option<&str>> = df.column("quarter").unwrap().utf8().unwrap().into_iter().collect();
+                assert_eq!(quarters, vec![Some("FY2023"), Some("FY2022")]);
+                let revenue: Vec<Option<f64>> = df.column("Revenue_EUR_B").unwrap().f64().unwrap().into_iter().collect();
+                assert_eq!(revenue, vec![Some(5.0), Some(4.0)]);
+                // Cover-page facts are not metrics
+                assert!(df.get_column_names().iter().all(|name| !name.starts_with("EntityCommonStock")));
+            }
+
+            #[test]
+            fn cover_page_facts_alone_are_not_financial_data() {
+                let facts: CompanyFacts = serde_json::from_str(
+                    r#"{"facts": {"dei": {"EntityCommonStockSharesOutstanding": {"units": {"shares": [{"val": 1, "fy": 2023, "fp": "FY"}]}}}}}"#,
+                )
+                .unwrap();
+                assert!(matches!(FinancialDataLoader::facts_to_dataframe(&facts, "SHELL"), Err(FinAIError::SecDataNotFound(_))));
+            }
+
+            #[test]
+            fn units_scale_and_label_by_their_unit_key() {
+                let cases = [
+                    ("USD", MetricUnit::Billions, 391_035_000_000.0, 391.035, "_B"),
+                    ("USD/shares", MetricUnit::PerShare, 6.13, 6.13, ""),
+                    ("shares", MetricUnit::MillionsOfShares, 15_204_137_000.0, 15_204.137, "_M"),
+                    ("pure", MetricUnit::Raw, 0.25, 0.25, ""),
+                ];
+                for (key, unit, reported, scaled, suffix) in cases {
+                    assert_eq!(MetricUnit::from_unit_key(key), unit, "{}", key);
+                    assert!((unit.scale(reported) - scaled).abs() < 1e-9, "{}", key);
+                    assert!((unit.unscale(scaled) - reported).abs() < 1e-3, "{}", key);
+                    assert_eq!(unit.column_suffix(), suffix);
+                    let column = format!("Metric_{}{}", key, suffix);
+                    assert_eq!(MetricUnit::from_column_name(&column), unit, "{}", column);
+                }
+
+                let format = NumberFormat::default();
+                assert_eq!(format_value(391.0353, MetricUnit::Billions, &format), "391.04B");
+                assert_eq!(format_value(6.13, MetricUnit::PerShare, &format), "$6.13");
+                assert_eq!(format_value(15_204.137, MetricUnit::MillionsOfShares, &format), "15204.14M shares");
+            }
+
+            #[test]
+            fn per_share_changes_under_a_dollar_are_cents() {
+                let eps = MetricUnit::PerShare;
+                assert_eq!(eps.format_change(6.13 - 5.94), "+19¢");
+                assert_eq!(eps.format_change(-0.19), "-19¢");
+                assert_eq!(eps.format_change(0.0), "+0¢");
+                // A dollar or more uses the configured number format
+                assert_eq!(eps.format_change(1.2), format!("+{}", eps.format(1.2)));
+                assert_eq!(eps.format_change(-1.2), format!("-{}", eps.format(1.2)));
+                assert_eq!(MetricUnit::Billions.format_change(-0.5), format!("-{}", MetricUnit::Billions.format(0.5)));
+
+                assert!(!eps.is_summable());
+                assert!([MetricUnit::Billions, MetricUnit::MillionsOfShares, MetricUnit::Raw].iter().all(|u| u.is_summable()));
+            }
+
+            #[test]
+            fn frame_columns_carry_the_unit() {
+                let facts: CompanyFacts = serde_json::from_str(
+                    r#"{"facts": {"us-gaap": {
+                        "Revenues": {"units": {"USD": [{"val": 391035000000, "fy": 2024, "fp": "FY"}]}},
+                        "EarningsPerShareDiluted": {"units": {"USD/shares": [{"val": 6.08, "fy": 2024, "fp": "FY"}]}},
+                        "CommonStockSharesOutstanding": {"units": {"shares": [{"val": 15115823000, "fy": 2024, "fp": "FY"}]}}
+                    }}}"#,
+                )
+                .unwrap();
+                let (df, _) = FinancialDataLoader::facts_to_dataframe(&facts, "AAPL").unwrap();
+                let value = |column: &str| df.column(column).unwrap().f64().unwrap().get(0).unwrap();
+                assert!((value("Revenues_USD_B") - 391.035).abs() < 1e-9);
+                assert_eq!(value("EarningsPerShareDiluted_USD/shares"), 6.08);
+                assert!((value("CommonStockSharesOutstanding_shares_M") - 15_115.823).abs() < 1e-9);
+            }
+
+            #[test]
+            fn ciks_are_validated_and_zero_padded() {
+                assert_eq!(FinancialDataLoader::normalize_cik("320193").unwrap(), "0000320193");
+                assert_eq!(FinancialDataLoader::normalize_cik(" 0000320193 ").unwrap(), "0000320193");
+                assert_eq!(FinancialDataLoader::normalize_cik("1234567890").unwrap(), "1234567890");
+                for invalid in ["", "12345678901", "32O193", "-320193", "320 193"] {
+                    assert!(
+                        matches!(FinancialDataLoader::normalize_cik(invalid), Err(FinAIError::TickerNotFound(_))),
+                        "{}",
+                        invalid
+                    );
+                }
+            }
+
+            #[test]
+            fn user_agent_is_built_from_the_contact() {
+                assert_eq!(sec_user_agent("Jane Doe jane@example.com"), "FINFILES AI/1.0 (Jane Doe jane@example.com)");
+                assert_eq!(sec_user_agent("  ops@example.org \n"), "FINFILES AI/1.0 (ops@example.org)");
+                assert!(!sec_user_agent("ops@example.org").contains("ai@finfiles.ai"));
+            }
+
+            // Trimmed from a real ownership document: one owner, an option exercise and a
+            // sale, plus the derivative side of the exercise
+            const FORM4_XML: &str = r#"<?xml version="1.0"?>
+                <ownershipDocument>
+                    <issuer><issuerCik>0000320193</issuerCik><issuerTradingSymbol>AAPL</issuerTradingSymbol></issuer>
+                    <reportingOwner>
+                        <reportingOwnerId><rptOwnerCik>0001214156</rptOwnerCik><rptOwnerName>COOK TIMOTHY D</rptOwnerName></reportingOwnerId>
+                    </reportingOwner>
+                    <nonDerivativeTable>
+                        <nonDerivativeTransaction>
+                            <securityTitle><value>Common Stock</value></securityTitle>
+                            <transactionDate><value>2024-04-01</value></transactionDate>
+                            <transactionCoding><transactionFormType>4</transactionFormType><transactionCode>M</transactionCode></transactionCoding>
+                            <transactionAmounts>
+                                <transactionShares><value>196410</value></transactionShares>
+                                <transactionPricePerShare><footnoteId id="F1"/></transactionPricePerShare>
+                            </transactionAmounts>
+                        </nonDerivativeTransaction>
+                        <nonDerivativeTransaction>
+                            <securityTitle><value>Common Stock</value></securityTitle>
+                            <transactionDate><value>2024-04-02-05:00</value></transactionDate>
+                            <transactionCoding><transactionFormType>4</transactionFormType><transactionCode>S</transactionCode></transactionCoding>
+                            <transactionAmounts>
+                                <transactionShares><value>88000</value></transactionShares>
+                                <transactionPricePerShare><value>169.03</value></transactionPricePerShare>
+                            </transactionAmounts>
+                        </nonDerivativeTransaction>
+                    </nonDerivativeTable>
+                    <derivativeTable>
+                        <derivativeTransaction>
+                            <securityTitle><value>Restricted Stock Unit</value></securityTitle>
+                            <transactionDate><value>2024-04-01</value></transactionDate>
+                            <transactionCoding><transactionFormType>4</transactionFormType><transactionCode>M</transactionCode></transactionCoding>
+                            <transactionAmounts>
+                                <transactionShares><value>196410</value></transactionShares>
+                                <transactionPricePerShare><value>0</value></transactionPricePerShare>
+                            </transactionAmounts>
+                        </derivativeTransaction>
+                    </derivativeTable>
+                </ownershipDocument>"#;
+
+            #[test]
+            fn form4_transactions_are_extracted() {
+                let transactions = parse_form4_xml(FORM4_XML).unwrap();
+                let tx = |date: &str, code: &str, shares: f64, price: Option<f64>| InsiderTransaction {
+                    insider: "COOK TIMOTHY D".to_string(),
+                    transaction_date: Some(date.to_string()),
+                    transaction_code: code.to_string(),
+                    shares: Some(shares),
+                    price,
+                };
+                assert_eq!(
+                    transactions,
+                    vec![
+                        tx("2024-04-01", "M", 196410.0, None),
+                        tx("2024-04-02", "S", 88000.0, Some(169.03)),
+                        tx("2024-04-01", "M", 196410.0, Some(0.0)),
+                    ]
+                );
+
+                let df = insider_transactions_to_dataframe(&transactions).unwrap();
+                assert_eq!(df.get_column_names(), vec!["insider", "transaction_date", "transaction_code", "shares", "price"]);
+                assert_eq!(df.height(), 3);
+                assert_eq!(insider_transactions_to_dataframe(&[]).unwrap().width(), 5);
+            }
+
+            #[test]
+            fn malformed_form4_xml_is_a_parsing_error() {
+                assert!(matches!(parse_form4_xml("<ownershipDocument><issuer></ownershipDocument>"), Err(FinAIError::DataParsing(_))));
+            }
+
+            // Prefixed the way EDGAR serves most information tables, with thousands
+            // separators and an escaped issuer name
+            const INFO_TABLE_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+                <ns1:informationTable xmlns:ns1="http://www.sec.gov/edgar/document/thirteenf/informationtable">
+                    <ns1:infoTable>
+                        <ns1:nameOfIssuer>APPLE INC</ns1:nameOfIssuer>
+                        <ns1:titleOfClass>COM</ns1:titleOfClass>
+                        <ns1:cusip>037833100</ns1:cusip>
+                        <ns1:value>174,347,572,000</ns1:value>
+                        <ns1:shrsOrPrnAmt><ns1:sshPrnamt>915,560,382</ns1:sshPrnamt><ns1:sshPrnamtType>SH</ns1:sshPrnamtType></ns1:shrsOrPrnAmt>
+                    </ns1:infoTable>
+                    <ns1:infoTable>
+                        <ns1:nameOfIssuer>JOHNSON &amp; JOHNSON</ns1:nameOfIssuer>
+                        <ns1:cusip>478160104</ns1:cusip>
+                        <ns1:value>n/a</ns1:value>
+                    </ns1:infoTable>
+                </ns1:informationTable>"#;
+
+            #[test]
+            fn info_table_rows_become_holdings() {
+                let holdings = parse_13f_info_table(INFO_TABLE_XML.as_bytes(), "BERKSHIRE HATHAWAY INC").unwrap();
+                assert_eq!(
+                    holdings,
+                    vec![
+                        Holding {
+                            holder: "BERKSHIRE HATHAWAY INC".to_string(),
+                            issuer: "APPLE INC".to_string(),
+                            cusip: "037833100".to_string(),
+                            shares: Some(915_560_382.0),
+                            value: Some(174_347_572_000.0),
+                        },
+                        Holding {
+                            holder: "BERKSHIRE HATHAWAY INC".to_string(),
+                            issuer: "JOHNSON & JOHNSON".to_string(),
+                            cusip: "478160104".to_string(),
+                            shares: None,
+                            value: None,
+                        },
+                    ]
+                );
+
+                let df = holdings_to_dataframe(&holdings).unwrap();
+                assert_eq!(df.get_column_names(), vec!["holder", "issuer", "cusip", "shares", "value"]);
+                assert_eq!(df.height(), 2);
+                assert_eq!(df.column("shares").unwrap().null_count(), 1);
+            }
+
+            #[test]
+            fn malformed_info_table_is_a_parsing_error() {
+                let xml = "<informationTable><infoTable><cusip>037833100</infoTable>";
+                assert!(matches!(parse_13f_info_table(xml.as_bytes(), "Holder"), Err(FinAIError::DataParsing(_))));
+            }
+
+            // A company_tickers.json map: keys are SEC's ranks (by market value)
+            // A trimmed EDGAR "browse by SIC" atom feed, with a duplicate entry for Apple
+            const SIC_FEED: &str = r#"<?xml version="1.0" encoding="ISO-8859-1" ?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>SIC 3571 - Electronic Computers</title>
+  <entry><content type="text/xml"><company-info><cik>0000320193</cik><name>Apple Inc.</name></company-info></content></entry>
+  <entry><content type="text/xml"><company-info><cik>0001571996</cik><name>Dell Technologies Inc.</name></company-info></content></entry>
+  <entry><content type="text/xml"><company-info><cik>0000999999</cik><name>Private Computers &amp; Co</name></company-info></content></entry>
+  <entry><content type="text/xml"><company-info><cik>0000047217</cik><name>HP Inc</name></company-info></content></entry>
+  <entry><content type="text/xml"><company-info><cik>0000320193</cik><name>Apple Inc.</name></company-info></content></entry>
+</feed>"#;
+
+            #[test]
+            fn sic_feed_lists_each_cik_once() {
+                assert_eq!(parse_company_ciks(SIC_FEED).unwrap(), vec!["0000320193", "0001571996", "0000999999", "0000047217"]);
+                assert!(parse_company_ciks("<feed><title>No results</title></feed>").unwrap().is_empty());
+                assert!(matches!(parse_company_ciks("<feed><cik>1</feed>"), Err(FinAIError::DataParsing(_))));
+            }
+
+            #[test]
+            fn peers_leave_out_the_company_and_unlisted_filers() {
+                let map = cik_map(&[
+                    ("AAPL", "320193", "Apple Inc."),
+                    ("DELL", "1571996", "Dell Technologies Inc."),
+                    ("HPQ", "47217", "HP Inc"),
+                    ("HPQ-WS", "47217", "HP Inc"),
+                ]);
+                let ciks = parse_company_ciks(SIC_FEED).unwrap();
+                // Leading zeros don't matter, and a company's first-listed ticker wins
+                assert_eq!(peer_tickers(&map, "0000320193", &ciks, MAX_PEERS), vec!["DELL", "HPQ"]);
+                assert_eq!(peer_tickers(&map, "0000320193", &ciks, 1), vec!["DELL"]);
+                assert!(peer_tickers(&map, "0000320193", &[], MAX_PEERS).is_empty());
+            }
+
+            #[test]
+            fn companies_without_an_sic_code_have_no_industry() {
+                assert_eq!(sic_code(" 3571 "), Some("3571"));
+                assert_eq!(sic_code(""), None);
+                assert_eq!(sic_code("   "), None);
+                assert_eq!(sic_code("35A1"), None);
+                let submissions: CompanySubmissions =
+                    serde_json::from_str(r#"{"name": "Shell Co", "filings": {"recent": {"accessionNumber": [], "form": []}}}"#).unwrap();
+                assert_eq!(sic_code(&submissions.sic), None);
+            }
+
+            fn cik_map(entries: &[(&str, &str, &str)]) -> HashMap<String, CikEntry> {
+                entries
+                    .iter()
+                    .enumerate()
+                    .map(|(rank, (ticker, cik, title))| {
+                        let entry = CikEntry { cik_str: cik.to_string(), ticker: ticker.to_string(), title: title.to_string() };
+                        (rank.to_string(), entry)
+                    })
+                    .collect()
+            }
+
+            fn suggestion_map() -> HashMap<String, CikEntry> {
+                cik_map(&[
+                    ("AAPL", "320193", "Apple Inc."),
+                    ("MSFT", "789019", "MICROSOFT CORP"),
+                    ("APPS", "317788", "Digital Turbine, Inc."),
+                    ("APP", "1751008", "AppLovin Corp"),
+                ])
+            }
+
+            #[test]
+            fn mistyped_ticker_suggests_the_closest_by_rank() {
+                let map = suggestion_map();
+                assert_eq!(suggest_tickers(&map, "APPL", 3), vec!["AAPL", "APPS", "APP"]);
+                assert_eq!(suggest_tickers(&map, " appl ", 1), vec!["AAPL"]);
+                assert_eq!(suggest_tickers(&map, "MICROSFT", 3), vec!["MSFT"]);
+                assert!(suggest_tickers(&map, "ZZZZZZ", 3).is_empty());
+                assert!(suggest_tickers(&map, "  ", 3).is_empty());
+            }
+
+            #[test]
+            fn unknown_ticker_error_carries_suggestions() {
+                let map = suggestion_map();
+                match lookup_cik(&map, "APPL") {
+                    Err(err @ FinAIError::TickerNotFoundWithSuggestions { .. }) => {
+                        assert_eq!(err.to_string(), "Ticker not found: APPL. Did you mean AAPL, APPS, APP?");
+                    }
+                    other => panic!("expected suggestions, got {other:?}"),
+                }
+                assert!(matches!(lookup_cik(&map, "ZZZZZZ"), Err(FinAIError::TickerNotFound(t)) if t == "ZZZZZZ"));
+                assert_eq!(lookup_cik(&map, "aapl").unwrap(), "320193");
+            }
+
+            #[test]
+            fn share_classes_resolve_to_their_common_cik() {
+                let map = cik_map(&[
+                    ("GOOGL", "1652044", "Alphabet Inc."),
+                    ("GOOG", "1652044", "Alphabet Inc."),
+                    ("BRK-B", "1067983", "BERKSHIRE HATHAWAY INC"),
+                    ("BRK-A", "1067983", "BERKSHIRE HATHAWAY INC"),
+                ]);
+                assert_eq!(lookup_cik(&map, "GOOGL").unwrap(), "1652044");
+                assert_eq!(lookup_cik(&map, "goog").unwrap(), "1652044");
+                for ticker in ["BRK-B", "BRK.B", "brk/b", "BRK.A"] {
+                    assert_eq!(lookup_cik(&map, ticker).unwrap(), "1067983", "{ticker}");
+                }
+                assert_eq!(normalize_ticker_symbol(" brk.b "), "BRK-B");
+            }
+            use std::sync::atomic::{AtomicUsize, Ordering};
+            use std::sync::Arc;
+
+            // One canned HTTP response: status, headers, body
+            type Scripted = (u16, &'static [(&'static str, &'static str)], &'static str);
+            const NO_HEADERS: &[(&str, &str)] = &[];
+
+            // Local endpoint that answers the n-th request with `script[n]` (repeating the
+            // last entry), returning its URL and a count of requests served
+            async fn spawn_scripted(script: Vec<Scripted>) -> (String, Arc<AtomicUsize>) {
+                let hits = Arc::new(AtomicUsize::new(0));
+                let served = hits.clone();
+                let app = axum::Router::new().route(
+                    "/data",
+                    axum::routing::get(move || {
+                        let n = served.fetch_add(1, Ordering::SeqCst);
+                        let (status, headers, body) = script[n.min(script.len() - 1)];
+                        async move {
+                            let mut response = axum::http::Response::builder().status(status);
+                            for (name, value) in headers {
+                                response = response.header(*name, *value);
+                            }
+                            response.body(axum::body::Body::from(body)).unwrap()
+                        }
+                    }),
+                );
+                let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+                let addr = listener.local_addr().unwrap();
+                tokio::spawn(async move { axum::serve(listener, app).await });
+                (format!("http://{}/data", addr), hits)
+            }
+
+            fn quick_retries(max_retries: u32) -> RetryPolicy {
+                RetryPolicy { max_retries, base_delay: Duration::from_millis(10), backoff: 2.0 }
+            }
+
+            async fn get_scripted(url: &str, retry: RetryPolicy) -> Result<reqwest::Response> {
+                FinancialDataLoader::get_with_retry(&Client::new(), url, retry, None, "test data", || {
+                    FinAIError::SecDataNotFound("test data".to_string())
+                })
+                .await
+            }
+
+            #[tokio::test]
+            async fn server_errors_are_retried_until_success() {
+                let (url, hits) = spawn_scripted(vec![(503, NO_HEADERS, "busy"), (503, NO_HEADERS, "busy"), (200, NO_HEADERS, "ok")]).await;
+                let response = get_scripted(&url, quick_retries(2)).await.unwrap();
+                assert_eq!(response.text().await.unwrap(), "ok");
+                assert_eq!(hits.load(Ordering::SeqCst), 3);
+            }
+
+            #[tokio::test]
+            async fn retries_stop_at_the_policy_limit() {
+                let (url, hits) = spawn_scripted(vec![(503, NO_HEADERS, "busy"), (503, NO_HEADERS, "busy"), (200, NO_HEADERS, "ok")]).await;
+                let err = get_scripted(&url, quick_retries(1)).await.unwrap_err();
+                assert!(matches!(err, FinAIError::Http { status: 503, .. }));
+                assert_eq!(hits.load(Ordering::SeqCst), 2);
+            }
+
+            #[test]
+            fn retry_delay_backs_off_with_bounded_jitter() {
+                let policy = RetryPolicy { max_retries: 3, base_delay: Duration::from_secs(2), backoff: 2.0 };
+                for (attempt, nominal) in [(0, 2.0), (1, 4.0), (2, 8.0)] {
+                    let delay = policy.delay(attempt).as_secs_f64();
+                    assert!(delay >= nominal * 0.75 && delay <= nominal * 1.25, "attempt {attempt}: {delay}");
+                }
+                // A backoff below 1 never shrinks the delay
+                let flat = RetryPolicy { backoff: 0.5, ..policy };
+                assert!(flat.delay(3).as_secs_f64() >= 1.5);
+                assert_eq!(RetryPolicy::none().max_retries, 0);
+            }
+
+            #[tokio::test]
+            async fn error_statuses_map_to_typed_errors() {
+                let (url, _) = spawn_scripted(vec![(404, NO_HEADERS, "")]).await;
+                assert!(matches!(get_scripted(&url, RetryPolicy::none()).await, Err(FinAIError::SecDataNotFound(_))));
+
+                let (url, _) = spawn_scripted(vec![(429, NO_HEADERS, "slow down")]).await;
+                assert!(matches!(
+                    get_scripted(&url, RetryPolicy::none()).await,
+                    Err(FinAIError::RateLimited { retry_after: None })
+                ));
+
+                let (url, _) = spawn_scripted(vec![(500, NO_HEADERS, "  internal error \n")]).await;
+                match get_scripted(&url, RetryPolicy::none()).await {
+                    Err(FinAIError::Http { status, body }) => {
+                        assert_eq!(status, 500);
+                        assert_eq!(body, "internal error");
+                    }
+                    other => panic!("expected an Http error, got {other:?}"),
+                }
+            }
+
+            #[tokio::test]
+            async fn not_found_and_client_errors_are_not_retried() {
+                let (url, hits) = spawn_scripted(vec![(404, NO_HEADERS, "")]).await;
+                assert!(get_scripted(&url, quick_retries(2)).await.is_err());
+                assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+                let (url, hits) = spawn_scripted(vec![(403, NO_HEADERS, "forbidden")]).await;
+                assert!(matches!(get_scripted(&url, quick_retries(2)).await, Err(FinAIError::Http { status: 403, .. })));
+                assert_eq!(hits.load(Ordering::SeqCst), 1);
+            }
+
+            #[test]
+            fn http_error_bodies_are_trimmed_and_capped() {
+                let long = "x".repeat(MAX_ERROR_BODY_LEN + 50);
+                match http_error(reqwest::StatusCode::BAD_GATEWAY, long) {
+                    FinAIError::Http { status, body } => {
+                        assert_eq!(status, 502);
+                        assert_eq!(body.chars().count(), MAX_ERROR_BODY_LEN + 1);
+                        assert!(body.ends_with('…'));
+                    }
+                    other => panic!("expected an Http error, got {other:?}"),
+                }
+                assert_eq!(http_error(reqwest::StatusCode::BAD_GATEWAY, "bad".to_string()).to_string(), "HTTP 502: bad");
+            }
+
+            fn download_dest(name: &str) -> std::path::PathBuf {
+                let dir = std::env::temp_dir().join(format!("finfiles-download-{}-{}", name, std::process::id()));
+                let _ = fs::remove_dir_all(&dir);
+                fs::create_dir_all(&dir).unwrap();
+                dir.join("aapl-20230930.htm")
+            }
+
+            #[tokio::test]
+            async fn document_is_saved_with_progress() {
+                let body = "<html><body>Annual report</body></html>";
+                let (url, _) = spawn_scripted(vec![(200, NO_HEADERS, body)]).await;
+                let dest = download_dest("ok");
+                let mut progress = Vec::new();
+                let saved = FinancialDataLoader::download_with_client(&Client::new(), &url, &dest, RetryPolicy::none(), &mut |received, total| {
+                    progress.push((received, total))
+                })
+                .await
+                .unwrap();
+
+                assert_eq!(saved, body.len() as u64);
+                assert_eq!(fs::read_to_string(&dest).unwrap(), body);
+                assert_eq!(progress.last(), Some(&(body.len() as u64, Some(body.len() as u64))));
+                assert!(!dest.with_file_name("aapl-20230930.htm.part").exists());
+            }
+
+            #[tokio::test]
+            async fn failed_download_leaves_the_existing_file_alone() {
+                let (url, _) = spawn_scripted(vec![(404, NO_HEADERS, "")]).await;
+                let dest = download_dest("missing");
+                fs::write(&dest, "previous copy").unwrap();
+                let result = FinancialDataLoader::download_with_client(&Client::new(), &url, &dest, RetryPolicy::none(), &mut |_, _| {}).await;
+
+                assert!(matches!(result, Err(FinAIError::SecDataNotFound(_))));
+                assert_eq!(fs::read_to_string(&dest).unwrap(), "previous copy");
+                assert!(!dest.with_file_name("aapl-20230930.htm.part").exists());
+            }
+
+            // Local endpoint that answers only after `delay`
+            async fn spawn_slow(delay: Duration) -> String {
+                let app = axum::Router::new().route(
+                    "/data",
+                    axum::routing::get(move || async move {
+                        tokio::time::sleep(delay).await;
+                        "late"
+                    }),
+                );
+                let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+                let addr = listener.local_addr().unwrap();
+                tokio::spawn(async move { axum::serve(listener, app).await });
+                format!("http://{}/data", addr)
+            }
+
+            #[tokio::test]
+            async fn slow_responses_time_out_per_request() {
+                let url = spawn_slow(Duration::from_secs(30)).await;
+                let started = std::time::Instant::now();
+                let result = FinancialDataLoader::get_with_retry(
+                    &Client::new(),
+                    &url,
+                    RetryPolicy::none(),
+                    Some(Duration::from_millis(200)),
+                    "company facts",
+                    || FinAIError::SecDataNotFound("company facts".to_string()),
+                )
+                .await;
+                assert!(started.elapsed() < Duration::from_secs(10));
+                assert!(matches!(result, Err(FinAIError::Network(m)) if m.starts_with("Timed out fetching company facts")));
+            }
+
+            #[test]
+            fn company_search_ranks_exact_then_prefix_then_word_then_substring() {
+                let map = cik_map(&[
+                    ("AAPL", "320193", "Apple Inc."),
+                    ("MSFT", "789019", "MICROSOFT CORP"),
+                    ("APLE", "1418121", "Apple Hospitality REIT, Inc."),
+                    ("mlp", "63330", "Maui Land & Pineapple Company, Inc."),
+                    ("GAPL", "1409197", "Golden Apple Oil & Gas Inc."),
+                    ("APL", "1000001", "Apple"),
+                ]);
+                let tickers = |query: &str, limit: usize| -> Vec<String> {
+                    search_companies(&map, query, limit).into_iter().map(|m| m.ticker).collect()
+                };
+                assert_eq!(tickers(" APPLE ", MAX_COMPANY_MATCHES), vec!["APL", "AAPL", "APLE", "GAPL", "MLP"]);
+                assert_eq!(tickers("apple", 2), vec!["APL", "AAPL"]);
+                assert!(tickers("", MAX_COMPANY_MATCHES).is_empty());
+                assert!(tickers("banana", MAX_COMPANY_MATCHES).is_empty());
+
+                let top = &search_companies(&map, "microsoft", 1)[0];
+                assert_eq!(top, &CompanyMatch { ticker: "MSFT".to_string(), title: "MICROSOFT CORP".to_string(), cik: "789019".to_string() });
+            }
+
+            const RETRY_AFTER_7S: &[(&str, &str)] = &[("Retry-After", "7")];
+            const RETRY_AFTER_NOW: &[(&str, &str)] = &[("Retry-After", "0")];
+            const RETRY_AFTER_1H: &[(&str, &str)] = &[("Retry-After", "3600")];
+
+            #[tokio::test]
+            async fn rate_limit_carries_the_retry_after_wait() {
+                let (url, _) = spawn_scripted(vec![(429, RETRY_AFTER_7S, "")]).await;
+                let err = get_scripted(&url, RetryPolicy::none()).await.unwrap_err();
+                assert!(matches!(err, FinAIError::RateLimited { retry_after: Some(wait) } if wait == Duration::from_secs(7)));
+                assert_eq!(err.to_string(), "Rate limited, retry in 7s");
+            }
+
+            #[tokio::test]
+            async fn retry_after_replaces_the_backoff_unless_too_long() {
+                let (url, hits) = spawn_scripted(vec![(429, RETRY_AFTER_NOW, ""), (200, NO_HEADERS, "ok")]).await;
+                assert_eq!(get_scripted(&url, quick_retries(1)).await.unwrap().text().await.unwrap(), "ok");
+                assert_eq!(hits.load(Ordering::SeqCst), 2);
+
+                let (url, hits) = spawn_scripted(vec![(429, RETRY_AFTER_1H, ""), (200, NO_HEADERS, "ok")]).await;
+                assert!(matches!(get_scripted(&url, quick_retries(1)).await, Err(FinAIError::RateLimited { .. })));
+                assert_eq!(hits.load(Ordering::SeqCst), 1);
+            }
+
+            #[test]
+            fn retry_after_accepts_seconds_or_an_http_date() {
+                let now = chrono::DateTime::parse_from_rfc2822("Wed, 21 Oct 2015 07:28:00 GMT").unwrap().with_timezone(&chrono::Utc);
+                assert_eq!(parse_retry_after(" 120 ", now), Some(Duration::from_secs(120)));
+                assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:30 GMT", now), Some(Duration::from_secs(30)));
+                assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:00:00 GMT", now), Some(Duration::ZERO));
+                assert_eq!(parse_retry_after("soon", now), None);
+            }
+
+            #[test]
+            fn unreported_periods_load_as_nulls() {
+                let facts: CompanyFacts = serde_json::from_str(
+                    r#"{"facts": {"us-gaap": {
+                        "Revenues": {"units": {"USD": [
+                            {"val": 30000000000, "fy": 2023, "fp": "FY", "end": "2023-12-31", "filed": "2024-02-01", "form": "10-K"},
+                            {"val": 25000000000, "fy": 2022, "fp": "FY", "end": "2022-12-31", "filed": "2023-02-01", "form": "10-K"},
+                            {"val": 20000000000, "fy": 2021, "fp": "FY", "end": "2021-12-31", "filed": "2022-02-01", "form": "10-K"}
+                        ]}},
+                        "NetIncomeLoss": {"units": {"USD": [
+                            {"val": 20000000000, "fy": 2023, "fp": "FY", "end": "2023-12-31", "filed": "2024-02-01", "form": "10-K"},
+                            {"val": 10000000000, "fy": 2021, "fp": "FY", "end": "2021-12-31", "filed": "2022-02-01", "form": "10-K"}
+                        ]}}
+                    }}}"#,
+                )
+                .unwrap();
+                let (df, _) = FinancialDataLoader::facts_to_dataframe(&facts, "TEST").unwrap();
+                let net_income: Vec<Option<f64>> = df.column("NetIncomeLoss_USD_B").unwrap().f64().unwrap().into_iter().collect();
+                assert_eq!(net_income, vec![Some(20.0), None, Some(10.0)]);
+            }
+
+            fn source(accession: &str, form: &str, filed: &str) -> FactSource {
+                FactSource { accession: accession.to_string(), form: form.to_string(), filed: filed.to_string() }
+            }
+
+            #[test]
+            fn each_period_records_its_source_filing() {
+                let facts: CompanyFacts = serde_json::from_str(IFRS_FACTS).unwrap();
+                let (df, _) = FinancialDataLoader::facts_to_dataframe(&facts, "ASML").unwrap();
+                assert_eq!(period_source(&df, 0), Some(source("0001234567-24-000010", "20-F", "2024-03-01")));
+                assert_eq!(period_source(&df, 1), Some(source("0001234567-23-000010", "20-F", "2023-03-01")));
+
+                // Frames without provenance columns (the demo data) have no sources
+                let plain = DataFrame::new(vec![Series::new("revenue", &[1.0])]).unwrap();
+                assert_eq!(period_source(&plain, 0), None);
+            }
+
+            #[test]
+            fn citations_use_what_the_source_has() {
+                assert_eq!(source("0000320193-23-000106", "10-K", "2023-11-03").citation(), "from 10-K filed 2023-11-03");
+                assert_eq!(source("0000320193-23-000106", "10-K", "").citation(), "from 10-K 0000320193-23-000106");
+                assert_eq!(source("0000320193-23-000106", "", "2023-11-03").citation(), "from filing 0000320193-23-000106 filed 2023-11-03");
+                assert_eq!(source("0000320193-23-000106", "", "").citation(), "from filing 0000320193-23-000106");
+            }
+
+            #[test]
+            fn the_filing_supplying_most_values_is_the_period_source() {
+                let original = source("A", "10-K", "2023-02-01");
+                let amendment = source("B", "10-K/A", "2023-06-01");
+                let sources = vec![original.clone(), amendment.clone(), original.clone()];
+                assert_eq!(dominant_source(&sources), Some(original.clone()));
+                // On a tie the later filing wins
+                assert_eq!(dominant_source(&[original, amendment.clone()]), Some(amendment));
+                assert_eq!(dominant_source(&[]), None);
+            }
+
+            // Client whose every request fails to connect: its proxy is a port nothing listens on
+            async fn unreachable_client() -> Client {
+                let addr = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+                Client::builder().proxy(reqwest::Proxy::all(format!("http://{}", addr)).unwrap()).build().unwrap()
+            }
+
+            #[tokio::test]
+            async fn stages_are_reported_in_order_up_to_a_failure() {
+                let client = unreachable_client().await;
+                let mut stages = Vec::new();
+                let result = FinancialDataLoader::load_sec_data_with_client(
+                    &client,
+                    "320193",
+                    "AAPL",
+                    RetryPolicy::none(),
+                    SecTimeouts::default(),
+                    &mut |stage| stages.push(stage),
+                )
+                .await;
+                assert!(matches!(result, Err(FinAIError::Network(_))));
+                // Submissions failed, so facts were never fetched
+                assert_eq!(stages, vec![LoadStage::FetchingSubmissions]);
+
+                // An invalid CIK fails before any stage starts
+                let mut stages = Vec::new();
+                let result = FinancialDataLoader::load_sec_data_with_client(
+                    &client,
+                    "not-a-cik",
+                    "not-a-cik",
+                    RetryPolicy::none(),
+                    SecTimeouts::default(),
+                    &mut |stage| stages.push(stage),
+                )
+                .await;
+                assert!(result.is_err());
+                assert!(stages.is_empty());
+            }
+
+            #[test]
+            fn stage_labels_follow_the_load() {
+                let labels: Vec<&str> = [LoadStage::ResolvingCik, LoadStage::FetchingSubmissions, LoadStage::FetchingFacts, LoadStage::BuildingFrame]
+                    .iter()
+                    .map(LoadStage::label)
+                    .collect();
+                assert_eq!(labels, vec!["Resolving CIK…", "Fetching submissions…", "Fetching company facts…", "Building data table…"]);
+            }
+        }
+    }
+
+    pub mod fixtures {
+        use super::backend::FilingRecord;
+        use super::error::*;
+        use polars::prelude::*;
+
+        // Ticker the bundled demo data is published under
+        pub const DEMO_TICKER: &str = "DEMO";
+        pub const DEMO_COMPANY: &str = "Demo Manufacturing Corp (sample data)";
+
+        // Quarterly financials in the loader's layout (most recent first, monetary
+        // values in billions), so every analysis, chart and export path works offline
+        const SAMPLE_QUARTERS: [&str; 4] = ["FY2024-Q3", "FY2024-Q2", "FY2024-Q1", "FY2023"];
+        const SAMPLE_METRICS: [(&str, [f64; 4]); 6] = [
+            ("Revenues_USD_B", [12.4, 11.9, 11.2, 43.8]),
+            ("NetIncomeLoss_USD_B", [1.6, 1.4, 1.1, 5.2]),
+            ("EarningsPerShareDiluted_USD/shares", [1.52, 1.33, 1.05, 4.96]),
+            ("Assets_USD_B", [58.3, 57.1, 55.9, 54.6]),
+            ("Liabilities_USD_B", [31.2, 30.8, 30.5, 30.9]),
+            ("CashAndCashEquivalentsAtCarryingValue_USD_B", [7.9, 7.2, 6.8, 6.1]),
+        ];
+
+        // (form, date, document, description)
+        const SAMPLE_FILINGS: [(&str, &str, &str, &str); 6] = [
+            ("10-Q", "2024-10-30", "demo-20240928.htm", "Quarterly report for Q3 2024"),
+            ("8-K", "2024-10-30", "demo-8k-20241030.htm", "Results of operations and financial condition"),
+            ("10-Q", "2024-07-31", "demo-20240629.htm", "Quarterly report for Q2 2024"),
+            ("10-Q", "2024-05-01", "demo-20240330.htm", "Quarterly report for Q1 2024"),
+            ("10-K", "2024-02-14", "demo-20231231.htm", "Annual report for fiscal 2023"),
+            ("DEF 14A", "2024-03-20", "demo-proxy-2024.htm", "Definitive proxy statement"),
+        ];
+
+        // The bundled financials; built in memory without any network access
+        pub fn sample_dataframe() -> Result<DataFrame> {
+            let mut columns = vec![Series::new("quarter", &SAMPLE_QUARTERS)];
+            for (name, values) in SAMPLE_METRICS.iter() {
+                columns.push(Series::new(name, values));
+            }
+            DataFrame::new(columns).map_err(|e| FinAIError::DataParsing(format!("Failed to build sample data: {e}")))
+        }
+
+        pub fn sample_filings() -> Vec<FilingRecord> {
+            SAMPLE_FILINGS
+                .iter()
+                .map(|(form, date, document, description)| FilingRecord {
+                    form: form.to_string(),
+                    date: date.to_string(),
+                    document: document.to_string(),
+                    document_url: String::new(),
+                    company_name: DEMO_COMPANY.to_string(),
+                    filing_type: description.to_string(),
+                    ai_summary: String::new(),
+                    ticker: DEMO_TICKER.to_string(),
+                    amendment: false,
+                }
+                .with_sentiment())
+                .collect()
+        }
+    }
+
+    pub mod export {
+        use super::config::AppConfig;
+        use super::error::*;
+        use crate::backend::{Bookmark, FilingRecord};
+        use chrono::Utc;
+        use polars::prelude::*;
+        use printpdf::{BuiltinFont, IndirectFontRef, Mm, PdfDocument, PdfLayerReference};
+        use rusqlite::{params, Connection};
+        use rust_xlsxwriter::{Color, Format, Workbook, Worksheet, XlsxError};
+        use std::fs::{self, File};
+        use std::io::BufWriter;
+        use std::path::{Path, PathBuf};
+
+        // Output formats supported by the export functions.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum ExportFormat {
+            Text,
+            Markdown,
+            Csv,
+            Json,
+            Pdf,
+            Parquet,
+            Xlsx,
+            Sqlite,
+            Html,
+        }
+
+        // Formats offered for filings export, in the order shown in the UI.
+        pub const FILING_EXPORT_FORMATS: [ExportFormat; 6] = [
+            ExportFormat::Csv,
+            ExportFormat::Xlsx,
+            ExportFormat::Pdf,
+            ExportFormat::Json,
+            ExportFormat::Parquet,
+            ExportFormat::Sqlite,
+        ];
+
+        impl ExportFormat {
+            // Pick a format from a file extension, defaulting to plain text.
+            pub fn from_path(path: &Path) -> Self {
+                match path.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase()).as_deref() {
+                    Some("md") | Some("markdown") => ExportFormat::Markdown,
+                    Some("csv") => ExportFormat::Csv,
+                    Some("json") => ExportFormat::Json,
+                    Some("pdf") => ExportFormat::Pdf,
+                    Some("parquet") => ExportFormat::Parquet,
+                    Some("xlsx") => ExportFormat::Xlsx,
+                    Some("sqlite") | Some("db") => ExportFormat::Sqlite,
+                    Some("html") | Some("htm") => ExportFormat::Html,
+                    _ => ExportFormat::Text,
+                }
+            }
+
+            pub fn extension(&self) -> &'static str {
+                match self {
+                    ExportFormat::Text => "txt",
+                    ExportFormat::Markdown => "md",
+                    ExportFormat::Csv => "csv",
+                    ExportFormat::Json => "json",
+                    ExportFormat::Pdf => "pdf",
+                    ExportFormat::Parquet => "parquet",
+                    ExportFormat::Xlsx => "xlsx",
+                    ExportFormat::Sqlite => "sqlite",
+                    ExportFormat::Html => "html",
+                }
+            }
+
+            pub fn label(&self) -> &'static str {
+                match self {
+                    ExportFormat::Text => "Text",
+                    ExportFormat::Markdown => "Markdown",
+                    ExportFormat::Csv => "CSV",
+                    ExportFormat::Json => "JSON",
+                    ExportFormat::Pdf => "PDF",
+                    ExportFormat::Parquet => "Parquet",
+                    ExportFormat::Xlsx => "Excel",
+                    ExportFormat::Sqlite => "SQLite",
+                    ExportFormat::Html => "HTML",
+                }
+            }
+        }
+
+        // A FilingRecord field that can be chosen as a CSV/Excel export column
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum FilingField {
+            Form,
+            Date,
+            Document,
+            Url,
+            Company,
+            FilingType,
+            AiSummary,
+        }
+
+        impl FilingField {
+            // Default export columns, in order
+            pub const ALL: [FilingField; 7] = [
+                FilingField::Form,
+                FilingField::Date,
+                FilingField::Document,
+                FilingField::Url,
+                FilingField::Company,
+                FilingField::FilingType,
+                FilingField::AiSummary,
+            ];
+
+            pub fn header(&self) -> &'static str {
+                match self {
+                    FilingField::Form => "Form",
+                    FilingField::Date => "Date",
+                    FilingField::Document => "Document",
+                    FilingField::Url => "Document URL",
+                    FilingField::Company => "Company",
+                    FilingField::FilingType => "Filing Type",
+                    FilingField::AiSummary => "AI Analysis",
+                }
+            }
+
+            // Name used in the config's `export_columns`
+            pub fn name(&self) -> &'static str {
+                match self {
+                    FilingField::Form => "form",
+                    FilingField::Date => "date",
+                    FilingField::Document => "document",
+                    FilingField::Url => "url",
+                    FilingField::Company => "company",
+                    FilingField::FilingType => "filing_type",
+                    FilingField::AiSummary => "ai_summary",
+                }
+            }
+
+            pub fn from_name(name: &str) -> Option<Self> {
+                let name = name.trim().to_lowercase().replace([' ', '-'], "_");
+                Self::ALL.iter().copied().find(|f| f.name() == name || f.header().to_lowercase().replace(' ', "_") == name)
+            }
+
+            // Parse a column list such as ["date", "form", "url"]
+            pub fn parse_list(names: &[String]) -> Result<Vec<FilingField>> {
+                let fields = names
+                    .iter()
+                    .map(|name| {
+                        Self::from_name(name).ok_or_else(|| {
+                            let known: Vec<&str> = Self::ALL.iter().map(|f| f.name()).collect();
+                            FinAIError::Export(format!("Unknown export column '{}'; expected one of {}.", name, known.join(", ")))
+                        })
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                validate_columns(&fields)?;
+                Ok(fields)
+            }
+
+            pub fn value<'a>(&self, rec: &'a FilingRecord) -> &'a str {
+                match self {
+                    FilingField::Form => &rec.form,
+                    FilingField::Date => &rec.date,
+                    FilingField::Document => &rec.document,
+                    FilingField::Url => &rec.document_url,
+                    FilingField::Company => &rec.company_name,
+                    FilingField::FilingType => &rec.filing_type,
+                    FilingField::AiSummary => &rec.ai_summary,
+                }
+            }
+        }
+
+        // A column selection must name at least one field and each field at most once
+        fn validate_columns(columns: &[FilingField]) -> Result<()> {
+            if columns.is_empty() {
+                return Err(FinAIError::Export("Choose at least one column to export.".to_string()));
+            }
+            for (i, field) in columns.iter().enumerate() {
+                if columns[..i].contains(field) {
+                    return Err(FinAIError::Export(format!("Export column '{}' is listed more than once.", field.name())));
+                }
+            }
+            Ok(())
+        }
+
+        // Export filings in the requested format to the exports directory. Returns the written path.
+        // Excel workbooks also get a sheet with the loaded financial metrics, when given.
+        // `columns` picks and orders the CSV and Excel filing columns (all of them when None).
+        pub fn export_filings(
+            records: &[FilingRecord],
+            metrics: Option<&DataFrame>,
+            format: ExportFormat,
+            columns: Option<&[FilingField]>,
+        ) -> Result<String> {
+            export_records(records, metrics, format, columns, "finfiles_filings")
+        }
+
+        // Export bookmarked filings to the exports directory. JSON keeps each bookmark's
+        // accession number and when it was added; other formats export the filings.
+        pub fn export_bookmarks(bookmarks: &[Bookmark], format: ExportFormat) -> Result<String> {
+            if format == ExportFormat::Json {
+                let path = export_path("finfiles_bookmarks", format)?;
+                let json = serde_json::to_string_pretty(bookmarks)
+                    .map_err(|e| FinAIError::Export(format!("Failed to serialize bookmarks: {e}")))?;
+                fs::write(&path, json).map_err(|e| FinAIError::Export(format!("Failed to write {}: {e}", path.display())))?;
+                return Ok(path.display().to_string());
+            }
+            let records: Vec<FilingRecord> = bookmarks.iter().map(|b| b.record.clone()).collect();
+            export_records(&records, None, format, None, "finfiles_bookmarks")
+        }
+
+        // `<export dir>/<stem>_<UTC timestamp>.<extension>`, creating the directory, so each
+        // export gets its own file
+        fn export_path(stem: &str, format: ExportFormat) -> Result<PathBuf> {
+            let dir = AppConfig::current().export_dir();
+            fs::create_dir_all(&dir)
+                .map_err(|e| FinAIError::Export(format!("Failed to create {}: {e}", dir.display())))?;
+            let name = format!("{}_{}", stem, Utc::now().format("%Y%m%d-%H%M%S"));
+            Ok(unused_path(&dir, &name, format.extension()))
+        }
+
+        // `<dir>/<name>.<extension>`, or `<name>_2`, `<name>_3`, ... when that file exists
+        // (several exports within one second)
+        fn unused_path(dir: &Path, name: &str, extension: &str) -> PathBuf {
+            let mut path = dir.join(format!("{}.{}", name, extension));
+            let mut n = 2;
+            while path.exists() {
+                path = dir.join(format!("{}_{}.{}", name, n, extension));
+                n += 1;
+            }
+            path
+        }
+
+        fn export_records(
+            records: &[FilingRecord],
+            metrics: Option<&DataFrame>,
+            format: ExportFormat,
+            columns: Option<&[FilingField]>,
+            stem: &str,
+        ) -> Result<String> {
+            let columns = columns.unwrap_or(&FilingField::ALL);
+            validate_columns(columns)?;
+            let path = export_path(stem, format)?;
+
+            match format {
+                ExportFormat::Csv => write_filings_csv(records, columns, &path)?,
+                ExportFormat::Json => write_filings_json(records, &path)?,
+                ExportFormat::Pdf => write_filings_pdf(records, &path)?,
+                ExportFormat::Parquet => {
+                    export_dataframe_parquet(&filings_to_dataframe(records)?, &path)?;
+                }
+                ExportFormat::Xlsx => write_filings_xlsx(records, columns, metrics, &path)?,
+                ExportFormat::Sqlite => {
+                    export_filings_sqlite(records, &path)?;
+                }
+                ExportFormat::Text | ExportFormat::Markdown | ExportFormat::Html => {
+                    return Err(FinAIError::Export(format!(
+                        "{} is not supported for filings; use CSV, Excel, JSON, PDF, Parquet, or SQLite.",
+                        format.label()
+                    )));
+                }
+            }
+            log::info!("FINFILES AI: Exported {} filings as {} to {}", records.len(), format.label(), path.display());
+            Ok(path.display().to_string())
+        }
+
+        fn write_filings_csv(records: &[FilingRecord], columns: &[FilingField], path: &Path) -> Result<()> {
+            let mut writer = csv::Writer::from_path(path)
+                .map_err(|e| FinAIError::Export(format!("Failed to create CSV: {e}")))?;
+            writer
+                .write_record(columns.iter().map(|c| c.header()))
+                .map_err(|e| FinAIError::Export(format!("Failed to write CSV header: {e}")))?;
+            for rec in records {
+                writer
+                    .write_record(columns.iter().map(|c| c.value(rec)))
+                    .map_err(|e| FinAIError::Export(format!("Failed to write CSV row: {e}")))?;
+            }
+            writer.flush().map_err(|e| FinAIError::Export(format!("Failed to write CSV: {e}")))
+        }
+
+        // Serialize every FilingRecord field as a pretty-printed JSON array ("[]" when empty).
+        fn write_filings_json(records: &[FilingRecord], path: &Path) -> Result<()> {
+            let json = serde_json::to_string_pretty(records)
+                .map_err(|e| FinAIError::Export(format!("Failed to serialize filings: {e}")))?;
+            fs::write(path, json).map_err(|e| FinAIError::Export(format!("Failed to write {}: {e}", path.display())))
+        }
+
+        // PDF layout (A4 landscape, millimetres)
+        const PDF_PAGE_WIDTH: f64 = 297.0;
+        const PDF_PAGE_HEIGHT: f64 = 210.0;
+        const PDF_MARGIN: f64 = 15.0;
+        const PDF_ROW_HEIGHT: f64 = 6.0;
+        const PDF_FONT_SIZE: f64 = 9.0;
+        // (header, x offset, max characters)
+        const PDF_COLUMNS: [(&str, f64, usize); 4] = [
+            ("Form", 15.0, 12),
+            ("Date", 45.0, 12),
+            ("Company", 80.0, 70),
+            ("Filing Type", 220.0, 30),
+        ];
+
+        // Write a paginated PDF table of filings. The header row repeats on every page.
+        fn write_filings_pdf(records: &[FilingRecord], path: &Path) -> Result<()> {
+            let pdf_err = |e: printpdf::Error| FinAIError::Export(format!("PDF error: {e}"));
+            let (doc, first_page, first_layer) =
+                PdfDocument::new("FINFILES SEC Filings", Mm(PDF_PAGE_WIDTH), Mm(PDF_PAGE_HEIGHT), "Filings");
+            let font = doc.add_builtin_font(BuiltinFont::Helvetica).map_err(pdf_err)?;
+            let bold = doc.add_builtin_font(BuiltinFont::HelveticaBold).map_err(pdf_err)?;
+
+            let title_y = PDF_PAGE_HEIGHT - PDF_MARGIN;
+            let first_row_y = title_y - 2.0 * PDF_ROW_HEIGHT;
+            let rows_per_page = ((first_row_y - PDF_MARGIN) / PDF_ROW_HEIGHT).floor().max(1.0) as usize;
+            // An empty export still gets one page with the header row
+            let pages: Vec<&[FilingRecord]> = if records.is_empty() {
+                vec![&[]]
+            } else {
+                records.chunks(rows_per_page).collect()
+            };
+            let page_count = pages.len();
+
+            for (page_idx, chunk) in pages.into_iter().enumerate() {
+                let layer = if page_idx == 0 {
+                    doc.get_page(first_page).get_layer(first_layer)
+                } else {
+                    let (page, layer) = doc.add_page(Mm(PDF_PAGE_WIDTH), Mm(PDF_PAGE_HEIGHT), "Filings");
+                    doc.get_page(page).get_layer(layer)
+                };
+                layer.use_text(
+                    format!("FINFILES SEC Filings (page {} of {})", page_idx + 1, page_count),
+                    12.0,
+                    Mm(PDF_MARGIN),
+                    Mm(title_y),
+                    &bold,
+                );
+                let header: Vec<String> = PDF_COLUMNS.iter().map(|(title, _, _)| title.to_string()).collect();
+                pdf_row(&layer, &bold, &header, title_y - PDF_ROW_HEIGHT);
+                for (i, rec) in chunk.iter().enumerate() {
+                    let cells = vec![rec.form.clone(), rec.date.clone(), rec.company_name.clone(), rec.filing_type.clone()];
+                    pdf_row(&layer, &font, &cells, first_row_y - i as f64 * PDF_ROW_HEIGHT);
+                }
+            }
+
+            let file = File::create(path)
+                .map_err(|e| FinAIError::Export(format!("Failed to create {}: {e}", path.display())))?;
+            doc.save(&mut BufWriter::new(file)).map_err(pdf_err)
+        }
+
+        fn pdf_row(layer: &PdfLayerReference, font: &IndirectFontRef, cells: &[String], y: f64) {
+            for ((_, x, max_chars), cell) in PDF_COLUMNS.iter().zip(cells) {
+                let text: String = cell.chars().take(*max_chars).collect();
+                layer.use_text(text, PDF_FONT_SIZE, Mm(*x), Mm(y), font);
+            }
+        }
+
+        // Write AI analysis output to the given path. Returns the written path.
+        pub fn export_analysis(analysis: &str, format: ExportFormat, path: &Path) -> Result<String> {
+            let contents = match format {
+                ExportFormat::Text => analysis.to_string(),
+                ExportFormat::Markdown => analysis_to_markdown(analysis),
+                other => {
+                    return Err(FinAIError::Export(format!(
+                        "{} is not supported for analysis; use Text or Markdown.",
+                        other.label()
+                    )));
+                }
+            };
+            fs::write(path, contents)
+                .map_err(|e| FinAIError::Export(format!("Failed to write {}: {e}", path.display())))?;
+            log::info!("FINFILES AI: Exported analysis to {}", path.display());
+            Ok(path.display().to_string())
+        }
+
+        // Sheet names used by the Excel export
+        pub const XLSX_FILINGS_SHEET: &str = "Filings";
+        pub const XLSX_METRICS_SHEET: &str = "Metrics";
+
+        fn xlsx_error(e: XlsxError) -> FinAIError {
+            FinAIError::Export(format!("Failed to write Excel workbook: {e}"))
+        }
+
+        // Bold white-on-blue header row, frozen so it stays visible while scrolling
+        fn write_xlsx_header(sheet: &mut Worksheet, headers: &[&str]) -> std::result::Result<(), XlsxError> {
+            let header_format = Format::new()
+                .set_bold()
+                .set_font_color(Color::White)
+                .set_background_color(Color::RGB(0x1565C0));
+            for (col, header) in headers.iter().enumerate() {
+                sheet.write_string_with_format(0, col as u16, *header, &header_format)?;
+            }
+            sheet.set_freeze_panes(1, 0)?;
+            Ok(())
+        }
+
+        // Write filings (and optionally metrics) to an .xlsx workbook with auto-sized columns
+        fn write_filings_xlsx(
+            records: &[FilingRecord],
+            columns: &[FilingField],
+            metrics: Option<&DataFrame>,
+            path: &Path,
+        ) -> Result<()> {
+            filings_workbook(records, columns, metrics)?.save(path).map_err(xlsx_error)
+        }
+
+        // The Filings sheet, then a Metrics sheet when metrics are given
+        fn filings_workbook(records: &[FilingRecord], columns: &[FilingField], metrics: Option<&DataFrame>) -> Result<Workbook> {
+            let mut workbook = Workbook::new();
+
+            let sheet = workbook.add_worksheet().set_name(XLSX_FILINGS_SHEET).map_err(xlsx_error)?;
+            let headers: Vec<&str> = columns.iter().map(|c| c.header()).collect();
+            write_xlsx_header(sheet, &headers).map_err(xlsx_error)?;
+            for (i, rec) in records.iter().enumerate() {
+                let row = (i + 1) as u32;
+                for (col, field) in columns.iter().enumerate() {
+                    sheet.write_string(row, col as u16, field.value(rec)).map_err(xlsx_error)?;
+                }
+            }
+            sheet.autofit();
+
+            if let Some(df) = metrics {
+                let sheet = workbook.add_worksheet().set_name(XLSX_METRICS_SHEET).map_err(xlsx_error)?;
+                let names = df.get_column_names();
+                write_xlsx_header(sheet, &names).map_err(xlsx_error)?;
+                for (col, series) in df.get_columns().iter().enumerate() {
+                    let col = col as u16;
+                    if let Ok(values) = series.f64() {
+                        for (i, value) in values.into_iter().enumerate() {
+                            if let Some(value) = value {
+                                sheet.write_number((i + 1) as u32, col, value).map_err(xlsx_error)?;
+                            }
+                        }
+                    } else if let Ok(values) = series.utf8() {
+                        for (i, value) in values.into_iter().enumerate() {
+                            if let Some(value) = value {
+                                sheet.write_string((i + 1) as u32, col, value).map_err(xlsx_error)?;
+                            }
+                        }
+                    }
+                }
+                sheet.autofit();
+            }
+
+            Ok(workbook)
+        }
+
+        // Write a DataFrame to Parquet with Snappy compression. Returns the written path.
+        pub fn export_dataframe_parquet(df: &DataFrame, path: &Path) -> Result<String> {
+            let file = File::create(path)
+                .map_err(|e| FinAIError::Export(format!("Failed to create {}: {e}", path.display())))?;
+            let mut df = df.clone();
+            ParquetWriter::new(file)
+                .with_compression(ParquetCompression::Snappy)
+                .finish(&mut df)
+                .map_err(|e| FinAIError::Export(format!("Failed to write Parquet to {}: {e}", path.display())))?;
+            log::info!("FINFILES AI: Exported {} rows as Parquet to {}", df.height(), path.display());
+            Ok(path.display().to_string())
+        }
+
+        // `filings` table of the SQLite export. Accession numbers are unique so re-exports
+        // update rows in place; filings without one (such as the demo data) can't be matched.
+        const SQLITE_FILINGS_SCHEMA: &str = "CREATE TABLE IF NOT EXISTS filings (
+            accession TEXT UNIQUE,
+            form TEXT NOT NULL,
+            filing_date TEXT NOT NULL,
+            document TEXT NOT NULL,
+            document_url TEXT NOT NULL,
+            company_name TEXT NOT NULL,
+            filing_type TEXT NOT NULL,
+            ai_summary TEXT NOT NULL,
+            ticker TEXT NOT NULL,
+            amendment INTEGER NOT NULL
+        )";
+
+        const SQLITE_UPSERT_FILING: &str = "INSERT INTO filings
+            (accession, form, filing_date, document, document_url, company_name, filing_type, ai_summary, ticker, amendment)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+            ON CONFLICT(accession) DO UPDATE SET
+                form = excluded.form,
+                filing_date = excluded.filing_date,
+                document = excluded.document,
+                document_url = excluded.document_url,
+                company_name = excluded.company_name,
+                filing_type = excluded.filing_type,
+                ai_summary = excluded.ai_summary,
+                ticker = excluded.ticker,
+                amendment = excluded.amendment";
+
+        // Write filings to a SQLite database in a single transaction, creating the `filings`
+        // table when missing and upserting on accession number. Returns the written path.
+        pub fn export_filings_sqlite(records: &[FilingRecord], path: &Path) -> Result<String> {
+            let sqlite_err = |e: rusqlite::Error| FinAIError::Export(format!("Failed to write SQLite {}: {e}", path.display()));
+            let mut conn = Connection::open(path).map_err(sqlite_err)?;
+            conn.execute_batch(SQLITE_FILINGS_SCHEMA).map_err(sqlite_err)?;
+            let tx = conn.transaction().map_err(sqlite_err)?;
+            {
+                let mut upsert = tx.prepare(SQLITE_UPSERT_FILING).map_err(sqlite_err)?;
+                for rec in records {
+                    upsert
+                        .execute(params![
+                            rec.accession(),
+                            rec.form,
+                            rec.date,
+                            rec.document,
+                            rec.document_url,
+                            rec.company_name,
+                            rec.filing_type,
+                            rec.ai_summary,
+                            rec.ticker,
+                            rec.amendment,
+                        ])
+                        .map_err(sqlite_err)?;
+                }
+            }
+            tx.commit().map_err(sqlite_err)?;
+            log::info!("FINFILES AI: Exported {} filings to SQLite {}", records.len(), path.display());
+            Ok(path.display().to_string())
+        }
+
+        // One column per FilingRecord field: the CSV export's columns, then the ticker
+        fn filings_to_dataframe(records: &[FilingRecord]) -> Result<DataFrame> {
+            let column = |name: &str, field: fn(&FilingRecord) -> &str| {
+                Series::new(name, records.iter().map(field).collect::<Vec<&str>>())
+            };
+            DataFrame::new(vec![
+                column("form", |r| r.form.as_str()),
+                column("date", |r| r.date.as_str()),
+                column("document", |r| r.document.as_str()),
+                column("document_url", |r| r.document_url.as_str()),
+                column("company_name", |r| r.company_name.as_str()),
+                column("filing_type", |r| r.filing_type.as_str()),
+                column("ai_summary", |r| r.ai_summary.as_str()),
+                column("ticker", |r| r.ticker.as_str()),
+            ])
+            .map_err(|e| FinAIError::Export(format!("Failed to build filings table: {e}")))
+        }
+
+        // Convert the AI's plain-text output into Markdown: the first line becomes a
+        // heading and "  • " bullets become list items.
+        fn analysis_to_markdown(analysis: &str) -> String {
+            let mut out = String::from("# FINFILES AI Analysis\n\n");
+            for line in analysis.lines() {
+                let trimmed = line.trim_start();
+                if let Some(item) = trimmed.strip_prefix('•') {
+                    out.push_str(&format!("- {}\n", item.trim()));
+                } else if trimmed.is_empty() {
+                    out.push('\n');
+                } else {
+                    out.push_str(&format!("{}\n\n", trimmed));
+                }
+            }
+            out
+        }
+
+        #[cfg(test)]
+        mod tests {
+            use super::*;
+
+            #[test]
+            fn exports_never_overwrite_an_earlier_file() {
+                let dir = std::env::temp_dir().join(format!("finfiles-export-{}", std::process::id()));
+                fs::create_dir_all(&dir).unwrap();
+                let first = unused_path(&dir, "finfiles_filings_20260101-120000", "csv");
+                assert_eq!(first, dir.join("finfiles_filings_20260101-120000.csv"));
+                fs::write(&first, "").unwrap();
+                let second = unused_path(&dir, "finfiles_filings_20260101-120000", "csv");
+                assert_eq!(second, dir.join("finfiles_filings_20260101-120000_2.csv"));
+                fs::write(&second, "").unwrap();
+                assert_eq!(unused_path(&dir, "finfiles_filings_20260101-120000", "csv"), dir.join("finfiles_filings_20260101-120000_3.csv"));
+                fs::remove_dir_all(&dir).unwrap();
+            }
+
+            fn filing(form: &str, date: &str, accession: &str) -> FilingRecord {
+                FilingRecord {
+                    form: form.to_string(),
+                    date: date.to_string(),
+                    document: format!("aapl-{}.htm", date),
+                    document_url: format!(
+                        "https://www.sec.gov/Archives/edgar/data/320193/{}/aapl-{}.htm",
+                        accession.replace('-', ""),
+                        date
+                    ),
+                    company_name: "Apple Inc.".to_string(),
+                    filing_type: format!("{} report", form),
+                    ticker: "AAPL".to_string(),
+                    ..FilingRecord::default()
+                }
+            }
+
+            fn sample_records() -> Vec<FilingRecord> {
+                vec![
+                    filing("10-K", "2023-11-03", "0000320193-23-000106"),
+                    filing("10-Q", "2023-08-04", "0000320193-23-000077"),
+                    filing("8-K", "2023-08-03", "0000320193-23-000075"),
+                ]
+            }
+
+            // Fresh scratch directory for one test
+            fn test_dir(name: &str) -> PathBuf {
+                let dir = std::env::temp_dir().join(format!("finfiles-export-{}-{}", name, std::process::id()));
+                let _ = fs::remove_dir_all(&dir);
+                fs::create_dir_all(&dir).unwrap();
+                dir
+            }
+
+            #[test]
+            fn pdf_export_writes_a_pdf_document() {
+                let dir = test_dir("pdf");
+                let path = dir.join("filings.pdf");
+                write_filings_pdf(&sample_records(), &path).unwrap();
+                assert!(fs::read(&path).unwrap().starts_with(b"%PDF"));
+                let empty = dir.join("empty.pdf");
+                write_filings_pdf(&[], &empty).unwrap();
+                assert!(fs::read(&empty).unwrap().starts_with(b"%PDF"));
+                fs::remove_dir_all(&dir).unwrap();
+            }
+
+            #[test]
+            fn json_export_reads_back_as_the_same_filings() {
+                let dir = test_dir("json");
+                let path = dir.join("filings.json");
+                let records = sample_records();
+                write_filings_json(&records, &path).unwrap();
+                let read: Vec<FilingRecord> = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+                assert_eq!(read, records);
+                write_filings_json(&[], &path).unwrap();
+                assert_eq!(fs::read_to_string(&path).unwrap(), "[]");
+                fs::remove_dir_all(&dir).unwrap();
+            }
+
+            #[test]
+            fn parquet_export_reads_back_with_the_same_schema() {
+                let dir = test_dir("parquet");
+                let filings = filings_to_dataframe(&sample_records()).unwrap();
+                let metrics = df!(
+                    "quarter" => &["FY2023-Q1", "FY2023-Q2"],
+                    "revenue_usd_billions" => &[Some(117.15), None],
+                )
+                .unwrap();
+                for (name, df) in [("filings.parquet", filings), ("metrics.parquet", metrics)] {
+                    let path = dir.join(name);
+                    export_dataframe_parquet(&df, &path).unwrap();
+                    let read = ParquetReader::new(File::open(&path).unwrap()).finish().unwrap();
+                    assert_eq!(read.schema(), df.schema());
+                    assert!(read.frame_equal_missing(&df), "{} differs after read-back", name);
+                }
+                fs::remove_dir_all(&dir).unwrap();
+            }
+
+            fn sheet_names(mut workbook: Workbook) -> Vec<String> {
+                workbook.worksheets().iter().map(|sheet| sheet.name()).collect()
+            }
+
+            #[test]
+            fn xlsx_export_names_its_sheets() {
+                let metrics = df!("quarter" => &["FY2023-Q1"], "revenue_usd_billions" => &[117.15]).unwrap();
+                let with_metrics = filings_workbook(&sample_records(), &FilingField::ALL, Some(&metrics)).unwrap();
+                assert_eq!(sheet_names(with_metrics), vec![XLSX_FILINGS_SHEET, XLSX_METRICS_SHEET]);
+                let filings_only = filings_workbook(&sample_records(), &FilingField::ALL, None).unwrap();
+                assert_eq!(sheet_names(filings_only), vec![XLSX_FILINGS_SHEET]);
+
+                let dir = test_dir("xlsx");
+                let path = dir.join("filings.xlsx");
+                write_filings_xlsx(&sample_records(), &FilingField::ALL, Some(&metrics), &path).unwrap();
+                // .xlsx files are zip archives
+                assert!(fs::read(&path).unwrap().starts_with(b"PK"));
+                fs::remove_dir_all(&dir).unwrap();
+            }
+
+            #[test]
+            fn csv_export_writes_the_chosen_columns_in_order() {
+                let names: Vec<String> = ["date", "Form", "document-url"].iter().map(|n| n.to_string()).collect();
+                let columns = FilingField::parse_list(&names).unwrap();
+                assert_eq!(columns, vec![FilingField::Date, FilingField::Form, FilingField::Url]);
+
+                let dir = test_dir("columns");
+                let path = dir.join("filings.csv");
+                let records = sample_records();
+                write_filings_csv(&records, &columns, &path).unwrap();
+                let mut reader = csv::Reader::from_path(&path).unwrap();
+                assert_eq!(reader.headers().unwrap().iter().collect::<Vec<_>>(), vec!["Date", "Form", "Document URL"]);
+                let rows: Vec<Vec<String>> =
+                    reader.records().map(|row| row.unwrap().iter().map(str::to_string).collect()).collect();
+                assert_eq!(rows[0], vec![records[0].date.clone(), records[0].form.clone(), records[0].document_url.clone()]);
+                assert_eq!(rows.len(), records.len());
+                fs::remove_dir_all(&dir).unwrap();
+            }
+
+            #[test]
+            fn invalid_column_lists_are_rejected() {
+                let list = |names: &[&str]| FilingField::parse_list(&names.iter().map(|n| n.to_string()).collect::<Vec<_>>());
+                assert!(list(&[]).is_err());
+                assert!(list(&["date", "price"]).is_err());
+                assert!(list(&["date", "Date"]).is_err());
+            }
+
+            #[test]
+            fn sqlite_export_upserts_on_accession() {
+                let dir = test_dir("sqlite");
+                let path = dir.join("filings.sqlite");
+                let mut records = sample_records();
+                export_filings_sqlite(&records, &path).unwrap();
+                records[0].ai_summary = "Sentiment: positive".to_string();
+                export_filings_sqlite(&records, &path).unwrap();
+
+                let conn = Connection::open(&path).unwrap();
+                let count: i64 = conn.query_row("SELECT COUNT(*) FROM filings", [], |row| row.get(0)).unwrap();
+                assert_eq!(count, 3);
+                let (form, summary, ticker): (String, String, String) = conn
+                    .query_row(
+                        "SELECT form, ai_summary, ticker FROM filings WHERE accession = ?1",
+                        params!["0000320193-23-000106"],
+                        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+                    )
+                    .unwrap();
+                assert_eq!((form.as_str(), summary.as_str(), ticker.as_str()), ("10-K", "Sentiment: positive", "AAPL"));
+                drop(conn);
+                fs::remove_dir_all(&dir).unwrap();
+            }
+
+            #[test]
+            fn analysis_export_round_trips() {
+                let dir = test_dir("analysis");
+                let analysis = "Revenue summary\n  • Revenue: 383.29B\n  • Net income: 97.00B";
+                let text = dir.join("analysis.txt");
+                assert_eq!(export_analysis(analysis, ExportFormat::from_path(&text), &text).unwrap(), text.display().to_string());
+                assert_eq!(fs::read_to_string(&text).unwrap(), analysis);
+
+                let markdown = dir.join("analysis.md");
+                export_analysis(analysis, ExportFormat::from_path(&markdown), &markdown).unwrap();
+                assert_eq!(
+                    fs::read_to_string(&markdown).unwrap(),
+                    "# FINFILES AI Analysis\n\nRevenue summary\n\n- Revenue: 383.29B\n- Net income: 97.00B\n"
+                );
+                assert!(export_analysis(analysis, ExportFormat::Pdf, &dir.join("analysis.pdf")).is_err());
+                fs::remove_dir_all(&dir).unwrap();
+            }
+        }
+    }
+
+    pub mod cli {
+        use super::ai::{CustomModelAIModule, FinancialAIModule, FinfilesAI, OnnxAIModule, RemoteLLMAIModule};
+        use super::data_ingestion::{load_ticker_file, FinancialDataLoader, LoadStage};
+        use super::error::*;
+        use super::fixtures;
+        use super::security::{sanitize_ticker, AuthManager, Permission, User};
+        use polars::prelude::*;
+        use std::future::Future;
+        use std::path::PathBuf;
+        use std::sync::Arc;
+
+        pub const USAGE: &str = "Usage: finfiles --headless (--ticker <TICKER> | --tickers-file <PATH>) --query <QUERY> [--backend finfiles|onnx|remote|custom] [--json] [--offline]";
+
+        // Options for running one analysis from the command line without GTK
+        #[derive(Debug, Clone, PartialEq)]
+        pub struct CliOptions {
+            pub ticker: Option<String>,
+            // File of tickers, one per line; analyzed after `ticker`
+            pub tickers_file: Option<PathBuf>,
+            pub query: String,
+            pub backend: String,
+            // Print the structured AnalysisResult as JSON instead of text
+            pub json: bool,
+            // Analyze the bundled demo data instead of loading the ticker from SEC
+            pub offline: bool,
+        }
+
+        impl CliOptions {
+            // Parse command-line arguments (without the program name). Returns None unless
+            // --headless or --cli is present.
+            pub fn parse(args: &[String]) -> Result<Option<Self>> {
+                if !args.iter().any(|a| a == "--headless" || a == "--cli") {
+                    return Ok(None);
+                }
+                let mut ticker = None;
+                let mut tickers_file = None;
+                let mut query = None;
+                let mut backend = "finfiles".to_string();
+                let mut json = false;
+                let mut offline = false;
+                let mut iter = args.iter();
+                while let Some(arg) = iter.next() {
+                    let mut value = |name: &str| {
+                        iter.next()
+                            .cloned()
+                            .ok_or_else(|| FinAIError::Config(format!("Missing value for {}. {}", name, USAGE)))
+                    };
+                    match arg.as_str() {
+                        "--headless" | "--cli" => {}
+                        "--ticker" => ticker = Some(value("--ticker")?),
+                        "--tickers-file" => tickers_file = Some(PathBuf::from(value("--tickers-file")?)),
+                        "--query" => query = Some(value("--query")?),
+                        "--backend" => backend = value("--backend")?,
+                        "--json" => json = true,
+                        "--offline" => offline = true,
+                        other => return Err(FinAIError::Config(format!("Unknown argument '{}'. {}", other, USAGE))),
+                    }
+                }
+                if ticker.is_none() && tickers_file.is_none() {
+                    return Err(FinAIError::Config(format!("--ticker or --tickers-file is required. {}", USAGE)));
+                }
+                let query = query.ok_or_else(|| FinAIError::Config(format!("--query is required. {}", USAGE)))?;
+                Ok(Some(Self { ticker, tickers_file, query, backend, json, offline }))
+            }
+
+            // Tickers to analyze: --ticker, then those read from --tickers-file. Reports on
+            // stderr how many the file supplied and how many of its lines were skipped.
+            pub fn tickers(&self) -> Result<Vec<String>> {
+                let mut tickers = Vec::new();
+                if let Some(ticker) = &self.ticker {
+                    let ticker = sanitize_ticker(ticker);
+                    if ticker.is_empty() {
+                        return Err(FinAIError::Config(format!("Invalid ticker. {}", USAGE)));
+                    }
+                    tickers.push(ticker);
+                }
+                if let Some(path) = &self.tickers_file {
+                    let parsed = load_ticker_file(path)?;
+                    eprintln!(
+                        "Loaded {} tickers from {} ({} skipped)",
+                        parsed.tickers.len(),
+                        path.display(),
+                        parsed.skipped
+                    );
+                    for ticker in parsed.tickers {
+                        if !tickers.contains(&ticker) {
+                            tickers.push(ticker);
+                        }
+                    }
+                }
+                if tickers.is_empty() {
+                    return Err(FinAIError::Config("No valid tickers to analyze.".to_string()));
+                }
+                Ok(tickers)
+            }
+        }
+
+        // Flags FINFILES handles itself when starting the GUI; GTK rejects them as unknown
+        pub const APP_FLAGS: &[&str] = &["--offline"];
+
+        // Command line to hand GTK: `argv` (program name first) without FINFILES' own flags
+        pub fn toolkit_args(argv: &[String]) -> Vec<String> {
+            argv.iter()
+                .enumerate()
+                .filter(|(i, arg)| *i == 0 || !APP_FLAGS.contains(&arg.as_str()))
+                .map(|(_, arg)| arg.clone())
+                .collect()
+        }
+
+        // Permission needed to analyze with a backend: the Remote LLM sends data off the
+        // machine and needs UseRemoteLLM, the rest FetchFilings
+        pub fn backend_permission(name: &str) -> Permission {
+            match name.trim().to_lowercase().as_str() {
+                "remote" | "remotellm" => Permission::UseRemoteLLM,
+                _ => Permission::FetchFilings,
+            }
+        }
+
+        // Construct the AI backend named on the command line
+        pub fn select_backend(name: &str) -> Result<Arc<dyn FinancialAIModule>> {
+            match name.trim().to_lowercase().as_str() {
+                "finfiles" | "finfiles-ai" => Ok(Arc::new(FinfilesAI::new()?)),
+                "onnx" => Ok(Arc::new(OnnxAIModule::new()?)),
+                "remote" | "remotellm" => Ok(Arc::new(RemoteLLMAIModule::new()?)),
+                "custom" => Ok(Arc::new(CustomModelAIModule::new("CustomModel".to_string())?)),
+                other => Err(FinAIError::Config(format!("Unknown backend '{}'. {}", other, USAGE))),
+            }
+        }
+
+        // Run the query against already-loaded data with the chosen backend
+        pub async fn analyze(options: &CliOptions, df: &DataFrame) -> Result<String> {
+            let backend = select_backend(&options.backend)?;
+            if options.json {
+                let result = backend.analyze_structured(df, &options.query).await?;
+                serde_json::to_string_pretty(&result)
+                    .map_err(|e| FinAIError::DataParsing(format!("Failed to serialize analysis: {e}")))
+            } else {
+                backend.analyze(df, &options.query).await
+            }
+        }
+
+        // Load SEC data for each ticker (or the demo data when offline) and run the query
+        // as the authenticated user
+        pub async fn run(options: &CliOptions, auth: &AuthManager, user: &User) -> Result<String> {
+            let offline = options.offline;
+            run_with_loader(options, auth, user, |ticker: String| async move {
+                if offline {
+                    fixtures::sample_dataframe()
+                } else {
+                    FinancialDataLoader::load_sec_data_for_ticker(&ticker, None::<fn(LoadStage)>).await
+                }
+            })
+            .await
+        }
+
+        // As `run`, loading each ticker's data with `load`. The user needs the backend's
+        // permission, and each ticker counts against their rate limit as in the API server.
+        // With several tickers each result gets a "== TICKER ==" heading; a ticker that
+        // fails is reported in its section without stopping the rest.
+        pub async fn run_with_loader<F, Fut>(options: &CliOptions, auth: &AuthManager, user: &User, load: F) -> Result<String>
+        where
+            F: Fn(String) -> Fut,
+            Fut: Future<Output = Result<DataFrame>>,
+        {
+            let permission = backend_permission(&options.backend);
+            if !auth.has_permission(user, permission) {
+                return Err(FinAIError::PermissionDenied(format!("{} lacks the {:?} permission.", user.username, permission)));
+            }
+            let tickers = options.tickers()?;
+            if tickers.len() == 1 {
+                return run_ticker(options, auth, user, &load, &tickers[0]).await;
+            }
+            let mut sections = Vec::new();
+            for ticker in &tickers {
+                let output = run_ticker(options, auth, user, &load, ticker).await.unwrap_or_else(|e| format!("Error: {e}"));
+                sections.push(format!("== {} ==\n{}", ticker, output));
+            }
+            Ok(sections.join("\n\n"))
+        }
+
+        async fn run_ticker<F, Fut>(options: &CliOptions, auth: &AuthManager, user: &User, load: &F, ticker: &str) -> Result<String>
+        where
+            F: Fn(String) -> Fut,
+            Fut: Future<Output = Result<DataFrame>>,
+        {
+            auth.check_rate_limit(user)?;
+            let df = load(ticker.to_string()).await?;
+            analyze(options, &df).await
+        }
+
+        #[cfg(test)]
+        mod tests {
+            use super::*;
+            use crate::security::RBACRole;
+            use std::cell::Cell;
+
+            fn options(backend: &str) -> CliOptions {
+                CliOptions {
+                    ticker: Some("aapl".to_string()),
+                    tickers_file: None,
+                    query: "revenue trend".to_string(),
+                    backend: backend.to_string(),
+                    json: false,
+                    offline: false,
+                }
+            }
+
+            fn user(role: RBACRole) -> User {
+                User { username: "tester".to_string(), roles: vec![role] }
+            }
+
+            #[tokio::test]
+            async fn headless_run_analyzes_the_loaded_ticker() {
+                let auth = AuthManager::new().with_roles_path(None);
+                let loaded = Cell::new(Vec::new());
+                let load = |ticker: String| {
+                    let mut seen = loaded.take();
+                    seen.push(ticker);
+                    loaded.set(seen);
+                    async { fixtures::sample_dataframe() }
+                };
+                let output = run_with_loader(&options("finfiles"), &auth, &user(RBACRole::Viewer), load).await.unwrap();
+                assert!(!output.trim().is_empty());
+                assert_eq!(loaded.take(), vec!["AAPL".to_string()]);
+            }
+
+            #[tokio::test]
+            async fn remote_backend_needs_permission() {
+                let auth = AuthManager::new().with_roles_path(None);
+                let loads = Cell::new(0);
+                let load = |_: String| {
+                    loads.set(loads.get() + 1);
+                    async { fixtures::sample_dataframe() }
+                };
+                let result = run_with_loader(&options("remote"), &auth, &user(RBACRole::Viewer), load).await;
+                assert!(matches!(result, Err(FinAIError::PermissionDenied(_))));
+                assert_eq!(loads.get(), 0);
+            }
+
+            #[tokio::test]
+            async fn each_ticker_counts_against_the_rate_limit() {
+                let auth = AuthManager::new().with_roles_path(None);
+                let viewer = user(RBACRole::Viewer);
+                for _ in 0..viewer.fetch_limit() {
+                    auth.check_rate_limit(&viewer).unwrap();
+                }
+                let result = run_with_loader(&options("finfiles"), &auth, &viewer, |_: String| async { fixtures::sample_dataframe() }).await;
+                assert!(matches!(result, Err(FinAIError::RateLimited { .. })));
+            }
+
+            #[test]
+            fn app_flags_are_hidden_from_gtk() {
+                let argv: Vec<String> = ["finfiles", "--offline", "--gapplication-service"].iter().map(|a| a.to_string()).collect();
+                assert_eq!(toolkit_args(&argv), vec!["finfiles", "--gapplication-service"]);
+                assert_eq!(toolkit_args(&argv[..1]), vec!["finfiles"]);
+            }
+        }
+    }
+
+    pub mod server {
+        use super::ai::{is_missing_metrics, list_metrics, with_timeout, AnalysisResult, MetricInfo};
+        use super::backend::{shutdown_services, shutdown_signal, AppState, FilingRecord, SHUTDOWN_TIMEOUT};
+        use super::cli::{backend_permission, select_backend};
+        use super::config::AppConfig;
+        use super::data_ingestion::LoadStage;
+        use super::error::*;
+        use super::filters::FilingFilters;
+        use super::security::{sanitize_ticker, AuthManager, Permission, User};
+        use axum::extract::{Query, State};
+        use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+        use axum::response::{IntoResponse, Response};
+        use axum::routing::{get, post};
+        use axum::{Json, Router};
+        use serde::{Deserialize, Serialize};
+        use std::sync::Arc;
+
+        pub const USAGE: &str = "Usage: finfiles --serve [--bind <ADDR:PORT>] [--offline]";
+
+        // Options for running FINFILES as an HTTP service instead of the GUI
+        #[derive(Debug, Clone, PartialEq)]
+        pub struct ServeOptions {
+            pub bind: String,
+            // Answer every request from the bundled demo data
+            pub offline: bool,
+        }
+
+        impl ServeOptions {
+            // Parse command-line arguments (without the program name). Returns None unless
+            // --serve is present. Defaults for the address and offline mode come from the config.
+            pub fn parse(args: &[String], config: &AppConfig) -> Result<Option<Self>> {
+                if !args.iter().any(|a| a == "--serve") {
+                    return Ok(None);
+                }
+                let mut bind = config.serve_addr();
+                let mut offline = config.offline;
+                let mut iter = args.iter();
+                while let Some(arg) = iter.next() {
+                    match arg.as_str() {
+                        "--serve" => {}
+                        "--bind" => {
+                            bind = iter
+                                .next()
+                                .cloned()
+                                .ok_or_else(|| FinAIError::Config(format!("Missing value for --bind. {}", USAGE)))?
+                        }
+                        "--offline" => offline = true,
+                        other => return Err(FinAIError::Config(format!("Unknown argument '{}'. {}", other, USAGE))),
+                    }
+                }
+                Ok(Some(Self { bind, offline }))
+            }
+        }
+
+        #[derive(Clone)]
+        pub struct ServerState {
+            pub app: Arc<AppState>,
+            pub auth: Arc<AuthManager>,
+            pub config: Arc<AppConfig>,
+        }
+
+        #[derive(Debug, Deserialize)]
+        pub struct TickerQuery {
+            pub ticker: String,
+        }
+
+        #[derive(Debug, Deserialize)]
+        pub struct AnalyzeRequest {
+            pub ticker: String,
+            pub query: String,
+            // Backend name as accepted by `--backend`; FINFILES AI when omitted
+            #[serde(default)]
+            pub backend: Option<String>,
+        }
+
+        #[derive(Debug, Serialize)]
+        pub struct FilingsResponse {
+            pub ticker: String,
+            pub filings: Vec<FilingRecord>,
+        }
+
+        #[derive(Debug, Serialize)]
+        pub struct AnalyzeResponse {
+            pub ticker: String,
+            pub backend: String,
+            pub result: AnalysisResult,
+        }
+
+        #[derive(Debug, Serialize)]
+        pub struct MetricsResponse {
+            pub ticker: String,
+            pub metrics: Vec<MetricInfo>,
+        }
+
+        // FinAIError rendered as a JSON body with a matching HTTP status
+        pub struct ApiError(pub FinAIError);
+
+        impl From<FinAIError> for ApiError {
+            fn from(e: FinAIError) -> Self {
+                ApiError(e)
+            }
+        }
+
+        impl ApiError {
+            pub fn status(&self) -> StatusCode {
+                match &self.0 {
+                    FinAIError::Auth(_) => StatusCode::UNAUTHORIZED,
+                    FinAIError::PermissionDenied(_) => StatusCode::FORBIDDEN,
+                    FinAIError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+                    FinAIError::TickerNotFound(_)
+                    | FinAIError::TickerNotFoundWithSuggestions { .. }
+                    | FinAIError::SecDataNotFound(_) => StatusCode::NOT_FOUND,
+                    FinAIError::Config(_) => StatusCode::BAD_REQUEST,
+                    FinAIError::Network(_) | FinAIError::Http { .. } => StatusCode::BAD_GATEWAY,
+                    e if is_missing_metrics(e) => StatusCode::UNPROCESSABLE_ENTITY,
+                    _ => StatusCode::INTERNAL_SERVER_ERROR,
+                }
+            }
+        }
+
+        impl IntoResponse for ApiError {
+            fn into_response(self) -> Response {
+                let status = self.status();
+                let body = Json(serde_json::json!({ "error": self.0.to_string() }));
+                let mut response = (status, body).into_response();
+                if let FinAIError::RateLimited { retry_after: Some(wait) } = &self.0 {
+                    if let Ok(value) = HeaderValue::from_str(&wait.as_secs().max(1).to_string()) {
+                        response.headers_mut().insert(header::RETRY_AFTER, value);
+                    }
+                }
+                response
+            }
+        }
+
+        type ApiResult<T> = std::result::Result<Json<T>, ApiError>;
+
+        // Authenticate the request and require the permission
+        fn authorize(state: &ServerState, headers: &HeaderMap, permission: Permission) -> Result<User> {
+            let authorization = headers.get(header::AUTHORIZATION).and_then(|v| v.to_str().ok());
+            let user = state.auth.authorize_bearer(authorization)?;
+            if !state.auth.has_permission(&user, permission) {
+                return Err(FinAIError::PermissionDenied(format!("{} lacks the {:?} permission.", user.username, permission)));
+            }
+            Ok(user)
+        }
+
+        fn ticker_param(ticker: &str) -> Result<String> {
+            let ticker = sanitize_ticker(ticker);
+            if ticker.is_empty() {
+                return Err(FinAIError::Config("A valid ticker is required.".to_string()));
+            }
+            Ok(ticker)
+        }
+
+        async fn filings(
+            State(state): State<ServerState>,
+            headers: HeaderMap,
+            Query(params): Query<TickerQuery>,
+        ) -> ApiResult<FilingsResponse> {
+            let user = authorize(&state, &headers, Permission::FetchFilings)?;
+            let ticker = ticker_param(&params.ticker)?;
+            state.auth.check_rate_limit(&user)?;
+            let batch = state.app.fetch_multiple_filings(vec![ticker.clone()], FilingFilters::default()).await;
+            if let Some((_, e)) = batch.failures.into_iter().next() {
+                return Err(e.into());
+            }
+            Ok(Json(FilingsResponse { ticker, filings: batch.records }))
+        }
+
+        async fn analyze(
+            State(state): State<ServerState>,
+            headers: HeaderMap,
+            Json(request): Json<AnalyzeRequest>,
+        ) -> ApiResult<AnalyzeResponse> {
+            let backend_name = request.backend.unwrap_or_else(|| "finfiles".to_string());
+            let user = authorize(&state, &headers, backend_permission(&backend_name))?;
+            let ticker = ticker_param(&request.ticker)?;
+            state.auth.check_rate_limit(&user)?;
+            let backend = select_backend(&backend_name)?;
+            let df = state.app.load_financials(&ticker, None::<fn(LoadStage)>).await?;
+            let result =
+                with_timeout(state.config.analysis_timeout(&backend_name), backend.analyze_structured(&df, &request.query))
+                    .await?;
+            Ok(Json(AnalyzeResponse { ticker, backend: backend_name, result }))
+        }
+
+        async fn metrics(
+            State(state): State<ServerState>,
+            headers: HeaderMap,
+            Query(params): Query<TickerQuery>,
+        ) -> ApiResult<MetricsResponse> {
+            let user = authorize(&state, &headers, Permission::FetchFilings)?;
+            let ticker = ticker_param(&params.ticker)?;
+            state.auth.check_rate_limit(&user)?;
+            let df = state.app.load_financials(&ticker, None::<fn(LoadStage)>).await?;
+            Ok(Json(MetricsResponse { ticker, metrics: list_metrics(&df) }))
+        }
+
+        // Routes served by `--serve`. Every endpoint takes the API token as a bearer token.
+        pub fn router(state: ServerState) -> Router {
+            Router::new()
+                .route("/filings", get(filings))
+                .route("/analyze", post(analyze))
+                .route("/metrics", get(metrics))
+                .with_state(state)
+        }
+
+        // Listen on the configured address until Ctrl+C, then stop backend services
+        pub async fn serve(options: &ServeOptions) -> Result<()> {
+            let auth = AuthManager::new();
+            if !auth.has_api_token() {
+                return Err(FinAIError::Config(format!(
+                    "Set {} to the bearer token clients must send.",
+                    super::security::API_TOKEN_ENV
+                )));
+            }
+            let user = auth
+                .authenticate_user()
+                .ok_or_else(|| FinAIError::Auth("No user identity for the server (set FINFILES_USER).".to_string()))?;
+            let config = AppConfig::current();
+            let app = AppState::new(user)?.with_offline(options.offline);
+            let state = ServerState { app: Arc::new(app), auth: Arc::new(auth), config: Arc::new(config) };
+
+            let listener = tokio::net::TcpListener::bind(&options.bind)
+                .await
+                .map_err(|e| FinAIError::Config(format!("Failed to bind {}: {e}", options.bind)))?;
+            log::info!("Serving FINFILES API on {}", options.bind);
+            let shutdown = shutdown_signal();
+            let stopping = shutdown.clone();
+            tokio::spawn(async move {
+                if tokio::signal::ctrl_c().await.is_ok() {
+                    stopping.cancel();
+                }
+            });
+            let served = axum::serve(listener, router(state))
+                .with_graceful_shutdown(shutdown.cancelled_owned())
+                .await
+                .map_err(|e| FinAIError::Network(format!("Server error: {e}")));
+            shutdown_services(SHUTDOWN_TIMEOUT).await;
+            served
+        }
+
+        #[cfg(test)]
+        mod tests {
+            use super::*;
+            use crate::security::RBACRole;
+
+            const TOKEN: &str = "test-token";
+
+            // Serve the router on a free local port, loading data from the bundled demo
+            // frame instead of SEC. Returns the base URL.
+            async fn spawn_server(role: RBACRole) -> String {
+                let user = User { username: "tester".to_string(), roles: vec![role] };
+                let app = AppState::new(user.clone())
+                    .unwrap()
+                    .with_offline(true)
+                    .with_cache(None)
+                    .with_watchlist_path(None)
+                    .with_bookmarks_path(None);
+                let auth = AuthManager::new()
+                    .with_roles_path(None)
+                    .with_api_token(Some(TOKEN.to_string()))
+                    .with_current_user(Some(user));
+                let state = ServerState { app: Arc::new(app), auth: Arc::new(auth), config: Arc::new(AppConfig::default()) };
+                let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+                let addr = listener.local_addr().unwrap();
+                tokio::spawn(async move { axum::serve(listener, router(state)).await });
+                format!("http://{}", addr)
+            }
+
+            async fn get_json(url: &str, token: Option<&str>) -> (StatusCode, serde_json::Value) {
+                let mut request = reqwest::Client::new().get(url);
+                if let Some(token) = token {
+                    request = request.bearer_auth(token);
+                }
+                let response = request.send().await.unwrap();
+                let status = StatusCode::from_u16(response.status().as_u16()).unwrap();
+                (status, response.json().await.unwrap())
+            }
+
+            async fn post_analyze(base: &str, body: serde_json::Value) -> (StatusCode, serde_json::Value) {
+                let response = reqwest::Client::new()
+                    .post(format!("{}/analyze", base))
+                    .bearer_auth(TOKEN)
+                    .json(&body)
+                    .send()
+                    .await
+                    .unwrap();
+                let status = StatusCode::from_u16(response.status().as_u16()).unwrap();
+                (status, response.json().await.unwrap())
+            }
+
+            #[test]
+            fn errors_map_to_http_statuses() {
+                let status = |e: FinAIError| ApiError(e).status();
+                assert_eq!(status(FinAIError::Auth("Invalid bearer token.".to_string())), StatusCode::UNAUTHORIZED);
+                assert_eq!(status(FinAIError::PermissionDenied("no export".to_string())), StatusCode::FORBIDDEN);
+                assert_eq!(status(FinAIError::RateLimited { retry_after: None }), StatusCode::TOO_MANY_REQUESTS);
+                assert_eq!(status(FinAIError::TickerNotFound("ZZZZ".to_string())), StatusCode::NOT_FOUND);
+                assert_eq!(status(FinAIError::Config("bad".to_string())), StatusCode::BAD_REQUEST);
+            }
+
+            #[tokio::test]
+            async fn requests_without_a_valid_token_are_unauthorized() {
+                let base = spawn_server(RBACRole::Viewer).await;
+                let (status, _) = get_json(&format!("{}/filings?ticker=AAPL", base), None).await;
+                assert_eq!(status, StatusCode::UNAUTHORIZED);
+                let (status, body) = get_json(&format!("{}/filings?ticker=AAPL", base), Some("wrong")).await;
+                assert_eq!(status, StatusCode::UNAUTHORIZED);
+                assert!(body["error"].as_str().unwrap().contains("Invalid bearer token"));
+            }
+
+            #[tokio::test]
+            async fn filings_and_metrics_come_from_the_loader() {
+                let base = spawn_server(RBACRole::Viewer).await;
+                let (status, body) = get_json(&format!("{}/filings?ticker=aapl", base), Some(TOKEN)).await;
+                assert_eq!(status, StatusCode::OK);
+                assert_eq!(body["ticker"], "AAPL");
+                assert!(!body["filings"].as_array().unwrap().is_empty());
+
+                let (status, body) = get_json(&format!("{}/metrics?ticker=AAPL", base), Some(TOKEN)).await;
+                assert_eq!(status, StatusCode::OK);
+                assert!(!body["metrics"].as_array().unwrap().is_empty());
+            }
+
+            #[tokio::test]
+            async fn invalid_ticker_is_a_bad_request() {
+                let base = spawn_server(RBACRole::Viewer).await;
+                let (status, _) = get_json(&format!("{}/metrics?ticker=..%2Fetc", base), Some(TOKEN)).await;
+                assert_eq!(status, StatusCode::BAD_REQUEST);
+            }
+
+            #[tokio::test]
+            async fn analyze_returns_a_structured_result() {
+                let base = spawn_server(RBACRole::Viewer).await;
+                let (status, body) = post_analyze(&base, serde_json::json!({ "ticker": "AAPL", "query": "summarize" })).await;
+                assert_eq!(status, StatusCode::OK);
+                assert_eq!(body["backend"], "finfiles");
+                assert_eq!(body["result"]["mode"], "summary");
+            }
+
+            #[tokio::test]
+            async fn missing_permission_is_forbidden() {
+                let base = spawn_server(RBACRole::Viewer).await;
+                let (status, body) =
+                    post_analyze(&base, serde_json::json!({ "ticker": "AAPL", "query": "summarize", "backend": "remote" })).await;
+                assert_eq!(status, StatusCode::FORBIDDEN);
+                assert!(body["error"].as_str().unwrap().contains("UseRemoteLLM"));
+            }
+        }
+    }
+
+    pub mod chat_ui {
+        use super::ai::{
+            comparison_tickers, install_uploaded_model, is_cross_ticker_query, is_missing_metrics, with_timeout, AnalysisCache,
+            AnalysisKey, FinancialAIModule, FinfilesAI, SharedAnalysisCache, NO_METRICS_MESSAGE,
+        };
+        use super::audit::{AuditLog, AuditRecord};
+        use super::backend::{DataState, SharedFrame, SharedTickerFrames};
+        use super::cli::toolkit_args;
+        use super::config::AppConfig;
+        use super::data_ingestion::{FinancialDataLoader, LoadStage};
+        use super::export::{export_analysis, export_dataframe_parquet, ExportFormat};
+        use super::security::{Permission, RBACRole};
+        use super::error::*;
+        use chrono::{SecondsFormat, Utc};
+        use futures::StreamExt;
+        use polars::prelude::*;
+        use gtk::prelude::*;
+        use gtk::{Application, ApplicationWindow, Box as GtkBox, Button, Entry, Orientation, ScrolledWindow, TextView, Spinner, ComboBoxText, FileChooserAction, FileChooserDialog, ResponseType, ListBox, Label, SelectionMode, MessageDialog, MessageType, ButtonsType};
+        use std::cell::RefCell;
+        use std::collections::HashSet;
+        use std::rc::Rc;
+        use serde::{Deserialize, Serialize};
+        use std::fs;
+        use std::path::{Path, PathBuf};
+        use std::sync::{Arc, Mutex, RwLock};
+
+        // One chat exchange: (backend, query, response, timestamp). The timestamp is
+        // RFC 3339 UTC, empty for entries saved before timestamps were recorded.
+        pub type ChatEntry = (String, String, String, String);
+
+        // Chat history entries restored at startup unless configured otherwise
+        pub const DEFAULT_HISTORY_LIMIT: usize = 200;
+
+        // On-disk form of a chat entry
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        struct StoredChatEntry {
+            backend: String,
+            query: String,
+            response: String,
+            #[serde(default)]
+            timestamp: String,
+        }
+
+        // Default location of the persisted chat history
+        pub fn default_chat_history_path() -> Option<PathBuf> {
+            dirs::data_dir().map(|dir| dir.join("finfiles").join("chat_history.json"))
+        }
+
+        // Serialize the most recent `limit` entries as JSON
+        pub fn chat_history_to_json(history: &[ChatEntry], limit: usize) -> Result<String> {
+            let start = history.len().saturating_sub(limit);
+            let stored: Vec<StoredChatEntry> = history[start..]
+                .iter()
+                .map(|(backend, query, response, timestamp)| StoredChatEntry {
+                    backend: backend.clone(),
+                    query: query.clone(),
+                    response: response.clone(),
+                    timestamp: timestamp.clone(),
+                })
+                .collect();
+            serde_json::to_string_pretty(&stored)
+                .map_err(|e| FinAIError::Unknown(format!("Failed to serialize chat history: {e}")))
+        }
+
+        // Parse persisted history, keeping the most recent `limit` entries
+        pub fn chat_history_from_json(json: &str, limit: usize) -> Result<Vec<ChatEntry>> {
+            let stored: Vec<StoredChatEntry> = serde_json::from_str(json)
+                .map_err(|e| FinAIError::DataParsing(format!("Invalid chat history: {e}")))?;
+            let start = stored.len().saturating_sub(limit);
+            Ok(stored
+                .into_iter()
+                .skip(start)
+                .map(|e| (e.backend, e.query, e.response, e.timestamp))
+                .collect())
+        }
 
-                // Download XBRL company financials
-                let facts: CompanyFacts = client.get(&filing_url)
-                    .send().await
-                    .map_err(|e| FinAIError::Network(format!("Failed to fetch company facts: {e}")))?
-                    .json().await
-                    .map_err(|e| FinAIError::DataParsing(format!("Failed to parse company facts: {e}")))?;
+        pub fn save_chat_history(path: &Path, history: &[ChatEntry], limit: usize) -> Result<()> {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)
+                    .map_err(|e| FinAIError::Unknown(format!("Failed to create {}: {e}", parent.display())))?;
+            }
+            fs::write(path, chat_history_to_json(history, limit)?)
+                .map_err(|e| FinAIError::Unknown(format!("Failed to write {}: {e}", path.display())))
+        }
 
-                // Extract all available metrics for the last 4 quarters
-                let mut quarter_set: HashSet<String> = HashSet::new();
-                // metric (with currency) -> quarter -> value
-                let mut metric_map: HashMap<String, HashMap<String, f64>> = HashMap::new();
+        // Load persisted history; a missing or corrupt file yields an empty history
+        pub fn load_chat_history(path: &Path, limit: usize) -> Vec<ChatEntry> {
+            let json = match fs::read_to_string(path) {
+                Ok(json) => json,
+                Err(_) => return Vec::new(),
+            };
+            chat_history_from_json(&json, limit).unwrap_or_else(|e| {
+                log::warn!("Ignoring chat history at {}: {}", path.display(), e);
+                Vec::new()
+            })
+        }
 
-                if let Some(us_gaap) = facts.facts.get("us-gaap") {
-                    for (metric, fact) in us_gaap {
-                        for (currency, units) in &fact.units {
-                            for item in units {
-                                if let (Some(q), Some(val)) = (item.fiscal_period.as_ref(), item.value) {
-                                    quarter_set.insert(q.clone());
-                                    let metric_key = format!("{}_{}", metric, currency);
-                                    metric_map.entry(metric_key)
-                                        .or_default()
-                                        .insert(q.clone(), val / 1_000_000_000.0); // billions
-                                }
-                            }
-                        }
-                    }
+        // Readable Markdown record of a chat session: one "## Exchange N" block per entry
+        // with its backend, timestamp, prompt and response
+        pub fn chat_transcript_markdown(history: &[ChatEntry]) -> String {
+            let mut out = String::from("# FINFILES AI Chat Transcript\n");
+            for (i, (backend, query, response, timestamp)) in history.iter().enumerate() {
+                out.push_str(&format!("\n## Exchange {}\n\n", i + 1));
+                out.push_str(&format!("- **Backend:** {}\n", backend));
+                if !timestamp.is_empty() {
+                    out.push_str(&format!("- **Time:** {}\n", timestamp));
+                }
+                out.push_str("\n**Prompt:**\n\n");
+                for line in query.lines() {
+                    out.push_str(&format!("> {}\n", line));
                 }
+                out.push_str(&format!("\n**Response:**\n\n{}\n", response.trim_end()));
+            }
+            out
+        }
 
-                let mut quarters: Vec<String> = quarter_set.into_iter().collect();
-                quarters.sort_by(|a, b| b.cmp(a)); // Descending (most recent first)
-                let quarters = quarters.into_iter().take(4).collect::<Vec<_>>();
+        fn escape_html(text: &str) -> String {
+            text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+        }
 
-                if quarters.is_empty() {
-                    return Err(FinAIError::SecDataNotFound(ticker.to_string()));
+        // Standalone HTML form of `chat_transcript_markdown`; responses keep their line
+        // breaks and table alignment
+        pub fn chat_transcript_html(history: &[ChatEntry]) -> String {
+            let mut out = String::from(
+                "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>FINFILES AI Chat Transcript</title>\n</head>\n<body>\n<h1>FINFILES AI Chat Transcript</h1>\n",
+            );
+            for (i, (backend, query, response, timestamp)) in history.iter().enumerate() {
+                out.push_str(&format!("<h2>Exchange {}</h2>\n<ul>\n<li><b>Backend:</b> {}</li>\n", i + 1, escape_html(backend)));
+                if !timestamp.is_empty() {
+                    out.push_str(&format!("<li><b>Time:</b> {}</li>\n", escape_html(timestamp)));
                 }
+                out.push_str(&format!(
+                    "</ul>\n<p><b>Prompt:</b></p>\n<blockquote>{}</blockquote>\n<p><b>Response:</b></p>\n<pre>{}</pre>\n",
+                    escape_html(query),
+                    escape_html(response.trim_end())
+                ));
+            }
+            out.push_str("</body>\n</html>\n");
+            out
+        }
 
-                // Build DataFrame columns dynamically
-                let mut columns: Vec<Series> = Vec::new();
-                columns.push(Series::new("quarter", &quarters));
+        // Write the transcript as Markdown, or HTML for .html paths
+        pub fn export_chat_transcript(history: &[ChatEntry], format: ExportFormat, path: &Path) -> Result<String> {
+            let contents = match format {
+                ExportFormat::Html => chat_transcript_html(history),
+                _ => chat_transcript_markdown(history),
+            };
+            fs::write(path, contents)
+                .map_err(|e| FinAIError::Export(format!("Failed to write {}: {e}", path.display())))?;
+            log::info!("FINFILES AI: Exported {} chat exchanges to {}", history.len(), path.display());
+            Ok(path.display().to_string())
+        }
 
-                // Include all available financial metrics 
-                let preferred_metrics: Vec<&str> = metric_map.keys().map(|k| k.as_str()).collect();
-                let mut included_metrics = Vec::new();
+        // Bold/monospace spans within one escaped line: **bold** and `code`. Unbalanced
+        // markers are left as typed.
+        fn inline_markdown_to_pango(escaped: &str) -> String {
+            let bold: Vec<&str> = escaped.split("**").collect();
+            let parts: Vec<String> = if bold.len() % 2 == 1 {
+                bold.iter()
+                    .enumerate()
+                    .map(|(i, part)| if i % 2 == 1 { format!("<b>{}</b>", part) } else { part.to_string() })
+                    .collect()
+            } else {
+                vec![escaped.to_string()]
+            };
+            parts
+                .iter()
+                .map(|part| {
+                    let code: Vec<&str> = part.split('`').collect();
+                    if code.len() % 2 == 0 {
+                        return part.clone();
+                    }
+                    code.iter()
+                        .enumerate()
+                        .map(|(i, c)| if i % 2 == 1 { format!("<tt>{}</tt>", c) } else { c.to_string() })
+                        .collect()
+                })
+                .collect()
+        }
 
-                for metric in &preferred_metrics {
-                    if let Some(qmap) = metric_map.get(*metric) {
-                        let vals: Vec<f64> = quarters.iter().map(|q| qmap.get(q).copied().unwrap_or(0.0)).collect();
-                        columns.push(Series::new(metric, vals));
-                        included_metrics.push(metric.to_string());
+        // Tables (polars output, pipe tables, space-aligned columns) render in monospace
+        fn is_table_line(line: &str) -> bool {
+            line.trim_start().starts_with('|')
+                || line.chars().any(|c| matches!(c, '│' | '┆' | '─' | '┌' | '└' | '╞' | '═'))
+                || line.trim().contains("   ")
+        }
+
+        // Convert the AI's lightweight Markdown into Pango markup: "# " headings, bullet
+        // items ("•", "-", "*"), **bold**, `code`, label lines ending in ':' and tables.
+        pub fn markdown_to_pango(text: &str) -> String {
+            text.lines()
+                .map(|line| {
+                    let trimmed = line.trim_start();
+                    let bullet = trimmed
+                        .strip_prefix("• ")
+                        .or_else(|| trimmed.strip_prefix("- "))
+                        .or_else(|| trimmed.strip_prefix("* "));
+                    if let Some(heading) = trimmed.strip_prefix("# ") {
+                        format!("<b><big>{}</big></b>", glib::markup_escape_text(heading))
+                    } else if let Some(item) = bullet {
+                        format!("  • {}", inline_markdown_to_pango(&glib::markup_escape_text(item)))
+                    } else if is_table_line(line) {
+                        format!("<tt>{}</tt>", glib::markup_escape_text(line))
+                    } else if trimmed.ends_with(':') {
+                        format!("<b>{}</b>", inline_markdown_to_pango(&glib::markup_escape_text(line)))
+                    } else {
+                        inline_markdown_to_pango(&glib::markup_escape_text(line))
                     }
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+
+        // Append a response to the chat buffer as formatted text, falling back to plain
+        // text if the generated markup doesn't parse
+        fn insert_rendered(buffer: &gtk::TextBuffer, text: &str) {
+            let markup = markdown_to_pango(text);
+            if pango::parse_markup(&markup, '\0').is_ok() {
+                buffer.insert_markup(&mut buffer.end_iter(), &markup);
+            } else {
+                buffer.insert(&mut buffer.end_iter(), text);
+            }
+        }
+
+        // The AI answer (without the user prompt) for the history entry at `index`
+        pub fn response_text(history: &[ChatEntry], index: usize) -> Option<String> {
+            history.get(index).map(|(_, _, response, _)| response.clone())
+        }
+
+        // Add a history panel row for one exchange, with a button that copies its answer.
+        // Rows and history entries share indices, so the row's position picks the entry.
+        fn append_history_row(list: &ListBox, history: &Rc<RefCell<Vec<ChatEntry>>>, backend: &str, query: &str) {
+            let row = gtk::ListBoxRow::new();
+            let row_box = GtkBox::new(Orientation::Horizontal, 5);
+            let label = Label::new(Some(&format!("{}: {}", backend, query)));
+            label.set_hexpand(true);
+            label.set_xalign(0.0);
+            let copy_button = Button::with_label("Copy");
+            copy_button.set_accessible_name(Some("Copy Response Button"));
+            copy_button.set_can_focus(true);
+            row_box.append(&label);
+            row_box.append(&copy_button);
+            row.set_child(Some(&row_box));
+            list.append(&row);
+
+            let history = history.clone();
+            let row_copy = row.clone();
+            copy_button.connect_clicked(move |button| {
+                let index = row_copy.index();
+                if index < 0 { return; }
+                if let Some(text) = response_text(&history.borrow(), index as usize) {
+                    button.clipboard().set_text(&text);
                 }
-                // Add any other metrics found 
-                for (metric, qmap) in &metric_map {
-                    if included_metrics.contains(metric) { continue; }
-                    let vals: Vec<f64> = quarters.iter().map(|q| qmap.get(q).copied().unwrap_or(0.0)).collect();
-                    columns.push(Series::new(metric, vals));
-                }
+            });
+        }
 
-                let df = DataFrame::new(columns)
-                    .map_err(|e| FinAIError::DataParsing(format!("Failed to build DataFrame: {e}")))?;
-                Ok(df)
+        // Backend picked by Ctrl+<digit>: 1 is the first backend, wrapping around when
+        // there are fewer backends than the digit. 0 and non-digits pick nothing.
+        pub fn backend_shortcut_index(digit: u32, backends: usize) -> Option<usize> {
+            if !(1..=9).contains(&digit) || backends == 0 {
+                return None;
             }
+            Some((digit as usize - 1) % backends)
         }
-    }
 
-    pub mod chat_ui {
-        use super::ai::{FinancialAIModule, CustomModelAIModule};
-        use super::error::*;
-        use polars::prelude::*;
-        use gtk::prelude::*;
-        use gtk::{Application, ApplicationWindow, Box as GtkBox, Button, Entry, Orientation, ScrolledWindow, TextView, Spinner, ComboBoxText, FileChooserAction, FileChooserDialog, ResponseType, ListBox, Label, SelectionMode, MessageDialog, MessageType, ButtonsType};
-        use std::cell::RefCell;
-        use std::rc::Rc;
-        use std::fs::OpenOptions;
-        use std::io::Write;
-        use std::path::PathBuf;
-        use std::sync::Arc;
+        // Show the active backend in the window title and tell assistive technology about it
+        fn announce_backend(window: &ApplicationWindow, combo: &ComboBoxText) {
+            if let Some(name) = combo.active_text() {
+                window.set_title(Some(&format!("FINFILES AI: Financial Data AI Chat ({})", name)));
+                combo.set_accessible_name(Some(&format!("Backend Selection: {}", name)));
+            }
+        }
+
+        // Empty the in-memory chat history, returning how many entries were dropped.
+        // The on-disk audit log is never touched.
+        pub fn clear_chat_history(history: &mut Vec<ChatEntry>) -> usize {
+            let cleared = history.len();
+            history.clear();
+            cleared
+        }
 
         pub struct FinancialAIChatApp {
             ai_modules: Vec<Arc<dyn FinancialAIModule>>,
-            data: DataFrame,
+            // Read at each query, so swapping the frame changes what the next query analyzes
+            data: SharedFrame,
+            // Every loaded ticker's financials, for "which of my tickers ..." questions
+            ticker_frames: SharedTickerFrames,
+            // The app's data state; while nothing is loaded, queries get its message instead
+            data_state: Option<tokio::sync::watch::Receiver<DataState>>,
             audit_log_path: PathBuf,
             username: String,
+            permissions: HashSet<Permission>,
+            history_path: Option<PathBuf>,
+            history_limit: usize,
+            analysis_cache: SharedAnalysisCache,
         }
 
         impl FinancialAIChatApp {
             pub fn new(ai_modules: Vec<Arc<dyn FinancialAIModule>>, data: DataFrame, audit_log_path: PathBuf, username: String) -> Self {
-                Self { ai_modules, data, audit_log_path, username }
+                // Nothing is permitted until with_permissions grants the user's permissions
+                let permissions = HashSet::new();
+                Self {
+                    ai_modules,
+                    data: Arc::new(RwLock::new(data)),
+                    ticker_frames: Arc::new(RwLock::new(Vec::new())),
+                    data_state: None,
+                    audit_log_path,
+                    username,
+                    permissions,
+                    history_path: default_chat_history_path(),
+                    history_limit: DEFAULT_HISTORY_LIMIT,
+                    analysis_cache: Arc::new(Mutex::new(AnalysisCache::default())),
+                }
+            }
+
+            // Share cached responses with the rest of the app (e.g. `AppState::analysis_cache`)
+            pub fn with_analysis_cache(mut self, cache: SharedAnalysisCache) -> Self {
+                self.analysis_cache = cache;
+                self
+            }
+
+            // Analyze a frame shared with the rest of the app (e.g. `AppState::ai_data`)
+            pub fn with_shared_data(mut self, data: SharedFrame) -> Self {
+                self.data = data;
+                self
+            }
+
+            // Follow the app's data state (`AppState::subscribe_data_state`)
+            pub fn with_data_state(mut self, data_state: tokio::sync::watch::Receiver<DataState>) -> Self {
+                self.data_state = Some(data_state);
+                self
+            }
+
+            // Answer cross-ticker questions from frames shared with the app (`AppState::ticker_frames`)
+            pub fn with_ticker_frames(mut self, frames: SharedTickerFrames) -> Self {
+                self.ticker_frames = frames;
+                self
+            }
+
+            // Persist chat history at the given path (None disables persistence)
+            pub fn with_history_path(mut self, path: Option<PathBuf>) -> Self {
+                self.history_path = path;
+                self
+            }
+
+            // Cap how many entries are kept and restored (at least 1)
+            pub fn with_history_limit(mut self, limit: usize) -> Self {
+                self.history_limit = limit.max(1);
+                self
+            }
+
+            // Restrict chat actions (model upload, remote LLM) to the given permissions
+            pub fn with_permissions(mut self, permissions: HashSet<Permission>) -> Self {
+                self.permissions = permissions;
+                self
             }
 
             pub fn run(&self) {
@@ -1117,8 +13079,14 @@ fn build_main_window(app: &Application, state: Arc<AppState>, auth: Arc<AuthMana
 
                 let ai_modules = Rc::new(RefCell::new(self.ai_modules.clone()));
                 let data = self.data.clone();
+                let ticker_frames = self.ticker_frames.clone();
+                let data_state = self.data_state.clone();
                 let audit_log_path = self.audit_log_path.clone();
                 let username = self.username.clone();
+                let permissions = self.permissions.clone();
+                let history_path = self.history_path.clone();
+                let history_limit = self.history_limit;
+                let analysis_cache = self.analysis_cache.clone();
 
                 app.connect_activate(move |app| {
                     let window = ApplicationWindow::builder()
@@ -1161,8 +13129,24 @@ fn build_main_window(app: &Application, state: Arc<AppState>, auth: Arc<AuthMana
                     for module in ai_modules.borrow().iter() {
                         backend_combo.append_text(module.backend_name());
                     }
-                    backend_combo.set_active(Some(0));
-                    backend_combo.set_accessible_name(Some("Backend Selection"));
+                    // Preselect the configured backend and remember the user's choice
+                    let preferred = AppConfig::current().ai_backend;
+                    let preferred_idx = preferred
+                        .as_deref()
+                        .and_then(|name| ai_modules.borrow().iter().position(|m| m.backend_name().eq_ignore_ascii_case(name)))
+                        .unwrap_or(0);
+                    backend_combo.set_active(Some(preferred_idx as u32));
+                    announce_backend(&window, &backend_combo);
+                    let window_backend = window.clone();
+                    backend_combo.connect_changed(move |combo| {
+                        announce_backend(&window_backend, combo);
+                        if let Some(name) = combo.active_text() {
+                            let mut config = AppConfig::current();
+                            if let Err(e) = config.update(|c| c.ai_backend = Some(name.to_string())) {
+                                log::error!("Failed to save config: {}", e);
+                            }
+                        }
+                    });
                     backend_combo.set_can_focus(true);
 
                     // Spinner for loading
@@ -1174,10 +13158,45 @@ fn build_main_window(app: &Application, state: Arc<AppState>, auth: Arc<AuthMana
                     save_button.set_accessible_name(Some("Save Data Button"));
                     save_button.set_can_focus(true);
 
+                    // Format used by "Save Data"
+                    let save_format_combo = ComboBoxText::new();
+                    save_format_combo.append_text(ExportFormat::Csv.label());
+                    save_format_combo.append_text(ExportFormat::Parquet.label());
+                    save_format_combo.set_active(Some(0));
+                    save_format_combo.set_accessible_name(Some("Save Data Format"));
+                    save_format_combo.set_can_focus(true);
+
                     // Upload custom model button
                     let upload_button = Button::with_label("Upload Model");
                     upload_button.set_accessible_name(Some("Upload Model Button"));
                     upload_button.set_can_focus(true);
+                    upload_button.set_sensitive(permissions.contains(&Permission::UploadModel));
+
+                    // Export the latest AI response
+                    let export_analysis_button = Button::with_label("Export Analysis");
+                    export_analysis_button.set_accessible_name(Some("Export Analysis Button"));
+                    export_analysis_button.set_can_focus(true);
+
+                    // Export the whole session as Markdown or HTML
+                    let export_transcript_button = Button::with_label("Export Transcript");
+                    export_transcript_button.set_accessible_name(Some("Export Transcript Button"));
+                    export_transcript_button.set_can_focus(true);
+
+                    // Clear the visible chat and in-memory history
+                    let clear_button = Button::with_label("Clear");
+                    clear_button.set_accessible_name(Some("Clear Chat Button"));
+                    clear_button.set_can_focus(true);
+
+                    // Forget cached analysis responses so the next queries recompute
+                    let clear_cache_button = Button::with_label("Clear Cache");
+                    clear_cache_button.set_accessible_name(Some("Clear Analysis Cache Button"));
+                    clear_cache_button.set_can_focus(true);
+
+                    // Audit log viewer (decrypts the log when encryption is configured)
+                    let audit_button = Button::with_label("Audit Log");
+                    audit_button.set_accessible_name(Some("View Audit Log Button"));
+                    audit_button.set_can_focus(true);
+                    audit_button.set_visible(permissions.contains(&Permission::ViewAuditLog));
 
                     // History panel
                     let history_list = ListBox::new();
@@ -1203,8 +13222,14 @@ fn build_main_window(app: &Application, state: Arc<AppState>, auth: Arc<AuthMana
                     hbox.append(&user_input);
                     hbox.append(&send_button);
                     hbox.append(&spinner);
+                    hbox.append(&save_format_combo);
                     hbox.append(&save_button);
                     hbox.append(&upload_button);
+                    hbox.append(&export_analysis_button);
+                    hbox.append(&export_transcript_button);
+                    hbox.append(&clear_button);
+                    hbox.append(&clear_cache_button);
+                    hbox.append(&audit_button);
 
                     chat_vbox.append(&hbox);
                     hsplit.append(&chat_vbox);
@@ -1220,6 +13245,8 @@ fn build_main_window(app: &Application, state: Arc<AppState>, auth: Arc<AuthMana
                     // State
                     let chat_history_clone = chat_history.clone();
                     let data_clone = data.clone();
+                    let ticker_frames_clone = ticker_frames.clone();
+                    let data_state_clone = data_state.clone();
                     let ai_modules = ai_modules.clone();
                     let backend_combo = backend_combo.clone();
                     let user_input = user_input.clone();
@@ -1229,11 +13256,44 @@ fn build_main_window(app: &Application, state: Arc<AppState>, auth: Arc<AuthMana
                     let username = username.clone();
 
                     // Store chat history
-                    let chat_history_vec = Rc::new(RefCell::new(Vec::<(String, String, String)>::new())); 
+                    let chat_history_vec = Rc::new(RefCell::new(Vec::<ChatEntry>::new()));
+
+                    // Restore persisted history into the chat view and history panel
+                    if let Some(path) = history_path.as_ref() {
+                        let restored = load_chat_history(path, history_limit);
+                        if let Some(buffer) = chat_history_clone.buffer() {
+                            for (backend, query, response, _) in &restored {
+                                buffer.insert_at_cursor(&format!("User ({}): {}\nFINFILES AI: ", backend, query));
+                                insert_rendered(&buffer, response);
+                                buffer.insert_at_cursor("\n");
+                            }
+                        }
+                        *chat_history_vec.borrow_mut() = restored.clone();
+                        for (backend, query, _, _) in &restored {
+                            append_history_row(&history_list.borrow(), &chat_history_vec, backend, query);
+                        }
+                    }
+
+                    let audit_log_path_clear = audit_log_path.clone();
+                    let username_clear = username.clone();
+                    let history_path_clear = history_path.clone();
+
+                    // Repeated questions about unchanged data are answered from here
+                    let analysis_cache = analysis_cache.clone();
+                    {
+                        let analysis_cache = analysis_cache.clone();
+                        clear_cache_button.connect_clicked(move |_| {
+                            let removed = analysis_cache.lock().unwrap().clear();
+                            log::info!("Cleared {} cached analysis responses", removed);
+                        });
+                    }
 
                     // Send button logic
+                    let analysis_cache_send = analysis_cache.clone();
                     let chat_history_vec2 = chat_history_vec.clone();
                     let history_list2 = history_list.clone();
+                    let permissions_send = permissions.clone();
+                    let history_path_send = history_path.clone();
                     send_button.connect_clicked(move |_| {
                         let input_text = user_input.text().to_string();
                         if input_text.trim().is_empty() { return; }
@@ -1257,7 +13317,21 @@ fn build_main_window(app: &Application, state: Arc<AppState>, auth: Arc<AuthMana
                                 return;
                             }
                         };
-                        let data = data_clone.clone();
+                        if ai_module.backend_name() == "RemoteLLM" && !permissions_send.contains(&Permission::UseRemoteLLM) {
+                            spinner.stop();
+                            let dialog = MessageDialog::new(
+                                Some(&window),
+                                gtk::DialogFlags::MODAL,
+                                MessageType::Error,
+                                ButtonsType::Ok,
+                                "Your role is not permitted to use the Remote LLM backend.",
+                            );
+                            dialog.run_async(|d, _| d.close());
+                            return;
+                        }
+                        let data = data_clone.read().unwrap().clone();
+                        let frames = ticker_frames_clone.read().unwrap().clone();
+                        let unavailable = data_state_clone.as_ref().and_then(|rx| rx.borrow().message());
                         let chat_history_clone = chat_history_clone.clone();
                         let user_input = user_input.clone();
                         let spinner = spinner.clone();
@@ -1265,10 +13339,116 @@ fn build_main_window(app: &Application, state: Arc<AppState>, auth: Arc<AuthMana
                         let history_list = history_list2.clone();
                         let audit_log_path = audit_log_path.clone();
                         let username = username.clone();
+                        let history_path = history_path_send.clone();
+                        let analysis_cache = analysis_cache_send.clone();
+                        let timeout = AppConfig::current().analysis_timeout(ai_module.backend_name());
 
                         glib::MainContext::default().spawn_local(async move {
-                            let response = match ai_module.analyze(&data, &input_text).await {
+                            // "Compare ..." with several comma-separated tickers switches to
+                            // multi-ticker comparison; "which of my tickers ..." checks every loaded ticker
+                            let compare_tickers = comparison_tickers(&input_text);
+                            let cross_ticker = compare_tickers.is_empty() && is_cross_ticker_query(&input_text) && !frames.is_empty();
+                            // Show how the built-in backend read the query, to help users rephrase
+                            let interpreted = if ai_module.backend_name() == FinfilesAI.backend_name() && compare_tickers.is_empty() && !cross_ticker {
+                                format!("(interpreted as: {}) ", FinfilesAI.explain(&input_text, &data))
+                            } else {
+                                String::new()
+                            };
+                            let prompt = format!("User ({}): {}\nFINFILES AI: {}", ai_module.backend_name(), input_text, interpreted);
+                            // Set once the prompt has been written and chunks are being appended
+                            let mut streamed = false;
+                            // Where the streamed (plain) response starts, so it can be re-rendered
+                            let mut response_start: Option<gtk::TextMark> = None;
+
+                            let cache_key = AnalysisKey::new(ai_module.backend_name(), &input_text, &data);
+                            let cached = analysis_cache.lock().unwrap().get(&cache_key);
+                            let single_ticker = compare_tickers.is_empty() && !cross_ticker;
+                            let result = if let Some(message) = unavailable.filter(|_| single_ticker) {
+                                Ok(format!("FINFILES AI: {}", message))
+                            } else if let Some(cached) = cached.filter(|_| single_ticker) {
+                                Ok(cached)
+                            } else if cross_ticker {
+                                FinfilesAI.rank_trends(&frames, &input_text)
+                            } else if compare_tickers.is_empty() {
+                                // The timeout covers the whole response; chunks already shown are kept
+                                let streamed_result = with_timeout(timeout, async {
+                                    match ai_module.analyze_stream(&data, &input_text).await {
+                                        Ok(mut chunks) => {
+                                            if let Some(buffer) = chat_history_clone.buffer() {
+                                                buffer.insert_at_cursor(&prompt);
+                                                response_start = Some(buffer.create_mark(None, &buffer.end_iter(), true));
+                                            }
+                                            streamed = true;
+                                            let mut text = String::new();
+                                            let mut failure = None;
+                                            while let Some(chunk) = chunks.next().await {
+                                                match chunk {
+                                                    Ok(chunk) => {
+                                                        if let Some(buffer) = chat_history_clone.buffer() {
+                                                            buffer.insert_at_cursor(&chunk);
+                                                        }
+                                                        text.push_str(&chunk);
+                                                    }
+                                                    Err(e) => {
+                                                        failure = Some(e);
+                                                        break;
+                                                    }
+                                                }
+                                            }
+                                            match failure {
+                                                Some(e) => Err(e),
+                                                None => Ok(text),
+                                            }
+                                        }
+                                        Err(e) => Err(e),
+                                    }
+                                })
+                                .await;
+                                if let Ok(text) = &streamed_result {
+                                    analysis_cache.lock().unwrap().put(cache_key, text.clone());
+                                }
+                                streamed_result
+                            } else {
+                                with_timeout(timeout, async {
+                                    let (known, unknown) = FinancialDataLoader::partition_known_tickers(&compare_tickers).await?;
+                                    let mut frames = Vec::new();
+                                    for ticker in &known {
+                                        match FinancialDataLoader::load_sec_data_for_ticker(ticker, None::<fn(LoadStage)>).await {
+                                            Ok(df) => frames.push((ticker.clone(), df)),
+                                            Err(e) => log::warn!("Comparison: skipping {}: {}", ticker, e),
+                                        }
+                                    }
+                                    let compare_key = AnalysisKey::for_frames(ai_module.backend_name(), &input_text, &frames);
+                                    let compare_cached = analysis_cache.lock().unwrap().get(&compare_key);
+                                    let mut response = match compare_cached {
+                                        Some(cached) => cached,
+                                        None => {
+                                            // The built-in backend aligns and ranks the tickers; others
+                                            // analyze each ticker's financials in turn
+                                            let response = if ai_module.backend_name() == FinfilesAI.backend_name() {
+                                                FinfilesAI.compare(&frames, &input_text)?
+                                            } else {
+                                                let mut sections = Vec::new();
+                                                for (ticker, df) in &frames {
+                                                    sections.push(format!("{}:\n{}", ticker, ai_module.analyze(df, &input_text).await?));
+                                                }
+                                                sections.join("\n\n")
+                                            };
+                                            analysis_cache.lock().unwrap().put(compare_key, response.clone());
+                                            response
+                                        }
+                                    };
+                                    if !unknown.is_empty() {
+                                        response.push_str(&format!("\n\nSkipped unknown tickers: {}", unknown.join(", ")));
+                                    }
+                                    Ok(response)
+                                })
+                                .await
+                            };
+                            let result_failed = result.is_err();
+                            let response = match result {
                                 Ok(r) => r,
+                                Err(e) if is_missing_metrics(&e) => NO_METRICS_MESSAGE.to_string(),
                                 Err(e) => {
                                     log::error!("AI analysis error: {:?}", e);
                                     let dialog = MessageDialog::new(
@@ -1283,22 +13463,45 @@ fn build_main_window(app: &Application, state: Arc<AppState>, auth: Arc<AuthMana
                                 }
                             };
                             if let Some(buffer) = chat_history_clone.buffer() {
-                                buffer.insert_at_cursor(&format!("User ({}): {}\nFINFILES AI: {}\n", ai_module.backend_name(), input_text, response));
+                                if streamed && result_failed {
+                                    // Keep the partial chunks and add the error after them
+                                    buffer.insert_at_cursor(&format!("\n{}\n", response));
+                                } else {
+                                    if let Some(mark) = response_start.as_ref() {
+                                        // Swap the plain streamed text for the formatted response
+                                        let mut start = buffer.iter_at_mark(mark);
+                                        let mut end = buffer.end_iter();
+                                        buffer.delete(&mut start, &mut end);
+                                        buffer.delete_mark(mark);
+                                    } else {
+                                        buffer.insert_at_cursor(&prompt);
+                                    }
+                                    insert_rendered(&buffer, &response);
+                                    buffer.insert_at_cursor("\n");
+                                }
                             }
                             user_input.set_text("");
 
-                            // Add to history panel
-                            let row = gtk::ListBoxRow::new();
-                            let label = Label::new(Some(&format!("{}: {}", ai_module.backend_name(), input_text)));
-                            row.set_child(Some(&label));
-                            history_list.borrow().append(&row);
+                            // Store in chat history vector and add to history panel
+                            chat_history_vec.borrow_mut().push((
+                                ai_module.backend_name().to_string(),
+                                input_text.clone(),
+                                response.clone(),
+                                Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+                            ));
+                            append_history_row(&history_list.borrow(), &chat_history_vec, ai_module.backend_name(), &input_text);
 
-                            // Store in chat history vector
-                            chat_history_vec.borrow_mut().push((ai_module.backend_name().to_string(), input_text.clone(), response.clone()));
+                            // Persist chat history
+                            if let Some(path) = history_path.as_ref() {
+                                if let Err(e) = save_chat_history(path, &chat_history_vec.borrow(), history_limit) {
+                                    log::warn!("Failed to save chat history: {}", e);
+                                }
+                            }
 
                             // Audit log
-                            if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(&audit_log_path) {
-                                let _ = writeln!(file, "[{}][user:{}] User: {}\nAI: {}\n", ai_module.backend_name(), username, input_text, response);
+                            let record = AuditRecord::new(&username, ai_module.backend_name(), "query", &input_text).with_response(&response);
+                            if let Err(e) = AuditLog::from_env(audit_log_path.clone()).record(&record) {
+                                log::warn!("Failed to write audit log: {}", e);
                             }
 
                             spinner.stop();
@@ -1308,23 +13511,122 @@ fn build_main_window(app: &Application, state: Arc<AppState>, auth: Arc<AuthMana
                     // Save button logic
                     let data_for_save = data.clone();
                     save_button.connect_clicked(move |_| {
+                        let format = match save_format_combo.active() {
+                            Some(1) => ExportFormat::Parquet,
+                            _ => ExportFormat::Csv,
+                        };
                         let dialog = FileChooserDialog::new(
                             Some("Save Data As"),
                             Some(&window),
                             FileChooserAction::Save,
                             &[("Cancel", ResponseType::Cancel), ("Save", ResponseType::Accept)],
                         );
-                        dialog.set_current_name("finfiles_ai_data.csv");
+                        dialog.set_current_name(&format!("finfiles_ai_data.{}", format.extension()));
+                        let data_for_save = data_for_save.clone();
                         dialog.run_async(move |dialog, resp| {
                             if resp == ResponseType::Accept {
                                 if let Some(path) = dialog.file().and_then(|f| f.path()) {
-                                    if let Err(e) = data_for_save.write_csv(&path) {
+                                    let data_for_save = data_for_save.read().unwrap().clone();
+                                    let saved = match format {
+                                        ExportFormat::Parquet => export_dataframe_parquet(&data_for_save, &path).map(|_| ()),
+                                        _ => data_for_save.write_csv(&path).map_err(|e| FinAIError::Export(e.to_string())),
+                                    };
+                                    if let Err(e) = saved {
                                         let err_dialog = MessageDialog::new(
                                             Some(&window),
                                             gtk::DialogFlags::MODAL,
                                             MessageType::Error,
                                             ButtonsType::Ok,
-                                            &format!("Failed to save CSV: {e}"),
+                                            &format!("Failed to save {}: {e}", format.label()),
+                                        );
+                                        err_dialog.run_async(|d, _| d.close());
+                                    }
+                                }
+                            }
+                            dialog.close();
+                        });
+                    });
+
+                    // Export analysis logic
+                    let chat_history_vec_export = chat_history_vec.clone();
+                    let window_export = window.clone();
+                    export_analysis_button.connect_clicked(move |_| {
+                        let latest = chat_history_vec_export.borrow().last().map(|(_, _, response, _)| response.clone());
+                        let analysis = match latest {
+                            Some(analysis) => analysis,
+                            None => {
+                                let dialog = MessageDialog::new(
+                                    Some(&window_export),
+                                    gtk::DialogFlags::MODAL,
+                                    MessageType::Info,
+                                    ButtonsType::Ok,
+                                    "No AI analysis to export yet.",
+                                );
+                                dialog.run_async(|d, _| d.close());
+                                return;
+                            }
+                        };
+                        let dialog = FileChooserDialog::new(
+                            Some("Export Analysis As"),
+                            Some(&window_export),
+                            FileChooserAction::Save,
+                            &[("Cancel", ResponseType::Cancel), ("Export", ResponseType::Accept)],
+                        );
+                        dialog.set_current_name("finfiles_ai_analysis.md");
+                        let window_export = window_export.clone();
+                        dialog.run_async(move |dialog, resp| {
+                            if resp == ResponseType::Accept {
+                                if let Some(path) = dialog.file().and_then(|f| f.path()) {
+                                    if let Err(e) = export_analysis(&analysis, ExportFormat::from_path(&path), &path) {
+                                        let err_dialog = MessageDialog::new(
+                                            Some(&window_export),
+                                            gtk::DialogFlags::MODAL,
+                                            MessageType::Error,
+                                            ButtonsType::Ok,
+                                            &format!("Failed to export analysis: {e}"),
+                                        );
+                                        err_dialog.run_async(|d, _| d.close());
+                                    }
+                                }
+                            }
+                            dialog.close();
+                        });
+                    });
+
+                    // Export transcript logic
+                    let chat_history_vec_transcript = chat_history_vec.clone();
+                    let window_transcript = window.clone();
+                    export_transcript_button.connect_clicked(move |_| {
+                        if chat_history_vec_transcript.borrow().is_empty() {
+                            let dialog = MessageDialog::new(
+                                Some(&window_transcript),
+                                gtk::DialogFlags::MODAL,
+                                MessageType::Info,
+                                ButtonsType::Ok,
+                                "No chat exchanges to export yet.",
+                            );
+                            dialog.run_async(|d, _| d.close());
+                            return;
+                        }
+                        let dialog = FileChooserDialog::new(
+                            Some("Export Transcript As"),
+                            Some(&window_transcript),
+                            FileChooserAction::Save,
+                            &[("Cancel", ResponseType::Cancel), ("Export", ResponseType::Accept)],
+                        );
+                        dialog.set_current_name("finfiles_chat_transcript.md");
+                        let history = chat_history_vec_transcript.clone();
+                        let window_transcript = window_transcript.clone();
+                        dialog.run_async(move |dialog, resp| {
+                            if resp == ResponseType::Accept {
+                                if let Some(path) = dialog.file().and_then(|f| f.path()) {
+                                    if let Err(e) = export_chat_transcript(&history.borrow(), ExportFormat::from_path(&path), &path) {
+                                        let err_dialog = MessageDialog::new(
+                                            Some(&window_transcript),
+                                            gtk::DialogFlags::MODAL,
+                                            MessageType::Error,
+                                            ButtonsType::Ok,
+                                            &format!("Failed to export transcript: {e}"),
                                         );
                                         err_dialog.run_async(|d, _| d.close());
                                     }
@@ -1334,9 +13636,85 @@ fn build_main_window(app: &Application, state: Arc<AppState>, auth: Arc<AuthMana
                         });
                     });
 
+                    // Clear chat logic (confirmation first; the audit log is kept for compliance)
+                    let chat_history_vec_clear = chat_history_vec.clone();
+                    let history_list_clear = history_list.clone();
+                    let chat_history_view_clear = chat_history.clone();
+                    let window_clear = window.clone();
+                    clear_button.connect_clicked(move |_| {
+                        let dialog = MessageDialog::new(
+                            Some(&window_clear),
+                            gtk::DialogFlags::MODAL,
+                            MessageType::Question,
+                            ButtonsType::YesNo,
+                            "Clear the chat history? The audit log is not affected.",
+                        );
+                        let chat_history_vec = chat_history_vec_clear.clone();
+                        let history_list = history_list_clear.clone();
+                        let chat_history_view = chat_history_view_clear.clone();
+                        let audit_log_path = audit_log_path_clear.clone();
+                        let username = username_clear.clone();
+                        let history_path = history_path_clear.clone();
+                        dialog.run_async(move |dialog, resp| {
+                            if resp == ResponseType::Yes {
+                                if let Some(buffer) = chat_history_view.buffer() {
+                                    buffer.set_text("");
+                                }
+                                let list = history_list.borrow();
+                                while let Some(child) = list.first_child() {
+                                    list.remove(&child);
+                                }
+                                let cleared = clear_chat_history(&mut chat_history_vec.borrow_mut());
+                                if let Some(path) = history_path.as_ref() {
+                                    if let Err(e) = save_chat_history(path, &[], history_limit) {
+                                        log::warn!("Failed to save chat history: {}", e);
+                                    }
+                                }
+                                let record = AuditRecord::new(&username, "chat", "clear_history", &format!("{} entries", cleared));
+                                if let Err(e) = AuditLog::from_env(audit_log_path.clone()).record(&record) {
+                                    log::warn!("Failed to write audit log: {}", e);
+                                }
+                            }
+                            dialog.close();
+                        });
+                    });
+
+                    // View audit log logic
+                    let audit_log_path_view = audit_log_path.clone();
+                    let window_audit = window.clone();
+                    audit_button.connect_clicked(move |_| {
+                        let audit_log = AuditLog::from_env(audit_log_path_view.clone());
+                        let entries = match audit_log.read_entries() {
+                            Ok(entries) => entries,
+                            Err(e) => {
+                                let dialog = MessageDialog::new(
+                                    Some(&window_audit),
+                                    gtk::DialogFlags::MODAL,
+                                    MessageType::Error,
+                                    ButtonsType::Ok,
+                                    &format!("Failed to read audit log: {e}"),
+                                );
+                                dialog.run_async(|d, _| d.close());
+                                return;
+                            }
+                        };
+                        let text = TextView::new();
+                        text.set_editable(false);
+                        text.buffer().set_text(&entries.join("\n\n"));
+                        let viewer = gtk::Window::builder()
+                            .title(format!("Audit Log ({})", audit_log.path().display()))
+                            .transient_for(&window_audit)
+                            .default_width(800)
+                            .default_height(500)
+                            .child(&ScrolledWindow::builder().child(&text).build())
+                            .build();
+                        viewer.show();
+                    });
+
                     // Upload custom model logic
                     let ai_modules_upload = ai_modules.clone();
                     let backend_combo_upload = backend_combo.clone();
+                    let window_upload = window.clone();
                     upload_button.connect_clicked(move |_| {
                         let dialog = FileChooserDialog::new(
                             Some("Upload Model"),
@@ -1344,14 +13722,28 @@ fn build_main_window(app: &Application, state: Arc<AppState>, auth: Arc<AuthMana
                             FileChooserAction::Open,
                             &[("Cancel", ResponseType::Cancel), ("Upload", ResponseType::Accept)],
                         );
+                        let ai_modules_upload = ai_modules_upload.clone();
+                        let backend_combo_upload = backend_combo_upload.clone();
+                        let window_upload = window_upload.clone();
                         dialog.run_async(move |dialog, resp| {
                             if resp == ResponseType::Accept {
                                 if let Some(file) = dialog.file().and_then(|f| f.path()) {
-                                    // For demo: just use file name as model name
-                                    let name = file.file_name().and_then(|n| n.to_str()).unwrap_or("CustomModel").to_string();
-                                    if let Ok(custom_module) = CustomModelAIModule::new(name.clone()) {
-                                        ai_modules_upload.borrow_mut().push(Arc::new(custom_module));
-                                        backend_combo_upload.append_text("CustomModel");
+                                    match install_uploaded_model(&file) {
+                                        Ok(module) => {
+                                            let label = format!("ONNX ({})", module.model_name);
+                                            ai_modules_upload.borrow_mut().push(Arc::new(module));
+                                            backend_combo_upload.append_text(&label);
+                                        }
+                                        Err(e) => {
+                                            let err_dialog = MessageDialog::new(
+                                                Some(&window_upload),
+                                                gtk::DialogFlags::MODAL,
+                                                MessageType::Error,
+                                                ButtonsType::Ok,
+                                                &format!("Model rejected: {e}"),
+                                            );
+                                            err_dialog.run_async(|d, _| d.close());
+                                        }
                                     }
                                 }
                             }
@@ -1359,11 +13751,22 @@ fn build_main_window(app: &Application, state: Arc<AppState>, auth: Arc<AuthMana
                         });
                     });
 
-                    // Accessibility: Keyboard shortcuts (Enter to send, Ctrl+S to save, Ctrl+U to upload)
+                    // Accessibility: Keyboard shortcuts (Enter to send, Ctrl+S to save, Ctrl+U to upload,
+                    // Ctrl+1..Ctrl+9 to pick a backend)
                     let send_button_shortcut = send_button.clone();
                     let save_button_shortcut = save_button.clone();
                     let upload_button_shortcut = upload_button.clone();
+                    let backend_combo_shortcut = backend_combo.clone();
+                    let ai_modules_shortcut = ai_modules.clone();
                     window.add_controller(&gtk::EventControllerKey::new().connect_key_pressed(move |_, key, _, _| {
+                        if key.state().contains(gdk::ModifierType::CONTROL_MASK) {
+                            let digit = key.keyval().to_unicode().and_then(|c| c.to_digit(10));
+                            let index = digit.and_then(|d| backend_shortcut_index(d, ai_modules_shortcut.borrow().len()));
+                            if let Some(index) = index {
+                                backend_combo_shortcut.set_active(Some(index as u32));
+                                return true;
+                            }
+                        }
                         match key.keyval() {
                             gdk::keys::constants::Return => {
                                 if user_input.has_focus() {
@@ -1386,49 +13789,330 @@ fn build_main_window(app: &Application, state: Arc<AppState>, auth: Arc<AuthMana
 
                 });
 
-        window.add(&vbox);
-        window.show_all();
-        window
+                app.run_with_args(&toolkit_args(&std::env::args().collect::<Vec<_>>()));
+            }
+        }
+
+        #[cfg(test)]
+        mod tests {
+            use super::*;
+
+            #[test]
+            fn bold_and_code_spans() {
+                assert_eq!(markdown_to_pango("Revenue is **up** in `Q3`"), "Revenue is <b>up</b> in <tt>Q3</tt>");
+                // An unmatched marker is left as written
+                assert_eq!(markdown_to_pango("5 ** 2"), "5 ** 2");
+            }
+
+            #[test]
+            fn bullets_are_normalized() {
+                assert_eq!(markdown_to_pango("- one\n* **two**\n• three"), "  • one\n  • <b>two</b>\n  • three");
+            }
+
+            #[test]
+            fn headings_and_labels() {
+                assert_eq!(markdown_to_pango("# Summary"), "<b><big>Summary</big></b>");
+                assert_eq!(markdown_to_pango("Key risks:"), "<b>Key risks:</b>");
+            }
+
+            #[test]
+            fn markup_characters_are_escaped() {
+                let markup = markdown_to_pango("P/E < 20 & margin > 10%");
+                assert_eq!(markup, "P/E &lt; 20 &amp; margin &gt; 10%");
+                assert!(pango::parse_markup(&markup, '\0').is_ok());
+            }
+
+            #[test]
+            fn shortcut_digits_pick_backends_in_order() {
+                assert_eq!(backend_shortcut_index(1, 3), Some(0));
+                assert_eq!(backend_shortcut_index(2, 3), Some(1));
+                assert_eq!(backend_shortcut_index(3, 3), Some(2));
+            }
+
+            #[test]
+            fn shortcut_digits_wrap_around() {
+                assert_eq!(backend_shortcut_index(4, 3), Some(0));
+                assert_eq!(backend_shortcut_index(9, 3), Some(2));
+                assert_eq!(backend_shortcut_index(9, 1), Some(0));
+            }
+
+            #[test]
+            fn shortcut_digits_out_of_range_pick_nothing() {
+                assert_eq!(backend_shortcut_index(0, 3), None);
+                assert_eq!(backend_shortcut_index(10, 3), None);
+                assert_eq!(backend_shortcut_index(1, 0), None);
+            }
+            fn entry(backend: &str, query: &str, response: &str, timestamp: &str) -> ChatEntry {
+                (backend.to_string(), query.to_string(), response.to_string(), timestamp.to_string())
+            }
+
+            fn two_exchanges() -> Vec<ChatEntry> {
+                vec![
+                    entry("FINFILES AI", "Summarize revenue", "Revenue: 383.29B\n", "2026-01-05T10:00:00Z"),
+                    entry("RemoteLLM", "Any <risks>?\nBe brief", "Supply & demand", ""),
+                ]
+            }
+
+            #[test]
+            fn markdown_transcript_has_one_block_per_exchange() {
+                assert_eq!(
+                    chat_transcript_markdown(&two_exchanges()),
+                    "# FINFILES AI Chat Transcript\n\
+                     \n## Exchange 1\n\n- **Backend:** FINFILES AI\n- **Time:** 2026-01-05T10:00:00Z\n\
+                     \n**Prompt:**\n\n> Summarize revenue\n\n**Response:**\n\nRevenue: 383.29B\n\
+                     \n## Exchange 2\n\n- **Backend:** RemoteLLM\n\
+                     \n**Prompt:**\n\n> Any <risks>?\n> Be brief\n\n**Response:**\n\nSupply & demand\n"
+                );
+            }
+
+            #[test]
+            fn html_transcript_escapes_markup() {
+                let html = chat_transcript_html(&two_exchanges());
+                assert!(html.contains("<h2>Exchange 2</h2>"));
+                assert!(html.contains("<blockquote>Any &lt;risks&gt;?\nBe brief</blockquote>"));
+                assert!(html.contains("<pre>Supply &amp; demand</pre>"));
+                assert!(html.ends_with("</body>\n</html>\n"));
+            }
+            #[test]
+            fn chat_history_saves_and_loads_the_latest_entries() {
+                let dir = std::env::temp_dir().join(format!("finfiles-chat-history-{}", std::process::id()));
+                let path = dir.join("chat_history.json");
+                let history: Vec<ChatEntry> =
+                    (1..=5).map(|i| entry("FINFILES AI", &format!("query {}", i), &format!("answer {}", i), "")).collect();
+                save_chat_history(&path, &history, DEFAULT_HISTORY_LIMIT).unwrap();
+                assert_eq!(load_chat_history(&path, DEFAULT_HISTORY_LIMIT), history);
+                assert_eq!(load_chat_history(&path, 2), history[3..].to_vec());
+
+                save_chat_history(&path, &history, 3).unwrap();
+                assert_eq!(load_chat_history(&path, DEFAULT_HISTORY_LIMIT), history[2..].to_vec());
+                fs::remove_dir_all(&dir).unwrap();
+            }
+
+            #[test]
+            fn missing_or_corrupt_history_loads_empty() {
+                let path = std::env::temp_dir().join(format!("finfiles-chat-history-corrupt-{}.json", std::process::id()));
+                assert!(load_chat_history(&path, DEFAULT_HISTORY_LIMIT).is_empty());
+                fs::write(&path, "{not json").unwrap();
+                assert!(load_chat_history(&path, DEFAULT_HISTORY_LIMIT).is_empty());
+                fs::remove_file(&path).unwrap();
+            }
+
+            #[test]
+            fn history_without_timestamps_still_loads() {
+                let json = r#"[{"backend": "FINFILES AI", "query": "q", "response": "r"}]"#;
+                assert_eq!(chat_history_from_json(json, 10).unwrap(), vec![entry("FINFILES AI", "q", "r", "")]);
+            }
+            #[test]
+            fn clearing_history_empties_it_and_counts_entries() {
+                let mut history = two_exchanges();
+                assert_eq!(clear_chat_history(&mut history), 2);
+                assert!(history.is_empty());
+                assert_eq!(clear_chat_history(&mut history), 0);
+            }
+            #[test]
+            fn response_text_picks_the_answer_by_index() {
+                let history = two_exchanges();
+                assert_eq!(response_text(&history, 0).as_deref(), Some("Revenue: 383.29B\n"));
+                assert_eq!(response_text(&history, 1).as_deref(), Some("Supply & demand"));
+                assert_eq!(response_text(&history, 2), None);
+            }
+            fn revenue_frame(revenue: &[f64]) -> DataFrame {
+                let quarters: Vec<String> = (1..=revenue.len()).map(|q| format!("2024Q{q}")).collect();
+                DataFrame::new(vec![Series::new("quarter", quarters), Series::new("revenue", revenue)]).unwrap()
+            }
+
+            fn first_revenue(data: &SharedFrame) -> Option<f64> {
+                data.read().unwrap().column("revenue").ok().and_then(|c| c.f64().unwrap().get(0))
+            }
+
+            #[test]
+            fn chat_follows_the_frame_the_app_swaps_in() {
+                let user = crate::security::User { username: "tester".to_string(), roles: vec![RBACRole::Viewer] };
+                let state = crate::backend::AppState::new(user)
+                    .unwrap()
+                    .with_offline(true)
+                    .with_cache(None)
+                    .with_watchlist_path(None)
+                    .with_bookmarks_path(None);
+                let chat = FinancialAIChatApp::new(Vec::new(), DataFrame::default(), PathBuf::new(), "tester".to_string())
+                    .with_shared_data(state.ai_data())
+                    .with_history_path(None);
+                assert!(Arc::ptr_eq(&chat.data, &state.ai_data()));
+                assert_eq!(chat.data.read().unwrap().height(), 0);
+
+                state.set_data_state(DataState::Loaded(revenue_frame(&[90.8, 85.8])));
+                assert_eq!(first_revenue(&chat.data), Some(90.8));
+
+                // A later fetch replaces the frame in place; the chat sees it without being rebuilt
+                state.set_data_state(DataState::Loaded(revenue_frame(&[61.9, 62.0, 65.6])));
+                assert_eq!(chat.data.read().unwrap().height(), 3);
+                assert_eq!(first_revenue(&chat.data), Some(61.9));
+
+                state.set_data_state(DataState::Loading);
+                assert_eq!(chat.data.read().unwrap().height(), 0);
+            }
+        }
     }
 
-    use std::sync::Arc;
-    use polars::prelude::*;
-    use crate::ai::{FinfilesAI, OnnxAIModule, RemoteLLMAIModule, FinancialAIModule, CustomModelAIModule};
-    use crate::data_ingestion::FinancialDataLoader;
-    use crate::chat_ui::FinancialAIChatApp;
-    use crate::error::*;
+    // Crate-level checks: the library API on a mocked frame (no GTK or network
+    // access), and the main window's accessibility helpers
+    #[cfg(test)]
+    mod tests {
+        use crate::ai::{AnalysisResult, FinancialAIModule, FinfilesAI};
+        use atk::prelude::*;
+        use gtk::prelude::*;
+        use polars::prelude::*;
+
+        #[tokio::test]
+        async fn analyzes_a_mocked_frame_through_the_library_api() {
+            let df = df!(
+                "quarter" => &["2023-Q1", "2023-Q2"],
+                "revenue" => &[100.0, 120.0],
+                "net_income" => &[10.0, 14.0],
+            )
+            .unwrap();
+            let ai = FinfilesAI::new().unwrap();
+            match ai.analyze_structured(&df, "Summarize").await.unwrap() {
+                AnalysisResult::Summary { periods, metrics } => {
+                    assert_eq!(periods, 2);
+                    let names: Vec<&str> = metrics.iter().map(|m| m.metric.as_str()).collect();
+                    assert_eq!(names, vec!["revenue", "net_income"]);
+                }
+                other => panic!("expected a summary, got {:?}", other),
+            }
+            assert!(!ai.analyze(&df, "Summarize").await.unwrap().is_empty());
+        }
+
+        #[test]
+        fn status_label_is_exposed_as_a_status_bar() {
+            // Needs a display; skipped where GTK can't start (e.g. headless CI)
+            if gtk::init().is_err() {
+                return;
+            }
+            let label = gtk::Label::new(Some("Ready."));
+            super::mark_as_status(&label, "Status");
+            let accessible = label.get_accessible().unwrap();
+            assert_eq!(accessible.get_role(), atk::Role::Statusbar);
+            assert_eq!(accessible.get_name().as_deref(), Some("Status"));
+        }
+
+        #[test]
+        fn busy_spinner_runs_only_while_busy() {
+            // Needs a display; skipped where GTK can't start (e.g. headless CI)
+            if gtk::init().is_err() {
+                return;
+            }
+            let spinner = gtk::Spinner::new();
+            super::set_busy(&spinner, true);
+            assert!(spinner.get_property_active());
+            super::set_busy(&spinner, false);
+            assert!(!spinner.get_property_active());
+        }
+    }
 
     // Main Entry Point - FINFILES AI
 
     #[tokio::main]
     async fn main() -> Result<()> {
         env_logger::init();
-        log::info!("Starting AA SEC EDGAR + FINFILES AI: Unified Financial Data & AI Platform...");
 
-        // Prompt user for ticker (for initial SEC data and AI chat)
-        println!("Enter the stock ticker symbol (e.g., AAPL, MSFT): ");
-        let mut ticker = String::new();
-        std::io::stdin().read_line(&mut ticker)?;
-        let ticker = ticker.trim();
+        let args: Vec<String> = std::env::args().skip(1).collect();
+
+        // Server mode: answer HTTP API requests until stopped, without GTK
+        match server::ServeOptions::parse(&args, &config::AppConfig::current()) {
+            Ok(Some(options)) => {
+                if let Err(e) = server::serve(&options).await {
+                    eprintln!("Error: {e}");
+                    std::process::exit(1);
+                }
+                return Ok(());
+            }
+            Ok(None) => {}
+            Err(e) => {
+                eprintln!("{e}");
+                std::process::exit(2);
+            }
+        }
 
-        // Data ingestion from SEC EDGAR (async, with loading indicator in UI)
-        println!("Loading SEC EDGAR data for {ticker}...");
-        let ai_data = match FinancialDataLoader::load_sec_data_for_ticker(ticker).await {
-            Ok(df) => Some(df),
+        // Headless mode: run one analysis and exit without touching GTK or a display.
+        // The user is authenticated first, as in the GUI.
+        match cli::CliOptions::parse(&args) {
+            Ok(Some(options)) => {
+                let auth = security::AuthManager::new();
+                let user = match auth.authenticate_user() {
+                    Some(user) => user,
+                    None => {
+                        eprintln!("Authentication failed. Exiting.");
+                        std::process::exit(1);
+                    }
+                };
+                match cli::run(&options, &auth, &user).await {
+                    Ok(output) => {
+                        println!("{output}");
+                        return Ok(());
+                    }
+                    Err(e) => {
+                        eprintln!("Error: {e}");
+                        std::process::exit(1);
+                    }
+                }
+            }
+            Ok(None) => {}
             Err(e) => {
-                eprintln!("Error loading SEC data: {e}");
-                None
+                eprintln!("{e}");
+                std::process::exit(2);
+            }
+        }
+
+        run_gui(&args).await
+    }
+
+    // The GTK application: load the initial financials, then open the main window
+    async fn run_gui(args: &[String]) -> Result<()> {
+        log::info!("Starting AA SEC EDGAR + FINFILES AI: Unified Financial Data & AI Platform...");
+
+        // Offline demo mode: bundled sample data, no SEC contact or network needed
+        let offline = args.iter().any(|a| a == "--offline") || config::AppConfig::current().offline;
+        let initial_data = if offline {
+            println!("Offline mode: using bundled demo data.");
+            match fixtures::sample_dataframe() {
+                Ok(df) => backend::DataState::Loaded(df),
+                Err(e) => {
+                    eprintln!("Error loading demo data: {e}");
+                    backend::DataState::Error(e.to_string())
+                }
+            }
+        } else {
+            // Prompt user for ticker (for initial SEC data and AI chat)
+            println!("Enter the stock ticker symbol (e.g., AAPL, MSFT): ");
+            let mut ticker = String::new();
+            std::io::stdin().read_line(&mut ticker)?;
+            let ticker = ticker.trim();
+
+            // Data ingestion from SEC EDGAR (async, with loading indicator in UI)
+            println!("Loading SEC EDGAR data for {ticker}...");
+            let progress = |stage: LoadStage| println!("  {}", stage.label());
+            match FinancialDataLoader::load_sec_data_for_ticker(ticker, Some(progress)).await {
+                Ok(df) => backend::DataState::Loaded(df),
+                Err(e) => {
+                    eprintln!("Error loading SEC data: {e}");
+                    backend::DataState::Error(e.to_string())
+                }
             }
         };
 
         // Modular AI/ML engine selection (EDGAR-powered, ready for multi-backend)
-        let ai_modules: Vec<Arc<dyn FinancialAIModule>> = vec![
+        let mut ai_modules: Vec<Arc<dyn FinancialAIModule>> = vec![
             Arc::new(FinfilesAI::new()?),
-            Arc::new(OnnxAIModule::new()?),
             Arc::new(RemoteLLMAIModule::new()?),
             Arc::new(CustomModelAIModule::new()?),
             // CustomModelAIModule(s) can be added at runtime via UI
         ];
+        // ONNX backend is only offered when our independent model file is present
+        match OnnxAIModule::new() {
+            Ok(onnx) => ai_modules.insert(1, Arc::new(onnx)),
+            Err(e) => log::warn!("FINFILES AI: ONNX backend unavailable: {}", e),
+        }
 
         // Security, backend, and GTK app setup
         logging::init();
@@ -1444,7 +14128,7 @@ fn build_main_window(app: &Application, state: Arc<AppState>, auth: Arc<AuthMana
         let user = user.unwrap();
 
         // RBAC: Only allow access to permitted tickers/features
-        if !auth.has_role(&user, RBACRole::User) {
+        if !auth.has_permission(&user, Permission::FetchFilings) {
             eprintln!("Access denied. Contact support.");
             return Ok(());
         }
@@ -1458,10 +14142,30 @@ fn build_main_window(app: &Application, state: Arc<AppState>, auth: Arc<AuthMana
             Default::default(),
         );
 
-        let state = Arc::new(AppState::new(user.clone()));
+        // Stop background services (and the realtime feed) before the process exits,
+        // whether the last window closed or Ctrl+C was pressed
+        app.connect_shutdown(|_| {
+            tokio::task::block_in_place(|| {
+                tokio::runtime::Handle::current().block_on(backend::shutdown_services(backend::SHUTDOWN_TIMEOUT))
+            });
+        });
+        {
+            let app = app.clone();
+            glib::MainContext::default().spawn_local(async move {
+                if tokio::signal::ctrl_c().await.is_ok() {
+                    app.quit();
+                }
+            });
+        }
+
+        let state = Arc::new(
+            AppState::new(user.clone())?
+                .with_ws_url(std::env::var("FINFILES_WS_URL").ok())
+                .with_offline(offline),
+        );
         let auth_arc = auth.clone();
         let ai_modules_for_ui = ai_modules.clone();
-        let ai_data_for_ui = ai_data.clone();
+        let initial_data_for_ui = initial_data.clone();
         let audit_log_path_for_ui = audit_log_path.clone();
         let username_for_ui = username.clone();
 
@@ -1471,14 +14175,14 @@ fn build_main_window(app: &Application, state: Arc<AppState>, auth: Arc<AuthMana
                 state.clone(),
                 auth_arc.clone(),
                 ai_modules_for_ui.clone(),
-                ai_data_for_ui.clone(),
+                initial_data_for_ui.clone(),
                 audit_log_path_for_ui.clone(),
                 username_for_ui.clone(),
             );
             window.present();
         });
 
-        app.run();
+        app.run_with_args(&cli::toolkit_args(&std::env::args().collect::<Vec<_>>()));
 
         Ok(())
     }